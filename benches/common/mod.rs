@@ -0,0 +1,92 @@
+//! * The helpers shared by the benchmarks
+#![allow(dead_code)]
+use std::{
+    io::{self, Cursor, Read, Write, Seek, SeekFrom},
+    time::{Duration, Instant},
+};
+
+use flac::{FlacEncoder, FlacDecoder, options::{FlacEncoderParams, FlacCompression, FlacAudioForm}, closure_objects::{SamplesInfo, FlacReadStatus}};
+
+pub type MemoryEncoder<'a, 'b> = FlacEncoder<'a, &'b mut Cursor<Vec<u8>>>;
+
+/// * Encode into memory with the fastest compression level, `write` feeds the samples. Returns the time spent.
+pub fn encode(channels: u16, write: impl FnOnce(&mut MemoryEncoder<'_, '_>)) -> Duration {
+    let params = FlacEncoderParams {
+        compression: FlacCompression::Level0,
+        channels,
+        ..FlacEncoderParams::new()
+    };
+    encode_with(&params, write).1
+}
+
+/// * Encode into memory with the parameters, `write` feeds the samples. Returns the FLAC file and the time spent.
+pub fn encode_with(params: &FlacEncoderParams, write: impl FnOnce(&mut MemoryEncoder<'_, '_>)) -> (Vec<u8>, Duration) {
+    let mut cursor = Cursor::new(Vec::new());
+    let elapsed = {
+        let mut encoder = FlacEncoder::new(
+            &mut cursor,
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {
+                writer.write_all(data)
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {
+                writer.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {
+                writer.stream_position()
+            }),
+            params
+        ).unwrap();
+        encoder.initialize().unwrap();
+        let start = Instant::now();
+        write(&mut encoder);
+        encoder.finish().unwrap();
+        start.elapsed()
+    };
+    (cursor.into_inner(), elapsed)
+}
+
+/// * Decode the whole FLAC file from memory, `on_write` receives the samples. Returns the time spent.
+pub fn decode(data: &[u8], scale_to_i32_range: bool, mut on_write: impl FnMut(&[Vec<i32>])) -> Duration {
+    let length = data.len() as u64;
+    let mut decoder = FlacDecoder::new(
+        Cursor::new(data),
+        Box::new(|reader: &mut Cursor<&[u8]>, data: &mut [u8]| -> (usize, FlacReadStatus) {
+            match reader.read(data) {
+                Ok(0) => (0, FlacReadStatus::Eof),
+                Ok(size) => (size, FlacReadStatus::GoOn),
+                Err(_) => (0, FlacReadStatus::Abort),
+            }
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>, position: u64| -> Result<(), io::Error> {
+            reader.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            reader.stream_position()
+        }),
+        Box::new(move |_reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            Ok(length)
+        }),
+        Box::new(move |reader: &mut Cursor<&[u8]>| -> bool {
+            reader.position() >= length
+        }),
+        Box::new(|samples: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {
+            on_write(samples);
+            Ok(())
+        }),
+        Box::new(|error| panic!("{error}")),
+        false,
+        scale_to_i32_range,
+        FlacAudioForm::ChannelArray,
+    ).unwrap();
+    let start = Instant::now();
+    decoder.decode_all().into_result().unwrap();
+    decoder.finish().unwrap();
+    start.elapsed()
+}
+
+/// * A 440 Hz sine wave at 44100 Hz, 16 bits per sample.
+pub fn sample(i: usize) -> i32 {
+    ((i as f64 * 440.0 * std::f64::consts::TAU / 44100.0).sin() * 16384.0) as i32
+}
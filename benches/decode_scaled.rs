@@ -0,0 +1,30 @@
+//! * Decode a 24-bit FLAC file with and without `scale_to_i32_range`, run with `cargo bench`
+mod common;
+
+use std::{hint::black_box, time::Duration};
+
+use common::{encode_with, decode, sample};
+use flac::options::{FlacEncoderParams, FlacCompression};
+
+const ROUNDS: u32 = 10;
+
+fn main() {
+    let params = FlacEncoderParams {
+        compression: FlacCompression::Level0,
+        bits_per_sample: 24,
+        ..FlacEncoderParams::new()
+    };
+
+    // A minute of 24-bit stereo audio at 44100 Hz
+    let samples: Vec<i32> = (0..44100 * 60).flat_map(|i| [sample(i) << 8, -sample(i) << 8]).collect();
+    let (data, _) = encode_with(&params, |encoder| encoder.write_interleaved_samples(&samples).unwrap());
+
+    let mut unscaled = Duration::ZERO;
+    let mut scaled = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        unscaled += decode(&data, false, |samples| {black_box(samples);});
+        scaled += decode(&data, true, |samples| {black_box(samples);});
+    }
+    println!("Decoding without scaling: {:?} per minute of audio", unscaled / ROUNDS);
+    println!("Decoding with scaling:    {:?} per minute of audio", scaled / ROUNDS);
+}
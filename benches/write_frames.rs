@@ -0,0 +1,25 @@
+//! * Compare the `Vec`-based `write_frames()` with the array-based `write_stereo_frames()`, run with `cargo bench`
+mod common;
+
+use std::{hint::black_box, time::Duration};
+
+use common::{encode, sample};
+
+const ROUNDS: u32 = 10;
+const CHUNK: usize = 4096;
+
+fn main() {
+    let frames: Vec<Vec<i32>> = (0..CHUNK).map(|i| vec![sample(i), -sample(i)]).collect();
+    let arrays: Vec<[i32; 2]> = (0..CHUNK).map(|i| [sample(i), -sample(i)]).collect();
+
+    // A minute of audio at 44100 Hz
+    let chunks = 44100 * 60 / CHUNK;
+    let mut vec_based = Duration::ZERO;
+    let mut array_based = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        vec_based += encode(2, |encoder| for _ in 0..chunks {encoder.write_frames(black_box(&frames)).unwrap()});
+        array_based += encode(2, |encoder| for _ in 0..chunks {encoder.write_stereo_frames(black_box(&arrays)).unwrap()});
+    }
+    println!("write_frames():        {:?} per minute of audio", vec_based / ROUNDS);
+    println!("write_stereo_frames(): {:?} per minute of audio", array_based / ROUNDS);
+}
@@ -0,0 +1,37 @@
+//! * Compare `write_stereos()` and `write_mono_channel()` with collecting the interleaved samples into a new `Vec` for every call, run with `cargo bench`
+mod common;
+
+use std::{hint::black_box, time::Duration};
+
+use common::{encode, sample};
+
+const ROUNDS: u32 = 10;
+const CHUNK: usize = 4096;
+
+fn main() {
+    let stereos: Vec<(i32, i32)> = (0..CHUNK).map(|i| (sample(i), -sample(i))).collect();
+    let monos: Vec<i32> = (0..CHUNK).map(sample).collect();
+
+    // 4 million samples per channel
+    let chunks = 4_000_000 / CHUNK;
+    let mut collected = Duration::ZERO;
+    let mut stereo = Duration::ZERO;
+    let mut collected_mono = Duration::ZERO;
+    let mut mono = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        collected += encode(2, |encoder| for _ in 0..chunks {
+            let samples: Vec<i32> = black_box(&stereos).iter().flat_map(|(l, r)| [*l, *r]).collect();
+            encoder.write_interleaved_samples(&samples).unwrap();
+        });
+        stereo += encode(2, |encoder| for _ in 0..chunks {encoder.write_stereos(black_box(&stereos)).unwrap()});
+        collected_mono += encode(2, |encoder| for _ in 0..chunks {
+            let samples: Vec<i32> = black_box(&monos).iter().flat_map(|mono| [*mono, *mono]).collect();
+            encoder.write_interleaved_samples(&samples).unwrap();
+        });
+        mono += encode(2, |encoder| for _ in 0..chunks {encoder.write_mono_channel(black_box(&monos)).unwrap()});
+    }
+    println!("Collect stereos, then write_interleaved_samples(): {:?}", collected / ROUNDS);
+    println!("write_stereos():                                  {:?}", stereo / ROUNDS);
+    println!("Collect monos, then write_interleaved_samples():   {:?}", collected_mono / ROUNDS);
+    println!("write_mono_channel():                             {:?}", mono / ROUNDS);
+}
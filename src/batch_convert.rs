@@ -0,0 +1,232 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, Write, Seek, SeekFrom},
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::flac::{FlacEncoder, FlacEncoderError, FlacEncoderInitError, FlacDecoderError, FlacDecoderInitError, FlacEncoderParams};
+
+/// ## A conversion job for `convert()`
+/// * The reader and the writer are created by the factories when a thread takes the job, so the files aren't opened all at once.
+pub struct ConvertJob<Reader, WriteSeek> {
+    /// * The label to identify the job in the results, e.g. the file name.
+    pub label: String,
+
+    /// * Create the reader of the input.
+    pub reader: Box<dyn FnOnce() -> io::Result<Reader> + Send>,
+
+    /// * Create the writer of the FLAC file.
+    pub writer: Box<dyn FnOnce() -> io::Result<WriteSeek> + Send>,
+
+    /// * The parameters of the encoder for this job.
+    pub params: FlacEncoderParams,
+}
+
+impl<Reader, WriteSeek> Debug for ConvertJob<Reader, WriteSeek> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("ConvertJob")
+            .field("label", &self.label)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// ## Why a conversion job failed
+#[derive(Debug)]
+pub enum JobError {
+    /// * Failed to create the reader or the writer, or your `job_fn` failed to read the input.
+    Io(io::Error),
+
+    /// * The encoder failed.
+    Encoder(FlacEncoderError),
+
+    /// * The decoder failed, e.g. when re-compressing the FLAC files.
+    Decoder(FlacDecoderError),
+
+    /// * The job wasn't started because of the cancel token.
+    Cancelled,
+
+    /// * Your `metadata_fn` or `job_fn` panicked, with the panic message.
+    Panicked(String),
+}
+
+impl Display for JobError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "IO error: {error}"),
+            Self::Encoder(error) => write!(f, "{error}"),
+            Self::Decoder(error) => write!(f, "{error}"),
+            Self::Cancelled => write!(f, "The job was cancelled before it started"),
+            Self::Panicked(message) => write!(f, "The job panicked: {message}"),
+        }
+    }
+}
+
+impl Error for JobError {}
+
+impl From<io::Error> for JobError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<FlacEncoderError> for JobError {
+    fn from(error: FlacEncoderError) -> Self {
+        Self::Encoder(error)
+    }
+}
+
+impl From<FlacEncoderInitError> for JobError {
+    fn from(error: FlacEncoderInitError) -> Self {
+        Self::Encoder(error.into())
+    }
+}
+
+impl From<FlacDecoderError> for JobError {
+    fn from(error: FlacDecoderError) -> Self {
+        Self::Decoder(error)
+    }
+}
+
+impl From<FlacDecoderInitError> for JobError {
+    fn from(error: FlacDecoderInitError) -> Self {
+        Self::Decoder(error.into())
+    }
+}
+
+/// ## The result of a conversion job, see `convert()`
+#[derive(Debug)]
+pub struct JobResult<WriteSeek> {
+    /// * The label of the job.
+    pub label: String,
+
+    /// * The writer after the FLAC file is finished, or why the job failed.
+    pub result: Result<WriteSeek, JobError>,
+
+    /// * How long the job took, zero if it wasn't started.
+    pub elapsed: Duration,
+}
+
+impl<WriteSeek> JobResult<WriteSeek> {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// ## Convert many inputs into FLAC files on `threads` threads, the results are in the order of the jobs.
+/// * For each job, the encoder is created over its writer with its parameters, then your `metadata_fn` inserts the tags and the pictures of the input, e.g. by `insert_comments()` or `add_picture()`,
+///   then the encoder is initialized, then your `job_fn` reads the input and feeds the samples to the encoder, then the encoder is finished.
+/// * The threads take the jobs from a shared queue, zero or one thread means converting on the current thread.
+/// * A panic in `metadata_fn` or `job_fn` fails only its job with `JobError::Panicked`.
+pub fn convert<I, Reader, WriteSeek, M, F>(jobs: I, threads: usize, metadata_fn: M, job_fn: F) -> Vec<JobResult<WriteSeek>>
+where
+    I: IntoIterator<Item = ConvertJob<Reader, WriteSeek>>,
+    Reader: Send,
+    WriteSeek: Write + Seek + Send,
+    M: Fn(&mut Reader, &mut FlacEncoder<&mut WriteSeek>) -> Result<(), JobError> + Sync,
+    F: Fn(&mut Reader, &mut FlacEncoder<&mut WriteSeek>) -> Result<(), JobError> + Sync {
+    convert_with_cancel(jobs, threads, Arc::new(AtomicBool::new(false)), metadata_fn, job_fn)
+}
+
+/// ## The same as `convert()`, but setting the `cancel_token` stops the pending jobs
+/// * The jobs already running are finished, the pending ones fail with `JobError::Cancelled`.
+///   Pass the same token to your decoders in `job_fn` by `set_cancel_token()` to stop the running ones too.
+pub fn convert_with_cancel<I, Reader, WriteSeek, M, F>(jobs: I, threads: usize, cancel_token: Arc<AtomicBool>, metadata_fn: M, job_fn: F) -> Vec<JobResult<WriteSeek>>
+where
+    I: IntoIterator<Item = ConvertJob<Reader, WriteSeek>>,
+    Reader: Send,
+    WriteSeek: Write + Seek + Send,
+    M: Fn(&mut Reader, &mut FlacEncoder<&mut WriteSeek>) -> Result<(), JobError> + Sync,
+    F: Fn(&mut Reader, &mut FlacEncoder<&mut WriteSeek>) -> Result<(), JobError> + Sync {
+    let queue = Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>());
+    let num_jobs = queue.lock().unwrap().len();
+    let results = Mutex::new((0..num_jobs).map(|_| None).collect::<Vec<Option<JobResult<WriteSeek>>>>());
+
+    let worker = || {
+        loop {
+            let Some((i, job)) = queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            let result = if cancel_token.load(Ordering::Relaxed) {
+                JobResult {
+                    label: job.label,
+                    result: Err(JobError::Cancelled),
+                    elapsed: Duration::ZERO,
+                }
+            } else {
+                let start = Instant::now();
+                let result = panic::catch_unwind(AssertUnwindSafe(|| run_job(job.reader, job.writer, &job.params, &metadata_fn, &job_fn)))
+                    .unwrap_or_else(|payload| Err(JobError::Panicked(panic_message(payload))));
+                JobResult {
+                    label: job.label,
+                    result,
+                    elapsed: start.elapsed(),
+                }
+            };
+            results.lock().unwrap()[i] = Some(result);
+        }
+    };
+
+    if threads <= 1 {
+        worker();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..threads.min(num_jobs) {
+                scope.spawn(worker);
+            }
+        });
+    }
+
+    results.into_inner().unwrap().into_iter().flatten().collect()
+}
+
+/// * The message of the panic payload, it's a `&str` or a `String` for the `panic!()` with a message.
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(|| "Unknown panic".to_owned(), |message| message.to_string()),
+    }
+}
+
+/// * Run one job on the current thread.
+fn run_job<Reader, WriteSeek, M, F>(
+    reader: Box<dyn FnOnce() -> io::Result<Reader> + Send>,
+    writer: Box<dyn FnOnce() -> io::Result<WriteSeek> + Send>,
+    params: &FlacEncoderParams,
+    metadata_fn: &M,
+    job_fn: &F,
+) -> Result<WriteSeek, JobError>
+where
+    WriteSeek: Write + Seek,
+    M: Fn(&mut Reader, &mut FlacEncoder<&mut WriteSeek>) -> Result<(), JobError>,
+    F: Fn(&mut Reader, &mut FlacEncoder<&mut WriteSeek>) -> Result<(), JobError> {
+    let mut reader = reader()?;
+    let mut writer = writer()?;
+    let mut encoder = FlacEncoder::new(
+        &mut writer,
+        Box::new(|writer: &mut &mut WriteSeek, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)
+        }),
+        Box::new(|writer: &mut &mut WriteSeek, position: u64| -> Result<(), io::Error> {
+            writer.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut WriteSeek| -> Result<u64, io::Error> {
+            writer.stream_position()
+        }),
+        params
+    )?;
+    metadata_fn(&mut reader, &mut encoder)?;
+    encoder.initialize()?;
+    job_fn(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+    drop(encoder);
+    Ok(writer)
+}
@@ -0,0 +1,109 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, Read, Write, Seek},
+};
+
+use crate::flac::{FlacCompression, FlacEncoder, FlacEncoderError, FlacEncoderParams};
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo};
+use crate::encode_interleaved::CHUNK_FRAMES;
+use crate::transcode::TranscodeError;
+
+/// * All of the compression levels, tried by `best_compression()` when no level is given.
+const ALL_LEVELS: [FlacCompression; 9] = [
+    FlacCompression::Level0,
+    FlacCompression::Level1,
+    FlacCompression::Level2,
+    FlacCompression::Level3,
+    FlacCompression::Level4,
+    FlacCompression::Level5,
+    FlacCompression::Level6,
+    FlacCompression::Level7,
+    FlacCompression::Level8,
+];
+
+/// ## Find which of the compression `levels` produces the smallest FLAC file of the audio from the `reader`, returns the level and the size in bytes
+/// * The FLAC file is decoded into memory once, then encoded at every level with its Vorbis comments, the encoded files are only counted, never kept.
+/// * If some levels produce the same size, the first one in `levels` wins, so list the faster levels first. An empty `levels` tries all of the levels.
+/// * Fails with `FLAC_DECODER_DAMAGED_STREAM` if the decoder reported an error in the stream, e.g. a frame CRC mismatch.
+pub fn best_compression<ReadSeek>(reader: ReadSeek, levels: &[FlacCompression]) -> Result<(FlacCompression, u64), TranscodeError>
+where
+    ReadSeek: Read + Seek {
+    let samples = RefCell::new(Vec::<i32>::new());
+    // libFLAC passes the silence in place of the damaged frame, the first error it reported fails the comparison.
+    let stream_error = Cell::new(None);
+    let mut decoder = FlacDecoder::with_default_io(
+        reader,
+        Box::new(|frames: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {
+            samples.borrow_mut().extend(frames.iter().flatten());
+            Ok(())
+        }),
+        Box::new(|error: FlacInternalDecoderError| {
+            if stream_error.get().is_none() {
+                stream_error.set(Some(error));
+            }
+        }),
+        true,
+        false,
+        FlacAudioForm::FrameArray,
+    )?;
+    decoder.decode_all().into_result()?;
+    if let Some(error) = stream_error.get() {
+        return Err(FlacDecoderError::damaged_stream(error, "best_compression").into());
+    }
+    let stream_info = decoder.get_stream_info().copied();
+    let comments: Vec<(String, String)> = decoder.get_comments().iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+    decoder.finish()?;
+    drop(decoder);
+    let Some(stream_info) = stream_info else {
+        return Err(FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_END_OF_STREAM, "best_compression").into());
+    };
+    let samples = samples.into_inner();
+    let comments: Vec<(&str, &str)> = comments.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+
+    let mut best = None;
+    for &compression in if levels.is_empty() {&ALL_LEVELS[..]} else {levels} {
+        let params = FlacEncoderParams {
+            compression,
+            channels: stream_info.channels as u16,
+            sample_rate: stream_info.sample_rate,
+            bits_per_sample: stream_info.bits_per_sample,
+            total_samples_estimate: (samples.len() / stream_info.channels.max(1) as usize) as u64,
+            ..FlacEncoderParams::new()
+        };
+        let size = encoded_size(&params, &samples, &comments)?;
+        if best.is_none_or(|(_, best_size)| size < best_size) {
+            best = Some((compression, size));
+        }
+    }
+    Ok(best.unwrap())
+}
+
+/// * Encode the samples without keeping the FLAC file, returns its size in bytes.
+/// * The writer can't seek, so libFLAC doesn't rewrite the STREAMINFO block at `finish()`, and `bytes_written()` is the size of the FLAC file.
+fn encoded_size(params: &FlacEncoderParams, samples: &[i32], comments: &[(&str, &str)]) -> Result<u64, FlacEncoderError> {
+    let channels = (params.channels as usize).max(1);
+    let mut encoder = FlacEncoder::new(
+        io::empty(),
+        Box::new(|writer: &mut io::Empty, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)
+        }),
+        Box::new(|_writer: &mut io::Empty, _position: u64| -> Result<(), io::Error> {
+            Err(io::Error::from(io::ErrorKind::NotSeekable))
+        }),
+        Box::new(|_writer: &mut io::Empty| -> Result<u64, io::Error> {
+            Err(io::Error::from(io::ErrorKind::NotSeekable))
+        }),
+        params
+    )?;
+    for (key, value) in comments {
+        encoder.insert_comments(key, value)?;
+    }
+    encoder.initialize()?;
+    for chunk in samples.chunks(CHUNK_FRAMES * channels) {
+        encoder.write_interleaved_samples(chunk)?;
+    }
+    encoder.finish()?;
+    Ok(encoder.bytes_written())
+}
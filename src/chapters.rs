@@ -0,0 +1,243 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    io::{Read, Write, Seek},
+    time::Duration,
+};
+
+use libflac_sys::*;
+
+use crate::flac::{
+    FlacEncoderUnmovable, FlacDecoderUnmovable, FlacEncoderInitError,
+    FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType,
+};
+
+/// ## The maximum number of chapters, the `CHAPTERxxx` comment convention only has three digits for the chapter number.
+pub const MAX_CHAPTERS: usize = 999;
+
+/// ## The maximum number of chapters that could be stored in the cue sheet.
+/// Track number 0 is illegal and track number 255 is used by the lead-out track.
+pub const MAX_CUE_SHEET_CHAPTERS: usize = 254;
+
+/// ## The track number of the lead-out track of a non-CD cue sheet.
+const LEAD_OUT_TRACK_NO: u8 = 255;
+
+/// ## A chapter of a podcast or an audio book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// * Where the chapter starts
+    pub start: Duration,
+
+    /// * The title of the chapter
+    pub title: String,
+
+    /// * An optional URL for the chapter
+    pub url: Option<String>,
+}
+
+/// ## The chapters of the FLAC file
+/// * The chapters are stored in two ways: a cue sheet with a track per chapter, and the `CHAPTER001`/`CHAPTER001NAME`/`CHAPTER001URL` comments.
+/// * The cue sheet can't store the titles and the URLs, so the comments take precedence when reading the chapters back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Chapters {
+    chapters: Vec<Chapter>,
+}
+
+impl Chapters {
+    pub fn new() -> Self {
+        Self {
+            chapters: Vec::new(),
+        }
+    }
+
+    /// * Add a chapter, the chapters don't need to be pushed in order.
+    pub fn push(&mut self, start: Duration, title: &str, url: Option<&str>) {
+        self.chapters.push(Chapter {
+            start,
+            title: title.to_owned(),
+            url: url.map(|url| url.to_owned()),
+        });
+    }
+
+    /// * The number of the chapters, the chapters starting at the same time count once.
+    pub fn len(&self) -> usize {
+        self.chapters.iter().map(|chapter| chapter.start).collect::<BTreeSet<_>>().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chapters.is_empty()
+    }
+
+    /// * Get the chapters sorted by the start time. If some chapters start at the same time, only the first pushed one is kept.
+    pub fn get_chapters(&self) -> Vec<Chapter> {
+        let mut chapters = self.chapters.clone();
+        chapters.sort_by_key(|chapter| chapter.start);
+        chapters.dedup_by_key(|chapter| chapter.start);
+        chapters
+    }
+
+    /// * Write the chapters as the comments and the cue sheet to the encoder, must be called before `initialize()`
+    /// * The lead-out track of the cue sheet is at the end of the audio, so `total_samples_estimate` of the encoder parameters must be set, and the last chapter must start before it.
+    /// * More than `MAX_CUE_SHEET_CHAPTERS` chapters is an error because the cue sheet can't hold them, see `apply_comments_to()` to write only the comments then.
+    pub fn apply_to<WriteSeek>(&self, encoder: &mut FlacEncoderUnmovable<'_, WriteSeek>, sample_rate: u32) -> Result<(), FlacEncoderInitError>
+    where
+        WriteSeek: Write + Seek {
+        let chapters = self.get_chapters();
+        if chapters.is_empty() {
+            return Ok(());
+        }
+        if chapters.len() > MAX_CUE_SHEET_CHAPTERS {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "Chapters::apply_to"));
+        }
+
+        let mut tracks = BTreeMap::<u8, FlacCueTrack>::new();
+        for (i, chapter) in chapters.iter().enumerate() {
+            let track_no = i as u8 + 1;
+            tracks.insert(track_no, new_cue_track(track_no, duration_to_samples(chapter.start, sample_rate), vec![
+                FlacCueSheetIndex {
+                    offset: 0,
+                    number: 1,
+                }
+            ]));
+        }
+        let last_offset = tracks.values().last().map_or(0, |track| track.offset);
+        let lead_out = encoder.get_params().total_samples_estimate;
+        if lead_out <= last_offset {
+            // Without the length of the audio, the last chapter would have no length.
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "Chapters::apply_to"));
+        }
+        tracks.insert(LEAD_OUT_TRACK_NO, new_cue_track(LEAD_OUT_TRACK_NO, lead_out, Vec::new()));
+        self.apply_comments_to(encoder)?;
+        encoder.insert_cue_sheet(&FlacCueSheet {
+            media_catalog_number: [0; 129],
+            lead_in: 0,
+            is_cd: false,
+            tracks,
+        })
+    }
+
+    /// * Write the chapters only as the `CHAPTERxxx` comments to the encoder, must be called before `initialize()`
+    /// * It doesn't need the length of the audio, and holds up to `MAX_CHAPTERS` chapters, more than that is an error.
+    pub fn apply_comments_to<WriteSeek>(&self, encoder: &mut FlacEncoderUnmovable<'_, WriteSeek>) -> Result<(), FlacEncoderInitError>
+    where
+        WriteSeek: Write + Seek {
+        let chapters = self.get_chapters();
+        if chapters.len() > MAX_CHAPTERS {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "Chapters::apply_comments_to"));
+        }
+        for (i, chapter) in chapters.iter().enumerate() {
+            let key = format!("CHAPTER{:03}", i + 1);
+            encoder.insert_comments(&key, &format_timestamp(chapter.start))?;
+            encoder.insert_comments(&format!("{key}NAME"), &chapter.title)?;
+            if let Some(url) = &chapter.url {
+                encoder.insert_comments(&format!("{key}URL"), url)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// * Retrieve the chapters from a decoder which has read the metadata.
+    /// * The `CHAPTERxxx` comments take precedence, the first cue sheet is used if there are no such comments.
+    /// * The chapters from the cue sheet have no titles, and need the sample rate from the STREAMINFO block.
+    pub fn from_decoder<ReadSeek>(decoder: &FlacDecoderUnmovable<'_, ReadSeek>) -> Self
+    where
+        ReadSeek: Read + Seek {
+        let from_comments = Self::from_comments(decoder.get_comments());
+        if !from_comments.is_empty() {
+            return from_comments;
+        }
+        match (decoder.get_cue_sheets().first(), decoder.get_stream_info().map(|info| info.sample_rate)) {
+            (Some(cue_sheet), Some(sample_rate)) => Self::from_cue_sheet(cue_sheet, sample_rate),
+            _ => Self::new(),
+        }
+    }
+
+    /// * Parse the `CHAPTERxxx` comments, the comments with an unparseable timestamp are ignored.
+    pub fn from_comments(comments: &BTreeMap<String, String>) -> Self {
+        let mut ret = Self::new();
+        for i in 1..=MAX_CHAPTERS {
+            let key = format!("CHAPTER{i:03}");
+            let Some(start) = comments.get(&key).and_then(|timestamp| parse_timestamp(timestamp)) else {
+                continue;
+            };
+            ret.push(
+                start,
+                comments.get(&format!("{key}NAME")).map_or("", |title| title.as_str()),
+                comments.get(&format!("{key}URL")).map(|url| url.as_str()),
+            );
+        }
+        ret
+    }
+
+    /// * Use every track of the cue sheet as a chapter, except the lead-out track which is the last track.
+    pub fn from_cue_sheet(cue_sheet: &FlacCueSheet, sample_rate: u32) -> Self {
+        let mut ret = Self::new();
+        let num_tracks = cue_sheet.tracks.len();
+        for track in cue_sheet.tracks.values().take(num_tracks.saturating_sub(1)) {
+            // The chapter starts at index 01, where the track starts after the pregap.
+            let index_offset = track.indices.iter().find(|index| index.number == 1).map_or(0, |index| index.offset);
+            ret.push(samples_to_duration(track.offset + index_offset, sample_rate), "", None);
+        }
+        ret
+    }
+}
+
+fn new_cue_track(track_no: u8, offset: u64, indices: Vec<FlacCueSheetIndex>) -> FlacCueTrack {
+    FlacCueTrack {
+        offset,
+        track_no,
+        isrc: [0; 13],
+        type_: FlacTrackType::Audio,
+        pre_emphasis: false,
+        indices,
+    }
+}
+
+fn duration_to_samples(duration: Duration, sample_rate: u32) -> u64 {
+    (duration.as_nanos() * sample_rate as u128 / 1_000_000_000) as u64
+}
+
+fn samples_to_duration(samples: u64, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        Duration::ZERO
+    } else {
+        Duration::from_nanos((samples as u128 * 1_000_000_000 / sample_rate as u128) as u64)
+    }
+}
+
+/// * Format the duration as `HH:MM:SS.mmm`
+fn format_timestamp(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    let (hours, millis) = (millis / 3_600_000, millis % 3_600_000);
+    let (minutes, millis) = (millis / 60_000, millis % 60_000);
+    let (seconds, millis) = (millis / 1000, millis % 1000);
+    format!("{hours:02}:{minutes:02}:{seconds:02}.{millis:03}")
+}
+
+/// * Parse `HH:MM:SS.mmm`, the hours and the fraction are optional.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let timestamp = timestamp.trim();
+    let (hms, fraction) = match timestamp.split_once('.') {
+        Some((hms, fraction)) => (hms, fraction),
+        None => (timestamp, ""),
+    };
+    let mut seconds = 0u64;
+    let parts: Vec<&str> = hms.split(':').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    for part in parts {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        if fraction.len() > 9 || !fraction.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        format!("{fraction:0<9}").parse::<u32>().ok()?
+    };
+    Some(Duration::new(seconds, nanos))
+}
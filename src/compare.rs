@@ -0,0 +1,167 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, Read, Seek},
+};
+
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo, DecodeEvent};
+
+/// ## The first difference between the audio of two FLAC files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioDifference {
+    /// * The sample rates of `a` and `b`
+    SampleRate(u32, u32),
+
+    /// * The numbers of channels of `a` and `b`
+    Channels(u32, u32),
+
+    /// * The bits per sample of `a` and `b`
+    BitsPerSample(u32, u32),
+
+    /// * The sample differs at the sample position (per channel) of the channel.
+    Sample {
+        position: u64,
+        channel: u32,
+        a: i32,
+        b: i32,
+    },
+
+    /// * The samples are the same as far as both go, but the lengths in samples per channel differ.
+    Length(u64, u64),
+}
+
+impl Display for AudioDifference {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::SampleRate(a, b) => write!(f, "The sample rates differ: {a} Hz and {b} Hz"),
+            Self::Channels(a, b) => write!(f, "The numbers of channels differ: {a} and {b}"),
+            Self::BitsPerSample(a, b) => write!(f, "The bits per sample differ: {a} and {b}"),
+            Self::Sample{position, channel, a, b} => write!(f, "The sample at {position} of channel {channel} differs: {a} and {b}"),
+            Self::Length(a, b) => write!(f, "The lengths differ: {a} and {b} samples"),
+        }
+    }
+}
+
+/// ## Check whether two FLAC files have the same audio, e.g. to assert a lossless transcode preserved the audio.
+/// * See `compare_audio()`
+pub fn audio_equal<ReadSeek1, ReadSeek2>(a: ReadSeek1, b: ReadSeek2) -> Result<bool, FlacDecoderError>
+where
+    ReadSeek1: Read + Seek,
+    ReadSeek2: Read + Seek {
+    Ok(compare_audio(a, b)?.is_none())
+}
+
+/// ## Compare the audio of two FLAC files sample by sample, returns the first difference, or `None` if the audio is the same.
+/// * The metadata is ignored, and so are the block sizes, the flattened sample streams are compared.
+/// * Both files are decoded side by side, only the samples of the current frames are kept in memory.
+pub fn compare_audio<ReadSeek1, ReadSeek2>(a: ReadSeek1, b: ReadSeek2) -> Result<Option<AudioDifference>, FlacDecoderError>
+where
+    ReadSeek1: Read + Seek,
+    ReadSeek2: Read + Seek {
+    let mut a = SampleStream::new(a)?;
+    let mut b = SampleStream::new(b)?;
+
+    // The interleaved position
+    let mut position = 0u64;
+    loop {
+        a.fill()?;
+        b.fill()?;
+        if let (Some(info_a), Some(info_b)) = (&a.samples_info, &b.samples_info) {
+            if info_a.sample_rate != info_b.sample_rate {
+                return Ok(Some(AudioDifference::SampleRate(info_a.sample_rate, info_b.sample_rate)));
+            }
+            if info_a.channels != info_b.channels {
+                return Ok(Some(AudioDifference::Channels(info_a.channels, info_b.channels)));
+            }
+            if info_a.bits_per_sample != info_b.bits_per_sample {
+                return Ok(Some(AudioDifference::BitsPerSample(info_a.bits_per_sample, info_b.bits_per_sample)));
+            }
+        }
+        if a.pending.is_empty() || b.pending.is_empty() {
+            break;
+        }
+        let channels = a.samples_info.as_ref().map_or(1, |info| info.channels.max(1)) as u64;
+        let count = a.pending.len().min(b.pending.len());
+        for (sample_a, sample_b) in a.pending.drain(..count).zip(b.pending.drain(..count)) {
+            if sample_a != sample_b {
+                return Ok(Some(AudioDifference::Sample {
+                    position: position / channels,
+                    channel: (position % channels) as u32,
+                    a: sample_a,
+                    b: sample_b,
+                }));
+            }
+            position += 1;
+        }
+    }
+
+    // One of them ended, count the rest of the other.
+    let length_a = a.finish()?;
+    let length_b = b.finish()?;
+    if length_a != length_b {
+        Ok(Some(AudioDifference::Length(length_a, length_b)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// * Pulls the interleaved samples from a decoder frame by frame.
+struct SampleStream<'a, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    decoder: FlacDecoder<'a, ReadSeek>,
+    samples_info: Option<SamplesInfo>,
+    pending: VecDeque<i32>,
+    samples: u64,
+    ended: bool,
+}
+
+impl<ReadSeek> SampleStream<'_, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    fn new(reader: ReadSeek) -> Result<Self, FlacDecoderError> {
+        Ok(Self {
+            decoder: FlacDecoder::with_default_io(
+                reader,
+                Box::new(|_samples: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {Ok(())}),
+                // The damaged frames are decoded as silence, which shows up as the difference.
+                Box::new(|_error: FlacInternalDecoderError| {}),
+                false,
+                false,
+                FlacAudioForm::FrameArray,
+            )?,
+            samples_info: None,
+            pending: VecDeque::new(),
+            samples: 0,
+            ended: false,
+        })
+    }
+
+    /// * Decode until there are some samples pending or the stream ends.
+    fn fill(&mut self) -> Result<(), FlacDecoderError> {
+        while self.pending.is_empty() && !self.ended {
+            match self.decoder.next_event()? {
+                DecodeEvent::Audio(frames, samples_info) => {
+                    self.samples += samples_info.samples as u64;
+                    self.pending.extend(frames.into_iter().flatten());
+                    self.samples_info = Some(samples_info);
+                }
+                DecodeEvent::Metadata(_) => (),
+                DecodeEvent::EndOfStream => self.ended = true,
+            }
+        }
+        Ok(())
+    }
+
+    /// * Decode the rest of the stream, returns the total samples per channel.
+    fn finish(&mut self) -> Result<u64, FlacDecoderError> {
+        while !self.ended {
+            self.pending.clear();
+            self.fill()?;
+        }
+        self.decoder.finish()?;
+        Ok(self.samples)
+    }
+}
@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Debug,
+    io::{self, Read, Seek},
+};
+
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo};
+use crate::flac::{FlacStreamInfo, DecoderLimits};
+use crate::transcode::convert_bit_depth;
+
+/// ## Decode the whole FLAC file into the interleaved 16-bit PCM samples, with its STREAMINFO block for the format
+/// * The samples of other bit depths are converted: the wider ones are right-shifted with rounding, the narrower ones are left-shifted.
+/// * All of the decoded audio is kept in memory, that's 2 bytes per sample per channel. See `decode_to_i16_with_limits()` to cap it.
+/// * Fails with `FLAC_DECODER_DAMAGED_STREAM` if the decoder reported an error in the stream, e.g. a frame CRC mismatch.
+pub fn decode_to_i16<ReadSeek>(reader: ReadSeek) -> Result<(FlacStreamInfo, Vec<i16>), FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    decode_to_i16_with_limits(reader, DecoderLimits::default())
+}
+
+/// ## Decode the whole FLAC file into the interleaved 16-bit PCM samples, rejecting the files beyond the `limits`
+/// * E.g. set `max_total_samples` to avoid the surprising multi-GB allocations, the file exceeding it fails with `FLAC_DECODER_LIMIT_EXCEEDED`.
+pub fn decode_to_i16_with_limits<ReadSeek>(reader: ReadSeek, limits: DecoderLimits) -> Result<(FlacStreamInfo, Vec<i16>), FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    decode_to(reader, limits, to_i16, "decode_to_i16")
+}
+
+/// ## Decode the whole FLAC file into the interleaved 8-bit unsigned PCM samples, e.g. for the 8-bit WAV file, with its STREAMINFO block for the format
+/// * The samples are converted by `util::convert_to_u8()`, the silence is 128.
+pub fn decode_to_u8<ReadSeek>(reader: ReadSeek) -> Result<(FlacStreamInfo, Vec<u8>), FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    decode_to_u8_with_limits(reader, DecoderLimits::default())
+}
+
+/// ## Decode the whole FLAC file into the interleaved 8-bit unsigned PCM samples, rejecting the files beyond the `limits`
+pub fn decode_to_u8_with_limits<ReadSeek>(reader: ReadSeek, limits: DecoderLimits) -> Result<(FlacStreamInfo, Vec<u8>), FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    decode_to(reader, limits, convert_to_u8, "decode_to_u8")
+}
+
+/// ## Convert the signed sample of the bit depth into the 8-bit unsigned sample of the WAV file
+/// * FLAC's 8-bit samples are signed, the WAV file's are unsigned with the midpoint at 128, i.e. -128 becomes 0, 0 becomes 128, 127 becomes 255.
+/// * The samples of other bit depths are converted to 8-bit by `util::convert_bit_depth()` first.
+pub fn convert_to_u8(sample: i32, bits_per_sample: u32) -> u8 {
+    (convert_bit_depth(sample, bits_per_sample, 8) + 128) as u8
+}
+
+/// * Decode the whole FLAC file into the interleaved samples converted by `convert`.
+fn decode_to<ReadSeek, T>(reader: ReadSeek, limits: DecoderLimits, convert: fn(i32, u32) -> T, function: &'static str) -> Result<(FlacStreamInfo, Vec<T>), FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    let samples = RefCell::new(Vec::<T>::new());
+    // libFLAC passes the silence in place of the damaged frame, the first error it reported fails the decoding.
+    let stream_error = Cell::new(None);
+    let mut decoder = FlacDecoder::with_default_io(
+        reader,
+        Box::new(|frames: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+            let bits_per_sample = samples_info.bits_per_sample;
+            samples.borrow_mut().extend(frames.iter().flatten().map(|&sample| convert(sample, bits_per_sample)));
+            Ok(())
+        }),
+        Box::new(|error: FlacInternalDecoderError| {
+            if stream_error.get().is_none() {
+                stream_error.set(Some(error));
+            }
+        }),
+        true,
+        false,
+        FlacAudioForm::FrameArray,
+    )?;
+    decoder.set_limits(limits);
+    decoder.decode_all().into_result()?;
+    if let Some(error) = stream_error.get() {
+        return Err(FlacDecoderError::damaged_stream(error, function));
+    }
+    let stream_info = decoder.get_stream_info().copied();
+    decoder.finish()?;
+    drop(decoder);
+    let Some(stream_info) = stream_info else {
+        return Err(FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_END_OF_STREAM, function));
+    };
+    Ok((stream_info, samples.into_inner()))
+}
+
+/// * Convert the sample of the bit depth into 16-bit.
+fn to_i16(sample: i32, bits_per_sample: u32) -> i16 {
+    convert_bit_depth(sample, bits_per_sample, 16) as i16
+}
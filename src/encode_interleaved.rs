@@ -0,0 +1,56 @@
+#![allow(dead_code)]
+
+use std::{
+    io::{self, Write, Seek, SeekFrom},
+};
+
+use libflac_sys::*;
+
+use crate::flac::{FlacEncoder, FlacEncoderError, FlacEncoderParams};
+
+/// * How many audio frames are passed to the encoder at a time.
+pub(crate) const CHUNK_FRAMES: usize = 4096;
+
+/// ## Encode the interleaved samples into the `writer` as a whole FLAC file with the Vorbis comments, returns the size of the FLAC file in bytes
+/// * See `FlacEncoderParams` for the information on how to provide your samples in the `[i32]` array.
+/// * The number of samples must be a multiple of the channels, otherwise `FLAC__STREAM_ENCODER_FRAMING_ERROR` is returned before anything is written.
+/// * The comment keys must be legal Vorbis comment field names, e.g. a key containing '=' fails with `FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA`.
+pub fn encode_interleaved<WriteSeek>(mut writer: WriteSeek, params: &FlacEncoderParams, samples: &[i32], comments: &[(&str, &str)]) -> Result<u64, FlacEncoderError>
+where
+    WriteSeek: Write + Seek {
+    let channels = (params.channels as usize).max(1);
+    if !samples.len().is_multiple_of(channels) {
+        return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "encode_interleaved"));
+    }
+    let Ok(start) = writer.stream_position() else {
+        return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_IO_ERROR, "encode_interleaved"));
+    };
+    let mut encoder = FlacEncoder::new(
+        &mut writer,
+        Box::new(|writer: &mut &mut WriteSeek, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut WriteSeek, position: u64| -> Result<(), io::Error> {
+            writer.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut WriteSeek| -> Result<u64, io::Error> {
+            writer.stream_position()
+        }),
+        params
+    )?;
+    for (key, value) in comments {
+        encoder.insert_comments(key, value)?;
+    }
+    encoder.initialize()?;
+    for chunk in samples.chunks(CHUNK_FRAMES * channels) {
+        encoder.write_interleaved_samples(chunk)?;
+    }
+    encoder.finish()?;
+    // `finish()` seeks back to the end after libFLAC rewrote the STREAMINFO block, which `bytes_written()` counts twice.
+    let Ok(end) = encoder.tell() else {
+        return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_IO_ERROR, "encode_interleaved"));
+    };
+    Ok(end - start)
+}
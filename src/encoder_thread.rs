@@ -0,0 +1,175 @@
+#![allow(dead_code)]
+
+use std::{
+    io::{self, Write, Seek, SeekFrom},
+    sync::{Arc, atomic::{AtomicU64, Ordering}, mpsc::{self, SyncSender, Receiver, TrySendError}},
+    thread::{self, JoinHandle},
+};
+
+use libflac_sys::*;
+
+use crate::flac::{FlacEncoder, FlacEncoderError, FlacEncoderParams};
+
+/// ## What `SampleSender` does when the queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// * Wait for the encoder thread to take some samples from the queue, the producer is slowed down to the encoding speed.
+    #[default]
+    Block,
+
+    /// * Drop the samples and count them, see `dropped_frames()`, the producer never waits, e.g. on a real-time audio thread.
+    Drop,
+}
+
+/// * The messages to the encoder thread
+#[derive(Debug)]
+enum Message {
+    Samples(Vec<i32>),
+    Finish,
+}
+
+/// ## The cheap `Send` handle to feed the samples to the encoder thread, clone it to feed from more threads.
+/// * The samples of each call are encoded in the order they were queued.
+#[derive(Debug, Clone)]
+pub struct SampleSender {
+    sender: SyncSender<Message>,
+    channels: u16,
+    backpressure: Backpressure,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+impl SampleSender {
+    /// * Queue the interleaved samples (interleaved by channels) to encode.
+    /// * If the encoder thread has stopped because of an error, this returns the `FLAC__STREAM_ENCODER_IO_ERROR` error, call `EncoderHandle::finish()` to get the error of the encoder.
+    pub fn send_interleaved(&self, samples: &[i32]) -> Result<(), FlacEncoderError> {
+        if !samples.len().is_multiple_of(self.channels as usize) {
+            return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "SampleSender::send_interleaved"));
+        }
+        self.send(samples.to_vec(), "SampleSender::send_interleaved")
+    }
+
+    /// * Queue the audio frames to encode, each audio frame has a sample for every channel.
+    /// * See `send_interleaved()`
+    pub fn send_frames(&self, frames: &[Vec<i32>]) -> Result<(), FlacEncoderError> {
+        if frames.iter().any(|frame| frame.len() != self.channels as usize) {
+            return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "SampleSender::send_frames"));
+        }
+        self.send(frames.concat(), "SampleSender::send_frames")
+    }
+
+    /// * How many audio frames were dropped because the queue was full, only with `Backpressure::Drop`.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    fn send(&self, samples: Vec<i32>, function: &'static str) -> Result<(), FlacEncoderError> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let num_frames = (samples.len() / self.channels as usize) as u64;
+        let result = match self.backpressure {
+            Backpressure::Block => self.sender.send(Message::Samples(samples)).map_err(|_| ()),
+            Backpressure::Drop => match self.sender.try_send(Message::Samples(samples)) {
+                Ok(_) => Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.dropped_frames.fetch_add(num_frames, Ordering::Relaxed);
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => Err(()),
+            },
+        };
+        result.map_err(|_| FlacEncoderError::new(FLAC__STREAM_ENCODER_IO_ERROR, function))
+    }
+}
+
+/// ## The handle of the encoder thread, call `finish()` to complete the FLAC file and get the `writer` back.
+#[derive(Debug)]
+pub struct EncoderHandle<WriteSeek>
+where
+    WriteSeek: Write + Seek + Send + 'static {
+    sender: SyncSender<Message>,
+    thread: JoinHandle<(WriteSeek, Result<(), FlacEncoderError>)>,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+impl<WriteSeek> EncoderHandle<WriteSeek>
+where
+    WriteSeek: Write + Seek + Send + 'static {
+    /// * Encode the rest of the queued samples, finish the FLAC file, and join the encoder thread.
+    /// * Returns the `writer` and the result of the encoding. The samples sent after this are not encoded.
+    pub fn finish(self) -> (WriteSeek, Result<(), FlacEncoderError>) {
+        // If the encoder thread has stopped, the message can't be sent, and it's fine.
+        let _ = self.sender.send(Message::Finish);
+        match self.thread.join() {
+            Ok(ret) => ret,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// * How many audio frames were dropped because the queue was full, only with `Backpressure::Drop`.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+}
+
+/// ## Spawn a thread to encode the samples from the `SampleSender`, blocks the producer when the queue is full.
+/// * See `spawn_encoder_with()`
+pub fn spawn_encoder<WriteSeek>(writer: WriteSeek, params: &FlacEncoderParams, queue_depth: usize) -> (SampleSender, EncoderHandle<WriteSeek>)
+where
+    WriteSeek: Write + Seek + Send + 'static {
+    spawn_encoder_with(writer, params, queue_depth, Backpressure::default())
+}
+
+/// ## Spawn a thread to encode the samples from the `SampleSender`, e.g. to capture the audio on a real-time thread and encode it on another.
+/// * The queue holds at most `queue_depth` calls of `send_interleaved()` or `send_frames()` (at least 1), `backpressure` decides what happens when it's full.
+/// * The encoder is created and initialized in the thread, if that fails, the error is returned by `EncoderHandle::finish()`.
+pub fn spawn_encoder_with<WriteSeek>(writer: WriteSeek, params: &FlacEncoderParams, queue_depth: usize, backpressure: Backpressure) -> (SampleSender, EncoderHandle<WriteSeek>)
+where
+    WriteSeek: Write + Seek + Send + 'static {
+    let (sender, receiver) = mpsc::sync_channel(queue_depth.max(1));
+    let dropped_frames = Arc::new(AtomicU64::new(0));
+    let params = *params;
+    let thread = thread::spawn(move || {
+        let mut writer = writer;
+        let result = encode_from(&mut writer, &params, receiver);
+        (writer, result)
+    });
+    (
+        SampleSender {
+            sender: sender.clone(),
+            channels: params.channels,
+            backpressure,
+            dropped_frames: dropped_frames.clone(),
+        },
+        EncoderHandle {
+            sender,
+            thread,
+            dropped_frames,
+        },
+    )
+}
+
+/// * The encoder thread, drains the queue into the encoder until `Message::Finish` comes or all of the senders are dropped.
+fn encode_from<WriteSeek>(writer: &mut WriteSeek, params: &FlacEncoderParams, receiver: Receiver<Message>) -> Result<(), FlacEncoderError>
+where
+    WriteSeek: Write + Seek {
+    let mut encoder = FlacEncoder::new(
+        writer,
+        Box::new(|writer: &mut &mut WriteSeek, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)
+        }),
+        Box::new(|writer: &mut &mut WriteSeek, position: u64| -> Result<(), io::Error> {
+            writer.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut WriteSeek| -> Result<u64, io::Error> {
+            writer.stream_position()
+        }),
+        params
+    )?;
+    encoder.initialize()?;
+    while let Ok(Message::Samples(samples)) = receiver.recv() {
+        encoder.write_interleaved_samples(&samples)?;
+    }
+    encoder.finish()
+}
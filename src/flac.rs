@@ -10,18 +10,27 @@ const SHOW_CALLBACKS: bool = false;
 use std::{
     any::Any,
     borrow::Cow,
-    ffi::{CStr, c_void},
+    ffi::{CStr, c_void, c_long},
     fmt::{self, Debug, Display, Formatter},
-    io::{self, Read, Write, Seek, SeekFrom},
+    fs::File,
+    io::{self, Read, Write, Seek, SeekFrom, BufReader},
     ops::{Deref, DerefMut},
-    collections::BTreeMap,
+    path::Path,
+    collections::{BTreeMap, BTreeSet, VecDeque},
     ptr,
     slice,
+    sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}},
+    time::Duration,
 };
 
+use crate::io_stats::IoStats;
+use crate::header_spool::{AccurateHeader, HeaderSpool};
+use crate::metadata_report::MetadataReport;
+
 /// ## The compression level of the FLAC file
 /// A higher number means less file size. Default compression level is 5
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FlacCompression {
     /// Almost no compression
     Level0 = 0,
@@ -37,8 +46,60 @@ pub enum FlacCompression {
     Level8 = 8
 }
 
+impl FlacCompression {
+    /// * Map a quality from 0 to 100, e.g. from a "quality" slider, to the compression levels in 9 equal buckets:
+    ///   0-11 is `Level0`, 12-22 is `Level1`, 23-33 is `Level2`, 34-44 is `Level3`, 45-56 is `Level4`,
+    ///   57-67 is `Level5`, 68-78 is `Level6`, 79-89 is `Level7`, 90-100 is `Level8`.
+    /// * A quality above 100 is treated as 100.
+    pub fn from_quality(quality: u8) -> Self {
+        match quality.min(100) as u32 * 9 / 101 {
+            0 => Self::Level0,
+            1 => Self::Level1,
+            2 => Self::Level2,
+            3 => Self::Level3,
+            4 => Self::Level4,
+            5 => Self::Level5,
+            6 => Self::Level6,
+            7 => Self::Level7,
+            _ => Self::Level8,
+        }
+    }
+
+    /// * The compression level of the number from 0 to 8, `None` for other numbers.
+    pub fn from_level(level: u32) -> Option<Self> {
+        match level {
+            0 => Some(Self::Level0),
+            1 => Some(Self::Level1),
+            2 => Some(Self::Level2),
+            3 => Some(Self::Level3),
+            4 => Some(Self::Level4),
+            5 => Some(Self::Level5),
+            6 => Some(Self::Level6),
+            7 => Some(Self::Level7),
+            8 => Some(Self::Level8),
+            _ => None,
+        }
+    }
+}
+
+/// ## The container of the encoded FLAC stream, or of the FLAC stream to decode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FlacContainer {
+    /// * The native FLAC file, starts with `fLaC`.
+    #[default]
+    Native,
+
+    /// * The FLAC stream in the Ogg container, e.g. the `.oga` file. Needs the `ogg` feature of this crate to build libFLAC with Ogg,
+    ///   otherwise `initialize()` fails with `StreamEncoderInitStatusUnsupportedContainer`, or `StreamDecoderInitStatusUnsupportedContainer` for the decoder.
+    /// * libFLAC reads the Ogg page of the STREAMINFO block back to rewrite it at `finish()`, your `writer` isn't readable,
+    ///   so the header is only rewritten if `buffer_for_header` or `accurate_header` is set.
+    Ogg,
+}
+
 /// ## Parameters for the encoder to encode the audio.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FlacEncoderParams {
     /// * If set to true, the FLAC encoder will send the encoded data to a decoder to verify if the encoding is successful, and the encoding process will be slower.
     pub verify_decoded: bool,
@@ -59,8 +120,44 @@ pub struct FlacEncoderParams {
 
     /// * How many samples you will put into the encoder, set to zero if you don't know.
     pub total_samples_estimate: u64,
+
+    /// * If set to true, the whole encoded FLAC file is kept in memory until `finish()`, then it's written through your `on_write()` closure at once.
+    ///   The STREAMINFO block is patched in memory with the exact total samples and the MD5 sum, so that the header is correct even if your `writer` can't seek, e.g. a pipe or a socket.
+    ///   Your `on_seek()` and `on_tell()` closures are not called at all in this mode.
+    /// * This trades memory for a correct header, the memory usage is the size of the whole FLAC file.
+    ///   To limit the memory or to spool to a temporary file instead, see `set_accurate_header()` of the encoder.
+    pub buffer_for_header: bool,
+
+    /// * If set to true, a PADDING block of `DEFAULT_PADDING_LENGTH` bytes is written after the other metadata blocks,
+    ///   so that the taggers can edit the metadata in place without rewriting the whole FLAC file.
+    /// * The default is `false` to keep the output the same as before, `true` is recommended for the files to be tagged later, as the `flac` command line tool does.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_padding: bool,
+
+    /// * The block size in samples per channel of every FLAC frame, e.g. `Some(1024)` for the low latency streaming.
+    /// * `None` lets the compression level decide, which is 4096 for most levels. A block size that FLAC doesn't allow fails `initialize()` with `StreamEncoderInitStatusInvalidBlockSize`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub block_size: Option<u32>,
+
+    /// * The container of the encoded FLAC stream, the default is the native FLAC file.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub container: FlacContainer,
+
+    /// * The serial number of the Ogg stream, only for `FlacContainer::Ogg`. It should differ between the streams chained or multiplexed in one Ogg file.
+    /// * `None` picks a random one for each `initialize()`, as the `flac` command line tool does.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ogg_serial_number: Option<u32>,
+}
+
+/// * A random Ogg serial number for `FlacEncoderParams::ogg_serial_number`, from the randomly seeded hasher of the standard library.
+fn random_serial_number() -> u32 {
+    use std::hash::{BuildHasher, RandomState};
+    RandomState::new().hash_one(std::time::SystemTime::now()) as u32
 }
 
+/// ## The length of the PADDING block in bytes written by `FlacEncoderParams::auto_padding`, the same as the `flac` command line tool.
+pub const DEFAULT_PADDING_LENGTH: u32 = 8192;
+
 impl FlacEncoderParams {
     pub fn new() -> Self {
         Self {
@@ -70,6 +167,11 @@ impl FlacEncoderParams {
             sample_rate: 44100,
             bits_per_sample: 16,
             total_samples_estimate: 0,
+            buffer_for_header: false,
+            auto_padding: false,
+            block_size: None,
+            container: FlacContainer::Native,
+            ogg_serial_number: None,
         }
     }
 }
@@ -135,6 +237,21 @@ macro_rules! impl_FlacError {
     }
 }
 
+/// * The error code of `FlacEncoderError` when writing samples after the FLAC stream is finished, not from libFLAC
+pub const FLAC_ENCODER_FINISHED: u32 = 0x100;
+
+/// * The error code of `FlacEncoderError` when a sample doesn't fit in the bits per sample, see `check_samples()`, not from libFLAC
+pub const FLAC_ENCODER_SAMPLE_OUT_OF_RANGE: u32 = 0x101;
+
+/// * The error code of `FlacEncoderError` from `finish()` when the total samples differ from the estimate more than `set_strict_total_samples()` allows, and the header couldn't be rewritten, not from libFLAC
+pub const FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH: u32 = 0x102;
+
+/// * The error code of `FlacEncoderError` when a setting that can only be changed before `initialize()` is changed after it, not from libFLAC
+pub const FLAC_ENCODER_ALREADY_INITIALIZED: u32 = 0x103;
+
+/// * The error code of `FlacEncoderError` from `continue_new_stream()` when the current FLAC stream isn't finished yet, not from libFLAC
+pub const FLAC_ENCODER_NOT_FINISHED: u32 = 0x104;
+
 /// ## Error info for the encoder, most of the encoder functions return this.
 #[derive(Debug, Clone, Copy)]
 pub struct FlacEncoderError {
@@ -146,6 +263,15 @@ pub struct FlacEncoderError {
 
     /// * Which function generates this error
     pub function: &'static str,
+
+    /// * Which metadata block is invalid, if `initialize()` failed because of it.
+    pub failed_block: Option<MetadataBlockId>,
+
+    /// * The size of the block, if it's invalid because it's too large.
+    pub too_large: Option<MetadataTooLarge>,
+
+    /// * The first sample that doesn't fit in the bits per sample, for the `FLAC_ENCODER_SAMPLE_OUT_OF_RANGE` error.
+    pub out_of_range: Option<SampleOutOfRange>,
 }
 
 impl FlacEncoderError {
@@ -154,12 +280,22 @@ impl FlacEncoderError {
             code,
             message: Self::get_message_from_code(code),
             function,
+            failed_block: None,
+            too_large: None,
+            out_of_range: None,
         }
     }
 
     pub fn get_message_from_code(code: u32) -> &'static str {
-        unsafe {
-            CStr::from_ptr(*FLAC__StreamEncoderStateString.as_ptr().add(code as usize)).to_str().unwrap()
+        match code {
+            FLAC_ENCODER_FINISHED => "The FLAC stream is finished, a native FLAC stream can't be appended to since its STREAMINFO block and MD5 signature cover the whole stream. Use `continue_new_stream()` to start a new stream.",
+            FLAC_ENCODER_SAMPLE_OUT_OF_RANGE => "A sample doesn't fit in the bits per sample of the encoder.",
+            FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH => "The total samples differ from the estimate, and the STREAMINFO block couldn't be rewritten since the writer can't seek.",
+            FLAC_ENCODER_NOT_FINISHED => "The FLAC stream isn't finished, call `finish()` before `continue_new_stream()`.",
+            FLAC_ENCODER_ALREADY_INITIALIZED => "The encoder is already initialized, the setting can only be changed before calling `initialize()`.",
+            _ => unsafe {
+                CStr::from_ptr(*FLAC__StreamEncoderStateString.as_ptr().add(code as usize)).to_str().unwrap()
+            },
         }
     }
 }
@@ -195,6 +331,21 @@ pub enum FlacEncoderErrorCode {
 
     /// * Memory allocation failed
     StreamEncoderMemoryAllocationError = FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR as isize,
+
+    /// * The FLAC stream is finished, it can't be appended to.
+    StreamEncoderFinished = FLAC_ENCODER_FINISHED as isize,
+
+    /// * A sample doesn't fit in the bits per sample.
+    StreamEncoderSampleOutOfRange = FLAC_ENCODER_SAMPLE_OUT_OF_RANGE as isize,
+
+    /// * The total samples differ from the estimate, and the STREAMINFO block wasn't rewritten.
+    StreamEncoderTotalSamplesMismatch = FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH as isize,
+
+    /// * The FLAC stream isn't finished.
+    StreamEncoderNotFinished = FLAC_ENCODER_NOT_FINISHED as isize,
+
+    /// * The encoder is already initialized.
+    StreamEncoderAlreadyInitialized = FLAC_ENCODER_ALREADY_INITIALIZED as isize,
 }
 
 impl Display for FlacEncoderErrorCode {
@@ -209,6 +360,11 @@ impl Display for FlacEncoderErrorCode {
             Self::StreamEncoderIOError => write!(f, "An I/O error occurred while opening/reading/writing a file."),
             Self::StreamEncoderFramingError => write!(f, "An error occurred while writing the stream; usually, the `on_write()` returned an error."),
             Self::StreamEncoderMemoryAllocationError => write!(f, "Memory allocation failed."),
+            Self::StreamEncoderFinished => write!(f, "The FLAC stream is finished, it can't be appended to."),
+            Self::StreamEncoderSampleOutOfRange => write!(f, "A sample doesn't fit in the bits per sample."),
+            Self::StreamEncoderTotalSamplesMismatch => write!(f, "The total samples differ from the estimate, and the STREAMINFO block wasn't rewritten."),
+            Self::StreamEncoderNotFinished => write!(f, "The FLAC stream isn't finished."),
+            Self::StreamEncoderAlreadyInitialized => write!(f, "The encoder is already initialized."),
         }
     }
 }
@@ -226,6 +382,11 @@ impl From<u32> for FlacEncoderErrorCode {
             FLAC__STREAM_ENCODER_IO_ERROR => StreamEncoderIOError,
             FLAC__STREAM_ENCODER_FRAMING_ERROR  => StreamEncoderFramingError,
             FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR => StreamEncoderMemoryAllocationError,
+            FLAC_ENCODER_FINISHED => StreamEncoderFinished,
+            FLAC_ENCODER_SAMPLE_OUT_OF_RANGE => StreamEncoderSampleOutOfRange,
+            FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH => StreamEncoderTotalSamplesMismatch,
+            FLAC_ENCODER_NOT_FINISHED => StreamEncoderNotFinished,
+            FLAC_ENCODER_ALREADY_INITIALIZED => StreamEncoderAlreadyInitialized,
             o => panic!("Not an encoder error code: {o}."),
         }
     }
@@ -244,6 +405,13 @@ pub struct FlacEncoderInitError {
 
     /// * Which function generates this error
     pub function: &'static str,
+
+    /// * Which metadata block is invalid, for the `FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA` error from `initialize()`,
+    ///   or which block would be invalid, for the error from adding the metadata.
+    pub failed_block: Option<MetadataBlockId>,
+
+    /// * The size of the block, if it's invalid because it's too large, see `MetadataTooLarge`.
+    pub too_large: Option<MetadataTooLarge>,
 }
 
 impl FlacEncoderInitError {
@@ -252,6 +420,8 @@ impl FlacEncoderInitError {
             code,
             message: Self::get_message_from_code(code),
             function,
+            failed_block: None,
+            too_large: None,
         }
     }
 
@@ -369,6 +539,8 @@ impl From<FlacEncoderError> for FlacEncoderInitError {
             code: err.code,
             message: err.message,
             function: err.function,
+            failed_block: err.failed_block,
+            too_large: err.too_large,
         }
     }
 }
@@ -379,33 +551,53 @@ impl From<FlacEncoderInitError> for FlacEncoderError {
             code: err.code,
             message: err.message,
             function: err.function,
+            failed_block: err.failed_block,
+            too_large: err.too_large,
+            out_of_range: None,
         }
     }
 }
 
 /// ## Available comment keys for metadata usage.
-pub const COMMENT_KEYS: [&str; 33] = [
+/// * The list is advisory, not exhaustive: `insert_comments()` accepts any legal Vorbis comment field name,
+///   e.g. the keys written by the MusicBrainz tagging tools, or your own custom keys.
+/// * A legal field name is made of the printable ASCII characters except `=`, the keys are case-insensitive but normally uppercase.
+pub const COMMENT_KEYS: [&str; 47] = [
     "ACTOR",
     "ALBUM",
     "ARTIST",
     "ALBUMARTIST",
+    "BPM",
     "COMMENT",
     "COMPOSER",
     "CONTACT",
+    "CONTENT_TYPE",
     "COPYRIGHT",
     "COVERART",
     "COVERARTMIME",
     "DATE",
     "DESCRIPTION",
     "DIRECTOR",
+    "DISCNUMBER",
+    "DISCTOTAL",
     "ENCODED_BY",
     "ENCODED_USING",
     "ENCODER",
     "ENCODER_OPTIONS",
     "GENRE",
+    "INITIALKEY",
     "ISRC",
     "LICENSE",
     "LOCATION",
+    "LYRICS",
+    "MUSICBRAINZ_ALBUMARTISTID",
+    "MUSICBRAINZ_ALBUMID",
+    "MUSICBRAINZ_ARTISTID",
+    "MUSICBRAINZ_DISCID",
+    "MUSICBRAINZ_RELEASEGROUPID",
+    "MUSICBRAINZ_RELEASETRACKID",
+    "MUSICBRAINZ_TRACKID",
+    "MUSICBRAINZ_WORKID",
     "ORGANIZATION",
     "PERFORMER",
     "PRODUCER",
@@ -420,12 +612,79 @@ pub const COMMENT_KEYS: [&str; 33] = [
     "vendor"
 ];
 
+/// ## The type of the picture, the same as the picture type of the ID3v2 APIC frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum FlacPictureType {
+    #[default]
+    Other = 0,
+
+    /// * 32x32 pixels file icon, PNG only
+    FileIcon = 1,
+    OtherFileIcon = 2,
+    FrontCover = 3,
+    BackCover = 4,
+    LeafletPage = 5,
+
+    /// * e.g. the label side of the CD
+    Media = 6,
+    LeadArtist = 7,
+    Artist = 8,
+    Conductor = 9,
+    Band = 10,
+    Composer = 11,
+    Lyricist = 12,
+    RecordingLocation = 13,
+    DuringRecording = 14,
+    DuringPerformance = 15,
+    MovieScreenCapture = 16,
+    BrightColoredFish = 17,
+    Illustration = 18,
+    BandLogotype = 19,
+    PublisherLogotype = 20,
+}
+
+impl From<u32> for FlacPictureType {
+    /// * The reserved picture types are treated as `Other`
+    fn from(picture_type: u32) -> Self {
+        use FlacPictureType::*;
+        match picture_type {
+            1 => FileIcon,
+            2 => OtherFileIcon,
+            3 => FrontCover,
+            4 => BackCover,
+            5 => LeafletPage,
+            6 => Media,
+            7 => LeadArtist,
+            8 => Artist,
+            9 => Conductor,
+            10 => Band,
+            11 => Composer,
+            12 => Lyricist,
+            13 => RecordingLocation,
+            14 => DuringRecording,
+            15 => DuringPerformance,
+            16 => MovieScreenCapture,
+            17 => BrightColoredFish,
+            18 => Illustration,
+            19 => BandLogotype,
+            20 => PublisherLogotype,
+            _ => Other,
+        }
+    }
+}
+
 /// ## Picture data, normally the cover of the CD
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PictureData {
     /// * The binary picture data as a byte array
     pub picture: Vec<u8>,
 
+    /// * The type of the picture, e.g. the front cover
+    pub picture_type: FlacPictureType,
+
     /// * The mime type of the picture data
     pub mime_type: String,
 
@@ -449,6 +708,7 @@ impl Debug for PictureData {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         fmt.debug_struct("PictureData")
             .field("picture", &format_args!("[u8; {}]", self.picture.len()))
+            .field("picture_type", &self.picture_type)
             .field("mime_type", &self.mime_type)
             .field("description", &self.description)
             .field("width", &self.width)
@@ -463,6 +723,7 @@ impl PictureData {
     pub fn new() -> Self {
         Self {
             picture: Vec::<u8>::new(),
+            picture_type: FlacPictureType::Other,
             mime_type: "".to_owned(),
             description: "".to_owned(),
             width: 0,
@@ -483,6 +744,65 @@ impl Default for PictureData {
     }
 }
 
+/// ## An APPLICATION block, the data of a third-party application identified by the 4-byte ID
+#[derive(Clone, PartialEq, Eq)]
+pub struct FlacApplication {
+    /// * The registered application ID, see `known_name()`
+    pub id: [u8; 4],
+
+    /// * The application data
+    pub data: Vec<u8>,
+}
+
+/// * The registered application IDs, see [https://xiph.org/flac/id.html]
+const KNOWN_APPLICATION_IDS: [(&[u8; 4], &str); 24] = [
+    (b"ATCH", "FlacFile"),
+    (b"BSOL", "beSolo"),
+    (b"BUGS", "Bugs Player"),
+    (b"Cues", "GoldWave cue points"),
+    (b"Fica", "CUE Splitter"),
+    (b"Ftol", "flac-tools"),
+    (b"MOTB", "MOTB MetaCzar"),
+    (b"MPSE", "MP3 Stream Editor"),
+    (b"MuML", "MusicML: Music Metadata Language"),
+    (b"RIFF", "Sound Devices RIFF chunk storage"),
+    (b"SFFL", "Sound Font FLAC"),
+    (b"SONY", "Sony Creative Software"),
+    (b"SQEZ", "flacsqueeze"),
+    (b"TtWv", "TwistedWave"),
+    (b"UITS", "UITS Embedding tools"),
+    (b"aiff", "FLAC AIFF chunk storage"),
+    (b"imag", "flac-image"),
+    (b"peem", "Parseable Embedded Extensible Metadata"),
+    (b"qfst", "QFLAC Studio"),
+    (b"riff", "FLAC RIFF chunk storage"),
+    (b"tune", "TagTuner"),
+    (b"w64 ", "FLAC Wave64 chunk storage"),
+    (b"xbat", "XBAT"),
+    (b"xmcd", "xmcd"),
+];
+
+impl FlacApplication {
+    /// * The application ID as a string, the non-printable or non-ASCII bytes are replaced by `'?'`.
+    pub fn id_str(&self) -> String {
+        self.id.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' {byte as char} else {'?'}).collect()
+    }
+
+    /// * The name of the application if the ID is a registered one, e.g. `"FLAC RIFF chunk storage"` for `riff`.
+    pub fn known_name(&self) -> Option<&'static str> {
+        KNOWN_APPLICATION_IDS.iter().find(|(id, _)| **id == self.id).map(|(_, name)| *name)
+    }
+}
+
+impl Debug for FlacApplication {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("FlacApplication")
+            .field("id", &self.id_str())
+            .field("data", &format_args!("[u8; {}]", self.data.len()))
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 struct FlacMetadata {
@@ -538,14 +858,171 @@ impl Drop for FlacCueTrackWrap {
     }
 }
 
+/// * Scales signed PCM samples to full i32 dynamic range.
+/// * `bits`: Valid bits in `sample` (1-32).
+/// * Example: 8-bit samples [-128, 127] → [i32::MIN, i32::MAX]
+/// * The lower bits are filled by repeating the offset binary form of the sample, so the mapping is strictly monotonic,
+///   the minimum sample maps to `i32::MIN`, the maximum sample maps to `i32::MAX`, and zero maps to just above zero, e.g. `0x8000` for 16 bits.
+/// * The bits above `bits` in `sample` are ignored, so an out-of-range sample can't overflow.
+pub(crate) fn scale_to_i32(sample: i32, bits: u32) -> i32 {
+    assert!((1..=32).contains(&bits));
+    match bits {
+        // The constant bit depths let the compiler unroll the bit replication.
+        8 => replicate_bits(sample, 8),
+        12 => replicate_bits(sample, 12),
+        16 => replicate_bits(sample, 16),
+        20 => replicate_bits(sample, 20),
+        24 => replicate_bits(sample, 24),
+        32 => sample,
+        _ => replicate_bits(sample, bits),
+    }
+}
+
+/// * Scale all of the samples in place, see `scale_to_i32()`
+/// * The loops for the common bit depths have no branches for the compiler to vectorize them.
+pub(crate) fn scale_slice_to_i32(samples: &mut [i32], bits: u32) {
+    fn scale_all<const BITS: u32>(samples: &mut [i32]) {
+        for sample in samples.iter_mut() {
+            *sample = replicate_bits(*sample, BITS);
+        }
+    }
+    assert!((1..=32).contains(&bits));
+    match bits {
+        8 => scale_all::<8>(samples),
+        12 => scale_all::<12>(samples),
+        16 => scale_all::<16>(samples),
+        20 => scale_all::<20>(samples),
+        24 => scale_all::<24>(samples),
+        32 => (),
+        _ => samples.iter_mut().for_each(|sample| *sample = replicate_bits(*sample, bits)),
+    }
+}
+
+/// * Shift the sample to the upper bits of `i32`, the lower bits are zero, so `scaled >> (32 - bits)` is the sample again.
+/// * The bits above `bits` in `sample` are shifted out.
+pub(crate) fn shift_to_i32(sample: i32, bits: u32) -> i32 {
+    assert!((1..=32).contains(&bits));
+    ((sample as u32) << (32 - bits)) as i32
+}
+
+/// * Scale all of the samples in place by the `scaling`, see `scale_to_i32()` and `shift_to_i32()`
+pub(crate) fn scale_slice_to_i32_by(samples: &mut [i32], bits: u32, scaling: I32Scaling) {
+    match scaling {
+        I32Scaling::BitReplication => scale_slice_to_i32(samples, bits),
+        I32Scaling::MsbShift => samples.iter_mut().for_each(|sample| *sample = shift_to_i32(*sample, bits)),
+    }
+}
+
+/// * Put the sample in the upper bits, then fill the lower bits by repeating its offset binary form, `bits` must be 1 to 31.
+#[inline(always)]
+fn replicate_bits(sample: i32, bits: u32) -> i32 {
+    let upper = (sample as u32) << (32 - bits);
+    // The offset binary form is just the sign bit flipped.
+    let offset_binary = upper ^ 0x8000_0000;
+    let mut result = upper;
+    let mut shift = bits;
+    while shift < 32 {
+        result |= offset_binary >> shift;
+        shift += bits;
+    }
+    result as i32
+}
+
+/// * Any key is allowed as long as it's a legal Vorbis comment field name: not empty, printable ASCII characters except `=`
+fn is_legal_comment_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|c| (0x20..=0x7D).contains(&c) && c != b'=')
+}
+
+/// * The length of a comment in the VORBIS_COMMENT block, "KEY=value" with its 4-byte length.
+fn comment_len(key: &str, value: &str) -> usize {
+    4 + key.len() + 1 + value.len()
+}
+
+/// * The length of the VORBIS_COMMENT block written by libFLAC: its vendor string, the number of comments, and the comments.
+fn vorbis_comment_block_len(comments: &BTreeMap<String, String>) -> usize {
+    let vendor_string_len = unsafe {CStr::from_ptr(FLAC__VENDOR_STRING)}.to_bytes().len();
+    4 + vendor_string_len + 4 + comments.iter().map(|(key, value)| comment_len(key, value)).sum::<usize>()
+}
+
+/// * The length of the PICTURE block: the picture type, the lengths of the strings and the data, width, height, depth, colors.
+fn picture_block_len(mime_type: &str, description: &str, picture: &[u8]) -> usize {
+    4 * 8 + mime_type.len() + description.len() + picture.len()
+}
+
+/// * The length of a seek point in the SEEKTABLE block.
+const SEEK_POINT_LEN: usize = 18;
+
 fn make_sz(s: &str) -> String {
     let mut s = s.to_owned();
     if !s.ends_with('\0') {s.push('\0');}
     s
 }
 
+/// * Format the bytes as lowercase hex, e.g. the MD5 sum.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// * The CRC-16 of the FLAC frame footer, the polynomial is `x^16 + x^15 + x^2 + 1`, starting from zero.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| if crc & 0x8000 != 0 {(crc << 1) ^ 0x8005} else {crc << 1})
+    })
+}
+
+/// ## The `client_data` of the libFLAC callbacks, a heap cell holding the pointer to the encoder or the decoder
+/// * libFLAC keeps the `client_data` given on the initialization, and calls back while our method holds `&mut self`.
+///   If the callbacks reborrowed the pointer taken on the initialization, the `&mut self` of the method would be invalidated under the Stacked Borrows rules.
+/// * So the pointer in the cell is replaced by `bind()` right before each call into libFLAC, derived from the `&mut self` of the caller,
+///   then the callbacks reborrow it by `target()` as a child of that `&mut self`.
+/// * A callback that calls into libFLAC again, e.g. to get the decode position, must `bind()` again from its own reborrow,
+///   and `restore()` the outer binding afterwards: the callback writes through its own reborrow after the nested call, which invalidates
+///   the pointer it bound, while the outer pointer stays valid for the next callbacks of the same outer call.
+/// * libFLAC is foreign code, Miri can't run the encode and decode paths, so `test_client_data` checks this with a library model that calls back the same way.
+pub(crate) struct ClientData {
+    cell: *mut *mut c_void,
+}
+
+impl ClientData {
+    pub(crate) fn new() -> Self {
+        Self {
+            cell: Box::into_raw(Box::new(ptr::null_mut())),
+        }
+    }
+
+    /// * The `client_data` to pass to libFLAC on the initialization, it never changes.
+    pub(crate) fn as_ptr(&self) -> *mut c_void {
+        self.cell as *mut c_void
+    }
+
+    /// * Point the callbacks to `target`, which must be derived from the `&mut` that is calling into libFLAC.
+    /// * Returns the previous binding, to be given to `restore()` after the call.
+    pub(crate) fn bind<T>(&self, target: *mut T) -> *mut c_void {
+        unsafe {std::mem::replace(&mut *self.cell, target as *mut c_void)}
+    }
+
+    /// * Put back the binding returned by `bind()` after the call into libFLAC returns.
+    pub(crate) fn restore(&self, previous: *mut c_void) {
+        unsafe {*self.cell = previous};
+    }
+
+    /// * Get the target in the callback.
+    /// # Safety
+    /// * The `client_data` must come from `as_ptr()`, and `bind()` must have been called with a `T` that's still borrowed by the caller of libFLAC.
+    pub(crate) unsafe fn target<'a, T>(client_data: *mut c_void) -> &'a mut T {
+        unsafe {&mut *(*(client_data as *mut *mut c_void) as *mut T)}
+    }
+}
+
+impl Drop for ClientData {
+    fn drop(&mut self) {
+        drop(unsafe {Box::from_raw(self.cell)});
+    }
+}
+
 /// ## The track type
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FlacTrackType {
     Audio,
     NonAudio,
@@ -561,6 +1038,7 @@ impl Display for FlacTrackType {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct FlacCueSheetIndex {
     /// * Offset in samples, relative to the track offset, of the index point.
@@ -571,6 +1049,7 @@ pub struct FlacCueSheetIndex {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct FlacCueTrack {
     /// * In samples
@@ -580,6 +1059,7 @@ pub struct FlacCueTrack {
     pub track_no: u8,
 
     /// * ISRC
+    #[cfg_attr(feature = "serde", serde(with = "fixed_string"))]
     pub isrc: [i8; 13],
 
     /// * What type is this track, is it audio or not.
@@ -624,10 +1104,40 @@ impl Display for FlacCueTrack {
     }
 }
 
+/// * Serialize the NUL-terminated `[i8; N]` strings of the cue sheet as strings, e.g. the ISRC and the media catalog number.
+#[cfg(feature = "serde")]
+mod fixed_string {
+    use serde::{Serializer, Deserializer, Deserialize, de::Error};
+
+    pub fn serialize<S, const N: usize>(array: &[i8; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer {
+        let bytes: Vec<u8> = array.iter().map(|c| *c as u8).take_while(|c| *c != 0).collect();
+        serializer.serialize_str(&String::from_utf8_lossy(&bytes))
+    }
+
+    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[i8; N], D::Error>
+    where
+        D: Deserializer<'de> {
+        let string = String::deserialize(deserializer)?;
+        // Keep the NUL terminator
+        if string.len() >= N {
+            return Err(D::Error::custom(format!("The string \"{string}\" is longer than {} bytes", N - 1)));
+        }
+        let mut array = [0i8; N];
+        for (c, byte) in array.iter_mut().zip(string.bytes()) {
+            *c = byte as i8;
+        }
+        Ok(array)
+    }
+}
+
 /// ## Cue sheet for the FLAC audio
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FlacCueSheet {
     /// * media_catalog_number
+    #[cfg_attr(feature = "serde", serde(with = "fixed_string"))]
     pub media_catalog_number: [i8; 129],
 
     /// * In samples
@@ -644,6 +1154,94 @@ impl FlacCueSheet {
     pub fn get_media_catalog_number(&self) -> String {
         String::from_utf8_lossy(&self.media_catalog_number.iter().map(|c|{*c as u8}).collect::<Vec<u8>>()).to_string()
     }
+
+    /// * The track number of the lead-out track, 170 for CD-DA, 255 otherwise.
+    pub fn lead_out_track_no(&self) -> u8 {
+        if self.is_cd {170} else {255}
+    }
+
+    /// * Iterate over the tracks in the order of their numbers, without the lead-out track.
+    /// * The data tracks of a CD are included, tell them by `type_`.
+    pub fn audio_tracks(&self) -> impl Iterator<Item = &FlacCueTrack> {
+        let lead_out_track_no = self.lead_out_track_no();
+        self.tracks.values().filter(move |track| track.track_no != lead_out_track_no)
+    }
+
+    /// * The lead-out track, its offset is the end of the last track. `None` if the cue sheet lacks it.
+    pub fn lead_out(&self) -> Option<&FlacCueTrack> {
+        self.tracks.get(&self.lead_out_track_no())
+    }
+
+    /// * Check the cue sheet by `FLAC__format_cuesheet_is_legal()` as `initialize()` does, with the CD-DA rules if `is_cd` is set.
+    /// * The error is `FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA`, its `message` tells which rule is broken.
+    pub fn check_legal(&self) -> Result<(), FlacEncoderInitError> {
+        let mut metadata = FlacMetadata::new_cue_sheet()?;
+        metadata.set_cue_sheet_info(self);
+        for (track_no, cue_track) in self.tracks.iter() {
+            metadata.insert_cue_track(*track_no, cue_track)?;
+        }
+        let mut violation = ptr::null();
+        unsafe {
+            let cue_sheet = &(*metadata.metadata).data.cue_sheet;
+            if FLAC__format_cuesheet_is_legal(cue_sheet, cue_sheet.is_cd, &mut violation) != 0 {
+                return Ok(());
+            }
+        }
+        let mut error = FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FLAC__format_cuesheet_is_legal");
+        // The violations are the string literals of libFLAC.
+        if !violation.is_null() && let Ok(message) = unsafe {CStr::from_ptr(violation)}.to_str() {
+            error.message = message;
+        }
+        Err(error)
+    }
+
+    /// ## Append the tracks of `other` to this cue sheet, e.g. to assemble the cue sheets of the discs of a box set into one for the whole FLAC file
+    /// * `sample_offset` is where `other` starts in the merged audio, normally the offset of the lead-out track here, i.e. the length of the first disc.
+    ///   It can't be before the lead-out track here, and `other` must have the lead-out track after all of its tracks.
+    /// * The tracks of `other` are shifted by `sample_offset` and renumbered to follow the last track here, the index offsets are relative to the track so they're kept.
+    /// * The lead-out track here is replaced by the shifted one of `other`. The media catalog number, the lead-in and `is_cd` are kept.
+    /// * The merged cue sheet is checked by `check_legal()`, e.g. for CD-DA there can't be more than 99 tracks and `sample_offset` must be a multiple of 588.
+    ///   On error this cue sheet isn't changed.
+    pub fn merge(&mut self, other: &FlacCueSheet, sample_offset: u64) -> Result<(), FlacEncoderInitError> {
+        let invalid = |message: &'static str| FlacEncoderInitError {
+            message,
+            ..FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FlacCueSheet::merge")
+        };
+        if self.lead_out().is_some_and(|lead_out| sample_offset < lead_out.offset) {
+            return Err(invalid("The tracks to append start before the lead-out track"));
+        }
+        match other.lead_out() {
+            Some(lead_out) if other.audio_tracks().all(|track| track.offset < lead_out.offset) => (),
+            _ => return Err(invalid("The cue sheet to append lacks the lead-out track after its tracks")),
+        }
+        let lead_out_track_no = self.lead_out_track_no();
+        let mut merged = self.clone();
+        merged.tracks.remove(&lead_out_track_no);
+        if merged.tracks.values().any(|track| track.offset > sample_offset) {
+            return Err(invalid("The tracks to append start before the last track"));
+        }
+        let first_track_no = merged.tracks.keys().last().map_or(1, |track_no| track_no.saturating_add(1));
+        if other.audio_tracks().count() > lead_out_track_no.saturating_sub(first_track_no) as usize {
+            return Err(invalid("Too many tracks to number them before the lead-out track"));
+        }
+        for (track, track_no) in other.audio_tracks().zip(first_track_no..) {
+            merged.tracks.insert(track_no, FlacCueTrack {
+                offset: track.offset + sample_offset,
+                track_no,
+                ..track.clone()
+            });
+        }
+        if let Some(lead_out) = other.lead_out() {
+            merged.tracks.insert(lead_out_track_no, FlacCueTrack {
+                offset: lead_out.offset + sample_offset,
+                track_no: lead_out_track_no,
+                ..lead_out.clone()
+            });
+        }
+        merged.check_legal()?;
+        *self = merged;
+        Ok(())
+    }
 }
 
 impl Debug for FlacCueSheet {
@@ -691,6 +1289,40 @@ impl FlacMetadata {
         }
     }
 
+    pub fn new_seek_table() -> Result<Self, FlacEncoderError> {
+        let ret = Self {
+            metadata: unsafe {FLAC__metadata_object_new(FLAC__METADATA_TYPE_SEEKTABLE)},
+        };
+        if ret.metadata.is_null() {
+            Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_new(FLAC__METADATA_TYPE_SEEKTABLE)"))
+        } else {
+            Ok(ret)
+        }
+    }
+
+    pub fn new_padding(length: u32) -> Result<Self, FlacEncoderError> {
+        let ret = Self {
+            metadata: unsafe {FLAC__metadata_object_new(FLAC__METADATA_TYPE_PADDING)},
+        };
+        if ret.metadata.is_null() {
+            Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_new(FLAC__METADATA_TYPE_PADDING)"))
+        } else {
+            unsafe {(*ret.metadata).length = length};
+            Ok(ret)
+        }
+    }
+
+    pub fn new_application() -> Result<Self, FlacEncoderError> {
+        let ret = Self {
+            metadata: unsafe {FLAC__metadata_object_new(FLAC__METADATA_TYPE_APPLICATION)},
+        };
+        if ret.metadata.is_null() {
+            Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_new(FLAC__METADATA_TYPE_APPLICATION)"))
+        } else {
+            Ok(ret)
+        }
+    }
+
     pub fn new_picture() -> Result<Self, FlacEncoderError> {
         let ret = Self {
             metadata: unsafe {FLAC__metadata_object_new(FLAC__METADATA_TYPE_PICTURE)},
@@ -702,7 +1334,7 @@ impl FlacMetadata {
         }
     }
 
-    pub fn insert_comments(&self, key: &'static str, value: &str) -> Result<(), FlacEncoderError> {
+    pub fn insert_comments(&self, key: &str, value: &str) -> Result<(), FlacEncoderError> {
         unsafe {
             // ATTENTION:
             // Any strings to be added to the entry must be NUL terminated.
@@ -724,6 +1356,31 @@ impl FlacMetadata {
         Ok(())
     }
 
+    /// * Append the placeholder seek points for the sample numbers, the encoder fills in the offsets while encoding.
+    /// * The points are sorted and the duplicates are removed, then the seek table is checked by `FLAC__format_seektable_is_legal()`
+    pub fn set_seek_points(&mut self, sample_numbers: &[u64]) -> Result<(), FlacEncoderError> {
+        unsafe {
+            if FLAC__metadata_object_seektable_template_append_points(self.metadata, sample_numbers.as_ptr() as *mut u64, sample_numbers.len() as u32) == 0 {
+                Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_seektable_template_append_points"))
+            } else if FLAC__metadata_object_seektable_template_sort(self.metadata, 1) == 0 {
+                Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_seektable_template_sort"))
+            } else if FLAC__format_seektable_is_legal(&(*self.metadata).data.seek_table) == 0 {
+                Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FLAC__format_seektable_is_legal").into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_cue_sheet_info(&mut self, cue_sheet: &FlacCueSheet) {
+        unsafe {
+            let data = &mut (*self.metadata).data.cue_sheet;
+            data.media_catalog_number = cue_sheet.media_catalog_number;
+            data.lead_in = cue_sheet.lead_in;
+            data.is_cd = if cue_sheet.is_cd {1} else {0};
+        }
+    }
+
     pub fn insert_cue_track(&mut self, track_no: u8, cue_track: &FlacCueTrack) -> Result<(), FlacEncoderError> {
         unsafe {
             let mut track = FlacCueTrackWrap::new()?;
@@ -746,23 +1403,41 @@ impl FlacMetadata {
                     number: index.number,
                 }
             }).collect();
-            track_data.indices = indices.as_mut_ptr();
-            if FLAC__metadata_object_cuesheet_set_track(self.metadata, track_no as u32, track.get_mut_ptr(), 1) == 0 {
-                eprintln!("Failed to create new cuesheet track for {track_no} {cue_track}:  {:?}", FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_cuesheet_set_track"));
+            track_data.indices = if indices.is_empty() {ptr::null_mut()} else {indices.as_mut_ptr()};
+
+            // Append the track to the end, libFLAC copies the track and its indices.
+            let num_tracks = (*self.metadata).data.cue_sheet.num_tracks;
+            let inserted = FLAC__metadata_object_cuesheet_insert_track(self.metadata, num_tracks, track.get_mut_ptr(), 1);
+
+            // The indices are owned by the `Vec`, don't let `FLAC__metadata_object_cuesheet_track_delete()` free them.
+            let track_data = track.get_ref_mut();
+            track_data.indices = ptr::null_mut();
+            track_data.num_indices = 0;
+            if inserted == 0 {
+                return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_cuesheet_insert_track"));
             }
         }
         Ok(())
     }
 
-    pub fn set_picture(&mut self, picture_binary: &mut [u8], description: &mut str, mime_type: &mut str) -> Result<(), FlacEncoderError> {
-        let mut desc_sz = make_sz(description);
-        let mut mime_sz = make_sz(mime_type);
+    pub fn set_picture(&mut self, picture: &PictureData) -> Result<(), FlacEncoderError> {
+        let mut desc_sz = make_sz(&picture.description);
+        let mut mime_sz = make_sz(&picture.mime_type);
+        let mut picture_binary = picture.picture.clone();
         unsafe {
-            if FLAC__metadata_object_picture_set_data(self.metadata, picture_binary.as_mut_ptr(), picture_binary.len() as u32, 0) == 0 {
+            let raw = &mut (*self.metadata).data.picture;
+            raw.type_ = picture.picture_type as u32;
+            raw.width = picture.width;
+            raw.height = picture.height;
+            raw.depth = picture.depth;
+            raw.colors = picture.colors;
+
+            // The data are copied by libFLAC, the `FLAC__StreamMetadata` owns the copies.
+            if FLAC__metadata_object_picture_set_data(self.metadata, picture_binary.as_mut_ptr(), picture_binary.len() as u32, 1) == 0 {
                 Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_picture_set_data"))
-            } else if FLAC__metadata_object_picture_set_mime_type(self.metadata, desc_sz.as_mut_ptr() as *mut i8, 0) == 0 {
+            } else if FLAC__metadata_object_picture_set_mime_type(self.metadata, mime_sz.as_mut_ptr() as *mut i8, 1) == 0 {
                 Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_picture_set_mime_type"))
-            } else if FLAC__metadata_object_picture_set_description(self.metadata, mime_sz.as_mut_ptr(), 0) == 0 {
+            } else if FLAC__metadata_object_picture_set_description(self.metadata, desc_sz.as_mut_ptr(), 1) == 0 {
                 Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_picture_set_description"))
             } else {
                 Ok(())
@@ -771,6 +1446,22 @@ impl FlacMetadata {
     }
 }
 
+impl FlacMetadata {
+    pub fn set_application(&mut self, application: &FlacApplication) -> Result<(), FlacEncoderError> {
+        let mut data = application.data.clone();
+        unsafe {
+            (*self.metadata).data.application.id = application.id;
+
+            // The data are copied by libFLAC, the `FLAC__StreamMetadata` owns the copies.
+            if FLAC__metadata_object_application_set_data(self.metadata, data.as_mut_ptr(), data.len() as u32, 1) == 0 {
+                Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__metadata_object_application_set_data"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
 impl Default for FlacMetadata {
     fn default() -> Self {
         Self {
@@ -788,17 +1479,40 @@ impl Drop for FlacMetadata {
     }
 }
 
+/// ## The data of a write from the encoder, for your `on_frame()` closure, see `set_on_frame()`
+#[derive(Debug, Clone, Copy)]
+pub struct EncodedFrame<'a> {
+    /// * The encoded bytes, a whole frame, or the metadata
+    pub data: &'a [u8],
+
+    /// * How many samples per channel are in the frame, zero for the metadata
+    pub samples: u32,
+
+    /// * The number of the frame, meaningless for the metadata
+    pub frame_number: u32,
+}
+
+impl EncodedFrame<'_> {
+    /// * Is this the `fLaC` signature or the metadata blocks rather than an audio frame
+    pub fn is_metadata(&self) -> bool {
+        self.samples == 0
+    }
+}
+
 /// ## The encoder's core structure, but can't move after `initialize()` has been called.
 /// Use a `Box` to contain it, or just don't move it will be fine.
 pub struct FlacEncoderUnmovable<'a, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     /// * See: <https://xiph.org/flac/api/group__flac__stream__encoder.html>
     encoder: *mut FLAC__StreamEncoder,
 
     /// * This is a piece of allocated memory as the libFLAC form, for libFLAC to access the metadata that you provided to it.
     metadata: Vec<FlacMetadata>,
 
+    /// * Which block each of `metadata` is, to tell which block is invalid.
+    metadata_ids: Vec<MetadataBlockId>,
+
     /// * Is encoder initialized or not
     encoder_initialized: bool,
 
@@ -819,7 +1533,7 @@ where
     on_tell: Box<dyn FnMut(&mut WriteSeek) -> Result<u64, io::Error> + 'a>,
 
     /// * The metadata to be added to the FLAC file. You can only add the metadata before calling `initialize()`
-    comments: BTreeMap<&'static str, String>,
+    comments: BTreeMap<String, String>,
 
     /// * The cue sheets to be added to the FLAC file. You can only add the cue sheets before calling `initialize()`
     cue_sheets: Vec<FlacCueSheet>,
@@ -827,13 +1541,58 @@ where
     /// * The pictures to be added to the FLAC file. You can only add the pictures before calling `initialize()`
     pictures: Vec<PictureData>,
 
+    /// * The sample numbers of the seek points to be added to the FLAC file. You can only add the seek points before calling `initialize()`
+    seek_points: BTreeSet<u64>,
+
+    /// * The APPLICATION blocks to be added to the FLAC file. You can only add the APPLICATION blocks before calling `initialize()`
+    applications: Vec<FlacApplication>,
+
     /// * Did you called `finish()`. This variable prevents a duplicated finish.
     finished: bool,
+
+    /// * The reusable buffer for `write_stereos()` and `write_mono_channel()` to convert the samples to the interleaved form.
+    interleave_buffer: Vec<i32>,
+
+    /// * How to get an accurate STREAMINFO block when the `writer` can't seek, see `set_accurate_header()`.
+    accurate_header: AccurateHeader,
+
+    /// * If `buffer_for_header` or `accurate_header` is set, the encoded FLAC file is kept here until `finish()`.
+    header_buffer: Option<HeaderSpool>,
+
+    /// * The statistics of the calls to your IO closures, if enabled.
+    io_stats: Option<IoStats>,
+
+    /// * Your closure to receive every encoded frame separately, see `set_on_frame()`.
+    on_frame: Option<Box<dyn FnMut(&EncodedFrame) -> Result<(), io::Error> + 'a>>,
+
+    /// * Does `reset_with_writer()` keep the comments, see `set_keep_comments_on_reset()`.
+    keep_comments_on_reset: bool,
+
+    /// * Do the write methods call `check_samples()` first, see `set_sample_checking()`.
+    sample_checking: bool,
+
+    /// * The total bytes libFLAC wrote out for the current stream, see `bytes_written()`.
+    bytes_written: u64,
+
+    /// * The STREAMINFO block libFLAC gave at `finish()`, see `final_stream_info()`.
+    final_stream_info: Option<FlacStreamInfo>,
+
+    /// * Did a seek succeed in `finish()`, i.e. libFLAC rewrote the STREAMINFO block.
+    header_rewritten: bool,
+
+    /// * The total samples compared with the estimate, see `finish_report()`.
+    finish_report: Option<FinishReport>,
+
+    /// * How many samples per channel a wrong STREAMINFO block can be off by before `finish()` fails, see `set_strict_total_samples()`.
+    strict_total_samples: Option<u64>,
+
+    /// * The `client_data` of the callbacks, see `ClientData`.
+    client_data: ClientData,
 }
 
 impl<'a, WriteSeek> FlacEncoderUnmovable<'a, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     pub fn new(
         writer: WriteSeek,
         on_write: Box<dyn FnMut(&mut WriteSeek, &[u8]) -> Result<(), io::Error> + 'a>,
@@ -841,9 +1600,10 @@ where
         on_tell: Box<dyn FnMut(&mut WriteSeek) -> Result<u64, io::Error> + 'a>,
         params: &FlacEncoderParams
     ) -> Result<Self, FlacEncoderError> {
-        let ret = Self {
+        let mut ret = Self {
             encoder: unsafe {FLAC__stream_encoder_new()},
             metadata: Vec::<FlacMetadata>::new(),
+            metadata_ids: Vec::new(),
             encoder_initialized: false,
             params: *params,
             writer,
@@ -853,15 +1613,60 @@ where
             comments: BTreeMap::new(),
             cue_sheets: Vec::new(),
             pictures: Vec::new(),
+            seek_points: BTreeSet::new(),
+            applications: Vec::new(),
             finished: false,
+            interleave_buffer: Vec::new(),
+            accurate_header: AccurateHeader::Off,
+            header_buffer: None,
+            io_stats: None,
+            on_frame: None,
+            keep_comments_on_reset: false,
+            sample_checking: false,
+            bytes_written: 0,
+            final_stream_info: None,
+            header_rewritten: false,
+            finish_report: None,
+            strict_total_samples: None,
+            client_data: ClientData::new(),
         };
         if ret.encoder.is_null() {
             Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_MEMORY_ALLOCATION_ERROR, "FLAC__stream_encoder_new"))
         } else {
+            ret.header_buffer = ret.new_header_spool("FlacEncoderUnmovable::new")?;
             Ok(ret)
         }
     }
 
+    /// * Create the spool for `accurate_header`, or the unlimited memory spool for `buffer_for_header`.
+    fn new_header_spool(&self, function: &'static str) -> Result<Option<HeaderSpool>, FlacEncoderError> {
+        let accurate_header = match (&self.accurate_header, self.params.buffer_for_header) {
+            (AccurateHeader::Off, true) => &AccurateHeader::BufferToMemory(usize::MAX),
+            (accurate_header, _) => accurate_header,
+        };
+        HeaderSpool::new(accurate_header).map_err(|_| FlacEncoderError::new(FLAC__STREAM_ENCODER_IO_ERROR, function))
+    }
+
+    /// * Set how to get an accurate STREAMINFO block when your `writer` can't seek, e.g. a socket or the stdout, see `AccurateHeader`. You can only set it before calling `initialize()`.
+    /// * It overrides `buffer_for_header` of the parameters unless it's `AccurateHeader::Off`.
+    /// * The temporary file of `AccurateHeader::BufferToTemp` is created here, failing to create it, e.g. it exists, returns the `FLAC__STREAM_ENCODER_IO_ERROR` error.
+    /// * Setting it after `initialize()` returns the `FLAC_ENCODER_ALREADY_INITIALIZED` error.
+    pub fn set_accurate_header(&mut self, accurate_header: AccurateHeader) -> Result<(), FlacEncoderError> {
+        if self.encoder_initialized {
+            return Err(FlacEncoderError::new(FLAC_ENCODER_ALREADY_INITIALIZED, "FlacEncoderUnmovable::set_accurate_header"));
+        }
+        // Drop the old spool first in case the new one is the same temporary file.
+        self.header_buffer = None;
+        self.accurate_header = accurate_header;
+        self.header_buffer = self.new_header_spool("FlacEncoderUnmovable::set_accurate_header")?;
+        Ok(())
+    }
+
+    /// * Get how to get an accurate STREAMINFO block.
+    pub fn get_accurate_header(&self) -> &AccurateHeader {
+        &self.accurate_header
+    }
+
     /// * If the status code is ok then return `Ok(())` else return `Err()`
     pub fn get_status_as_result(&self, function: &'static str) -> Result<(), FlacEncoderError> {
         let code = unsafe {FLAC__stream_encoder_get_state(self.encoder)};
@@ -878,6 +1683,15 @@ where
         Err(FlacEncoderError::new(code, function))
     }
 
+    /// * The error when libFLAC refused to process the samples, tells if it's because the stream is finished.
+    fn get_process_error(&self, function: &'static str) -> Result<(), FlacEncoderError> {
+        if self.finished {
+            Err(FlacEncoderError::new(FLAC_ENCODER_FINISHED, function))
+        } else {
+            self.get_status_as_error(function)
+        }
+    }
+
     /// * The pointer to the struct, as `client_data` to be transferred to a field of the libFLAC encoder `private_` struct.
     /// * All of the callback functions need the `client_data` to retrieve `self`, and libFLAC forgot to provide a function for us to change the `client_data`
     /// * That's why our struct is `Unmovable`
@@ -892,12 +1706,28 @@ where
         self as *mut Self
     }
 
+    /// * Point the callbacks to `self`, then call into libFLAC with the encoder. Every call that may call back must go through it, see `ClientData`.
+    fn call_libflac<R>(&mut self, f: impl FnOnce(*mut FLAC__StreamEncoder) -> R) -> R {
+        let encoder = self.encoder;
+        let this = self as *mut Self;
+        let previous = self.client_data.bind(this);
+        let ret = f(encoder);
+        self.client_data.restore(previous);
+        ret
+    }
+
     /// * Insert a metadata key-value pair before calling to `initialize()`
-    pub fn insert_comments(&mut self, key: &'static str, value: &str) -> Result<(), FlacEncoderInitError> {
+    /// * The key isn't restricted to `COMMENT_KEYS`, any legal Vorbis comment field name is accepted, e.g. `MUSICBRAINZ_TRACKID`
+    pub fn insert_comments(&mut self, key: &str, value: &str) -> Result<(), FlacEncoderInitError> {
         if self.encoder_initialized {
             Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_ALREADY_INITIALIZED, "FlacEncoderUnmovable::insert_comments"))
+        } else if !is_legal_comment_key(key) {
+            Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FlacEncoderUnmovable::insert_comments"))
         } else {
-            if let Some(old_value) = self.comments.insert(key, value.to_owned()) {
+            let old_len = self.comments.get(key).map_or(0, |old_value| comment_len(key, old_value));
+            let size = vorbis_comment_block_len(&self.comments) - old_len + comment_len(key, value);
+            MetadataTooLarge::check(MetadataBlockId{kind: FlacMetadataType::VorbisComment, index: 0}, size, "FlacEncoderUnmovable::insert_comments")?;
+            if let Some(old_value) = self.comments.insert(key.to_owned(), value.to_owned()) {
                 eprintln!("\"{key}\" is changed to \"{value}\" from \"{old_value}\"");
             }
             Ok(())
@@ -916,11 +1746,20 @@ where
 
     /// * Add a picture before calling to `initialize()`
     pub fn add_picture(&mut self, picture_binary: &[u8], description: &str, mime_type: &str, width: u32, height: u32, depth: u32, colors: u32) -> Result<(), FlacEncoderInitError> {
+        self.add_picture_of_type(picture_binary, FlacPictureType::Other, description, mime_type, width, height, depth, colors)
+    }
+
+    /// * Add a picture of the picture type, e.g. the front cover, before calling to `initialize()`
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_picture_of_type(&mut self, picture_binary: &[u8], picture_type: FlacPictureType, description: &str, mime_type: &str, width: u32, height: u32, depth: u32, colors: u32) -> Result<(), FlacEncoderInitError> {
         if self.encoder_initialized {
             Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_ALREADY_INITIALIZED, "FlacEncoderUnmovable::set_picture"))
         } else {
+            let size = picture_block_len(mime_type, description, picture_binary);
+            MetadataTooLarge::check(MetadataBlockId{kind: FlacMetadataType::Picture, index: self.pictures.len()}, size, "FlacEncoderUnmovable::set_picture")?;
             self.pictures.push(PictureData{
                 picture: picture_binary.to_vec(),
+                picture_type,
                 description: description.to_owned(),
                 mime_type: mime_type.to_owned(),
                 width,
@@ -932,6 +1771,36 @@ where
         }
     }
 
+    /// * Add the seek points at the sample numbers before calling to `initialize()`, e.g. a seek point at every track start of the cue sheet.
+    /// * The duplicated sample numbers are merged. If `total_samples_estimate` is set, the sample numbers must be less than it.
+    /// * The encoder fills in the seek points while encoding, each point moves to the start of the frame which contains its sample number.
+    /// * The seek table can only be filled if the writer is seekable, since it's written before the audio.
+    pub fn add_seek_points(&mut self, sample_numbers: &[u64]) -> Result<(), FlacEncoderInitError> {
+        if self.encoder_initialized {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_ALREADY_INITIALIZED, "FlacEncoderUnmovable::add_seek_points"));
+        }
+        let total_samples = self.params.total_samples_estimate;
+        if sample_numbers.iter().any(|sample_number| *sample_number == SEEK_POINT_PLACEHOLDER || (total_samples > 0 && *sample_number >= total_samples)) {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FlacEncoderUnmovable::add_seek_points"));
+        }
+        let new_points = sample_numbers.iter().filter(|sample_number| !self.seek_points.contains(sample_number)).collect::<BTreeSet<_>>().len();
+        let size = (self.seek_points.len() + new_points) * SEEK_POINT_LEN;
+        MetadataTooLarge::check(MetadataBlockId{kind: FlacMetadataType::SeekTable, index: 0}, size, "FlacEncoderUnmovable::add_seek_points")?;
+        self.seek_points.extend(sample_numbers.iter().copied());
+        Ok(())
+    }
+
+    /// * Add an APPLICATION block before calling to `initialize()`, the blocks are written in the order of adding them.
+    /// * E.g. the foreign metadata from `store_foreign_metadata()` to keep the WAV or AIFF chunks through the FLAC file.
+    pub fn add_application(&mut self, id: [u8; 4], data: &[u8]) -> Result<(), FlacEncoderInitError> {
+        if self.encoder_initialized {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_ALREADY_INITIALIZED, "FlacEncoderUnmovable::add_application"));
+        }
+        MetadataTooLarge::check(MetadataBlockId{kind: FlacMetadataType::Application, index: self.applications.len()}, 4 + data.len(), "FlacEncoderUnmovable::add_application")?;
+        self.applications.push(FlacApplication{id, data: data.to_vec()});
+        Ok(())
+    }
+
     #[cfg(feature = "id3")]
     pub fn inherit_metadata_from_id3(&mut self, tag: &id3::Tag) -> Result<(), FlacEncoderInitError> {
         if let Some(artist) = tag.artist() {self.insert_comments("ARTIST", artist)?;}
@@ -939,7 +1808,17 @@ where
         if let Some(title) = tag.title() {self.insert_comments("TITLE", title)?;}
         if let Some(genre) = tag.genre() {self.insert_comments("GENRE", genre)?;}
         for picture in tag.pictures() {
-            self.add_picture(&picture.data, &picture.description, &picture.mime_type, 0, 0, 0, 0)?;
+            // The ID3 picture types are the same numbers as the FLAC ones, only one file icon and one front cover are kept.
+            // The size of the icon is unknown, so it can't be the 32x32 PNG file icon.
+            let picture_type = match FlacPictureType::from(u8::from(picture.picture_type) as u32) {
+                FlacPictureType::FileIcon => FlacPictureType::OtherFileIcon,
+                picture_type => picture_type,
+            };
+            let unique = matches!(picture_type, FlacPictureType::OtherFileIcon | FlacPictureType::FrontCover);
+            if unique && self.has_picture_of_type(picture_type) {
+                continue;
+            }
+            self.add_picture_of_type(&picture.data, picture_type, &picture.description, &picture.mime_type, 0, 0, 0, 0)?;
         }
         let comm_str = tag.comments().enumerate().map(|(i, comment)| -> String {
             let lang = &comment.lang;
@@ -963,6 +1842,9 @@ where
             if FLAC__stream_encoder_set_compression_level(self.encoder, self.params.compression as u32) == 0 {
                 return self.get_status_as_error("FLAC__stream_encoder_set_compression_level");
             }
+            if let Some(block_size) = self.params.block_size && FLAC__stream_encoder_set_blocksize(self.encoder, block_size) == 0 {
+                return self.get_status_as_error("FLAC__stream_encoder_set_blocksize");
+            }
             if FLAC__stream_encoder_set_channels(self.encoder, self.params.channels as u32) == 0 {
                 return self.get_status_as_error("FLAC__stream_encoder_set_channels");
             }
@@ -975,26 +1857,54 @@ where
             if self.params.total_samples_estimate > 0 && FLAC__stream_encoder_set_total_samples_estimate(self.encoder, self.params.total_samples_estimate) == 0 {
                 return self.get_status_as_error("FLAC__stream_encoder_set_total_samples_estimate");
             }
+            // libFLAC without Ogg refuses the serial number, leave it to the init function to report the unsupported container.
+            if cfg!(feature = "ogg") && self.params.container == FlacContainer::Ogg {
+                let serial_number = self.params.ogg_serial_number.unwrap_or_else(random_serial_number);
+                if FLAC__stream_encoder_set_ogg_serial_number(self.encoder, serial_number as c_long) == 0 {
+                    return self.get_status_as_error("FLAC__stream_encoder_set_ogg_serial_number");
+                }
+            }
 
             let set_metadata: Result<(), FlacEncoderError> = {
+                let id = |kind: FlacMetadataType, index: usize| MetadataBlockId{kind, index};
                 if !self.comments.is_empty() {
                     let metadata = FlacMetadata::new_vorbis_comment()?;
                     for (key, value) in self.comments.iter() {
                         metadata.insert_comments(key, value)?;
                     }
                     self.metadata.push(metadata);
+                    self.metadata_ids.push(id(FlacMetadataType::VorbisComment, 0));
+                }
+                if !self.seek_points.is_empty() {
+                    let mut metadata = FlacMetadata::new_seek_table()?;
+                    metadata.set_seek_points(&self.seek_points.iter().copied().collect::<Vec<u64>>())?;
+                    self.metadata.push(metadata);
+                    self.metadata_ids.push(id(FlacMetadataType::SeekTable, 0));
                 }
-                for cue_sheet in self.cue_sheets.iter() {
+                for (index, cue_sheet) in self.cue_sheets.iter().enumerate() {
                     let mut metadata = FlacMetadata::new_cue_sheet()?;
+                    metadata.set_cue_sheet_info(cue_sheet);
                     for (track_no, cue_track) in cue_sheet.tracks.iter() {
                         metadata.insert_cue_track(*track_no, cue_track)?;
                     }
                     self.metadata.push(metadata);
+                    self.metadata_ids.push(id(FlacMetadataType::CueSheet, index));
                 }
-                for picture in self.pictures.iter_mut() {
+                for (index, picture) in self.pictures.iter().enumerate() {
                     let mut metadata = FlacMetadata::new_picture()?;
-                    metadata.set_picture(&mut picture.picture, &mut picture.description, &mut picture.mime_type)?;
+                    metadata.set_picture(picture)?;
+                    self.metadata.push(metadata);
+                    self.metadata_ids.push(id(FlacMetadataType::Picture, index));
+                }
+                for (index, application) in self.applications.iter().enumerate() {
+                    let mut metadata = FlacMetadata::new_application()?;
+                    metadata.set_application(application)?;
                     self.metadata.push(metadata);
+                    self.metadata_ids.push(id(FlacMetadataType::Application, index));
+                }
+                if self.params.auto_padding {
+                    self.metadata.push(FlacMetadata::new_padding(DEFAULT_PADDING_LENGTH)?);
+                    self.metadata_ids.push(id(FlacMetadataType::Padding, 0));
                 }
                 if !self.metadata.is_empty() {
                     if FLAC__stream_encoder_set_metadata(self.encoder, self.metadata.as_mut_ptr() as *mut *mut FLAC__StreamMetadata, self.metadata.len() as u32) == 0 {
@@ -1009,15 +1919,36 @@ where
             if let Err(e) = set_metadata {
                 eprintln!("When setting the metadata: {:?}", e);
             }
-            let ret = FLAC__stream_encoder_init_stream(self.encoder,
-                Some(Self::write_callback),
-                Some(Self::seek_callback),
-                Some(Self::tell_callback),
-                Some(Self::metadata_callback),
-                self.as_mut_ptr() as *mut c_void,
-            );
+            if let Some((failed_block, too_large)) = self.find_invalid_metadata_block() {
+                self.metadata.clear();
+                self.metadata_ids.clear();
+                return Err(FlacEncoderInitError {
+                    failed_block: Some(failed_block),
+                    too_large,
+                    ..FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FlacEncoderUnmovable::initialize")
+                }.into());
+            }
+            // libFLAC writes the `fLaC` signature and the metadata blocks right here, through the callbacks.
+            let client_data = self.client_data.as_ptr();
+            let (ret, function) = match self.params.container {
+                FlacContainer::Native => (self.call_libflac(|encoder| FLAC__stream_encoder_init_stream(encoder,
+                    Some(Self::write_callback),
+                    Some(Self::seek_callback),
+                    Some(Self::tell_callback),
+                    Some(Self::metadata_callback),
+                    client_data,
+                )), "FLAC__stream_encoder_init_stream"),
+                FlacContainer::Ogg => (self.call_libflac(|encoder| FLAC__stream_encoder_init_ogg_stream(encoder,
+                    Some(Self::read_callback),
+                    Some(Self::write_callback),
+                    Some(Self::seek_callback),
+                    Some(Self::tell_callback),
+                    Some(Self::metadata_callback),
+                    client_data,
+                )), "FLAC__stream_encoder_init_ogg_stream"),
+            };
             if ret != 0 {
-                return Err(FlacEncoderInitError::new(ret, "FLAC__stream_encoder_init_stream").into());
+                return Err(FlacEncoderInitError::new(ret, function).into());
             } else {
                 self.encoder_initialized = true;
             }
@@ -1026,16 +1957,161 @@ where
         self.get_status_as_result("FlacEncoderUnmovable::Init()")
     }
 
-    /// * Retrieve the params from the encoder where you provided it for the creation of the encoder.
-    pub fn get_params(&self) -> FlacEncoderParams {
+    /// * Check the metadata blocks one by one as libFLAC does in `initialize()`, and that each block fits in `MAX_METADATA_BLOCK_LENGTH`.
+    /// * Returns the first invalid block, so that the `FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA` error tells which block it is, and its size if it's too large.
+    fn find_invalid_metadata_block(&self) -> Option<(MetadataBlockId, Option<MetadataTooLarge>)> {
+        let mut has_file_icon_standard = false;
+        let mut has_file_icon = false;
+        self.metadata.iter().zip(self.metadata_ids.iter()).find(|(metadata, _)| unsafe {
+            let metadata = &*metadata.metadata;
+            if metadata.length as usize > MAX_METADATA_BLOCK_LENGTH {
+                return true;
+            }
+            match metadata.type_ {
+                FLAC__METADATA_TYPE_SEEKTABLE => FLAC__format_seektable_is_legal(&metadata.data.seek_table) == 0,
+                FLAC__METADATA_TYPE_CUESHEET => FLAC__format_cuesheet_is_legal(&metadata.data.cue_sheet, metadata.data.cue_sheet.is_cd, ptr::null_mut()) == 0,
+                FLAC__METADATA_TYPE_PICTURE => {
+                    let picture = &metadata.data.picture;
+                    if FLAC__format_picture_is_legal(picture, ptr::null_mut()) == 0 {
+                        return true;
+                    }
+                    // Only one file icon of each type, the standard one must be a 32x32 PNG.
+                    match picture.type_ {
+                        FLAC__STREAM_METADATA_PICTURE_TYPE_FILE_ICON_STANDARD => {
+                            let mime_type = CStr::from_ptr(picture.mime_type).to_bytes();
+                            let duplicated = has_file_icon_standard;
+                            has_file_icon_standard = true;
+                            duplicated || (mime_type != b"image/png" && mime_type != b"-->") || picture.width != 32 || picture.height != 32
+                        },
+                        FLAC__STREAM_METADATA_PICTURE_TYPE_FILE_ICON => {
+                            let duplicated = has_file_icon;
+                            has_file_icon = true;
+                            duplicated
+                        },
+                        _ => false,
+                    }
+                },
+                _ => false,
+            }
+        }).map(|(metadata, id)| {
+            let length = unsafe {(*metadata.metadata).length} as usize;
+            (*id, (length > MAX_METADATA_BLOCK_LENGTH).then_some(MetadataTooLarge {kind: id.kind, size: length, limit: MAX_METADATA_BLOCK_LENGTH}))
+        })
+    }
+
+    /// * The summary of the queued metadata block, e.g. the type, the MIME type and the size of a picture, to report the `failed_block` of the error.
+    /// * Returns `None` if there's no such block.
+    pub fn describe_metadata_block(&self, id: MetadataBlockId) -> Option<String> {
+        let (kind, index) = (id.kind, id.index);
+        match kind {
+            FlacMetadataType::VorbisComment if index == 0 && !self.comments.is_empty() => Some(format!("{} comments", self.comments.len())),
+            FlacMetadataType::SeekTable if index == 0 && !self.seek_points.is_empty() => Some(format!("{} seek points", self.seek_points.len())),
+            FlacMetadataType::CueSheet => self.cue_sheets.get(index).map(|cue_sheet| format!("{} tracks", cue_sheet.tracks.len())),
+            FlacMetadataType::Picture => self.pictures.get(index).map(|picture| format!("{:?} {}, {} bytes", picture.picture_type, picture.mime_type, picture.picture.len())),
+            FlacMetadataType::Application => self.applications.get(index).map(|application| format!("{}, {} bytes", application.id_str(), application.data.len())),
+            FlacMetadataType::Padding if index == 0 && self.params.auto_padding => Some(format!("{DEFAULT_PADDING_LENGTH} bytes of padding")),
+            _ => None,
+        }
+    }
+
+    /// * Retrieve the params from the encoder where you provided it for the creation of the encoder.
+    pub fn get_params(&self) -> FlacEncoderParams {
         self.params
     }
 
-    unsafe extern "C" fn write_callback(_encoder: *const FLAC__StreamEncoder, buffer: *const u8, bytes: usize, _samples: u32, _current_frame: u32, client_data: *mut c_void) -> u32 {
+    /// * Is there a picture of the picture type queued, e.g. to avoid adding a second front cover.
+    pub fn has_picture_of_type(&self, picture_type: FlacPictureType) -> bool {
+        self.pictures.iter().any(|picture| picture.picture_type == picture_type)
+    }
+
+    /// * How many pictures were queued by `add_picture()` to be written on `initialize()`
+    pub fn queued_picture_count(&self) -> usize {
+        self.pictures.len()
+    }
+
+    /// * The total size in bytes of the picture data queued by `add_picture()`, without the mime types and the descriptions.
+    pub fn queued_picture_bytes(&self) -> usize {
+        self.pictures.iter().map(|picture| picture.picture.len()).sum()
+    }
+
+    /// * Estimate how many bytes the metadata will occupy in the FLAC file, from the `fLaC` marker to the first audio frame.
+    /// * Counts the STREAMINFO block, the comments, the cue sheets, the pictures, and the APPLICATION blocks you queued before calling to `initialize()`
+    /// * The VORBIS_COMMENT block is always written by libFLAC with its own vendor string, even if you didn't insert any comments.
+    pub fn estimated_metadata_size(&self) -> usize {
+        // Every metadata block has a 4-byte header: the last-block flag, the block type, and the 24-bit length.
+        const BLOCK_HEADER: usize = 4;
+
+        // The `fLaC` marker and the STREAMINFO block
+        let mut size = 4 + BLOCK_HEADER + 34;
+
+        // VORBIS_COMMENT: the vendor string, the number of comments, and each comment as "KEY=value" with its length.
+        size += BLOCK_HEADER + vorbis_comment_block_len(&self.comments);
+
+        // SEEKTABLE: 18 bytes for each seek point.
+        if !self.seek_points.is_empty() {
+            size += BLOCK_HEADER + self.seek_points.len() * SEEK_POINT_LEN;
+        }
+
+        // CUESHEET: 396 bytes for the header, 36 bytes for each track, 12 bytes for each index.
+        size += self.cue_sheets.iter().map(|cue_sheet| {
+            BLOCK_HEADER + 396 + cue_sheet.tracks.values().map(|track| 36 + track.indices.len() * 12).sum::<usize>()
+        }).sum::<usize>();
+
+        // PICTURE: the picture type, the lengths of the strings and the data, width, height, depth, colors.
+        size += self.pictures.iter().map(|picture| {
+            BLOCK_HEADER + picture_block_len(&picture.mime_type, &picture.description, &picture.picture)
+        }).sum::<usize>();
+
+        // APPLICATION: the ID and the data.
+        size += self.applications.iter().map(|application| BLOCK_HEADER + 4 + application.data.len()).sum::<usize>();
+
+        // PADDING: all zero.
+        if self.params.auto_padding {
+            size += BLOCK_HEADER + DEFAULT_PADDING_LENGTH as usize;
+        }
+        size
+    }
+
+    /// * Only for Ogg, libFLAC reads the Ogg page of the STREAMINFO block back at `finish()`, it can only be read from the spool of `buffer_for_header` or `accurate_header`.
+    unsafe extern "C" fn read_callback(_encoder: *const FLAC__StreamEncoder, buffer: *mut u8, bytes: *mut usize, client_data: *mut c_void) -> u32 {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        let Some(header_buffer) = this.header_buffer.as_mut() else {
+            // libFLAC gives up rewriting the header after its seek succeeded.
+            this.header_rewritten = false;
+            return FLAC__STREAM_ENCODER_READ_STATUS_UNSUPPORTED;
+        };
+        let buf = unsafe {slice::from_raw_parts_mut(buffer, *bytes)};
+        match header_buffer.read(buf) {
+            Ok(0) => {
+                unsafe {*bytes = 0};
+                FLAC__STREAM_ENCODER_READ_STATUS_END_OF_STREAM
+            },
+            Ok(bytes_read) => {
+                unsafe {*bytes = bytes_read};
+                FLAC__STREAM_ENCODER_READ_STATUS_CONTINUE
+            },
+            Err(_) => FLAC__STREAM_ENCODER_READ_STATUS_ABORT,
+        }
+    }
+
+    unsafe extern "C" fn write_callback(_encoder: *const FLAC__StreamEncoder, buffer: *const u8, bytes: usize, samples: u32, current_frame: u32, client_data: *mut c_void) -> u32 {
         #[cfg(debug_assertions)]
         if SHOW_CALLBACKS {println!("write_callback([u8; {bytes}])");}
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        match (this.on_write)(&mut this.writer, unsafe {slice::from_raw_parts(buffer, bytes)}) {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        let data = unsafe {slice::from_raw_parts(buffer, bytes)};
+        if let Some(on_frame) = this.on_frame.as_mut() && let Err(e) = on_frame(&EncodedFrame {data, samples, frame_number: current_frame}) {
+            eprintln!("On `write_callback()`: {:?}", e);
+            return FLAC__STREAM_ENCODER_WRITE_STATUS_FATAL_ERROR;
+        }
+        this.bytes_written += bytes as u64;
+        let result = match this.header_buffer.as_mut() {
+            Some(header_buffer) => header_buffer.write_all(data),
+            None => {
+                if let Some(io_stats) = this.io_stats.as_mut() {io_stats.record_write(data.len())}
+                (this.on_write)(&mut this.writer, data)
+            }
+        };
+        match result {
             Ok(_) => FLAC__STREAM_ENCODER_WRITE_STATUS_OK,
             Err(e) => {
                 eprintln!("On `write_callback()`: {:?}", e);
@@ -1047,9 +2123,20 @@ where
     unsafe extern "C" fn seek_callback(_encoder: *const FLAC__StreamEncoder, absolute_byte_offset: u64, client_data: *mut c_void) -> u32 {
         #[cfg(debug_assertions)]
         if SHOW_CALLBACKS {println!("seek_callback({absolute_byte_offset})");}
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        match (this.on_seek)(&mut this.writer, absolute_byte_offset) {
-            Ok(_) => FLAC__STREAM_ENCODER_SEEK_STATUS_OK,
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        let result = match this.header_buffer.as_mut() {
+            Some(header_buffer) => header_buffer.seek(SeekFrom::Start(absolute_byte_offset)).map(|_| ()),
+            None => {
+                if let Some(io_stats) = this.io_stats.as_mut() {io_stats.record_seek(absolute_byte_offset)}
+                (this.on_seek)(&mut this.writer, absolute_byte_offset)
+            }
+        };
+        match result {
+            Ok(_) => {
+                // libFLAC only seeks to rewrite the metadata blocks at `finish()`.
+                this.header_rewritten = true;
+                FLAC__STREAM_ENCODER_SEEK_STATUS_OK
+            },
             Err(e) => {
                 match e.kind() {
                     io::ErrorKind::NotSeekable => FLAC__STREAM_ENCODER_SEEK_STATUS_UNSUPPORTED,
@@ -1060,8 +2147,12 @@ where
     }
 
     unsafe extern "C" fn tell_callback(_encoder: *const FLAC__StreamEncoder, absolute_byte_offset: *mut u64, client_data: *mut c_void) -> u32 {
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        match (this.on_tell)(&mut this.writer) {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        let result = match this.header_buffer.as_mut() {
+            Some(header_buffer) => header_buffer.position(),
+            None => this.call_on_tell(),
+        };
+        match result {
             Ok(offset) => {
                 #[cfg(debug_assertions)]
                 if SHOW_CALLBACKS {println!("tell_callback() == {offset}");}
@@ -1078,15 +2169,109 @@ where
     }
 
     unsafe extern "C" fn metadata_callback(_encoder: *const FLAC__StreamEncoder, metadata: *const FLAC__StreamMetadata, client_data: *mut c_void) {
-        let _this = unsafe {&mut *(client_data as *mut Self)};
-        let _meta = unsafe {*metadata};
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        let meta = unsafe {*metadata};
         #[cfg(debug_assertions)]
-        if SHOW_CALLBACKS {println!("{:?}", WrappedStreamMetadata(_meta))}
+        if SHOW_CALLBACKS {println!("{:?}", WrappedStreamMetadata(meta))}
+        if meta.type_ == FLAC__METADATA_TYPE_STREAMINFO {
+            this.final_stream_info = Some(unsafe {meta.data.stream_info}.into());
+        }
+    }
+
+    /// * Get the final STREAMINFO block libFLAC gave at `finish()`, with the exact total samples, the MD5 sum of the input audio and the min/max frame sizes.
+    /// * If your `writer` can't seek and `buffer_for_header` isn't set, the header in the FLAC file wasn't rewritten, log or transmit these values out-of-band instead.
+    /// * `None` before `finish()`, or if the encoding failed.
+    pub fn final_stream_info(&self) -> Option<FlacStreamInfo> {
+        self.final_stream_info
     }
 
     /// * Calls your `on_tell()` closure to get the current writing position.
     pub fn tell(&mut self) -> Result<u64, io::Error> {
-        (self.on_tell)(&mut self.writer)
+        self.call_on_tell()
+    }
+
+    /// * Get the offset in the FLAC file where the next encoded bytes go, e.g. for a multiplexer to track the output size precisely.
+    /// * Unlike `tell()`, if `buffer_for_header` or `accurate_header` is set, it's the position in the spool, since nothing is passed to your `on_write()` closure until `finish()`.
+    /// * It's the file offset, which goes back when `finish()` seeks back to rewrite the STREAMINFO block, while `bytes_written()` is the cumulative count that never goes back.
+    pub fn output_position(&mut self) -> Result<u64, io::Error> {
+        match self.header_buffer.as_mut() {
+            Some(header_buffer) => header_buffer.position(),
+            None => self.call_on_tell(),
+        }
+    }
+
+    /// * Get the total bytes libFLAC wrote out for the current stream, including the ones kept in the spool of `buffer_for_header` or `accurate_header`.
+    /// * The rewritten STREAMINFO block is counted again, so after `finish()` it can be more than the size of the FLAC file, see `output_position()`.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn call_on_tell(&mut self) -> Result<u64, io::Error> {
+        let result = (self.on_tell)(&mut self.writer);
+        if let Some(io_stats) = self.io_stats.as_mut() {io_stats.record_tell(result.as_ref().ok().copied())}
+        result
+    }
+
+    /// * Enable or disable the statistics of the calls to your IO closures, enabling it starts the statistics from zero.
+    /// * If `buffer_for_header` is set, only the final write in `finish()` calls your closures.
+    pub fn set_io_stats_enabled(&mut self, enabled: bool) {
+        self.io_stats = if enabled {Some(IoStats::new())} else {None};
+    }
+
+    /// * Get the statistics of the calls to your IO closures, `None` if it's not enabled.
+    pub fn io_stats(&self) -> Option<&IoStats> {
+        self.io_stats.as_ref()
+    }
+
+    /// * Reset the statistics to measure the next phase separately.
+    pub fn reset_io_stats(&mut self) {
+        if let Some(io_stats) = self.io_stats.as_mut() {io_stats.reset()}
+    }
+
+    /// * Set your closure to receive every encoded frame separately with its sample count and frame number, e.g. to packetize the frames into a container.
+    /// * It's called right before the data is passed to your `on_write()` closure, or to the spool, for every write, the metadata writes have zero samples, see `EncodedFrame::is_metadata()`.
+    ///   `finish()` seeks back and rewrites the STREAMINFO block as a metadata write if the `writer` can seek.
+    /// * Returning an error fails the encoding like your `on_write()` closure does.
+    pub fn set_on_frame(&mut self, on_frame: Option<Box<dyn FnMut(&EncodedFrame) -> Result<(), io::Error> + 'a>>) {
+        self.on_frame = on_frame;
+    }
+
+    /// * Check that every sample fits in the bits per sample of the encoder, e.g. `-32768..=32767` for 16 bits, without encoding anything.
+    /// * libFLAC fails the encoding with `FLAC__STREAM_ENCODER_CLIENT_ERROR` on such a sample without telling which one, and the encoder can't go on after that.
+    /// * The error is `FLAC_ENCODER_SAMPLE_OUT_OF_RANGE`, its `out_of_range` tells the index and the value of the first sample that doesn't fit.
+    pub fn check_samples(&self, samples: &[i32]) -> Result<(), FlacEncoderError> {
+        let bits_per_sample = self.params.bits_per_sample;
+        if bits_per_sample >= 32 {
+            return Ok(());
+        }
+        let max = (1i32 << (bits_per_sample - 1)) - 1;
+        let min = -max - 1;
+        match samples.iter().position(|sample| !(min..=max).contains(sample)) {
+            None => Ok(()),
+            Some(index) => Err(FlacEncoderError {
+                out_of_range: Some(SampleOutOfRange {index, value: samples[index], bits_per_sample}),
+                ..FlacEncoderError::new(FLAC_ENCODER_SAMPLE_OUT_OF_RANGE, "FlacEncoderUnmovable::check_samples")
+            }),
+        }
+    }
+
+    /// * Set whether the write methods call `check_samples()` before encoding, the default is `false`.
+    /// * The index of the error is where the sample is in the samples of the call in the interleaved order, e.g. for `write_monos()` it's the audio frame times the channels plus the channel.
+    ///   Nothing of the call is encoded if any of the samples doesn't fit, and the encoder stays usable.
+    pub fn set_sample_checking(&mut self, sample_checking: bool) {
+        self.sample_checking = sample_checking;
+    }
+
+    /// * Do the write methods call `check_samples()` before encoding.
+    pub fn get_sample_checking(&self) -> bool {
+        self.sample_checking
+    }
+
+    /// * Is the encoder bound to the streamable subset, e.g. for the hardware players. libFLAC binds it by default.
+    /// * While bound, `initialize()` fails with `FLAC__STREAM_ENCODER_INIT_STATUS_NOT_STREAMABLE` if the settings violate the subset, e.g. a block size over 4608 for 48 kHz or less,
+    ///   so after `initialize()` succeeded, `true` means the output is within the subset. See `check_subset()` to check an existing FLAC file.
+    pub fn is_streamable_subset(&self) -> bool {
+        unsafe {FLAC__stream_encoder_get_streamable_subset(self.encoder) != 0}
     }
 
     /// * Encode the interleaved samples (interleaved by channels)
@@ -1095,33 +2280,103 @@ where
         #[cfg(debug_assertions)]
         if SHOW_CALLBACKS {println!("write_interleaved_samples([i32; {}])", samples.len());}
         if samples.is_empty() {return Ok(())}
-        if samples.len() % self.params.channels as usize != 0 {
+        if !samples.len().is_multiple_of(self.params.channels as usize) {
             Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "FlacEncoderUnmovable::write_interleaved_samples"))
         } else {
+            if self.sample_checking {
+                self.check_samples(samples)?;
+            }
+            let num_frames = samples.len() as u32 / self.params.channels as u32;
             unsafe {
-                if FLAC__stream_encoder_process_interleaved(self.encoder, samples.as_ptr(), samples.len() as u32 / self.params.channels as u32) == 0 {
-                    return self.get_status_as_error("FLAC__stream_encoder_process_interleaved");
+                if self.call_libflac(|encoder| FLAC__stream_encoder_process_interleaved(encoder, samples.as_ptr(), num_frames)) == 0 {
+                    return self.get_process_error("FLAC__stream_encoder_process_interleaved");
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// * Encode the interleaved samples coming in chunks of any length, e.g. from an upstream producer that doesn't care about the channels.
+    /// * The audio frame split by a chunk boundary is completed by the next chunk, so the chunks don't need to be multiples of the channels,
+    ///   only all of them together do, otherwise `FLAC__STREAM_ENCODER_FRAMING_ERROR` is returned after the complete frames are encoded.
+    pub fn write_interleaved_chunks<'c, I>(&mut self, chunks: I) -> Result<(), FlacEncoderError>
+    where
+        I: IntoIterator<Item = &'c [i32]> {
+        let channels = self.params.channels as usize;
+        // The partial audio frame, FLAC has at most 8 channels.
+        let mut partial = [0i32; 8];
+        let mut partial_len = 0;
+        for mut chunk in chunks {
+            if partial_len > 0 {
+                let size = (channels - partial_len).min(chunk.len());
+                partial[partial_len..partial_len + size].copy_from_slice(&chunk[..size]);
+                partial_len += size;
+                chunk = &chunk[size..];
+                if partial_len < channels {
+                    continue;
                 }
+                self.write_interleaved_samples(&partial[..channels])?;
             }
+            let aligned = chunk.len() - chunk.len() % channels;
+            self.write_interleaved_samples(&chunk[..aligned])?;
+            partial_len = chunk.len() - aligned;
+            partial[..partial_len].copy_from_slice(&chunk[aligned..]);
+        }
+        if partial_len > 0 {
+            Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "FlacEncoderUnmovable::write_interleaved_chunks"))
+        } else {
             Ok(())
         }
     }
 
+    /// * Encode `frames` audio frames of silence for all of the channels, e.g. to pad the track to a fixed length.
+    /// * The zero samples are fed to the encoder in block-sized chunks from a reusable buffer, you don't need to allocate the zero samples for the whole silence.
+    pub fn write_silence(&mut self, frames: u64) -> Result<(), FlacEncoderError> {
+        #[cfg(debug_assertions)]
+        if SHOW_CALLBACKS {println!("write_silence({frames})");}
+        let channels = self.params.channels as usize;
+        let blocksize = unsafe {FLAC__stream_encoder_get_blocksize(self.encoder)}.max(1) as u64;
+        let mut remaining = frames;
+        while remaining > 0 {
+            let num_frames = remaining.min(blocksize) as usize;
+            let buffer = self.take_interleave_buffer(num_frames * channels);
+            self.process_interleave_buffer(buffer, num_frames)?;
+            remaining -= num_frames as u64;
+        }
+        Ok(())
+    }
+
     /// * Encode mono audio. Regardless of the channel setting of the FLAC encoder, the sample will be duplicated to the number of channels to accomplish the encoding
     /// * See `FlacEncoderParams` for the information on how to provide your samples in the `[i32]` array.
     pub fn write_mono_channel(&mut self, monos: &[i32]) -> Result<(), FlacEncoderError> {
         #[cfg(debug_assertions)]
         if SHOW_CALLBACKS {println!("write_mono_channel([i32; {}])", monos.len());}
         if monos.is_empty() {return Ok(())}
+        if self.sample_checking {
+            self.check_samples(monos)?;
+        }
         match self.params.channels {
             1 => unsafe {
-                if FLAC__stream_encoder_process_interleaved(self.encoder, monos.as_ptr(), monos.len() as u32) == 0 {
-                    return self.get_status_as_error("FLAC__stream_encoder_process_interleaved");
+                if self.call_libflac(|encoder| FLAC__stream_encoder_process_interleaved(encoder, monos.as_ptr(), monos.len() as u32)) == 0 {
+                    return self.get_process_error("FLAC__stream_encoder_process_interleaved");
                 }
                 Ok(())
             },
-            2 => self.write_stereos(&monos.iter().map(|mono| -> (i32, i32){(*mono, *mono)}).collect::<Vec<(i32, i32)>>()),
-            o => self.write_frames(&monos.iter().map(|mono| -> Vec<i32> {(0..o).map(|_|{*mono}).collect()}).collect::<Vec<Vec<i32>>>()),
+            2 => {
+                let mut buffer = self.take_interleave_buffer(monos.len() * 2);
+                for (frame, mono) in buffer.chunks_exact_mut(2).zip(monos) {
+                    frame[0] = *mono;
+                    frame[1] = *mono;
+                }
+                self.process_interleave_buffer(buffer, monos.len())
+            },
+            o => {
+                let mut buffer = self.take_interleave_buffer(monos.len() * o as usize);
+                for (frame, mono) in buffer.chunks_exact_mut(o as usize).zip(monos) {
+                    frame.fill(*mono);
+                }
+                self.process_interleave_buffer(buffer, monos.len())
+            },
         }
     }
 
@@ -1134,18 +2389,48 @@ where
         if SHOW_CALLBACKS {println!("write_stereos([(i32, i32); {}])", stereos.len());}
         if stereos.is_empty() {return Ok(())}
         match self.params.channels {
-            1 => self.write_mono_channel(&stereos.iter().map(|(l, r): &(i32, i32)| -> i32 {((*l as i64 + *r as i64) / 2) as i32}).collect::<Vec<i32>>()),
-            2 => unsafe {
-                let samples: Vec<i32> = stereos.iter().flat_map(|(l, r): &(i32, i32)| -> [i32; 2] {[*l, *r]}).collect();
-                if FLAC__stream_encoder_process_interleaved(self.encoder, samples.as_ptr(), stereos.len() as u32) == 0 {
-                    return self.get_status_as_error("FLAC__stream_encoder_process_interleaved");
+            1 => {
+                let mut buffer = self.take_interleave_buffer(stereos.len());
+                for (mono, (l, r)) in buffer.iter_mut().zip(stereos) {
+                    *mono = ((*l as i64 + *r as i64) / 2) as i32;
                 }
-                Ok(())
+                self.process_interleave_buffer(buffer, stereos.len())
+            },
+            2 => {
+                let mut buffer = self.take_interleave_buffer(stereos.len() * 2);
+                for (frame, (l, r)) in buffer.chunks_exact_mut(2).zip(stereos) {
+                    frame[0] = *l;
+                    frame[1] = *r;
+                }
+                self.process_interleave_buffer(buffer, stereos.len())
             },
             o => panic!("Can't turn stereo audio into {o} channels audio."),
         }
     }
 
+    /// * Take the reusable buffer for converting the samples to the interleaved form, resized to `len` samples.
+    fn take_interleave_buffer(&mut self, len: usize) -> Vec<i32> {
+        let mut buffer = std::mem::take(&mut self.interleave_buffer);
+        buffer.clear();
+        buffer.resize(len, 0);
+        buffer
+    }
+
+    /// * Encode the interleaved samples in the buffer, then keep the buffer for the next call.
+    fn process_interleave_buffer(&mut self, buffer: Vec<i32>, num_frames: usize) -> Result<(), FlacEncoderError> {
+        if self.sample_checking && let Err(e) = self.check_samples(&buffer) {
+            self.interleave_buffer = buffer;
+            return Err(e);
+        }
+        let ret = if unsafe {self.call_libflac(|encoder| FLAC__stream_encoder_process_interleaved(encoder, buffer.as_ptr(), num_frames as u32)) == 0} {
+            self.get_process_error("FLAC__stream_encoder_process_interleaved")
+        } else {
+            Ok(())
+        };
+        self.interleave_buffer = buffer;
+        ret
+    }
+
     /// * Encode multiple mono channels into the multi-channel encoder.
     /// * See `FlacEncoderParams` for the information on how to provide your samples in the `i32` way.
     pub fn write_monos(&mut self, monos: &[Vec<i32>]) -> Result<(), FlacEncoderError> {
@@ -1161,9 +2446,19 @@ where
                         return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "FlacEncoderUnmovable::write_monos"));
                     }
                 }
+                if self.sample_checking {
+                    // The first sample in the interleaved order.
+                    let out_of_range = monos.iter().enumerate().filter_map(|(channel, mono)| {
+                        self.check_samples(mono).err().map(|e| (e.out_of_range.unwrap().index * monos.len() + channel, e))
+                    }).min_by_key(|(index, _)| *index);
+                    if let Some((index, mut e)) = out_of_range {
+                        e.out_of_range = e.out_of_range.map(|out_of_range| SampleOutOfRange {index, ..out_of_range});
+                        return Err(e);
+                    }
+                }
                 let ptr_arr: Vec<*const i32> = monos.iter().map(|v|{v.as_ptr()}).collect();
-                if FLAC__stream_encoder_process(self.encoder, ptr_arr.as_ptr(), len as u32) == 0 {
-                    self.get_status_as_error("FLAC__stream_encoder_process")
+                if self.call_libflac(|encoder| FLAC__stream_encoder_process(encoder, ptr_arr.as_ptr(), len as u32)) == 0 {
+                    self.get_process_error("FLAC__stream_encoder_process")
                 } else {
                     Ok(())
                 }
@@ -1182,23 +2477,172 @@ where
                 panic!("On FlacEncoderUnmovable::write_frames(): a frame size {} does not match the encoder channels.", frame.len())
             } else {frame.to_vec()}
         }).collect();
+        if self.sample_checking {
+            self.check_samples(&samples)?;
+        }
         unsafe {
-            if FLAC__stream_encoder_process_interleaved(self.encoder, samples.as_ptr(), frames.len() as u32) == 0 {
-                return self.get_status_as_error("FLAC__stream_encoder_process_interleaved");
+            if self.call_libflac(|encoder| FLAC__stream_encoder_process_interleaved(encoder, samples.as_ptr(), frames.len() as u32)) == 0 {
+                return self.get_process_error("FLAC__stream_encoder_process_interleaved");
             }
         }
         Ok(())
     }
 
+    /// * Encode samples by the fixed-size audio frame array, the samples are passed to the encoder without being copied.
+    /// * `N` must be the number of channels of the encoder, otherwise an error is returned.
+    /// * See `FlacEncoderParams` for the information on how to provide your samples in the `i32` way.
+    pub fn write_frames_array<const N: usize>(&mut self, frames: &[[i32; N]]) -> Result<(), FlacEncoderError> {
+        #[cfg(debug_assertions)]
+        if SHOW_CALLBACKS {println!("write_frames_array([[i32; {N}]; {}])", frames.len());}
+        if N != self.params.channels as usize {
+            return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "FlacEncoderUnmovable::write_frames_array"));
+        }
+        // `[[i32; N]]` has the same layout as the interleaved `[i32]`
+        self.write_interleaved_samples(frames.as_flattened())
+    }
+
+    /// * Encode stereo audio frames without copying the samples, the encoder must be stereo.
+    pub fn write_stereo_frames(&mut self, frames: &[[i32; 2]]) -> Result<(), FlacEncoderError> {
+        self.write_frames_array(frames)
+    }
+
+    /// * Encode the 16-bit stereo audio from the separate left and right channels, e.g. from two sources.
+    /// * The encoder must be stereo with 16 bits per sample, and both of the channels must have the same length, otherwise an error is returned.
+    pub fn write_stereo_planar_i16(&mut self, left: &[i16], right: &[i16]) -> Result<(), FlacEncoderError> {
+        #[cfg(debug_assertions)]
+        if SHOW_CALLBACKS {println!("write_stereo_planar_i16([i16; {}], [i16; {}])", left.len(), right.len());}
+        if self.params.channels != 2 || self.params.bits_per_sample != 16 || left.len() != right.len() {
+            return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "FlacEncoderUnmovable::write_stereo_planar_i16"));
+        }
+        if left.is_empty() {return Ok(())}
+        let mut buffer = self.take_interleave_buffer(left.len() * 2);
+        for (frame, (l, r)) in buffer.chunks_exact_mut(2).zip(left.iter().zip(right)) {
+            frame[0] = *l as i32;
+            frame[1] = *r as i32;
+        }
+        self.process_interleave_buffer(buffer, left.len())
+    }
+
+    /// * Start a new independent FLAC stream in the same `writer` after the finished one, with the new parameters, then call `initialize()` to begin it.
+    /// * A native FLAC file can't be appended to, this doesn't append to the finished stream. The streams are just concatenated, the result is not a valid single FLAC file, most decoders only read the first stream.
+    ///   It's for the containers that chain the streams, or for the tools that cut the streams by their byte offsets, see `tell()` before calling this.
+    /// * The queued comments, pictures, cue sheets, seek points and APPLICATION blocks are cleared, add them again for the new stream.
+    /// * Calling it before `finish()` returns the `FLAC_ENCODER_NOT_FINISHED` error.
+    pub fn continue_new_stream(&mut self, params: &FlacEncoderParams) -> Result<(), FlacEncoderError> {
+        if !self.finished {
+            return Err(FlacEncoderError::new(FLAC_ENCODER_NOT_FINISHED, "FlacEncoderUnmovable::continue_new_stream"));
+        }
+        self.start_new_stream(params, false, "FlacEncoderUnmovable::continue_new_stream")
+    }
+
+    /// * Finish the current FLAC file if it was initialized and isn't finished, then start a new FLAC file in the `new_writer` with the same libFLAC encoder, then call `initialize()` to begin it.
+    /// * It saves creating and deleting the libFLAC encoder for each of many short files. The old `writer` is dropped, `None` keeps the current parameters.
+    /// * The queued pictures, cue sheets, seek points and APPLICATION blocks are cleared, the comments are cleared too unless `set_keep_comments_on_reset()` is set, e.g. for the album-wide tags.
+    pub fn reset_with_writer(&mut self, new_writer: WriteSeek, params: Option<&FlacEncoderParams>) -> Result<(), FlacEncoderError> {
+        if self.encoder_initialized && !self.finished {
+            self.finish()?;
+        }
+        // Only the fields are replaced, `self` stays where libFLAC's client data points to.
+        self.writer = new_writer;
+        let params = params.copied().unwrap_or(self.params);
+        self.start_new_stream(&params, self.keep_comments_on_reset, "FlacEncoderUnmovable::reset_with_writer")
+    }
+
+    /// * Set whether `reset_with_writer()` keeps the comments for the next FLAC file, e.g. the album-wide tags, the default is `false`.
+    pub fn set_keep_comments_on_reset(&mut self, keep_comments_on_reset: bool) {
+        self.keep_comments_on_reset = keep_comments_on_reset;
+    }
+
+    /// * Get whether `reset_with_writer()` keeps the comments.
+    pub fn get_keep_comments_on_reset(&self) -> bool {
+        self.keep_comments_on_reset
+    }
+
+    /// * Clear the queued metadata and get ready for `initialize()` of the next stream.
+    fn start_new_stream(&mut self, params: &FlacEncoderParams, keep_comments: bool, function: &'static str) -> Result<(), FlacEncoderError> {
+        self.params = *params;
+        self.metadata.clear();
+        self.metadata_ids.clear();
+        if !keep_comments {
+            self.comments.clear();
+        }
+        self.cue_sheets.clear();
+        self.pictures.clear();
+        self.seek_points.clear();
+        self.applications.clear();
+        self.header_buffer = None;
+        self.header_buffer = self.new_header_spool(function)?;
+        self.encoder_initialized = false;
+        self.finished = false;
+        self.bytes_written = 0;
+        self.final_stream_info = None;
+        self.header_rewritten = false;
+        self.finish_report = None;
+        Ok(())
+    }
+
     /// * After sending all of the samples to encode, must call `finish()` to complete encoding.
+    /// * After that, writing more samples returns the `FLAC_ENCODER_FINISHED` error, see `continue_new_stream()`.
+    /// * Then `finish_report()` tells whether the total samples matched the estimate. If `set_strict_total_samples()` is set and the STREAMINFO block is wrong by more than it, the `FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH` error is returned, the stream is finished anyway.
     pub fn finish(&mut self) -> Result<(), FlacEncoderError> {
         if self.finished {
             return Ok(())
         }
         #[cfg(debug_assertions)]
         if SHOW_CALLBACKS {println!("finish()");}
+        self.header_rewritten = false;
+        self.finish_stream()?;
+        let report = self.final_stream_info.map(|stream_info| FinishReport {
+            estimated: self.params.total_samples_estimate,
+            actual: stream_info.total_samples,
+            header_rewritten: self.header_rewritten,
+        });
+        self.finish_report = report;
+        match (report, self.strict_total_samples) {
+            (Some(report), Some(tolerance)) if report.header_is_wrong() && report.difference() > tolerance => {
+                Err(FlacEncoderError::new(FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH, "FlacEncoderUnmovable::finish"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// * Get how the total samples compare with `total_samples_estimate`, and whether the STREAMINFO block was rewritten, see `FinishReport`.
+    /// * `None` before `finish()`, or if the encoding failed.
+    pub fn finish_report(&self) -> Option<FinishReport> {
+        self.finish_report
+    }
+
+    /// * Make `finish()` fail if the STREAMINFO block in the output tells the total samples off by more than `tolerance` samples per channel, for the pipelines where the duration must be correct.
+    /// * The block is only wrong if the writer can't seek, see `FinishReport`. `None` turns it off, which is the default.
+    pub fn set_strict_total_samples(&mut self, tolerance: Option<u64>) {
+        self.strict_total_samples = tolerance;
+    }
+
+    /// * Get the tolerance set by `set_strict_total_samples()`.
+    pub fn get_strict_total_samples(&self) -> Option<u64> {
+        self.strict_total_samples
+    }
+
+    fn finish_stream(&mut self) -> Result<(), FlacEncoderError> {
         unsafe {
-            if FLAC__stream_encoder_finish(self.encoder) != 0 {
+            let state = FLAC__stream_encoder_get_state(self.encoder);
+            if self.call_libflac(|encoder| FLAC__stream_encoder_finish(encoder)) != 0 {
+                if state != FLAC__STREAM_ENCODER_OK && state != FLAC__STREAM_ENCODER_UNINITIALIZED && self.header_buffer.take().is_some() {
+                    // The encoding failed, don't write the incomplete FLAC file from the spool.
+                    self.finished = true;
+                    return Err(FlacEncoderError::new(state, "FlacEncoderUnmovable::finish"));
+                }
+                if let Some(mut header_buffer) = self.header_buffer.take() {
+                    // The STREAMINFO block was patched in the spool by libFLAC, now write the whole FLAC file in order.
+                    let (writer, on_write, io_stats) = (&mut self.writer, &mut self.on_write, &mut self.io_stats);
+                    return match header_buffer.drain_to(|data| {
+                        if let Some(io_stats) = io_stats.as_mut() {io_stats.record_write(data.len())}
+                        on_write(writer, data)
+                    }) {
+                        Ok(_) => {self.finished = true; Ok(())},
+                        Err(_) => Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_IO_ERROR, "FlacEncoderUnmovable::finish")),
+                    };
+                }
                 match self.writer.seek(SeekFrom::End(0)) {
                     Ok(_) => {self.finished = true; Ok(())},
                     Err(_) => Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_IO_ERROR, "self.writer.seek(SeekFrom::End(0))")),
@@ -1224,7 +2668,7 @@ where
     pub fn finalize(self) {}
 }
 
-impl<'a, WriteSeek> Debug for FlacEncoderUnmovable<'_, WriteSeek>
+impl<WriteSeek> Debug for FlacEncoderUnmovable<'_, WriteSeek>
 where
     WriteSeek: Write + Seek + Debug {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
@@ -1237,15 +2681,24 @@ where
             .field("on_tell", &"{{closure}}")
             .field("comments", &self.comments)
             .field("cue_sheets", &self.cue_sheets)
+            .field("seek_points", &self.seek_points)
             .field("pictures", &format_args!("..."))
+            .field("applications", &self.applications)
             .field("finished", &self.finished)
+            .field("io_stats", &self.io_stats)
+            .field("on_frame", &self.on_frame.as_ref().map(|_| "{{closure}}"))
+            .field("keep_comments_on_reset", &self.keep_comments_on_reset)
+            .field("sample_checking", &self.sample_checking)
+            .field("final_stream_info", &self.final_stream_info)
+            .field("finish_report", &self.finish_report)
+            .field("strict_total_samples", &self.strict_total_samples)
             .finish()
     }
 }
 
-impl<'a, WriteSeek> Drop for FlacEncoderUnmovable<'_, WriteSeek>
+impl<WriteSeek> Drop for FlacEncoderUnmovable<'_, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     fn drop(&mut self) {
         self.on_drop();
     }
@@ -1255,13 +2708,13 @@ where
 /// This is the struct that should be mainly used by you.
 pub struct FlacEncoder<'a, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     encoder: Box<FlacEncoderUnmovable<'a, WriteSeek>>,
 }
 
 impl<'a, WriteSeek> FlacEncoder<'a, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     pub fn new(
         writer: WriteSeek,
         on_write: Box<dyn FnMut(&mut WriteSeek, &[u8]) -> Result<(), io::Error> + 'a>,
@@ -1278,7 +2731,7 @@ where
     pub fn finalize(self) {}
 }
 
-impl<'a, WriteSeek> Debug for FlacEncoder<'_, WriteSeek>
+impl<WriteSeek> Debug for FlacEncoder<'_, WriteSeek>
 where
     WriteSeek: Write + Seek + Debug {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
@@ -1290,7 +2743,7 @@ where
 
 impl<'a, WriteSeek> Deref for FlacEncoder<'a, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     type Target = FlacEncoderUnmovable<'a, WriteSeek>;
     fn deref(&self) -> &FlacEncoderUnmovable<'a, WriteSeek> {
         &self.encoder
@@ -1299,12 +2752,183 @@ where
 
 impl<'a, WriteSeek> DerefMut for FlacEncoder<'a, WriteSeek>
 where
-    WriteSeek: Write + Seek + Debug {
+    WriteSeek: Write + Seek {
     fn deref_mut(&mut self) -> &mut FlacEncoderUnmovable<'a, WriteSeek> {
         &mut self.encoder
     }
 }
 
+/// ## The object-safe `Write + Seek`, for choosing the writer at runtime, e.g. a file, the stdout or the memory, see `DynFlacEncoder`
+/// * Every `Write + Seek` type implements it.
+pub trait WriteSeekAny: Write + Seek {}
+
+impl<T> WriteSeekAny for T
+where
+    T: Write + Seek + ?Sized {}
+
+impl Debug for dyn WriteSeekAny + '_ {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str("dyn WriteSeekAny")
+    }
+}
+
+/// ## The `FlacEncoder` over the boxed writer, one encoder type for all of the writers, see `FlacEncoder::new_dyn()`
+pub type DynFlacEncoder<'a> = FlacEncoder<'a, Box<dyn WriteSeekAny + 'a>>;
+
+impl<'a> FlacEncoder<'a, Box<dyn WriteSeekAny + 'a>> {
+    /// * Create the encoder over the boxed writer, the closures write, seek and tell by the writer itself.
+    pub fn new_dyn(writer: Box<dyn WriteSeekAny + 'a>, params: &FlacEncoderParams) -> Result<Self, FlacEncoderError> {
+        Self::new(
+            writer,
+            Box::new(|writer: &mut Box<dyn WriteSeekAny + 'a>, data: &[u8]| -> Result<(), io::Error> {
+                writer.write_all(data)
+            }),
+            Box::new(|writer: &mut Box<dyn WriteSeekAny + 'a>, position: u64| -> Result<(), io::Error> {
+                writer.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|writer: &mut Box<dyn WriteSeekAny + 'a>| -> Result<u64, io::Error> {
+                writer.stream_position()
+            }),
+            params
+        )
+    }
+}
+
+/// * Forward the `&mut self` methods of the wrapped encoder or decoder, the `Send` wrappers only deref to it immutably.
+macro_rules! forward_mut {
+    ($target:ident; $($name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+        $(
+            #[doc = concat!(" * The same as `", stringify!($target), "::", stringify!($name), "()`.")]
+            pub fn $name(&mut self, $($arg: $ty),*) $(-> $ret)? {
+                self.0.$name($($arg),*)
+            }
+        )*
+    }
+}
+
+/// ## A `FlacEncoder` that can be sent to another thread, e.g. to encode on a worker thread.
+/// * `FlacEncoder` isn't `Send` because it holds the libFLAC encoder and the metadata as raw pointers, and your closures may not be `Send`.
+///   The libFLAC encoder has no thread affinity, it just must not be used by two threads at the same time.
+///   `SendFlacEncoder` only takes the `Send` closures, and it's not `Sync`, so it's used by one thread at a time.
+/// * It's `Send` only if the `writer` is `Send`:
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<flac::SendFlacEncoder<'static, std::io::Cursor<std::rc::Rc<Vec<u8>>>>>();
+/// ```
+/// * It only derefs to `&FlacEncoder`, the encoder with the closures that aren't `Send` can't be put into it:
+/// ```compile_fail
+/// use std::{io::{Cursor, Write, Seek, SeekFrom}, rc::Rc};
+/// use flac::{FlacEncoder, SendFlacEncoder};
+/// use flac::options::FlacEncoderParams;
+///
+/// fn replace(mut send_enc: SendFlacEncoder<'static, Cursor<Vec<u8>>>) -> SendFlacEncoder<'static, Cursor<Vec<u8>>> {
+///     let rc = Rc::new(());
+///     *send_enc = FlacEncoder::new(
+///         Cursor::new(Vec::new()),
+///         Box::new(move |writer: &mut Cursor<Vec<u8>>, data: &[u8]| {let _ = rc.clone(); writer.write_all(data)}),
+///         Box::new(|writer: &mut Cursor<Vec<u8>>, position: u64| writer.seek(SeekFrom::Start(position)).map(|_| ())),
+///         Box::new(|writer: &mut Cursor<Vec<u8>>| writer.stream_position()),
+///         &FlacEncoderParams::new(),
+///     ).unwrap();
+///     send_enc
+/// }
+/// ```
+pub struct SendFlacEncoder<'a, WriteSeek>(FlacEncoder<'a, WriteSeek>)
+where
+    WriteSeek: Write + Seek;
+
+// SAFETY: All of the closures are `Send`, the `on_frame()` closure is only set by `SendFlacEncoder::set_on_frame()`, and the `writer` is `Send`.
+// We only deref to `&FlacEncoder`, so the encoder can't be replaced by one with the closures that aren't `Send`.
+// The libFLAC encoder, the metadata objects and the pointers to our own boxed struct are only used by the thread that owns us.
+unsafe impl<WriteSeek> Send for SendFlacEncoder<'_, WriteSeek>
+where
+    WriteSeek: Write + Seek + Send {}
+
+impl<'a, WriteSeek> SendFlacEncoder<'a, WriteSeek>
+where
+    WriteSeek: Write + Seek {
+    /// * The same as `FlacEncoder::new()`, but the closures must be `Send`.
+    pub fn new(
+        writer: WriteSeek,
+        on_write: Box<dyn FnMut(&mut WriteSeek, &[u8]) -> Result<(), io::Error> + Send + 'a>,
+        on_seek: Box<dyn FnMut(&mut WriteSeek, u64) -> Result<(), io::Error> + Send + 'a>,
+        on_tell: Box<dyn FnMut(&mut WriteSeek) -> Result<u64, io::Error> + Send + 'a>,
+        params: &FlacEncoderParams
+    ) -> Result<Self, FlacEncoderError> {
+        Ok(Self(FlacEncoder::new(writer, on_write, on_seek, on_tell, params)?))
+    }
+
+    /// * The same as `FlacEncoderUnmovable::set_on_frame()`, but the closure must be `Send`.
+    pub fn set_on_frame(&mut self, on_frame: Option<Box<dyn FnMut(&EncodedFrame) -> Result<(), io::Error> + Send + 'a>>) {
+        self.0.encoder.on_frame = on_frame.map(|on_frame| on_frame as Box<dyn FnMut(&EncodedFrame) -> Result<(), io::Error> + 'a>);
+    }
+
+    forward_mut! {FlacEncoderUnmovable;
+        set_accurate_header(accurate_header: AccurateHeader) -> Result<(), FlacEncoderError>;
+        insert_comments(key: &str, value: &str) -> Result<(), FlacEncoderInitError>;
+        insert_cue_sheet(cue_sheet: &FlacCueSheet) -> Result<(), FlacEncoderInitError>;
+        add_picture(picture_binary: &[u8], description: &str, mime_type: &str, width: u32, height: u32, depth: u32, colors: u32) -> Result<(), FlacEncoderInitError>;
+        add_picture_of_type(picture_binary: &[u8], picture_type: FlacPictureType, description: &str, mime_type: &str, width: u32, height: u32, depth: u32, colors: u32) -> Result<(), FlacEncoderInitError>;
+        add_seek_points(sample_numbers: &[u64]) -> Result<(), FlacEncoderInitError>;
+        add_application(id: [u8; 4], data: &[u8]) -> Result<(), FlacEncoderInitError>;
+        initialize() -> Result<(), FlacEncoderError>;
+        tell() -> Result<u64, io::Error>;
+        output_position() -> Result<u64, io::Error>;
+        set_io_stats_enabled(enabled: bool);
+        reset_io_stats();
+        set_sample_checking(sample_checking: bool);
+        write_interleaved_samples(samples: &[i32]) -> Result<(), FlacEncoderError>;
+        write_silence(frames: u64) -> Result<(), FlacEncoderError>;
+        write_mono_channel(monos: &[i32]) -> Result<(), FlacEncoderError>;
+        write_stereos(stereos: &[(i32, i32)]) -> Result<(), FlacEncoderError>;
+        write_monos(monos: &[Vec<i32>]) -> Result<(), FlacEncoderError>;
+        write_frames(frames: &[Vec<i32>]) -> Result<(), FlacEncoderError>;
+        write_stereo_frames(frames: &[[i32; 2]]) -> Result<(), FlacEncoderError>;
+        continue_new_stream(params: &FlacEncoderParams) -> Result<(), FlacEncoderError>;
+        reset_with_writer(new_writer: WriteSeek, params: Option<&FlacEncoderParams>) -> Result<(), FlacEncoderError>;
+        set_keep_comments_on_reset(keep_comments_on_reset: bool);
+        finish() -> Result<(), FlacEncoderError>;
+        set_strict_total_samples(tolerance: Option<u64>);
+    }
+
+    /// * The same as `FlacEncoderUnmovable::write_interleaved_chunks()`.
+    pub fn write_interleaved_chunks<'c, I>(&mut self, chunks: I) -> Result<(), FlacEncoderError>
+    where
+        I: IntoIterator<Item = &'c [i32]> {
+        self.0.write_interleaved_chunks(chunks)
+    }
+
+    /// * The same as `FlacEncoderUnmovable::write_frames_array()`.
+    pub fn write_frames_array<const N: usize>(&mut self, frames: &[[i32; N]]) -> Result<(), FlacEncoderError> {
+        self.0.write_frames_array(frames)
+    }
+
+    /// * Get the `FlacEncoder` back, it can't be sent to another thread anymore.
+    pub fn into_inner(self) -> FlacEncoder<'a, WriteSeek> {
+        self.0
+    }
+}
+
+impl<WriteSeek> Debug for SendFlacEncoder<'_, WriteSeek>
+where
+    WriteSeek: Write + Seek + Debug {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple("SendFlacEncoder")
+            .field(&self.0)
+            .finish()
+    }
+}
+
+impl<'a, WriteSeek> Deref for SendFlacEncoder<'a, WriteSeek>
+where
+    WriteSeek: Write + Seek {
+    type Target = FlacEncoder<'a, WriteSeek>;
+    fn deref(&self) -> &FlacEncoder<'a, WriteSeek> {
+        &self.0
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct FlacDecoderError {
     /// * This code is actually `FlacDecoderErrorCode`
@@ -1315,22 +2939,89 @@ pub struct FlacDecoderError {
 
     /// * Which function generates this error
     pub function: &'static str,
+
+    /// * The kind of the I/O error, for the `FLAC_DECODER_OPEN_FAILED` error.
+    pub io_error_kind: Option<io::ErrorKind>,
+
+    /// * The first error the decoder reported in the stream, for the `FLAC_DECODER_DAMAGED_STREAM` error.
+    pub internal_error: Option<FlacInternalDecoderError>,
 }
 
+/// ## The decoder error code for the decoding cancelled by the cancellation token, it's not a libFLAC state.
+pub const FLAC_DECODER_CANCELLED: u32 = 0x100;
+
+/// ## The decoder error code for the audio that isn't CD audio (44100 Hz, 16 bits, stereo) where it's required, it's not a libFLAC state.
+pub const FLAC_DECODER_NOT_CD_AUDIO: u32 = 0x101;
+
+/// ## The decoder error code for the FLAC file without a cue sheet where it's required, it's not a libFLAC state.
+pub const FLAC_DECODER_NO_CUE_SHEET: u32 = 0x102;
+
+/// ## The decoder error code for the audio exceeding the limits set by `set_limits()`, it's not a libFLAC state.
+pub const FLAC_DECODER_LIMIT_EXCEEDED: u32 = 0x103;
+
+/// ## The decoder error code for the wanted channel out of the range of the channels, it's not a libFLAC state.
+pub const FLAC_DECODER_CHANNEL_OUT_OF_RANGE: u32 = 0x104;
+
+/// ## The decoder error code for the stream without the `fLaC` signature, it's not a libFLAC state.
+pub const FLAC_DECODER_NOT_FLAC: u32 = 0x105;
+
+/// ## The decoder error code for an invalid argument given to a function, it's not a libFLAC state.
+pub const FLAC_DECODER_INVALID_ARGUMENT: u32 = 0x106;
+
+/// ## The decoder error code for the stream that ended before the total samples of the STREAMINFO block, it's not a libFLAC state.
+pub const FLAC_DECODER_TRUNCATED: u32 = 0x107;
+
+/// ## The decoder error code for the audio format that the function doesn't support, it's not a libFLAC state.
+pub const FLAC_DECODER_UNSUPPORTED_FORMAT: u32 = 0x108;
+
+/// ## The decoder error code for the file that couldn't be opened, the `io::ErrorKind` is in `io_error_kind`, it's not a libFLAC state.
+pub const FLAC_DECODER_OPEN_FAILED: u32 = 0x109;
+
+/// ## The decoder error code for the stream the decoder reported an error in, e.g. a frame CRC mismatch, the error is in `internal_error`, it's not a libFLAC state.
+pub const FLAC_DECODER_DAMAGED_STREAM: u32 = 0x10A;
+
 impl FlacDecoderError {
     pub fn new(code: u32, function: &'static str) -> Self {
         Self {
             code,
             message: Self::get_message_from_code(code),
             function,
+            io_error_kind: None,
+            internal_error: None,
+        }
+    }
+
+    /// * The `FLAC_DECODER_DAMAGED_STREAM` error for the first error the decoder reported in the stream.
+    pub fn damaged_stream(internal_error: FlacInternalDecoderError, function: &'static str) -> Self {
+        Self {
+            internal_error: Some(internal_error),
+            ..Self::new(FLAC_DECODER_DAMAGED_STREAM, function)
         }
     }
 
     pub fn get_message_from_code(code: u32) -> &'static str {
-        unsafe {
-            CStr::from_ptr(*FLAC__StreamDecoderStateString.as_ptr().add(code as usize)).to_str().unwrap()
+        match code {
+            FLAC_DECODER_CANCELLED => "The decoder was cancelled by the cancellation token.",
+            FLAC_DECODER_NOT_CD_AUDIO => "The audio is not CD audio, which must be 44100 Hz, 16 bits per sample, stereo.",
+            FLAC_DECODER_NO_CUE_SHEET => "The FLAC file has no cue sheet.",
+            FLAC_DECODER_LIMIT_EXCEEDED => "The audio exceeds the limits of the max total samples, the max channels or the max sample rate.",
+            FLAC_DECODER_CHANNEL_OUT_OF_RANGE => "The wanted channel is out of the range of the channels.",
+            FLAC_DECODER_NOT_FLAC => "The stream is not a FLAC file, the fLaC signature is missing.",
+            FLAC_DECODER_INVALID_ARGUMENT => "An argument given to the function is invalid.",
+            FLAC_DECODER_TRUNCATED => "The stream ended before all of the samples the STREAMINFO block declared were decoded.",
+            FLAC_DECODER_UNSUPPORTED_FORMAT => "The sample rate, the channels or the bits per sample are not supported, or they changed in the middle of the stream.",
+            FLAC_DECODER_OPEN_FAILED => "The file couldn't be opened.",
+            FLAC_DECODER_DAMAGED_STREAM => "The stream is damaged, the decoder reported an error in it, e.g. a frame CRC mismatch.",
+            _ => unsafe {
+                CStr::from_ptr(*FLAC__StreamDecoderStateString.as_ptr().add(code as usize)).to_str().unwrap()
+            },
         }
     }
+
+    /// * Is the decoding cancelled by the cancellation token, rather than aborted for other reasons.
+    pub fn is_cancelled(&self) -> bool {
+        self.code == FLAC_DECODER_CANCELLED
+    }
 }
 
 impl_FlacError!(FlacDecoderError);
@@ -1366,6 +3057,39 @@ pub enum FlacDecoderErrorCode {
 
     /// * The decoder is in the uninitialized state; one of the FLAC__stream_decoder_init_*() functions must be called before samples can be processed.
     StreamDecoderUninitialized = FLAC__STREAM_DECODER_UNINITIALIZED as isize,
+
+    /// * The decoder was cancelled by the cancellation token.
+    StreamDecoderCancelled = FLAC_DECODER_CANCELLED as isize,
+
+    /// * The audio is not CD audio, which must be 44100 Hz, 16 bits per sample, stereo.
+    StreamDecoderNotCdAudio = FLAC_DECODER_NOT_CD_AUDIO as isize,
+
+    /// * The FLAC file has no cue sheet.
+    StreamDecoderNoCueSheet = FLAC_DECODER_NO_CUE_SHEET as isize,
+
+    /// * The audio exceeds the limits of the max total samples, the max channels or the max sample rate.
+    StreamDecoderLimitExceeded = FLAC_DECODER_LIMIT_EXCEEDED as isize,
+
+    /// * The wanted channel is out of the range of the channels.
+    StreamDecoderChannelOutOfRange = FLAC_DECODER_CHANNEL_OUT_OF_RANGE as isize,
+
+    /// * The stream is not a FLAC file, the fLaC signature is missing.
+    StreamDecoderNotFlac = FLAC_DECODER_NOT_FLAC as isize,
+
+    /// * An argument given to the function is invalid.
+    StreamDecoderInvalidArgument = FLAC_DECODER_INVALID_ARGUMENT as isize,
+
+    /// * The stream ended before all of the samples the STREAMINFO block declared were decoded.
+    StreamDecoderTruncated = FLAC_DECODER_TRUNCATED as isize,
+
+    /// * The sample rate, the channels or the bits per sample are not supported, or they changed in the middle of the stream.
+    StreamDecoderUnsupportedFormat = FLAC_DECODER_UNSUPPORTED_FORMAT as isize,
+
+    /// * The file couldn't be opened.
+    StreamDecoderOpenFailed = FLAC_DECODER_OPEN_FAILED as isize,
+
+    /// * The stream is damaged, the decoder reported an error in it, e.g. a frame CRC mismatch.
+    StreamDecoderDamagedStream = FLAC_DECODER_DAMAGED_STREAM as isize,
 }
 
 impl Display for FlacDecoderErrorCode {
@@ -1381,6 +3105,17 @@ impl Display for FlacDecoderErrorCode {
             Self::StreamDecoderAborted => write!(f, "The decoder was aborted by the read or write callback."),
             Self::StreamDecoderMemoryAllocationError => write!(f, "An error occurred allocating memory. The decoder is in an invalid state and can no longer be used."),
             Self::StreamDecoderUninitialized => write!(f, "The decoder is in the uninitialized state; one of the FLAC__stream_decoder_init_*() functions must be called before samples can be processed."),
+            Self::StreamDecoderCancelled => write!(f, "The decoder was cancelled by the cancellation token."),
+            Self::StreamDecoderNotCdAudio => write!(f, "The audio is not CD audio, which must be 44100 Hz, 16 bits per sample, stereo."),
+            Self::StreamDecoderNoCueSheet => write!(f, "The FLAC file has no cue sheet."),
+            Self::StreamDecoderLimitExceeded => write!(f, "The audio exceeds the limits of the max total samples, the max channels or the max sample rate."),
+            Self::StreamDecoderChannelOutOfRange => write!(f, "The wanted channel is out of the range of the channels."),
+            Self::StreamDecoderNotFlac => write!(f, "The stream is not a FLAC file, the fLaC signature is missing."),
+            Self::StreamDecoderInvalidArgument => write!(f, "An argument given to the function is invalid."),
+            Self::StreamDecoderTruncated => write!(f, "The stream ended before all of the samples the STREAMINFO block declared were decoded."),
+            Self::StreamDecoderUnsupportedFormat => write!(f, "The sample rate, the channels or the bits per sample are not supported, or they changed in the middle of the stream."),
+            Self::StreamDecoderOpenFailed => write!(f, "The file couldn't be opened."),
+            Self::StreamDecoderDamagedStream => write!(f, "The stream is damaged, the decoder reported an error in it, e.g. a frame CRC mismatch."),
         }
     }
 }
@@ -1399,6 +3134,17 @@ impl From<u32> for FlacDecoderErrorCode {
             FLAC__STREAM_DECODER_ABORTED => StreamDecoderAborted,
             FLAC__STREAM_DECODER_MEMORY_ALLOCATION_ERROR => StreamDecoderMemoryAllocationError,
             FLAC__STREAM_DECODER_UNINITIALIZED => StreamDecoderUninitialized,
+            FLAC_DECODER_CANCELLED => StreamDecoderCancelled,
+            FLAC_DECODER_NOT_CD_AUDIO => StreamDecoderNotCdAudio,
+            FLAC_DECODER_NO_CUE_SHEET => StreamDecoderNoCueSheet,
+            FLAC_DECODER_LIMIT_EXCEEDED => StreamDecoderLimitExceeded,
+            FLAC_DECODER_CHANNEL_OUT_OF_RANGE => StreamDecoderChannelOutOfRange,
+            FLAC_DECODER_NOT_FLAC => StreamDecoderNotFlac,
+            FLAC_DECODER_INVALID_ARGUMENT => StreamDecoderInvalidArgument,
+            FLAC_DECODER_TRUNCATED => StreamDecoderTruncated,
+            FLAC_DECODER_UNSUPPORTED_FORMAT => StreamDecoderUnsupportedFormat,
+            FLAC_DECODER_OPEN_FAILED => StreamDecoderOpenFailed,
+            FLAC_DECODER_DAMAGED_STREAM => StreamDecoderDamagedStream,
             o => panic!("Not an decoder error code: {o}."),
         }
     }
@@ -1492,6 +3238,8 @@ impl From<FlacDecoderInitError> for FlacDecoderError {
             code: err.code,
             message: err.message,
             function: err.function,
+            io_error_kind: None,
+            internal_error: None,
         }
     }
 }
@@ -1539,6 +3287,9 @@ pub enum FlacInternalDecoderError {
 
     /// * The decoder encountered a otherwise valid frame in which the decoded samples exceeded the range offered by the stated bit depth.
     OutOfBounds,
+
+    /// * The decoder encountered a missing frame, the missing samples are filled with silence.
+    MissingFrame,
 }
 
 impl Display for FlacInternalDecoderError {
@@ -1550,14 +3301,60 @@ impl Display for FlacInternalDecoderError {
             Self::UnparseableStream => write!(f, "The decoder encountered reserved fields in use in the stream."),
             Self::BadMetadata => write!(f, "The decoder encountered a corrupted metadata block."),
             Self::OutOfBounds => write!(f, "The decoder encountered a otherwise valid frame in which the decoded samples exceeded the range offered by the stated bit depth."),
+            Self::MissingFrame => write!(f, "The decoder encountered a missing frame, the missing samples are filled with silence."),
         }
     }
 }
 
 impl std::error::Error for FlacInternalDecoderError {}
 
+/// ## Where the FLAC decoder internal error happened, for your `on_error_with_context()` closure to report.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeErrorContext {
+    /// * What went wrong
+    pub error: FlacInternalDecoderError,
+
+    /// * The byte position in the FLAC file where the decoder was, `None` if your `on_tell()` closure can't tell.
+    pub byte_position: Option<u64>,
+
+    /// * The sample position per channel right after the last decoded frame, `None` if no frame was decoded yet.
+    pub approx_sample: Option<u64>,
+
+    /// * How many frames were decoded before the error.
+    pub frame_count_so_far: u64,
+}
+
+impl Display for DecodeErrorContext {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} After {} frames", self.error, self.frame_count_so_far)?;
+        if let Some(byte_position) = self.byte_position {
+            write!(f, ", at byte {byte_position}")?;
+        }
+        if let Some(approx_sample) = self.approx_sample {
+            write!(f, ", near sample {approx_sample}")?;
+        }
+        write!(f, ".")
+    }
+}
+
+impl std::error::Error for DecodeErrorContext {}
+
+/// ## How `scale_to_i32_range` fills the lower bits of the samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum I32Scaling {
+    /// * Repeat the sample in the lower bits, the minimum sample maps to `i32::MIN` and the maximum sample maps to `i32::MAX`.
+    #[default]
+    BitReplication,
+
+    /// * Just shift the sample to the upper bits, the lower bits are zero, the maximum sample maps below `i32::MAX`.
+    ///   Shift it back to get the original sample, and zero stays zero.
+    MsbShift,
+}
+
 /// ## The form of audio samples
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FlacAudioForm {
     /// * For the frame array, each audio frame is one sample per channel.
     /// * For example, a stereo frame has two samples, one for left, and one for right.
@@ -1569,8 +3366,10 @@ pub enum FlacAudioForm {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SamplesInfo {
     /// * Number of samples per channel decoded from the FLAC frame
+    /// * This is not the number of the `i32` values, see `total_values()`, and see `outer_len()` and `inner_len()` for how the values are arranged.
     pub samples: u32,
 
     /// * Number of channels in the FLAC frame
@@ -1587,19 +3386,660 @@ pub struct SamplesInfo {
     pub audio_form: FlacAudioForm,
 }
 
-fn entry_to_str(entry: &FLAC__StreamMetadata_VorbisComment_Entry) -> Cow<'_, str> {
-    unsafe{String::from_utf8_lossy(slice::from_raw_parts(entry.entry, entry.length as usize))}
-}
+impl SamplesInfo {
+    /// * The number of the `i32` values of all the channels, `samples * channels`.
+    pub fn total_values(&self) -> usize {
+        self.samples as usize * self.channels as usize
+    }
 
-fn entry_to_string(entry: &FLAC__StreamMetadata_VorbisComment_Entry) -> String {
-    entry_to_str(entry).to_string()
+    /// * The length of the outer `Vec` passed to your `on_write()` closure, `samples` for `FrameArray`, `channels` for `ChannelArray`.
+    pub fn outer_len(&self) -> usize {
+        match self.audio_form {
+            FlacAudioForm::FrameArray => self.samples as usize,
+            FlacAudioForm::ChannelArray => self.channels as usize,
+        }
+    }
+
+    /// * The length of each inner `Vec` passed to your `on_write()` closure, `channels` for `FrameArray`, `samples` for `ChannelArray`.
+    pub fn inner_len(&self) -> usize {
+        match self.audio_form {
+            FlacAudioForm::FrameArray => self.channels as usize,
+            FlacAudioForm::ChannelArray => self.samples as usize,
+        }
+    }
+
+    /// * Get a sample by the frame index and the channel index from the data passed to your `on_write()` closure, no matter what the audio form is.
+    /// * Returns `None` if the index is out of range.
+    pub fn get_sample(&self, samples: &[Vec<i32>], frame: usize, channel: usize) -> Option<i32> {
+        let (outer, inner) = match self.audio_form {
+            FlacAudioForm::FrameArray => (frame, channel),
+            FlacAudioForm::ChannelArray => (channel, frame),
+        };
+        samples.get(outer)?.get(inner).copied()
+    }
+
+    /// * Iterate over the audio frames of the data passed to your `on_write()` closure, each audio frame has a sample for every channel.
+    pub fn frames_in<'a>(&self, samples: &'a [Vec<i32>]) -> impl Iterator<Item = Vec<i32>> + 'a {
+        let info = *self;
+        (0..self.samples as usize).map(move |frame| (0..info.channels as usize).map(|channel| info.get_sample(samples, frame, channel).unwrap_or(0)).collect())
+    }
+}
+
+/// * The decoded frames merged for one call to your `on_write()` closure, see `set_batch_frames()` of the decoder.
+#[derive(Debug)]
+struct FrameBatch {
+    samples: Vec<Vec<i32>>,
+
+    /// * Its `samples` is the samples per channel of all of the merged frames.
+    samples_info: SamplesInfo,
+
+    /// * How many frames were merged.
+    frames: usize,
+}
+
+impl FrameBatch {
+    fn new(samples_info: SamplesInfo) -> Self {
+        Self {
+            samples: Vec::new(),
+            samples_info: SamplesInfo {samples: 0, ..samples_info},
+            frames: 0,
+        }
+    }
+
+    /// * Can the frame be merged, i.e. it has the same channels, sample rate and bits per sample.
+    fn accepts(&self, samples_info: &SamplesInfo) -> bool {
+        let batch = &self.samples_info;
+        batch.channels == samples_info.channels && batch.sample_rate == samples_info.sample_rate && batch.bits_per_sample == samples_info.bits_per_sample
+    }
+
+    fn push(&mut self, samples: &[Vec<i32>], samples_info: &SamplesInfo) {
+        match self.samples_info.audio_form {
+            FlacAudioForm::FrameArray => self.samples.extend(samples.iter().cloned()),
+            FlacAudioForm::ChannelArray => {
+                if self.samples.len() < samples.len() {
+                    self.samples.resize_with(samples.len(), Vec::new);
+                }
+                for (channel, samples) in self.samples.iter_mut().zip(samples) {
+                    channel.extend_from_slice(samples);
+                }
+            }
+        }
+        self.samples_info.samples += samples_info.samples;
+        self.frames += 1;
+    }
+}
+
+/// * The sample number of a placeholder seek point, the same as `FLAC__STREAM_METADATA_SEEKPOINT_PLACEHOLDER`
+const SEEK_POINT_PLACEHOLDER: u64 = u64::MAX;
+
+/// * The header of an audio frame, resolved by the decoder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct FlacFrameHeader {
+    pub blocksize: u32,
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub bits_per_sample: u32,
+    pub channel_assignment: FLAC__ChannelAssignment,
+}
+
+/// ## A seek point from the SEEKTABLE block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlacSeekPoint {
+    /// * The sample number of the first sample in the target frame.
+    pub sample_number: u64,
+
+    /// * The offset in bytes of the target frame from the first frame.
+    pub stream_offset: u64,
+
+    /// * The number of samples in the target frame.
+    pub frame_samples: u32,
+}
+
+/// ## The preset of the decoder settings, see `set_profile()`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecoderProfile {
+    /// * Use the settings as they are.
+    #[default]
+    Default,
+
+    /// * Get the first audio frame as soon as possible, e.g. for a live stream.
+    /// * The MD5 checking is disabled, the PICTURE and CUESHEET blocks are skipped.
+    /// * Your `on_seek()`, `on_tell()` and `on_length()` closures are never called, so the decoder can't seek, and can't tell the length or the decode position.
+    LowLatency,
+}
+
+/// ## The limits of the audio that the decoder accepts, to safeguard against the untrusted FLAC files, see `set_limits()`
+/// * `None` means no limit, the default has no limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecoderLimits {
+    /// * The max total samples per channel, checked against the STREAMINFO block and the position of every frame.
+    pub max_total_samples: Option<u64>,
+
+    /// * The max number of channels, checked against the STREAMINFO block and every frame header.
+    pub max_channels: Option<u32>,
+
+    /// * The max sample rate, checked against the STREAMINFO block and every frame header.
+    pub max_sample_rate: Option<u32>,
+}
+
+impl DecoderLimits {
+    /// * Check the STREAMINFO block, a `total_samples` of 0 means unknown and passes.
+    fn accepts_stream_info(&self, stream_info: &FlacStreamInfo) -> bool {
+        self.max_total_samples.is_none_or(|max| stream_info.total_samples <= max)
+            && self.max_channels.is_none_or(|max| stream_info.channels <= max)
+            && self.max_sample_rate.is_none_or(|max| stream_info.sample_rate <= max)
+    }
+
+    /// * Check a frame header, `end` is the sample position after the frame.
+    fn accepts_frame(&self, end: u64, channels: u32, sample_rate: u32) -> bool {
+        self.max_total_samples.is_none_or(|max| end <= max)
+            && self.max_channels.is_none_or(|max| channels <= max)
+            && self.max_sample_rate.is_none_or(|max| sample_rate <= max)
+    }
+}
+
+/// ## The type of a metadata block, for choosing which metadata blocks the decoder reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlacMetadataType {
+    StreamInfo,
+    Padding,
+    Application,
+    SeekTable,
+    VorbisComment,
+    CueSheet,
+    Picture,
+}
+
+impl FlacMetadataType {
+    /// * The `FLAC__MetadataType` value
+    pub fn as_raw(&self) -> u32 {
+        match self {
+            Self::StreamInfo => FLAC__METADATA_TYPE_STREAMINFO,
+            Self::Padding => FLAC__METADATA_TYPE_PADDING,
+            Self::Application => FLAC__METADATA_TYPE_APPLICATION,
+            Self::SeekTable => FLAC__METADATA_TYPE_SEEKTABLE,
+            Self::VorbisComment => FLAC__METADATA_TYPE_VORBIS_COMMENT,
+            Self::CueSheet => FLAC__METADATA_TYPE_CUESHEET,
+            Self::Picture => FLAC__METADATA_TYPE_PICTURE,
+        }
+    }
+}
+
+/// ## Which metadata block of the encoder, e.g. the second cue sheet, see `FlacEncoderInitError::failed_block`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MetadataBlockId {
+    /// * The type of the block
+    pub kind: FlacMetadataType,
+
+    /// * The index among the blocks of the same type in the order they were added, e.g. `1` for the second picture.
+    pub index: usize,
+}
+
+impl Display for MetadataBlockId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?} #{}", self.kind, self.index)
+    }
+}
+
+/// ## The longest metadata block in bytes without its 4-byte header that libFLAC can write
+/// * The length is a 24-bit field of the block header, but libFLAC writes the block with its header into a buffer of at most 16 MiB,
+///   so it's 3 bytes less than the 24-bit field could hold.
+pub const MAX_METADATA_BLOCK_LENGTH: usize = (1 << 24) - 4;
+
+/// ## A metadata block too large to be written, see `FlacEncoderInitError::too_large`
+/// * E.g. a picture larger than 16 MiB, or too many comments in the VORBIS_COMMENT block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataTooLarge {
+    /// * The type of the block
+    pub kind: FlacMetadataType,
+
+    /// * The length of the block in bytes, without the block header.
+    pub size: usize,
+
+    /// * The longest length allowed, `MAX_METADATA_BLOCK_LENGTH`
+    pub limit: usize,
+}
+
+impl MetadataTooLarge {
+    /// * `Err` if the block of the `size` is too large, as the error of the `function` for the block `id`.
+    fn check(id: MetadataBlockId, size: usize, function: &'static str) -> Result<(), FlacEncoderInitError> {
+        if size <= MAX_METADATA_BLOCK_LENGTH {
+            Ok(())
+        } else {
+            Err(FlacEncoderInitError {
+                failed_block: Some(id),
+                too_large: Some(Self {kind: id.kind, size, limit: MAX_METADATA_BLOCK_LENGTH}),
+                ..FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, function)
+            })
+        }
+    }
+}
+
+impl Display for MetadataTooLarge {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "The {:?} block is {} bytes, longer than {} bytes", self.kind, self.size, self.limit)
+    }
+}
+
+/// ## A sample that doesn't fit in the bits per sample of the encoder, see `FlacEncoderError::out_of_range`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleOutOfRange {
+    /// * The index of the sample in the interleaved samples, i.e. the audio frame times the channels plus the channel.
+    pub index: usize,
+
+    /// * The value of the sample
+    pub value: i32,
+
+    /// * The bits per sample of the encoder
+    pub bits_per_sample: u32,
+}
+
+impl Display for SampleOutOfRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "The sample {} at {} doesn't fit in {} bits", self.value, self.index, self.bits_per_sample)
+    }
+}
+
+/// ## How the total samples of the finished stream compare with the estimate, see `FlacEncoderUnmovable::finish_report()`
+/// * libFLAC writes the estimate into the STREAMINFO block first, then at `finish()` it seeks back to rewrite the block with the actual total samples and the MD5 sum.
+/// * The block is rewritten if your `on_seek()` closure works, or if `buffer_for_header` or `accurate_header` is set. If `on_seek()` returns `io::ErrorKind::NotSeekable`, it's left as is, with the estimate and without the MD5 sum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinishReport {
+    /// * The `total_samples_estimate` of the parameters, zero means unknown.
+    pub estimated: u64,
+
+    /// * The total samples per channel actually encoded.
+    pub actual: u64,
+
+    /// * Did libFLAC rewrite the STREAMINFO block with the actual total samples.
+    pub header_rewritten: bool,
+}
+
+impl FinishReport {
+    /// * Does the STREAMINFO block in the output tell the wrong total samples, i.e. the estimate was given, it was wrong, and the block wasn't rewritten.
+    /// * A zero estimate tells that the total samples is unknown, it's never wrong.
+    pub fn header_is_wrong(&self) -> bool {
+        !self.header_rewritten && self.estimated != 0 && self.estimated != self.actual
+    }
+
+    /// * How many samples per channel the estimate is off by.
+    pub fn difference(&self) -> u64 {
+        self.estimated.abs_diff(self.actual)
+    }
+}
+
+/// ## A metadata block of the FLAC file, see `DecodeEvent`
+#[derive(Debug, Clone)]
+pub enum MetadataBlock {
+    StreamInfo(FlacStreamInfo),
+
+    /// * The length of the padding in bytes
+    Padding(u32),
+
+    Application {
+        id: [u8; 4],
+        data: Vec<u8>,
+    },
+
+    /// * The seek points, the placeholder points are not included.
+    SeekTable(Vec<FlacSeekPoint>),
+
+    /// * The vendor string and the comments in their original order, the keys are not converted to uppercase.
+    VorbisComment {
+        vendor_string: String,
+        comments: Vec<(String, String)>,
+    },
+
+    CueSheet(FlacCueSheet),
+
+    Picture(PictureData),
+
+    /// * A block type that is unknown to libFLAC, with the raw data.
+    Unknown {
+        type_: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl MetadataBlock {
+    /// * The type of the block, `None` for the `Unknown` block.
+    pub fn kind(&self) -> Option<FlacMetadataType> {
+        match self {
+            Self::StreamInfo(_) => Some(FlacMetadataType::StreamInfo),
+            Self::Padding(_) => Some(FlacMetadataType::Padding),
+            Self::Application{..} => Some(FlacMetadataType::Application),
+            Self::SeekTable(_) => Some(FlacMetadataType::SeekTable),
+            Self::VorbisComment{..} => Some(FlacMetadataType::VorbisComment),
+            Self::CueSheet(_) => Some(FlacMetadataType::CueSheet),
+            Self::Picture(_) => Some(FlacMetadataType::Picture),
+            Self::Unknown{..} => None,
+        }
+    }
+
+    /// * Convert the metadata block from libFLAC into our own.
+    /// * The null arrays and strings are read as empty ones whatever the counts say, see `slice_or_empty()`.
+    pub(crate) unsafe fn from_raw(metadata: &FLAC__StreamMetadata) -> Self {
+        unsafe {
+            match metadata.type_ {
+                FLAC__METADATA_TYPE_STREAMINFO => Self::StreamInfo(metadata.data.stream_info.into()),
+                FLAC__METADATA_TYPE_PADDING => Self::Padding(metadata.length),
+                FLAC__METADATA_TYPE_APPLICATION => {
+                    let application = metadata.data.application;
+                    let length = metadata.length.saturating_sub(4) as usize;
+                    Self::Application {
+                        id: application.id,
+                        data: slice_or_empty(application.data, length).to_vec(),
+                    }
+                },
+                FLAC__METADATA_TYPE_SEEKTABLE => {
+                    let seek_table = metadata.data.seek_table;
+                    Self::SeekTable(slice_or_empty(seek_table.points, seek_table.num_points as usize).iter()
+                        .filter(|point| point.sample_number != SEEK_POINT_PLACEHOLDER)
+                        .map(|point| FlacSeekPoint {
+                            sample_number: point.sample_number,
+                            stream_offset: point.stream_offset,
+                            frame_samples: point.frame_samples,
+                        }).collect())
+                },
+                FLAC__METADATA_TYPE_VORBIS_COMMENT => {
+                    let comments = metadata.data.vorbis_comment;
+                    Self::VorbisComment {
+                        vendor_string: entry_to_string(&comments.vendor_string),
+                        comments: slice_or_empty(comments.comments, comments.num_comments as usize).iter().map(|entry| {
+                            let comment = entry_to_string(entry);
+
+                            // The key pair is split by the equal notation, ignore the later equal notations.
+                            match comment.split_once('=') {
+                                Some((key, val)) => (key.to_owned(), val.to_owned()),
+                                None => (comment, String::new()),
+                            }
+                        }).collect(),
+                    }
+                },
+                FLAC__METADATA_TYPE_CUESHEET => {
+                    let cue_sheet = metadata.data.cue_sheet;
+                    Self::CueSheet(FlacCueSheet{
+                        media_catalog_number: cue_sheet.media_catalog_number,
+                        lead_in: cue_sheet.lead_in,
+                        is_cd: cue_sheet.is_cd != 0,
+                        tracks: slice_or_empty(cue_sheet.tracks, cue_sheet.num_tracks as usize).iter().map(|&track| -> (u8, FlacCueTrack) {
+                            (track.number, FlacCueTrack {
+                                offset: track.offset,
+                                track_no: track.number,
+                                isrc: track.isrc,
+                                type_: match track.type_() {
+                                    0 => FlacTrackType::Audio,
+                                    _ => FlacTrackType::NonAudio,
+                                },
+                                pre_emphasis: track.pre_emphasis() != 0,
+                                indices: slice_or_empty(track.indices, track.num_indices as usize).iter().map(|index| -> FlacCueSheetIndex {
+                                    FlacCueSheetIndex {
+                                        offset: index.offset,
+                                        number: index.number,
+                                    }
+                                }).collect()
+                            })
+                        }).collect(),
+                    })
+                },
+                FLAC__METADATA_TYPE_PICTURE => {
+                    let picture = metadata.data.picture;
+                    Self::Picture(PictureData{
+                        picture: slice_or_empty(picture.data, picture.data_length as usize).to_vec(),
+                        picture_type: FlacPictureType::from(picture.type_),
+                        description: cstr_or_empty(picture.description as *const i8),
+                        mime_type: cstr_or_empty(picture.mime_type),
+                        width: picture.width,
+                        height: picture.height,
+                        depth: picture.depth,
+                        colors: picture.colors,
+                    })
+                },
+                type_ => {
+                    let unknown = metadata.data.unknown;
+                    Self::Unknown {
+                        type_,
+                        data: slice_or_empty(unknown.data, metadata.length as usize).to_vec(),
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// ## An event from `next_event()`, the metadata blocks and the audio frames in the order they appear in the FLAC file.
+#[derive(Debug, Clone)]
+pub enum DecodeEvent {
+    Metadata(MetadataBlock),
+
+    /// * The samples of an audio frame in the form you chose, and the samples info.
+    Audio(Vec<Vec<i32>>, SamplesInfo),
+
+    /// * There are no more events, this is returned for every later call.
+    EndOfStream,
+}
+
+/// ## What one `decode()` call got
+/// * Unlike `DecodeEvent`, the samples are still passed to your `on_write()` closure, this just tells what happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeStep {
+    /// * An audio frame of `samples` samples per channel was decoded.
+    AudioFrame {
+        samples: u32,
+    },
+
+    /// * A metadata block of the type was read.
+    Metadata(FlacMetadataType),
+
+    /// * A metadata block of the type unknown to libFLAC was read, with the raw type.
+    UnknownMetadata(u32),
+
+    /// * The decoder reached the end of the stream.
+    EndOfStream,
+
+    /// * Nothing was delivered, e.g. a metadata block not in `set_metadata_respond()` was skipped, or the decoder couldn't process.
+    NothingDone,
+}
+
+/// ## How `decode_all()` ended
+/// * The `samples` are the samples per channel decoded by this `decode_all()` call.
+#[derive(Debug, Clone, Copy)]
+#[must_use = "the decoding may have failed or stopped before the end, check the outcome or use `into_result()`"]
+pub enum DecodeOutcome {
+    /// * The decoder reached the end of the stream with all of the samples the STREAMINFO block declared, or the total samples is unknown.
+    Completed {
+        samples: u64,
+    },
+
+    /// * Your `on_write()` closure returned an error, or the cancellation token was set.
+    StoppedByCaller {
+        samples: u64,
+    },
+
+    /// * The decoder reached the end of the stream before the total samples the STREAMINFO block declared, e.g. an interrupted download.
+    Truncated {
+        samples: u64,
+    },
+
+    /// * The decoding failed, e.g. the read error, or the limits set by `set_limits()` were exceeded.
+    Failed(FlacDecoderError),
+}
+
+impl DecodeOutcome {
+    /// * The samples per channel decoded, zero for `Failed`.
+    pub fn samples(&self) -> u64 {
+        match self {
+            Self::Completed{samples} | Self::StoppedByCaller{samples} | Self::Truncated{samples} => *samples,
+            Self::Failed(_) => 0,
+        }
+    }
+
+    /// * Was the stream decoded to the end with all of the samples.
+    pub fn is_completed(&self) -> bool {
+        matches!(self, Self::Completed{..})
+    }
+
+    /// * `Completed` into the samples decoded, the other outcomes into an error, for using `?` when only the complete decoding counts.
+    /// * `Truncated` is `FLAC_DECODER_TRUNCATED`, `StoppedByCaller` is `FLAC__STREAM_DECODER_ABORTED`. Match the outcome instead to accept them.
+    pub fn into_result(self) -> Result<u64, FlacDecoderError> {
+        match self {
+            Self::Completed{samples} => Ok(samples),
+            Self::StoppedByCaller{..} => Err(FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "DecodeOutcome::into_result")),
+            Self::Truncated{..} => Err(FlacDecoderError::new(FLAC_DECODER_TRUNCATED, "DecodeOutcome::into_result")),
+            Self::Failed(e) => Err(e),
+        }
+    }
+}
+
+/// ## The iterator over the audio frames from `audio_frames()`, the metadata blocks are skipped.
+/// * Each item is the samples of an audio frame in the form you chose, and the samples info. The iteration stops after an error.
+pub struct AudioFrames<'d, 'a, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    decoder: &'d mut FlacDecoderUnmovable<'a, ReadSeek>,
+    ended: bool,
+}
+
+impl<ReadSeek> Iterator for AudioFrames<'_, '_, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    type Item = Result<(Vec<Vec<i32>>, SamplesInfo), FlacDecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.ended {
+            match self.decoder.next_event() {
+                Ok(DecodeEvent::Metadata(_)) => (),
+                Ok(DecodeEvent::Audio(samples, samples_info)) => return Some(Ok((samples, samples_info))),
+                Ok(DecodeEvent::EndOfStream) => self.ended = true,
+                Err(e) => {
+                    self.ended = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<ReadSeek> Debug for AudioFrames<'_, '_, ReadSeek>
+where
+    ReadSeek: Read + Seek + Debug {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("AudioFrames")
+            .field("decoder", &self.decoder)
+            .field("ended", &self.ended)
+            .finish()
+    }
+}
+
+/// ## The STREAMINFO block of the FLAC file, the format of the audio and how it's framed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlacStreamInfo {
+    /// * The minimum block size in samples used in the stream.
+    pub min_blocksize: u32,
+
+    /// * The maximum block size in samples used in the stream.
+    pub max_blocksize: u32,
+
+    /// * The minimum frame size in bytes used in the stream, zero means unknown.
+    pub min_framesize: u32,
+
+    /// * The maximum frame size in bytes used in the stream, zero means unknown.
+    pub max_framesize: u32,
+
+    /// * The sample rate in Hz.
+    pub sample_rate: u32,
+
+    /// * The number of channels.
+    pub channels: u32,
+
+    /// * The bits per sample.
+    pub bits_per_sample: u32,
+
+    /// * The total samples per channel in the stream, zero means unknown.
+    pub total_samples: u64,
+
+    /// * The MD5 signature of the unencoded audio data, all zero means unknown.
+    pub md5sum: [u8; 16],
+}
+
+impl FlacStreamInfo {
+    /// * The total samples per channel, `None` if it's unknown, e.g. the FLAC file was encoded from a live stream.
+    pub fn known_total_samples(&self) -> Option<u64> {
+        (self.total_samples > 0).then_some(self.total_samples)
+    }
+
+    /// * The MD5 signature as lowercase hex, for displaying or comparing against the external checksums. `None` if it's all zero, i.e. unknown.
+    pub fn md5_hex(&self) -> Option<String> {
+        self.md5sum.iter().any(|&byte| byte != 0).then(|| to_hex(&self.md5sum))
+    }
+
+    /// * The duration of the audio, `None` if the total samples or the sample rate is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        if self.sample_rate == 0 {
+            return None;
+        }
+        let total_samples = self.known_total_samples()?;
+        Some(Duration::from_nanos((total_samples as u128 * 1_000_000_000 / self.sample_rate as u128) as u64))
+    }
+}
+
+impl From<FLAC__StreamMetadata_StreamInfo> for FlacStreamInfo {
+    fn from(stream_info: FLAC__StreamMetadata_StreamInfo) -> Self {
+        Self {
+            min_blocksize: stream_info.min_blocksize,
+            max_blocksize: stream_info.max_blocksize,
+            min_framesize: stream_info.min_framesize,
+            max_framesize: stream_info.max_framesize,
+            sample_rate: stream_info.sample_rate,
+            channels: stream_info.channels,
+            bits_per_sample: stream_info.bits_per_sample,
+            total_samples: stream_info.total_samples,
+            md5sum: stream_info.md5sum,
+        }
+    }
+}
+
+fn entry_to_str(entry: &FLAC__StreamMetadata_VorbisComment_Entry) -> Cow<'_, str> {
+    unsafe{String::from_utf8_lossy(slice_or_empty(entry.entry, entry.length as usize))}
+}
+
+/// * The array of `len` elements from libFLAC, the pointer may be null with a non-zero count from a crafted or corrupt file, then it's empty.
+unsafe fn slice_or_empty<'a, T>(data: *const T, len: usize) -> &'a [T] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        unsafe {slice::from_raw_parts(data, len)}
+    }
+}
+
+/// * The NUL-terminated string from libFLAC, empty if the pointer is null.
+unsafe fn cstr_or_empty(ptr: *const i8) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        unsafe {CStr::from_ptr(ptr)}.to_string_lossy().to_string()
+    }
+}
+
+/// * Get the length of the `reader` by seeking to the end of it, then seek back.
+fn get_reader_length<ReadSeek: Seek>(reader: &mut ReadSeek) -> Result<u64, io::Error> {
+    let position = reader.stream_position()?;
+    let length = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+    Ok(length)
+}
+
+fn entry_to_string(entry: &FLAC__StreamMetadata_VorbisComment_Entry) -> String {
+    entry_to_str(entry).to_string()
 }
 
 /// ## The decoder's core structure, but can't move after `initialize()` has been called.
 /// Use a `Box` to contain it, or just don't move it will be fine.
 pub struct FlacDecoderUnmovable<'a, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     /// * See <https://xiph.org/flac/api/group__flac__stream__decoder.html>
     decoder: *mut FLAC__StreamDecoder,
 
@@ -1622,11 +4062,42 @@ where
     on_eof: Box<dyn FnMut(&mut ReadSeek) -> bool + 'a>,
 
     /// * Your `on_write()` closure, it's not for you to "write", but it's the decoder returns the decoded samples for you to use.
+    /// * The samples are in a buffer that's reused for the next frame, copy them out if you need to keep them, the decoding doesn't allocate memory for each frame.
     on_write: Box<dyn FnMut(&[Vec<i32>], &SamplesInfo) -> Result<(), io::Error> + 'a>,
 
     /// * Your `on_error()` closure. Normally it won't be called.
     on_error: Box<dyn FnMut(FlacInternalDecoderError) + 'a>,
 
+    /// * Your optional `on_end_of_stream()` closure, called once when the decoder reaches the end of the stream.
+    on_end_of_stream: Option<Box<dyn FnMut() + 'a>>,
+
+    /// * Was your `on_end_of_stream()` closure called.
+    end_of_stream_notified: bool,
+
+    /// * Your optional `on_error_with_context()` closure, called instead of your `on_error()` closure if set.
+    on_error_with_context: Option<Box<dyn FnMut(&DecodeErrorContext) + 'a>>,
+
+    /// * How many frames were decoded, for `DecodeErrorContext`.
+    frames_decoded: u64,
+
+    /// * How many samples per channel were decoded, for `DecodeOutcome`.
+    samples_decoded: u64,
+
+    /// * The sample position right after the last decoded frame, for `DecodeErrorContext`.
+    last_frame_end: Option<u64>,
+
+    /// * The sample number and the byte offset of the last decoded frame, see `last_frame_position()`.
+    last_frame_position: Option<(u64, u64)>,
+
+    /// * The byte offset where the next frame starts, known after the metadata or right after a frame, `None` after a seek or a lost sync.
+    next_frame_byte: Option<u64>,
+
+    /// * Does the stream use the variable block size, `None` if no frame was decoded yet.
+    variable_blocksize: Option<bool>,
+
+    /// * The block sizes of the first and the last decoded frames, to tell the variable block size.
+    frame_blocksizes: Option<(u32, u32)>,
+
     /// * Set to true to let the decoder check the MD5 sum of the decoded samples.
     md5_checking: bool,
 
@@ -1636,6 +4107,9 @@ where
     /// * Scale to `i32` range or not, if set to true, the sample will be scaled to the whole range of `i32` [-2147483648, +2147483647] if bits per sample is not 32.
     pub scale_to_i32_range: bool,
 
+    /// * How the samples are scaled if `scale_to_i32_range` is set, the default is `I32Scaling::BitReplication`.
+    pub i32_scaling: I32Scaling,
+
     /// * The desired form of audio you want to receive.
     pub desired_audio_form: FlacAudioForm,
 
@@ -1650,11 +4124,110 @@ where
 
     /// * The cue sheets read from the FLAC file.
     pub cue_sheets: Vec<FlacCueSheet>,
+
+    /// * The APPLICATION blocks read from the FLAC file.
+    pub applications: Vec<FlacApplication>,
+
+    /// * The seek points read from the FLAC file, without the placeholder points.
+    pub seek_points: Vec<FlacSeekPoint>,
+
+    /// * The STREAMINFO block read from the FLAC file.
+    stream_info: Option<FlacStreamInfo>,
+
+    /// * The cancellation token, the decoder checks it before every read.
+    cancel_token: Option<Arc<AtomicBool>>,
+
+    /// * Did the read callback abort the decoding because of the cancellation token.
+    cancelled: bool,
+
+    /// * Did your `on_write()` closure return an error to stop the decoding.
+    stopped_by_caller: bool,
+
+    /// * The limits of the audio that the decoder accepts.
+    limits: DecoderLimits,
+
+    /// * The crate-specific error code that aborted the decoding from the callbacks, e.g. `FLAC_DECODER_LIMIT_EXCEEDED`.
+    aborted_by: Option<u32>,
+
+    /// * The channels to deliver to your `on_write()` closure in order, `None` for all of the channels.
+    channels_wanted: Option<Vec<usize>>,
+
+    /// * While scanning, the decoded frames are captured here with their sample positions instead of being passed to your `on_write()` closure.
+    captured_frames: Option<Vec<(u64, Vec<Vec<i32>>, SamplesInfo)>>,
+
+    /// * Was some audio skipped by `scan()`, then the MD5 checking can't be done.
+    audio_skipped: bool,
+
+    /// * The samples decoded by `read_at()` beyond the returned range: their start position, the channel arrays, and `frames_decoded` when they were kept.
+    read_ahead: Option<(u64, Vec<Vec<i32>>, u64)>,
+
+    /// * How many frames `decode_all()` decodes before the first call to your `on_write()` closure.
+    prebuffer_frames: usize,
+
+    /// * How many frames are merged into one call to your `on_write()` closure, see `set_batch_frames()`.
+    batch_frames: usize,
+    batch: Option<FrameBatch>,
+
+    /// * The first sample and the CRC-16 of each decoded frame, if `set_collect_frame_crcs()` was set.
+    collect_frame_crcs: bool,
+    frame_crcs: Vec<(u64, u16)>,
+
+    /// * Where the frame of the last record of `frame_crcs` ends.
+    frame_crc_end: Option<u64>,
+
+    /// * The bytes libFLAC read from the `reader` since the last recorded frame, for `frame_crcs` without reading them again.
+    frame_tee: Vec<u8>,
+
+    /// * The byte position in the FLAC file where `frame_tee` ends, known after libFLAC tells or seeks the `reader`.
+    frame_tee_end: Option<u64>,
+
+    /// * The total samples counted by `total_samples()` when the STREAMINFO block doesn't know it.
+    counted_total_samples: Option<u64>,
+
+    /// * While `total_samples()` rewinds to the first frame, the metadata blocks are read again by libFLAC but they were already kept.
+    replaying_metadata: bool,
+
+    /// * While `next_event()` is decoding, the metadata blocks and the audio frames are queued here instead of being passed to your `on_write()` closure.
+    capture_events: bool,
+    events: VecDeque<DecodeEvent>,
+
+    /// * What the callbacks got during the last `decode()`.
+    last_step: Option<DecodeStep>,
+
+    /// * The statistics of the calls to your IO closures, if enabled.
+    io_stats: Option<IoStats>,
+
+    /// * The reusable buffer of the decoded samples passed to your `on_write()` closure, it only grows, so the steady-state decoding doesn't allocate.
+    output_buffer: Vec<Vec<i32>>,
+
+    /// * The types of the metadata blocks to read, `None` for all of them.
+    metadata_respond: Option<Vec<FlacMetadataType>>,
+
+    /// * Read the STREAMINFO block or not, see `set_stream_info_respond()`.
+    stream_info_respond: bool,
+
+    /// * `decode_metadata()` stops after the first picture of this type.
+    stop_after_picture: Option<FlacPictureType>,
+
+    /// * Did the picture for `stop_after_picture` show up.
+    picture_found: bool,
+
+    /// * The preset of the settings, applied in `initialize()`.
+    profile: DecoderProfile,
+
+    /// * The container of the FLAC stream to decode, applied in `initialize()`.
+    container: FlacContainer,
+
+    /// * The length of the `reader` shared with the default `on_length()` and `on_eof()` closures, updated by `reset_with_reader()`.
+    default_io_length: Option<Arc<AtomicU64>>,
+
+    /// * The `client_data` of the callbacks, see `ClientData`.
+    client_data: ClientData,
 }
 
 impl<'a, ReadSeek> FlacDecoderUnmovable<'a, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     pub fn new(
         reader: ReadSeek,
         on_read: Box<dyn FnMut(&mut ReadSeek, &mut [u8]) -> (usize, FlacReadStatus) + 'a>,
@@ -1678,14 +4251,60 @@ where
             on_eof,
             on_write,
             on_error,
+            on_end_of_stream: None,
+            end_of_stream_notified: false,
+            on_error_with_context: None,
+            frames_decoded: 0,
+            samples_decoded: 0,
+            last_frame_end: None,
+            last_frame_position: None,
+            next_frame_byte: None,
+            variable_blocksize: None,
+            frame_blocksizes: None,
             md5_checking,
             finished: false,
             scale_to_i32_range,
+            i32_scaling: I32Scaling::default(),
             desired_audio_form,
             vendor_string: None,
             comments: BTreeMap::new(),
             pictures: Vec::<PictureData>::new(),
             cue_sheets: Vec::<FlacCueSheet>::new(),
+            applications: Vec::<FlacApplication>::new(),
+            seek_points: Vec::<FlacSeekPoint>::new(),
+            stream_info: None,
+            cancel_token: None,
+            cancelled: false,
+            stopped_by_caller: false,
+            limits: DecoderLimits::default(),
+            aborted_by: None,
+            channels_wanted: None,
+            captured_frames: None,
+            prebuffer_frames: 0,
+            batch_frames: 0,
+            batch: None,
+            collect_frame_crcs: false,
+            frame_crcs: Vec::new(),
+            frame_crc_end: None,
+            frame_tee: Vec::new(),
+            frame_tee_end: None,
+            counted_total_samples: None,
+            replaying_metadata: false,
+            audio_skipped: false,
+            read_ahead: None,
+            capture_events: false,
+            events: VecDeque::new(),
+            last_step: None,
+            io_stats: None,
+            output_buffer: Vec::new(),
+            metadata_respond: None,
+            stream_info_respond: true,
+            stop_after_picture: None,
+            picture_found: false,
+            profile: DecoderProfile::Default,
+            container: FlacContainer::Native,
+            default_io_length: None,
+            client_data: ClientData::new(),
         };
         if ret.decoder.is_null() {
             Err(FlacDecoderError::new(FLAC__STREAM_DECODER_MEMORY_ALLOCATION_ERROR, "FLAC__stream_decoder_new"))
@@ -1716,13 +4335,32 @@ where
         self as *mut Self
     }
 
+    /// * Point the callbacks to `self`, then call into libFLAC with the decoder. Every call that may call back must go through it, see `ClientData`.
+    fn call_libflac<R>(&mut self, f: impl FnOnce(*mut FLAC__StreamDecoder) -> R) -> R {
+        let decoder = self.decoder;
+        let this = self as *mut Self;
+        let previous = self.client_data.bind(this);
+        let ret = f(decoder);
+        self.client_data.restore(previous);
+        ret
+    }
+
     unsafe extern "C" fn read_callback(_decoder: *const FLAC__StreamDecoder, buffer: *mut u8, bytes: *mut usize, client_data: *mut c_void) -> u32 {
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        if unsafe {*bytes} == 0 {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        if this.cancel_token.as_ref().is_some_and(|token| token.load(Ordering::Relaxed)) {
+            this.cancelled = true;
+            unsafe {*bytes = 0};
+            FLAC__STREAM_DECODER_READ_STATUS_ABORT
+        } else if this.aborted_by.is_some() || unsafe {*bytes} == 0 {
             FLAC__STREAM_DECODER_READ_STATUS_ABORT
         } else {
             let buf = unsafe {slice::from_raw_parts_mut(buffer, *bytes)};
             let (bytes_read, status) = (this.on_read)(&mut this.reader, buf);
+            if let Some(io_stats) = this.io_stats.as_mut() {io_stats.record_read(bytes_read)}
+            if this.collect_frame_crcs {
+                this.frame_tee.extend_from_slice(&buf[..bytes_read.min(buf.len())]);
+                this.frame_tee_end = this.frame_tee_end.map(|end| end + bytes_read as u64);
+            }
             let ret = match status{
                 FlacReadStatus::GoOn => FLAC__STREAM_DECODER_READ_STATUS_CONTINUE,
                 FlacReadStatus::Eof => FLAC__STREAM_DECODER_READ_STATUS_END_OF_STREAM,
@@ -1735,8 +4373,8 @@ where
     }
 
     unsafe extern "C" fn seek_callback(_decoder: *const FLAC__StreamDecoder, absolute_byte_offset: u64, client_data: *mut c_void) -> u32 {
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        match (this.on_seek)(&mut this.reader, absolute_byte_offset) {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        match this.call_on_seek(absolute_byte_offset) {
             Ok(_) => FLAC__STREAM_DECODER_SEEK_STATUS_OK,
             Err(e) => {
                 match e.kind() {
@@ -1748,9 +4386,11 @@ where
     }
 
     unsafe extern "C" fn tell_callback(_decoder: *const FLAC__StreamDecoder, absolute_byte_offset: *mut u64, client_data: *mut c_void) -> u32 {
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        match (this.on_tell)(&mut this.reader) {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        match this.call_on_tell() {
             Ok(offset) => {
+                // The read position is where the teed bytes end.
+                this.frame_tee_end = Some(offset);
                 unsafe {*absolute_byte_offset = offset};
                 FLAC__STREAM_DECODER_TELL_STATUS_OK
             },
@@ -1764,7 +4404,7 @@ where
     }
 
     unsafe extern "C" fn length_callback(_decoder: *const FLAC__StreamDecoder, stream_length: *mut u64, client_data: *mut c_void) -> u32 {
-        let this = unsafe {&mut *(client_data as *mut Self)};
+        let this = unsafe {ClientData::target::<Self>(client_data)};
         match (this.on_length)(&mut this.reader) {
             Ok(length) => {
                 unsafe {*stream_length = length};
@@ -1780,126 +4420,261 @@ where
     }
 
     unsafe extern "C" fn eof_callback(_decoder: *const FLAC__StreamDecoder, client_data: *mut c_void) -> i32 {
-        let this = unsafe {&mut *(client_data as *mut Self)};
+        let this = unsafe {ClientData::target::<Self>(client_data)};
         if (this.on_eof)(&mut this.reader) {1} else {0}
     }
 
     unsafe extern "C" fn write_callback(_decoder: *const FLAC__StreamDecoder, frame: *const FLAC__Frame, buffer: *const *const i32, client_data: *mut c_void) -> u32 {
-        // Scales signed PCM samples to full i32 dynamic range.
-        // - `bits`: Valid bits in `sample` (1-32).
-        // - Example: 8-bit samples [-128, 127] → [i32::MIN, i32::MAX]
-        fn scale_to_i32(sample: i32, bits: u32) -> i32 {
-            assert!(bits <= 32);
-            if bits == 32 {
-                sample
-            } else {
-                fn scale_to_unsigned(sample: i32, bits: u32) -> u32 {
-                    let mask = (1u32 << bits) - 1;
-                    let mid_number = 1u32 << (bits - 1);
-                    ((sample as u32).wrapping_add(mid_number) & mask) << (32 - bits)
-                }
-                let mut lower_fill = scale_to_unsigned(sample, bits);
-                let mut result = (sample as u32) << (32 - bits);
-                while lower_fill > 0 {
-                    lower_fill >>= bits;
-                    result |= lower_fill;
-                }
-                result as i32
-            }
-        }
-
-        let this = unsafe {&mut *(client_data as *mut Self)};
+        let this = unsafe {ClientData::target::<Self>(client_data)};
         let frame = unsafe {*frame};
         let samples = frame.header.blocksize;
         let channels = frame.header.channels;
         let sample_rate = frame.header.sample_rate;
         let bits_per_sample = frame.header.bits_per_sample;
+        let position = match frame.header.number_type {
+            FLAC__FRAME_NUMBER_TYPE_SAMPLE_NUMBER => unsafe {frame.header.number.sample_number},
+            _ => (unsafe {frame.header.number.frame_number}) as u64 * samples as u64,
+        };
+        this.update_variable_blocksize(samples);
+
+        // Check the limits and the wanted channels before the buffer grows for the frame.
+        if this.aborted_by.is_none() && !this.limits.accepts_frame(position + samples as u64, channels, sample_rate) {
+            this.aborted_by = Some(FLAC_DECODER_LIMIT_EXCEEDED);
+        }
+        if this.aborted_by.is_none() && !this.accepts_channels(channels) {
+            this.aborted_by = Some(FLAC_DECODER_CHANNEL_OUT_OF_RANGE);
+        }
+        if this.aborted_by.is_some() {
+            return FLAC__STREAM_DECODER_WRITE_STATUS_ABORT;
+        }
+        this.frames_decoded += 1;
+        this.samples_decoded += samples as u64;
+        this.last_frame_end = Some(position + samples as u64);
+
+        // The decode position is right after the frame in the write callback, it's where the next frame starts.
+        // It calls back to the tell callback, so it goes through `decode_position()` to bind `this` as the target.
+        let frame_end_byte = this.decode_position().ok();
+        if this.collect_frame_crcs && let Some(frame_end_byte) = frame_end_byte {
+            this.record_frame_crc(position, frame_end_byte);
+        }
+        this.last_frame_position = this.next_frame_byte.map(|frame_byte| (position, frame_byte));
+        this.next_frame_byte = frame_end_byte;
 
         let mut samples_info = SamplesInfo {
             samples,
-            channels,
+            channels: this.channels_wanted.as_ref().map_or(channels, |channels_wanted| channels_wanted.len() as u32),
             sample_rate,
             bits_per_sample,
             audio_form: this.desired_audio_form,
         };
 
-        let mut ret: Vec<Vec<i32>>;
-        match this.desired_audio_form {
-            FlacAudioForm::FrameArray => {
-                // Each `frame` contains one sample for each channel
-                ret = vec![Vec::<i32>::new(); samples as usize];
-                for s in 0..samples {
-                    for c in 0..channels {
-                        let channel = unsafe {*buffer.add(c as usize)};
-                        ret[s as usize].push(unsafe {*channel.add(s as usize)});
-                    }
-                }
-            },
-            FlacAudioForm::ChannelArray => {
-                // Each `channel` contains all samples for the channel
-                ret = vec![Vec::<i32>::new(); channels as usize];
-                for c in 0..channels {
-                    ret[c as usize] = unsafe {slice::from_raw_parts(*buffer.add(c as usize), samples as usize)}.to_vec();
-                }
-            }
+        // The samples are decoded into the reusable buffer, it only grows when a larger block size or more channels appear.
+        let outer_len = samples_info.outer_len();
+        if this.output_buffer.len() < outer_len {
+            this.output_buffer.resize_with(outer_len, Vec::new);
+        }
+        let ret = &mut this.output_buffer[..outer_len];
+        match this.channels_wanted.as_ref() {
+            Some(channels_wanted) => unsafe {Self::copy_channels(ret, buffer, samples as usize, this.desired_audio_form, channels_wanted.iter().copied())},
+            None => unsafe {Self::copy_channels(ret, buffer, samples as usize, this.desired_audio_form, 0..channels as usize)},
         }
 
         // Whatever it was, now it's just a two-dimensional array
         if this.scale_to_i32_range {
             for x in ret.iter_mut() {
-                for y in x.iter_mut() {
-                    *y = scale_to_i32(*y, bits_per_sample);
-                }
+                scale_slice_to_i32_by(x, bits_per_sample, this.i32_scaling);
             }
             samples_info.bits_per_sample = 32;
         }
 
-        match (this.on_write)(&ret, &samples_info) {
+        this.last_step = Some(DecodeStep::AudioFrame{samples});
+        if this.capture_events {
+            this.events.push_back(DecodeEvent::Audio(ret.to_vec(), samples_info));
+            return FLAC__STREAM_DECODER_WRITE_STATUS_CONTINUE;
+        }
+
+        if let Some(captured_frames) = this.captured_frames.as_mut() {
+            captured_frames.push((position, ret.to_vec(), samples_info));
+            return FLAC__STREAM_DECODER_WRITE_STATUS_CONTINUE;
+        }
+
+        // The buffer is taken out while writing, the batch needs `this` too.
+        let output_buffer = std::mem::take(&mut this.output_buffer);
+        let result = this.write_samples(&output_buffer[..outer_len], samples_info);
+        this.output_buffer = output_buffer;
+        match result {
             Ok(_) => FLAC__STREAM_DECODER_WRITE_STATUS_CONTINUE,
             Err(e) => {
                 eprintln!("On `write_callback()`: {:?}", e);
+                this.stopped_by_caller = true;
                 FLAC__STREAM_DECODER_WRITE_STATUS_ABORT
             },
         }
     }
 
+    /// * Record the CRC-16 of the decoded frame that ends at `frame_end_byte`, libFLAC already checked it against the footer, so it's taken from the footer.
+    /// * After a corrupted frame, libFLAC passes the silence in place of it before the next frame, with the same `frame_end_byte` as the next frame.
+    ///   The silence isn't a frame in the FLAC file, the next frame takes its record over.
+    fn record_frame_crc(&mut self, position: u64, frame_end_byte: u64) {
+        if self.frame_crc_end == Some(frame_end_byte) {
+            if let Some((first_sample, _)) = self.frame_crcs.last_mut() {
+                *first_sample = position;
+            }
+            return;
+        }
+        if let Some(footer) = self.teed_bytes(frame_end_byte.saturating_sub(2), frame_end_byte) {
+            self.frame_crcs.push((position, u16::from_be_bytes([footer[0], footer[1]])));
+            self.frame_crc_end = Some(frame_end_byte);
+        }
+        self.drop_teed_bytes(frame_end_byte);
+    }
+
+    /// * Record the CRC-16 of the corrupted frame dropped by libFLAC, hashed from the raw bytes between `frame_byte` and `frame_end_byte` without the footer.
+    /// * It starts after the last decoded frame. It differs from the footer, since it's the footer check that failed.
+    fn record_corrupted_frame_crc(&mut self, frame_byte: u64, frame_end_byte: u64) {
+        if let Some(bytes) = self.teed_bytes(frame_byte, frame_end_byte) {
+            let crc = crc16(&bytes[..bytes.len().saturating_sub(2)]);
+            self.frame_crcs.push((self.last_frame_end.unwrap_or(0), crc));
+            self.frame_crc_end = Some(frame_end_byte);
+        }
+        self.drop_teed_bytes(frame_end_byte);
+    }
+
+    /// * The bytes from `from` to `to` of the FLAC file that libFLAC already read, `None` if they weren't teed, e.g. `set_collect_frame_crcs()` was set in the middle of the frame.
+    fn teed_bytes(&self, from: u64, to: u64) -> Option<&[u8]> {
+        let start = self.frame_tee_end?.checked_sub(self.frame_tee.len() as u64)?;
+        let from = from.checked_sub(start)? as usize;
+        let to = to.checked_sub(start)? as usize;
+        self.frame_tee.get(from..to).filter(|bytes| !bytes.is_empty())
+    }
+
+    /// * Drop the teed bytes before `position`, the bytes after it are read ahead by libFLAC for the next frames.
+    fn drop_teed_bytes(&mut self, position: u64) {
+        match self.frame_tee_end {
+            Some(end) if position <= end => {
+                let keep = (end - position) as usize;
+                self.frame_tee.drain(..self.frame_tee.len().saturating_sub(keep));
+            }
+            _ => self.frame_tee.clear(),
+        }
+    }
+
+    /// * Pass the samples to your `on_write()` closure, or merge them into the batch if `set_batch_frames()` was set.
+    fn write_samples(&mut self, samples: &[Vec<i32>], samples_info: SamplesInfo) -> Result<(), io::Error> {
+        if self.batch_frames <= 1 {
+            self.flush_batch()?;
+            return (self.on_write)(samples, &samples_info);
+        }
+        if self.batch.as_ref().is_some_and(|batch| !batch.accepts(&samples_info)) {
+            self.flush_batch()?;
+        }
+        let batch = self.batch.get_or_insert_with(|| FrameBatch::new(samples_info));
+        batch.push(samples, &samples_info);
+        if batch.frames >= self.batch_frames {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    /// * Pass the merged frames of the batch to your `on_write()` closure, if any.
+    fn flush_batch(&mut self) -> Result<(), io::Error> {
+        match self.batch.take() {
+            Some(batch) => (self.on_write)(&batch.samples, &batch.samples_info),
+            None => Ok(()),
+        }
+    }
+
+    /// * Copy the channels of `channel_indices` from libFLAC's `buffer` in the audio form, the other channels are not touched.
+    unsafe fn copy_channels<I>(ret: &mut [Vec<i32>], buffer: *const *const i32, samples: usize, audio_form: FlacAudioForm, channel_indices: I)
+    where
+        I: Iterator<Item = usize> + Clone {
+        match audio_form {
+            FlacAudioForm::FrameArray => {
+                // Each `frame` contains one sample for each channel
+                for (s, frame) in ret.iter_mut().enumerate() {
+                    frame.clear();
+                    for c in channel_indices.clone() {
+                        let channel = unsafe {*buffer.add(c)};
+                        frame.push(unsafe {*channel.add(s)});
+                    }
+                }
+            },
+            FlacAudioForm::ChannelArray => {
+                // Each `channel` contains all samples for the channel
+                for (channel, c) in ret.iter_mut().zip(channel_indices) {
+                    channel.clear();
+                    channel.extend_from_slice(unsafe {slice::from_raw_parts(*buffer.add(c), samples)});
+                }
+            }
+        }
+    }
+
+    /// * libFLAC turns the frame numbers into the sample numbers before `write_callback()`, so the blocking strategy of the frame headers can't be seen.
+    ///   Instead, the stream uses the variable block size if the STREAMINFO block says the min and the max block sizes differ,
+    ///   or if a frame that isn't the last one has a different block size than the first frame.
+    fn update_variable_blocksize(&mut self, blocksize: u32) {
+        let from_stream_info = self.stream_info.is_some_and(|stream_info| stream_info.min_blocksize != stream_info.max_blocksize);
+        let (first, last) = self.frame_blocksizes.unwrap_or((blocksize, blocksize));
+        // The new frame shows that the last frame isn't the last one of the stream.
+        let from_frames = last != first;
+        self.frame_blocksizes = Some((first, blocksize));
+        self.variable_blocksize = Some(self.variable_blocksize == Some(true) || from_stream_info || from_frames);
+    }
+
+    /// * Are all of the wanted channels within the number of channels.
+    fn accepts_channels(&self, channels: u32) -> bool {
+        self.channels_wanted.as_ref().is_none_or(|channels_wanted| channels_wanted.iter().all(|c| *c < channels as usize))
+    }
+
     unsafe extern "C" fn metadata_callback(_decoder: *const FLAC__StreamDecoder, metadata: *const FLAC__StreamMetadata, client_data: *mut c_void) {
-        let this = unsafe {&mut *(client_data as *mut Self)};
+        if metadata.is_null() {
+            return;
+        }
+        let this = unsafe {ClientData::target::<Self>(client_data)};
         let metadata = unsafe {*metadata};
-        match metadata.type_ {
-            FLAC__METADATA_TYPE_VORBIS_COMMENT => unsafe {
-                let comments = metadata.data.vorbis_comment;
-
+        if metadata.is_last != 0 {
+            // The first frame starts right after the last metadata block.
+            this.next_frame_byte = this.decode_position().ok();
+        }
+        if this.replaying_metadata {
+            return;
+        }
+        let block = unsafe {MetadataBlock::from_raw(&metadata)};
+        this.last_step = Some(match block.kind() {
+            Some(kind) => DecodeStep::Metadata(kind),
+            None => DecodeStep::UnknownMetadata(metadata.type_),
+        });
+        if this.capture_events {
+            this.events.push_back(DecodeEvent::Metadata(block.clone()));
+        }
+        match block {
+            MetadataBlock::StreamInfo(stream_info) => {
+                // The metadata callback can't abort, the next read or write does.
+                if !this.limits.accepts_stream_info(&stream_info) {
+                    this.aborted_by = Some(FLAC_DECODER_LIMIT_EXCEEDED);
+                } else if !this.accepts_channels(stream_info.channels) {
+                    this.aborted_by = Some(FLAC_DECODER_CHANNEL_OUT_OF_RANGE);
+                }
+                this.stream_info = Some(stream_info);
+            },
+            MetadataBlock::VorbisComment{vendor_string, comments} => {
                 // First retrieve the vendor string
-                this.vendor_string = Some(entry_to_string(&comments.vendor_string));
+                this.vendor_string = Some(vendor_string);
 
                 // Then to get all of the key pairs, the key pairs should be all uppercase, but some of them are not.
                 // Read both the uppercase keys and the lowercase keys and store them, if it won't overwrite then we convert
                 // the key to uppercase and store it again.
                 let mut uppercase_keypairs = Vec::<(String, String)>::new();
-                for i in 0..comments.num_comments {
-                    let comment = entry_to_string(&*comments.comments.add(i as usize));
-
-                    // The key pair is split by the equal notation
-                    let mut iter = comment.split("=");
-                    if let Some(key) = iter.next() {
-                        let key = key.to_owned();
-
-                        // Ignore the later equal notations.
-                        let val = iter.map(|s: &str|{s.to_string()}).collect::<Vec<String>>().join("=");
-                        let key_upper = key.to_uppercase();
-                        if key != key_upper {
-                            uppercase_keypairs.push((key_upper, val.clone()));
-                        }
+                for (key, val) in comments {
+                    let key_upper = key.to_uppercase();
+                    if key != key_upper {
+                        uppercase_keypairs.push((key_upper, val.clone()));
+                    }
 
-                        // Duplication check
-                        let if_dup = format!("Duplicated comments: new comment is {key}: {val}, the previous is {key}: ");
-                        if let Some(old) = this.comments.insert(key, val) {
-                            eprintln!("{if_dup}{old}");
-                        }
-                    } else {
-                        // No equal notation here
-                        eprintln!("Invalid comment: {comment}");
+                    // Duplication check
+                    let if_dup = format!("Duplicated comments: new comment is {key}: {val}, the previous is {key}: ");
+                    if let Some(old) = this.comments.insert(key, val) {
+                        eprintln!("{if_dup}{old}");
                     }
                 }
 
@@ -1912,45 +4687,20 @@ where
                     }
                 }
             },
-            FLAC__METADATA_TYPE_PICTURE => unsafe {
-                let picture = metadata.data.picture;
-                this.pictures.push(PictureData{
-                    picture: slice::from_raw_parts(picture.data, picture.data_length as usize).to_vec(),
-                    description: CStr::from_ptr(picture.description as *const i8).to_string_lossy().to_string(),
-                    mime_type: CStr::from_ptr(picture.mime_type).to_string_lossy().to_string(),
-                    width: picture.width,
-                    height: picture.height,
-                    depth: picture.depth,
-                    colors: picture.colors,
-                });
+            MetadataBlock::Picture(picture) => {
+                if this.stop_after_picture == Some(picture.picture_type) {
+                    this.picture_found = true;
+                }
+                this.pictures.push(picture);
             },
-            FLAC__METADATA_TYPE_CUESHEET => unsafe {
-                let cue_sheet = metadata.data.cue_sheet;
-                this.cue_sheets.push(FlacCueSheet{
-                    media_catalog_number: cue_sheet.media_catalog_number,
-                    lead_in: cue_sheet.lead_in,
-                    is_cd: cue_sheet.is_cd != 0,
-                    tracks: (0..cue_sheet.num_tracks).map(|i| -> (u8, FlacCueTrack) {
-                        let track = *cue_sheet.tracks.add(i as usize);
-                        (track.number, FlacCueTrack {
-                            offset: track.offset,
-                            track_no: track.number,
-                            isrc: track.isrc,
-                            type_: match track.type_() {
-                                0 => FlacTrackType::Audio,
-                                _ => FlacTrackType::NonAudio,
-                            },
-                            pre_emphasis: track.pre_emphasis() != 0,
-                            indices: (0..track.num_indices).map(|i| -> FlacCueSheetIndex {
-                                let index = *track.indices.add(i as usize);
-                                FlacCueSheetIndex {
-                                    offset: index.offset,
-                                    number: index.number,
-                                }
-                            }).collect()
-                        })
-                    }).collect(),
-                });
+            MetadataBlock::SeekTable(seek_points) => {
+                this.seek_points = seek_points;
+            },
+            MetadataBlock::CueSheet(cue_sheet) => {
+                this.cue_sheets.push(cue_sheet);
+            },
+            MetadataBlock::Application{id, data} => {
+                this.applications.push(FlacApplication{id, data});
             },
             _ => {
                 #[cfg(debug_assertions)]
@@ -1960,59 +4710,176 @@ where
     }
 
     unsafe extern "C" fn error_callback(_decoder: *const FLAC__StreamDecoder, status: u32, client_data: *mut c_void) {
-        let this = unsafe {&mut *(client_data as *mut Self)};
-        (this.on_error)(match status {
+        let this = unsafe {ClientData::target::<Self>(client_data)};
+        if status == FLAC__STREAM_DECODER_ERROR_STATUS_FRAME_CRC_MISMATCH && this.collect_frame_crcs && let Some(frame_byte) = this.next_frame_byte && let Ok(frame_end_byte) = this.decode_position() {
+            this.record_corrupted_frame_crc(frame_byte, frame_end_byte);
+        }
+        // The bytes to skip for the sync aren't a part of the next frame.
+        this.next_frame_byte = None;
+        let error = match status {
             FLAC__STREAM_DECODER_ERROR_STATUS_LOST_SYNC => FlacInternalDecoderError::LostSync,
             FLAC__STREAM_DECODER_ERROR_STATUS_BAD_HEADER => FlacInternalDecoderError::BadHeader,
             FLAC__STREAM_DECODER_ERROR_STATUS_FRAME_CRC_MISMATCH => FlacInternalDecoderError::FrameCrcMismatch,
             FLAC__STREAM_DECODER_ERROR_STATUS_UNPARSEABLE_STREAM => FlacInternalDecoderError::UnparseableStream,
             FLAC__STREAM_DECODER_ERROR_STATUS_BAD_METADATA => FlacInternalDecoderError::BadMetadata,
+            FLAC__STREAM_DECODER_ERROR_STATUS_OUT_OF_BOUNDS => FlacInternalDecoderError::OutOfBounds,
+            FLAC__STREAM_DECODER_ERROR_STATUS_MISSING_FRAME => FlacInternalDecoderError::MissingFrame,
             o => panic!("Unknown value of `FLAC__StreamDecodeErrorStatus`: {o}"),
-        });
+        };
+        if this.on_error_with_context.is_none() {
+            return (this.on_error)(error);
+        }
+
+        // The decode position calls your `on_tell()` closure and subtracts what's buffered in the decoder.
+        let byte_position = this.decode_position().ok();
+        let context = DecodeErrorContext {
+            error,
+            byte_position,
+            approx_sample: this.last_frame_end,
+            frame_count_so_far: this.frames_decoded,
+        };
+        if let Some(on_error_with_context) = this.on_error_with_context.as_mut() {
+            on_error_with_context(&context);
+        }
     }
 
     /// * The `initialize()` function. Sets up all of the callback functions, sets `client_data` to the address of the `self` struct.
     pub fn initialize(&mut self) -> Result<(), FlacDecoderError> {
+        let low_latency = self.profile == DecoderProfile::LowLatency;
+        let metadata_respond = match (low_latency, self.metadata_respond.as_ref()) {
+            (false, metadata_respond) => metadata_respond.cloned(),
+            (true, metadata_respond) => {
+                let all = [FlacMetadataType::Padding, FlacMetadataType::Application, FlacMetadataType::SeekTable, FlacMetadataType::VorbisComment];
+                Some(metadata_respond.map_or(&all[..], |block_types| &block_types[..]).iter().copied().filter(|block_type| !matches!(block_type, FlacMetadataType::Picture | FlacMetadataType::CueSheet)).collect())
+            },
+        };
         unsafe {
-            if FLAC__stream_decoder_set_md5_checking(self.decoder, self.md5_checking as i32) == 0 {
+            if FLAC__stream_decoder_set_md5_checking(self.decoder, (self.md5_checking && !low_latency) as i32) == 0 {
                 return self.get_status_as_error("FLAC__stream_decoder_set_md5_checking");
             }
-            if FLAC__stream_decoder_set_metadata_respond_all(self.decoder) == 0 {
-                return self.get_status_as_error("FLAC__stream_decoder_set_metadata_respond_all");
-            }
-            let ret = FLAC__stream_decoder_init_stream(
-                self.decoder,
-                Some(Self::read_callback),
-                Some(Self::seek_callback),
-                Some(Self::tell_callback),
-                Some(Self::length_callback),
-                Some(Self::eof_callback),
-                Some(Self::write_callback),
-                Some(Self::metadata_callback),
-                Some(Self::error_callback),
-                self.as_mut_ptr() as *mut c_void,
-            );
+            match metadata_respond.as_ref() {
+                None => {
+                    if FLAC__stream_decoder_set_metadata_respond_all(self.decoder) == 0 {
+                        return self.get_status_as_error("FLAC__stream_decoder_set_metadata_respond_all");
+                    }
+                    if !self.stream_info_respond && FLAC__stream_decoder_set_metadata_ignore(self.decoder, FlacMetadataType::StreamInfo.as_raw()) == 0 {
+                        return self.get_status_as_error("FLAC__stream_decoder_set_metadata_ignore");
+                    }
+                },
+                Some(block_types) => {
+                    if FLAC__stream_decoder_set_metadata_ignore_all(self.decoder) == 0 {
+                        return self.get_status_as_error("FLAC__stream_decoder_set_metadata_ignore_all");
+                    }
+                    // The STREAMINFO block is read unless it's turned off, even if it's not in the list, the helpers need it.
+                    let block_types = [FlacMetadataType::StreamInfo].iter().chain(block_types.iter()).filter(|block_type| self.stream_info_respond || **block_type != FlacMetadataType::StreamInfo);
+                    for block_type in block_types {
+                        if FLAC__stream_decoder_set_metadata_respond(self.decoder, block_type.as_raw()) == 0 {
+                            return self.get_status_as_error("FLAC__stream_decoder_set_metadata_respond");
+                        }
+                    }
+                },
+            }
+            let client_data = self.client_data.as_ptr();
+            // The same callbacks read the native FLAC file and the Ogg FLAC file.
+            let (ret, function) = match self.container {
+                FlacContainer::Native => (self.call_libflac(|decoder| FLAC__stream_decoder_init_stream(decoder,
+                    Some(Self::read_callback),
+                    if low_latency {None} else {Some(Self::seek_callback)},
+                    if low_latency {None} else {Some(Self::tell_callback)},
+                    if low_latency {None} else {Some(Self::length_callback)},
+                    Some(Self::eof_callback),
+                    Some(Self::write_callback),
+                    Some(Self::metadata_callback),
+                    Some(Self::error_callback),
+                    client_data,
+                )), "FLAC__stream_decoder_init_stream"),
+                FlacContainer::Ogg => (self.call_libflac(|decoder| FLAC__stream_decoder_init_ogg_stream(decoder,
+                    Some(Self::read_callback),
+                    if low_latency {None} else {Some(Self::seek_callback)},
+                    if low_latency {None} else {Some(Self::tell_callback)},
+                    if low_latency {None} else {Some(Self::length_callback)},
+                    Some(Self::eof_callback),
+                    Some(Self::write_callback),
+                    Some(Self::metadata_callback),
+                    Some(Self::error_callback),
+                    client_data,
+                )), "FLAC__stream_decoder_init_ogg_stream"),
+            };
             if ret != 0 {
                 return Err(FlacDecoderError {
                     code: ret,
                     message: FlacDecoderInitError::get_message_from_code(ret),
-                    function: "FLAC__stream_decoder_init_stream",
+                    function,
+                    io_error_kind: None,
+                    internal_error: None,
                 });
             }
         }
         self.finished = false;
+        self.cancelled = false;
+        self.stopped_by_caller = false;
+        self.aborted_by = None;
+        self.end_of_stream_notified = false;
+        self.frames_decoded = 0;
+        self.samples_decoded = 0;
+        self.last_frame_end = None;
+        self.last_frame_position = None;
+        self.next_frame_byte = None;
+        self.variable_blocksize = None;
+        self.frame_blocksizes = None;
+        self.audio_skipped = false;
+        self.read_ahead = None;
+        self.batch = None;
+        self.frame_crcs.clear();
+        self.frame_crc_end = None;
+        self.frame_tee.clear();
+        self.frame_tee_end = None;
+        self.counted_total_samples = None;
+        self.events.clear();
         self.get_status_as_result("FlacDecoderUnmovable::Init()")
     }
 
+    /// * Finish the current FLAC file, then start decoding the FLAC file from the `new_reader` with the same libFLAC decoder.
+    /// * It saves creating and deleting the libFLAC decoder for each of many short files. The old `reader` is dropped, the closures and the settings are kept.
+    /// * The metadata read from the old file and the statistics are cleared, so nothing of the old file shows up in the new one.
+    /// * Your `on_length()` and `on_eof()` closures must query the `reader` they're given, the default ones of `with_default_io()` get the length of the `new_reader` here.
+    pub fn reset_with_reader(&mut self, new_reader: ReadSeek) -> Result<(), FlacDecoderError> {
+        // The MD5 checking failure of the old file doesn't matter for the new one.
+        let _ = self.finish();
+        // Only the fields are replaced, `self` stays where libFLAC's client data points to.
+        self.reader = new_reader;
+        if let Some(default_io_length) = self.default_io_length.clone() {
+            let length = get_reader_length(&mut self.reader).map_err(|_| FlacDecoderError::new(FLAC__STREAM_DECODER_SEEK_ERROR, "FlacDecoderUnmovable::reset_with_reader"))?;
+            default_io_length.store(length, Ordering::Relaxed);
+        }
+        self.vendor_string = None;
+        self.comments.clear();
+        self.pictures.clear();
+        self.cue_sheets.clear();
+        self.applications.clear();
+        self.seek_points.clear();
+        self.stream_info = None;
+        self.picture_found = false;
+        self.captured_frames = None;
+        self.last_step = None;
+        self.reset_io_stats();
+        self.initialize()
+    }
+
     /// * Seek to the specific sample position, may fail.
     pub fn seek(&mut self, frame_index: u64) -> Result<(), FlacDecoderError> {
+        if self.flush_batch().is_err() {
+            self.stopped_by_caller = true;
+            return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "FlacDecoderUnmovable::seek"));
+        }
+        self.next_frame_byte = None;
         for _retry in 0..3 {
             unsafe {
-                if FLAC__stream_decoder_seek_absolute(self.decoder, frame_index) == 0 {
+                if self.call_libflac(|decoder| FLAC__stream_decoder_seek_absolute(decoder, frame_index)) == 0 {
                     match FLAC__stream_decoder_get_state(self.decoder) {
                         FLAC__STREAM_DECODER_SEEK_STATUS_OK => panic!("`FLAC__stream_decoder_seek_absolute()` returned false, but the status of the decoder is `OK`"),
                         FLAC__STREAM_DECODER_SEEK_ERROR => {
-                            if FLAC__stream_decoder_reset(self.decoder) == 0 {
+                            if self.call_libflac(|decoder| FLAC__stream_decoder_reset(decoder)) == 0 {
                                 return self.get_status_as_error("FLAC__stream_decoder_reset");
                             } else {
                                 continue;
@@ -2028,9 +4895,114 @@ where
         Err(FlacDecoderError::new(FLAC__STREAM_DECODER_SEEK_ERROR, "FLAC__stream_decoder_seek_absolute"))
     }
 
+    /// * Decode `n` samples per channel starting at the sample position `start_sample`, e.g. for the random access of an audio editor.
+    /// * Returns the channel arrays regardless of the `FlacAudioForm`, honoring `set_channels_wanted()`. It's shorter than `n` if the stream ends, the arrays are empty if `start_sample` is beyond the end.
+    ///   Your `on_write()` closure isn't called for the decoded samples.
+    /// * The decoder stays right after the returned range, so the sequential calls, i.e. `start_sample` is where the last call ended, never seek but continue decoding.
+    ///   Otherwise it seeks to `start_sample` by `seek()`, the frame containing it is decoded from the exact sample.
+    /// * Decoding by other means in between, e.g. `decode()` or `seek()`, makes the next call seek.
+    pub fn read_at(&mut self, start_sample: u64, n: usize) -> Result<Vec<Vec<i32>>, FlacDecoderError> {
+        if self.stream_info.is_none() {
+            self.decode_metadata()?;
+        }
+        let channels = match (self.channels_wanted.as_ref(), self.stream_info) {
+            (Some(channels_wanted), _) => channels_wanted.len(),
+            (None, Some(stream_info)) => stream_info.channels as usize,
+            (None, None) => 0,
+        };
+        let mut ret: Vec<Vec<i32>> = vec![Vec::new(); channels];
+        let mut position = start_sample;
+        match self.read_ahead.take() {
+            Some((start, mut samples, frames_decoded)) if frames_decoded == self.frames_decoded && start <= start_sample && start_sample <= start + samples.first().map_or(0, |channel| channel.len()) as u64 => {
+                let skip = (start_sample - start) as usize;
+                for channel in samples.iter_mut() {
+                    channel.drain(..skip);
+                }
+                position += samples.first().map_or(0, |channel| channel.len()) as u64;
+                ret = samples;
+            },
+            _ => {
+                let total_samples = self.stream_info.map_or(0, |stream_info| stream_info.total_samples);
+                if total_samples > 0 && start_sample >= total_samples {
+                    self.read_ahead = Some((start_sample, ret.clone(), self.frames_decoded));
+                    return Ok(ret);
+                }
+                self.captured_frames = Some(Vec::new());
+                let seeked = self.seek(start_sample);
+                if seeked.is_err() {
+                    self.captured_frames = None;
+                }
+                seeked?;
+            },
+        }
+        if self.captured_frames.is_none() {
+            self.captured_frames = Some(Vec::new());
+        }
+        let decoded = loop {
+            for (frame_start, samples, samples_info) in self.captured_frames.replace(Vec::new()).unwrap_or_default() {
+                let frame_end = frame_start + samples_info.samples as u64;
+                if frame_end <= position {
+                    continue;
+                }
+                ret.resize_with(samples_info.channels as usize, Vec::new);
+                for (channel, channel_samples) in ret.iter_mut().enumerate() {
+                    channel_samples.extend((position.max(frame_start) - frame_start..samples_info.samples as u64).map(|frame| samples_info.get_sample(&samples, frame as usize, channel).unwrap_or(0)));
+                }
+                position = frame_end;
+            }
+            if position >= start_sample + n as u64 || self.is_end_of_stream() {
+                break Ok(());
+            }
+            match self.process_single() {
+                Ok(true) => (),
+                Ok(false) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.captured_frames = None;
+        decoded?;
+
+        // Keep the samples beyond the range for the next call.
+        let rest: Vec<Vec<i32>> = ret.iter_mut().map(|channel| channel.split_off(n.min(channel.len()))).collect();
+        let end = start_sample + ret.first().map_or(0, |channel| channel.len()) as u64;
+        self.read_ahead = Some((end, rest, self.frames_decoded));
+        Ok(ret)
+    }
+
     /// * Calls your `on_tell()` closure to get the read position
     pub fn tell(&mut self) -> Result<u64, io::Error> {
-        (self.on_tell)(&mut self.reader)
+        self.call_on_tell()
+    }
+
+    fn call_on_tell(&mut self) -> Result<u64, io::Error> {
+        let result = (self.on_tell)(&mut self.reader);
+        if let Some(io_stats) = self.io_stats.as_mut() {io_stats.record_tell(result.as_ref().ok().copied())}
+        result
+    }
+
+    fn call_on_seek(&mut self, position: u64) -> Result<(), io::Error> {
+        if let Some(io_stats) = self.io_stats.as_mut() {io_stats.record_seek(position)}
+        // The teed bytes are before the new position, a frame after it is read anew.
+        self.frame_tee.clear();
+        let result = (self.on_seek)(&mut self.reader, position);
+        self.frame_tee_end = result.is_ok().then_some(position);
+        result
+    }
+
+    /// * Enable or disable the statistics of the calls to your IO closures, enabling it starts the statistics from zero.
+    /// * Reset the statistics between `decode_metadata()`, `seek()` and `decode_all()` to measure each of them separately.
+    pub fn set_io_stats_enabled(&mut self, enabled: bool) {
+        self.io_stats = if enabled {Some(IoStats::new())} else {None};
+    }
+
+    /// * Get the statistics of the calls to your IO closures, `None` if it's not enabled.
+    pub fn io_stats(&self) -> Option<&IoStats> {
+        self.io_stats.as_ref()
+    }
+
+    /// * Reset the statistics to measure the next phase separately.
+    pub fn reset_io_stats(&mut self) {
+        if let Some(io_stats) = self.io_stats.as_mut() {io_stats.reset()}
     }
 
     /// * Calls your `on_length()` closure to get the length of the file
@@ -2043,6 +5015,12 @@ where
         (self.on_eof)(&mut self.reader)
     }
 
+    /// * Get the STREAMINFO block, available after `decode()` or `decode_metadata()` read it, before any samples are decoded.
+    /// * `None` if it wasn't read yet, or `set_stream_info_respond(false)` turned it off.
+    pub fn get_stream_info(&self) -> Option<&FlacStreamInfo> {
+        self.stream_info.as_ref()
+    }
+
     /// * Get the vendor string.
     pub fn get_vendor_string(&self) -> &Option<String> {
         &self.vendor_string
@@ -2053,23 +5031,425 @@ where
         &self.comments
     }
 
+    /// * The `ENCODER_OPTIONS` comment, the options of the `flac` command line tool that encoded the file, e.g. `-8 -V`. `None` if the file lacks it.
+    /// * See `util::compression_from_encoder_options()` and `original_params()` to re-encode the file like the original one.
+    pub fn original_encoder_options(&self) -> Option<&str> {
+        self.comments.get("ENCODER_OPTIONS").map(String::as_str)
+    }
+
     /// * Get all of the pictures
     pub fn get_pictures(&self) -> &Vec<PictureData> {
         &self.pictures
     }
 
+    /// * How many pictures were read
+    pub fn picture_count(&self) -> usize {
+        self.pictures.len()
+    }
+
+    /// * The first picture read, e.g. the cover for a thumbnail
+    pub fn first_picture(&self) -> Option<&PictureData> {
+        self.pictures.first()
+    }
+
+    /// * The first picture of the picture type, e.g. `FlacPictureType::FrontCover`
+    pub fn picture_by_type(&self, picture_type: FlacPictureType) -> Option<&PictureData> {
+        self.pictures_of_type(picture_type).next()
+    }
+
+    /// * All of the pictures of the picture type in the order of the file, e.g. the leaflet pages.
+    pub fn pictures_of_type(&self, picture_type: FlacPictureType) -> impl Iterator<Item = &PictureData> {
+        self.pictures.iter().filter(move |picture| picture.picture_type == picture_type)
+    }
+
+    /// * The picture to show as the cover: the front cover, or the first picture of the type `Other`, or the first picture of any type.
+    /// * The pictures written by the taggers that don't set the type are `Other`, so they come before e.g. the artist photo.
+    pub fn best_cover(&self) -> Option<&PictureData> {
+        self.picture_by_type(FlacPictureType::FrontCover)
+            .or_else(|| self.picture_by_type(FlacPictureType::Other))
+            .or_else(|| self.first_picture())
+    }
+
+    /// * Choose the types of the metadata blocks to read, the other blocks are skipped by libFLAC without being parsed. `None` to read all of them.
+    /// * The STREAMINFO block is always read even if it's not in the list, unless it's turned off by `set_stream_info_respond()`.
+    /// * The decoder is re-initialized to apply it, so it must be called before decoding anything.
+    pub fn set_metadata_respond(&mut self, block_types: Option<&[FlacMetadataType]>) -> Result<(), FlacDecoderError> {
+        self.check_nothing_decoded("FlacDecoderUnmovable::set_metadata_respond")?;
+        self.metadata_respond = block_types.map(|block_types| block_types.to_vec());
+        self.reinitialize()
+    }
+
+    /// * Choose whether to read the STREAMINFO block, the default is `true`, it's independent of `set_metadata_respond()`.
+    /// * libFLAC always uses the STREAMINFO block to decode, turning it off only stops it from reaching this decoder, e.g. to avoid the `DecodeEvent::Metadata` of it.
+    /// * But without it, these return `None` or skip their checks: `get_stream_info()`, `duration()`, `progress()`, the block sizes and the frame sizes, `metadata_report()`,
+    ///   the STREAMINFO checks of `set_limits()` and `set_channels_wanted()`, and the format checks of `transcode()` before encoding.
+    ///   `scan()` fails, and `decode_all()` can't tell `DecodeOutcome::Truncated`. Keep it on if you need any of them.
+    /// * The decoder is re-initialized to apply it, so it must be called before decoding anything.
+    pub fn set_stream_info_respond(&mut self, stream_info_respond: bool) -> Result<(), FlacDecoderError> {
+        self.check_nothing_decoded("FlacDecoderUnmovable::set_stream_info_respond")?;
+        self.stream_info_respond = stream_info_respond;
+        self.reinitialize()
+    }
+
+    /// * Get whether the STREAMINFO block is read.
+    pub fn get_stream_info_respond(&self) -> bool {
+        self.stream_info_respond
+    }
+
+    /// * Apply the preset of the settings, see `DecoderProfile` for what's sacrificed.
+    /// * The decoder is re-initialized to apply it, so it must be called before decoding anything.
+    pub fn set_profile(&mut self, profile: DecoderProfile) -> Result<(), FlacDecoderError> {
+        self.check_nothing_decoded("FlacDecoderUnmovable::set_profile")?;
+        self.profile = profile;
+        self.reinitialize()
+    }
+
+    pub fn get_profile(&self) -> DecoderProfile {
+        self.profile
+    }
+
+    /// * Choose the container of the FLAC stream, e.g. `FlacContainer::Ogg` for the `.oga` file, the default is `FlacContainer::Native`. The same closures read it.
+    /// * Decoding the Ogg FLAC needs the `ogg` feature of this crate, otherwise it fails with `StreamDecoderInitStatusUnsupportedContainer`, and the decoder stays uninitialized.
+    /// * libFLAC can't tell the byte position in the Ogg stream, so `last_frame_position()` and the frame CRCs aren't available for it.
+    /// * The decoder is re-initialized to apply it, so it must be called before decoding anything.
+    pub fn set_container(&mut self, container: FlacContainer) -> Result<(), FlacDecoderError> {
+        self.check_nothing_decoded("FlacDecoderUnmovable::set_container")?;
+        self.container = container;
+        self.reinitialize()
+    }
+
+    pub fn get_container(&self) -> FlacContainer {
+        self.container
+    }
+
+    /// * The settings applied in `initialize()` can only be changed before decoding anything.
+    fn check_nothing_decoded(&self, function: &'static str) -> Result<(), FlacDecoderError> {
+        let state = unsafe {FLAC__stream_decoder_get_state(self.decoder)};
+        if state != FLAC__STREAM_DECODER_SEARCH_FOR_METADATA && state != FLAC__STREAM_DECODER_UNINITIALIZED {
+            Err(FlacDecoderError::new(state, function))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// * If the decoder was initialized, initialize it again to apply the changed settings.
+    fn reinitialize(&mut self) -> Result<(), FlacDecoderError> {
+        if unsafe {FLAC__stream_decoder_get_state(self.decoder)} == FLAC__STREAM_DECODER_SEARCH_FOR_METADATA {
+            unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_finish(decoder))};
+            self.initialize()?;
+        }
+        Ok(())
+    }
+
+    /// * Let `decode_metadata()` stop right after the first picture of the picture type, e.g. to get the front cover of a big file quickly. `None` to read all of the metadata.
+    /// * The decoding could continue by `decode()` or `decode_all()` to read the rest of the metadata and the audio.
+    pub fn set_stop_after_picture(&mut self, picture_type: Option<FlacPictureType>) {
+        self.stop_after_picture = picture_type;
+    }
+
     /// * Get all of the cue sheets
     pub fn get_cue_sheets(&self) -> &Vec<FlacCueSheet> {
         &self.cue_sheets
     }
 
-    /// * Decode one FLAC frame, may get an audio frame or a metadata frame.
+    /// * Get all of the APPLICATION blocks
+    pub fn get_applications(&self) -> &Vec<FlacApplication> {
+        &self.applications
+    }
+
+    /// * Assemble all of the metadata read so far into one report, e.g. for the "show tags" command, call it after `decode_metadata()`.
+    pub fn metadata_report(&self) -> MetadataReport {
+        MetadataReport::new(
+            self.stream_info,
+            self.vendor_string.clone(),
+            self.comments.iter().map(|(key, value)| (key.clone(), value.clone())),
+            &self.pictures,
+            &self.cue_sheets,
+            self.seek_points.len(),
+            &self.applications,
+        )
+    }
+
+    /// * Get all of the seek points
+    pub fn get_seek_points(&self) -> &Vec<FlacSeekPoint> {
+        &self.seek_points
+    }
+
+    /// * The minimum block size in samples, available after the metadata was decoded, e.g. by `decode_metadata()`
+    pub fn min_blocksize(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.min_blocksize)
+    }
+
+    /// * The maximum block size in samples, available after the metadata was decoded, e.g. by `decode_metadata()`
+    pub fn max_blocksize(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.max_blocksize)
+    }
+
+    /// * The sample rate in Hz, available after the metadata was decoded, e.g. by `decode_metadata()`
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.sample_rate)
+    }
+
+    /// * The number of channels, available after the metadata was decoded, e.g. by `decode_metadata()`
+    pub fn channels(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.channels)
+    }
+
+    /// * The bits per sample, available after the metadata was decoded, e.g. by `decode_metadata()`
+    pub fn bits_per_sample(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.bits_per_sample)
+    }
+
+    /// * The least and the most samples per channel your `on_write()` closure gets at once, i.e. the minimum and the maximum block size.
+    /// * libFLAC decodes a whole frame before passing it out, so the playback can't start before the first block was read and decoded,
+    ///   the maximum block size is the latency floor of the stream. Available after the metadata was decoded, e.g. by `decode_metadata()`
+    pub fn latency_samples(&self) -> Option<(u32, u32)> {
+        self.stream_info.map(|stream_info| (stream_info.min_blocksize, stream_info.max_blocksize))
+    }
+
+    /// * The MD5 signature of the stream as lowercase hex, available after the metadata was decoded, e.g. by `decode_metadata()`. `None` if it's unknown, see `FlacStreamInfo::md5_hex()`.
+    pub fn md5_hex(&self) -> Option<String> {
+        self.stream_info?.md5_hex()
+    }
+
+    /// * The minimum frame size in bytes, available after the metadata was decoded, e.g. by `decode_metadata()`. Zero means unknown.
+    pub fn min_framesize(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.min_framesize)
+    }
+
+    /// * The maximum frame size in bytes, available after the metadata was decoded, e.g. by `decode_metadata()`. Zero means unknown.
+    pub fn max_framesize(&self) -> Option<u32> {
+        self.stream_info.map(|stream_info| stream_info.max_framesize)
+    }
+
+    /// * The duration of the audio from the STREAMINFO block, `None` if the metadata wasn't decoded yet or the total samples is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        self.stream_info?.duration()
+    }
+
+    /// * How much of the audio was decoded, from 0.0 to 1.0, by the end of the last decoded frame.
+    /// * `None` if the metadata wasn't decoded yet or the total samples is unknown, e.g. the FLAC file was encoded from a live stream.
+    pub fn progress(&self) -> Option<f64> {
+        let total_samples = self.stream_info?.known_total_samples()?;
+        Some((self.last_frame_end.unwrap_or(0) as f64 / total_samples as f64).min(1.0))
+    }
+
+    /// * The sample number and the byte offset of the frame header of the last decoded frame, e.g. to build a seek table for the FLAC file that lacks one.
+    /// * The byte offset is from the start of the `reader`, the seek points count from the first frame, subtract the offset of the first frame for them.
+    /// * `None` if the start of the frame isn't known, e.g. the first frame after a seek or after the decoder lost the sync, the next frame is known again.
+    pub fn last_frame_position(&self) -> Option<(u64, u64)> {
+        self.last_frame_position
+    }
+
+    /// * Set the cancellation token, set it to `true` from any thread to cancel the decoding, then `decode()` returns an error that `is_cancelled()`, `decode_all()` returns `DecodeOutcome::StoppedByCaller`.
+    /// * The token is checked before every read from your `on_read()` closure, so the decoding stops after at most one read's worth of data has been decoded.
+    /// * After being cancelled, the decoder could only be finished, but not continue decoding.
+    pub fn set_cancel_token(&mut self, cancel_token: Option<Arc<AtomicBool>>) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// * Get the cancellation token.
+    pub fn get_cancel_token(&self) -> Option<Arc<AtomicBool>> {
+        self.cancel_token.clone()
+    }
+
+    /// * Set the limits of the audio that the decoder accepts, e.g. for a server that accepts the user-uploaded FLAC files.
+    /// * If the STREAMINFO block or a frame header exceeds the limits, the decoding is aborted before the buffer for the frame grows, and `decode()` or `decode_all()` fails with the `FLAC_DECODER_LIMIT_EXCEEDED` error.
+    /// * After that, the decoder could only be finished, but not continue decoding.
+    pub fn set_limits(&mut self, limits: DecoderLimits) {
+        self.limits = limits;
+    }
+
+    /// * Get the limits of the audio that the decoder accepts.
+    pub fn get_limits(&self) -> DecoderLimits {
+        self.limits
+    }
+
+    /// * Set the channels to deliver to your `on_write()` closure in this order, e.g. `Some(&[0])` for only the left channel, `None` for all of the channels.
+    /// * The other channels are not copied, `SamplesInfo::channels` is the number of the wanted channels.
+    /// * If a wanted channel is out of the range of the channels of the STREAMINFO block or a frame, the decoding is aborted, and `decode()` or `decode_all()` fails with the `FLAC_DECODER_CHANNEL_OUT_OF_RANGE` error.
+    ///   An empty list also returns the error here.
+    pub fn set_channels_wanted(&mut self, channels_wanted: Option<&[usize]>) -> Result<(), FlacDecoderError> {
+        if channels_wanted.is_some_and(|channels_wanted| channels_wanted.is_empty()) {
+            return Err(FlacDecoderError::new(FLAC_DECODER_CHANNEL_OUT_OF_RANGE, "FlacDecoderUnmovable::set_channels_wanted"));
+        }
+        self.channels_wanted = channels_wanted.map(|channels_wanted| channels_wanted.to_vec());
+        Ok(())
+    }
+
+    /// * Set how many frames `decode_all()` decodes before the first call to your `on_write()` closure, then they're passed out one by one at once.
+    /// * Gives your audio output some samples in advance to start with, the default is zero, i.e. each frame is passed out once it's decoded.
+    /// * libFLAC can't pass out a part of a frame, see `latency_samples()` for the least latency the stream allows.
+    pub fn set_prebuffer_frames(&mut self, frames: usize) {
+        self.prebuffer_frames = frames;
+    }
+
+    /// * How many frames `decode_all()` decodes before the first call to your `on_write()` closure.
+    pub fn prebuffer_frames(&self) -> usize {
+        self.prebuffer_frames
+    }
+
+    /// * Merge every `frames` decoded frames into one call to your `on_write()` closure, for fewer and larger calls, e.g. writing to the disk.
+    /// * The `SamplesInfo` tells the samples per channel of all of the merged frames. A frame of different channels, sample rate or bits per sample starts a new batch.
+    /// * The remainder is passed at the end of the stream, before `seek()`, or by `finish()`. The default is zero, zero or one passes out each frame once it's decoded.
+    pub fn set_batch_frames(&mut self, frames: usize) {
+        self.batch_frames = frames;
+    }
+
+    /// * How many frames are merged into one call to your `on_write()` closure.
+    pub fn get_batch_frames(&self) -> usize {
+        self.batch_frames
+    }
+
+    /// * Set whether to record the first sample and the CRC-16 of each decoded frame, for `frame_crcs()`, the default is `false`.
+    /// * The bytes libFLAC reads are kept until the frame is decoded, the footer of each frame and the whole corrupted frame are taken from them, the `reader` isn't read again or seeked.
+    ///   Your `on_tell()` closure must return the read position, as for `FLAC__stream_decoder_get_decode_position()`, it doesn't have to seek.
+    /// * Combined with `frames_digest()`, the frame that changed between two versions of a FLAC file can be found.
+    pub fn set_collect_frame_crcs(&mut self, collect_frame_crcs: bool) {
+        self.collect_frame_crcs = collect_frame_crcs;
+    }
+
+    /// * Get whether to record the CRC-16 of each decoded frame.
+    pub fn get_collect_frame_crcs(&self) -> bool {
+        self.collect_frame_crcs
+    }
+
+    /// * The first sample and the CRC-16 of each decoded frame in the decoding order, see `set_collect_frame_crcs()`.
+    /// * The CRC-16 is the footer of the frame. For a corrupted frame, it's hashed from the bytes of the frame, which differs from its footer, the first sample is where the last decoded frame ends.
+    pub fn frame_crcs(&self) -> &[(u64, u16)] {
+        &self.frame_crcs
+    }
+
+    /// * Deliver only the `channels` to your `on_write()` closure in this order, e.g. `&[0]` for a speech pipeline that only needs the first channel of a multichannel recording.
+    /// * The same as `set_channels_wanted(Some(channels))`, but if the STREAMINFO block was already read, e.g. by `decode_metadata()`, the channels are checked against it right here.
+    /// * Only the selected channels are copied out of libFLAC's buffer, for all of the `FlacAudioForm`s, so it saves the memory and the time of the other channels.
+    pub fn select_channels(&mut self, channels: &[usize]) -> Result<(), FlacDecoderError> {
+        if let Some(stream_info) = self.stream_info && channels.iter().any(|channel| *channel >= stream_info.channels as usize) {
+            return Err(FlacDecoderError::new(FLAC_DECODER_CHANNEL_OUT_OF_RANGE, "FlacDecoderUnmovable::select_channels"));
+        }
+        self.set_channels_wanted(Some(channels))
+    }
+
+    /// * Get the channels to deliver to your `on_write()` closure.
+    pub fn get_channels_wanted(&self) -> Option<&[usize]> {
+        self.channels_wanted.as_deref()
+    }
+
+    /// * If the decoder was aborted by the cancellation token or the callbacks, turn the error into the `Cancelled` error or the crate-specific error, e.g. the `LimitExceeded` error.
+    fn get_decode_status_as_result(&self, function: &'static str) -> Result<(), FlacDecoderError> {
+        if self.cancelled {
+            Err(FlacDecoderError::new(FLAC_DECODER_CANCELLED, function))
+        } else if let Some(code) = self.aborted_by {
+            Err(FlacDecoderError::new(code, function))
+        } else {
+            self.get_status_as_result(function)
+        }
+    }
+
+    /// * The STREAMINFO block may exceed the limits or lack the wanted channels without aborting the decoder, since the rest of the metadata could have been buffered.
+    fn check_aborted_by(&self, function: &'static str) -> Result<(), FlacDecoderError> {
+        match self.aborted_by {
+            Some(code) => Err(FlacDecoderError::new(code, function)),
+            None => Ok(()),
+        }
+    }
+
+    /// * Has the decoder reached the end of the stream.
+    pub(crate) fn is_end_of_stream(&self) -> bool {
+        unsafe {FLAC__stream_decoder_get_state(self.decoder) == FLAC__STREAM_DECODER_END_OF_STREAM}
+    }
+
+    /// * Set your `on_error_with_context()` closure, it's called instead of your `on_error()` closure with where the error happened, e.g. for the corruption reports.
+    pub fn set_on_error_with_context(&mut self, on_error_with_context: Option<Box<dyn FnMut(&DecodeErrorContext) + 'a>>) {
+        self.on_error_with_context = on_error_with_context;
+    }
+
+    /// * Set your `on_end_of_stream()` closure, it's called once when `decode()` or `decode_all()` reaches the end of the stream, after the last `on_write()` call.
+    /// * Useful for the downstream stages to flush their buffers.
+    pub fn set_on_end_of_stream(&mut self, on_end_of_stream: Option<Box<dyn FnMut() + 'a>>) {
+        self.on_end_of_stream = on_end_of_stream;
+    }
+
+    /// * Call your `on_end_of_stream()` closure if the decoder just reached the end of the stream.
+    fn notify_end_of_stream(&mut self) -> Result<(), io::Error> {
+        if !self.end_of_stream_notified && self.is_end_of_stream() {
+            self.end_of_stream_notified = true;
+            // The remainder of the batch is the last `on_write()` call.
+            let ret = self.flush_batch();
+            if let Some(on_end_of_stream) = self.on_end_of_stream.as_mut() {
+                on_end_of_stream();
+            }
+            return ret;
+        }
+        Ok(())
+    }
+
+    /// * Decode one FLAC frame, may get an audio frame or a metadata frame, the returned `DecodeStep` tells which one.
     /// * Your closures will be called by the decoder when you call this method.
-    pub fn decode(&mut self) -> Result<bool, FlacDecoderError> {
-        if unsafe {FLAC__stream_decoder_process_single(self.decoder) != 0} {
+    pub fn decode(&mut self) -> Result<DecodeStep, FlacDecoderError> {
+        self.last_step = None;
+        if !self.process_single()? {
+            return Ok(DecodeStep::NothingDone);
+        }
+        Ok(match self.last_step.take() {
+            Some(step) => step,
+            None if self.is_end_of_stream() => DecodeStep::EndOfStream,
+            None => DecodeStep::NothingDone,
+        })
+    }
+
+    /// * The old `decode()`, `true` just means the decoder processed successfully, it tells nothing about what was decoded.
+    #[deprecated = "Use `decode()`, which tells what was decoded"]
+    pub fn decode_bool(&mut self) -> Result<bool, FlacDecoderError> {
+        self.process_single()
+    }
+
+    /// * Process one metadata block or audio frame, `false` if the decoder can't process.
+    pub(crate) fn process_single(&mut self) -> Result<bool, FlacDecoderError> {
+        if unsafe {FLAC__stream_decoder_get_state(self.decoder)} == FLAC__STREAM_DECODER_SEARCH_FOR_FRAME_SYNC {
+            // Between the frames, the next frame starts at the decode position unless the sync is lost.
+            self.next_frame_byte = self.decode_position().ok();
+        }
+        if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_process_single(decoder)) != 0} {
+            self.check_aborted_by("FLAC__stream_decoder_process_single")?;
+            if let Err(e) = self.notify_end_of_stream() {
+                eprintln!("On `write_callback()`: {:?}", e);
+                self.stopped_by_caller = true;
+                return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "FLAC__stream_decoder_process_single"));
+            }
+            Ok(true)
+        } else {
+            match self.get_decode_status_as_result("FLAC__stream_decoder_process_single") {
+                Ok(_) => Ok(false),
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    /// * Decode all of the metadata blocks, stop before the first audio frame. No samples will be returned via your `on_write()` closure.
+    /// * E.g. the comments, the pictures and the cue sheets for a library scanner, without decompressing any audio frame.
+    /// * Calling it again does nothing, `decode()` or `decode_all()` can continue from the first audio frame.
+    /// * If `set_stop_after_picture()` was set, it stops right after the picture of the picture type.
+    pub fn decode_metadata(&mut self) -> Result<bool, FlacDecoderError> {
+        if self.stop_after_picture.is_some() {
+            // Process the metadata blocks one by one to stop right after the picture.
+            self.picture_found = false;
+            while !self.picture_found && unsafe {FLAC__stream_decoder_get_state(self.decoder)} <= FLAC__STREAM_DECODER_READ_METADATA {
+                if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_process_single(decoder)) == 0} {
+                    return match self.get_decode_status_as_result("FLAC__stream_decoder_process_single") {
+                        Ok(_) => Ok(false),
+                        Err(e) => Err(e),
+                    };
+                }
+            }
+            self.check_aborted_by("FLAC__stream_decoder_process_single")?;
+            return Ok(true);
+        }
+        if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_process_until_end_of_metadata(decoder)) != 0} {
+            self.check_aborted_by("FLAC__stream_decoder_process_until_end_of_metadata")?;
             Ok(true)
         } else {
-            match self.get_status_as_result("FLAC__stream_decoder_process_single") {
+            match self.get_decode_status_as_result("FLAC__stream_decoder_process_until_end_of_metadata") {
                 Ok(_) => Ok(false),
                 Err(e) => Err(e),
             }
@@ -2077,21 +5457,332 @@ where
     }
 
     /// * Decode all of the FLAC frames, get all of the samples and metadata and pictures and cue sheets, etc.
-    pub fn decode_all(&mut self) -> Result<bool, FlacDecoderError> {
-        if unsafe {FLAC__stream_decoder_process_until_end_of_stream(self.decoder) != 0} {
+    /// * The returned `DecodeOutcome` tells whether the stream was decoded to the end, stopped by you, truncated, or failed.
+    pub fn decode_all(&mut self) -> DecodeOutcome {
+        let samples_before = self.samples_decoded;
+        if self.prebuffer_frames > 0 {
+            if let Err(e) = self.prebuffer() {
+                return DecodeOutcome::Failed(e);
+            }
+            if self.stopped_by_caller {
+                return DecodeOutcome::StoppedByCaller{samples: self.samples_decoded - samples_before};
+            }
+        }
+        let ret = unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_process_until_end_of_stream(decoder)) != 0};
+        let samples = self.samples_decoded - samples_before;
+        if ret {
+            if let Err(e) = self.notify_end_of_stream() {
+                eprintln!("On `write_callback()`: {:?}", e);
+                self.stopped_by_caller = true;
+                return DecodeOutcome::StoppedByCaller{samples};
+            }
+            return match self.stream_info {
+                Some(stream_info) if stream_info.total_samples > self.last_frame_end.unwrap_or(0) => DecodeOutcome::Truncated{samples},
+                _ => DecodeOutcome::Completed{samples},
+            };
+        }
+        if self.cancelled || self.stopped_by_caller {
+            return DecodeOutcome::StoppedByCaller{samples};
+        }
+        match self.get_decode_status_as_result("FLAC__stream_decoder_process_until_end_of_stream") {
+            Ok(_) => DecodeOutcome::Failed(FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "FLAC__stream_decoder_process_until_end_of_stream")),
+            Err(e) => DecodeOutcome::Failed(e),
+        }
+    }
+
+    /// * Decode up to `prebuffer_frames` frames, then pass them to your `on_write()` closure.
+    fn prebuffer(&mut self) -> Result<(), FlacDecoderError> {
+        self.captured_frames = Some(Vec::new());
+        let mut ret = Ok(());
+        while self.captured_frames.as_ref().is_some_and(|captured_frames| captured_frames.len() < self.prebuffer_frames) {
+            match self.process_single() {
+                Ok(true) if !self.is_end_of_stream() => (),
+                Ok(_) => break,
+                Err(e) => {
+                    ret = Err(e);
+                    break;
+                },
+            }
+        }
+        for (_, samples, samples_info) in self.captured_frames.take().unwrap_or_default() {
+            if let Err(e) = self.write_samples(&samples, samples_info) {
+                eprintln!("On `write_callback()`: {:?}", e);
+                self.stopped_by_caller = true;
+                break;
+            }
+        }
+        ret
+    }
+
+    /// * The old `decode_all()`, `Ok(false)` could mean a stop by you or anything else that left the decoder in a good state.
+    #[deprecated = "Use `decode_all()`, which tells how the decoding ended"]
+    pub fn decode_all_bool(&mut self) -> Result<bool, FlacDecoderError> {
+        match self.decode_all() {
+            DecodeOutcome::Completed{..} | DecodeOutcome::Truncated{..} => Ok(true),
+            DecodeOutcome::StoppedByCaller{..} if !self.cancelled => Ok(false),
+            DecodeOutcome::StoppedByCaller{..} => Err(FlacDecoderError::new(FLAC_DECODER_CANCELLED, "FLAC__stream_decoder_process_until_end_of_stream")),
+            DecodeOutcome::Failed(e) => Err(e),
+        }
+    }
+
+    /// * Get the next metadata block or audio frame in the order they appear in the FLAC file, for driving the decoding from a state machine.
+    /// * Your `on_write()` closure isn't called for the audio frames returned here, the metadata blocks are still stored, e.g. for `get_comments()`.
+    pub fn next_event(&mut self) -> Result<DecodeEvent, FlacDecoderError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            if self.is_end_of_stream() {
+                return Ok(DecodeEvent::EndOfStream);
+            }
+            self.capture_events = true;
+            let ret = self.process_single();
+            self.capture_events = false;
+            if !ret? && self.events.is_empty() {
+                return Ok(DecodeEvent::EndOfStream);
+            }
+        }
+    }
+
+    /// * Iterate over the audio frames, built on `next_event()`, the metadata blocks are still stored, e.g. for `get_comments()`.
+    pub fn audio_frames(&mut self) -> AudioFrames<'_, 'a, ReadSeek> {
+        AudioFrames {
+            decoder: self,
+            ended: false,
+        }
+    }
+
+    /// * Quickly scan the audio by decoding only one frame every `step`, e.g. for the silence detection or the rough loudness profiling.
+    /// * Your `f` closure gets the sample position of the decoded frame, the samples of the frame, and the samples info. Your `on_write()` closure isn't called.
+    /// * If the total samples is known, the decoder seeks to the frame for every `step`, using the seek table if there is one. The frame starts exactly at the requested position.
+    /// * Otherwise the frames are skipped without being decoded, the decoded frame starts within one block size before the requested position.
+    /// * The final frame is always visited. The MD5 checking is disabled since most of the audio is skipped.
+    pub fn scan<F>(&mut self, step: Duration, mut f: F) -> Result<(), FlacDecoderError>
+    where
+        F: FnMut(u64, &[Vec<i32>], &SamplesInfo) {
+        if self.stream_info.is_none() {
+            self.decode_metadata()?;
+        }
+        let Some(stream_info) = self.stream_info else {
+            return self.get_status_as_error("FlacDecoderUnmovable::scan");
+        };
+        self.audio_skipped = true;
+        let step = ((step.as_nanos() * stream_info.sample_rate as u128 / 1_000_000_000) as u64).max(1);
+        let ret = if stream_info.total_samples > 0 {
+            self.scan_by_seeking(step, &stream_info, &mut f)
+        } else {
+            self.scan_by_skipping(step, &stream_info, &mut f)
+        };
+        self.captured_frames = None;
+        ret
+    }
+
+    /// ## The total samples per channel of the stream, counted by skipping through the frames if the STREAMINFO block doesn't know it
+    /// * If the STREAMINFO block has the total samples, it's returned without any I/O. The metadata is decoded first if it wasn't yet.
+    /// * Otherwise, e.g. the FLAC file was encoded from a live stream, every remaining frame header is read to count the block sizes.
+    ///   The frames aren't decoded, but the whole rest of the file is read through your `on_read()` closure, so it's slow for the large files.
+    ///   The result is kept, calling it again costs nothing.
+    /// * After counting, the `reader` is moved back through your `on_seek()` closure to where the next frame starts, even if the counting failed,
+    ///   so the decoding continues as if it wasn't called.
+    /// * If no frame was decoded yet, the decoder is reset to the start of the stream to go on from the first frame, the MD5 checking is kept then.
+    ///   Otherwise the MD5 checking is disabled, like `scan()`, since libFLAC can't check the frames decoded before the counting.
+    pub fn total_samples(&mut self) -> Result<u64, FlacDecoderError> {
+        if self.stream_info.is_none() {
+            self.decode_metadata()?;
+        }
+        let Some(stream_info) = self.stream_info else {
+            return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_END_OF_STREAM, "FlacDecoderUnmovable::total_samples"));
+        };
+        if let Some(total_samples) = stream_info.known_total_samples().or(self.counted_total_samples) {
+            return Ok(total_samples);
+        }
+        // Nothing was decoded or skipped yet, so the MD5 of the stream can still be checked from the first frame.
+        let md5_intact = self.frames_decoded == 0 && !self.audio_skipped;
+        let byte_position = self.decode_position()?;
+        let mut total_samples = self.last_frame_end.unwrap_or(0);
+        let ret = self.count_remaining_samples(&mut total_samples);
+        let restored = if md5_intact {self.rewind_to_first_frame()} else {self.resync_at(byte_position)};
+        ret?;
+        restored?;
+        self.counted_total_samples = Some(total_samples);
+        Ok(total_samples)
+    }
+
+    /// * Reset the decoder to the start of the stream and read the metadata again without keeping it twice, it turns the MD5 checking back on.
+    fn rewind_to_first_frame(&mut self) -> Result<(), FlacDecoderError> {
+        self.next_frame_byte = None;
+        if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_reset(decoder)) == 0} {
+            return self.get_status_as_error("FLAC__stream_decoder_reset");
+        }
+        self.replaying_metadata = true;
+        let read = unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_process_until_end_of_metadata(decoder))};
+        self.replaying_metadata = false;
+        if read == 0 {
+            return self.get_status_as_error("FLAC__stream_decoder_process_until_end_of_metadata");
+        }
+        self.audio_skipped = false;
+        Ok(())
+    }
+
+    /// * Skip all of the remaining frames, add their block sizes to `total_samples`.
+    fn count_remaining_samples(&mut self, total_samples: &mut u64) -> Result<(), FlacDecoderError> {
+        while !self.is_end_of_stream() && self.skip_frame()? && !self.is_end_of_stream() {
+            *total_samples += self.frame_header().blocksize as u64;
+        }
+        Ok(())
+    }
+
+    /// * Pass the captured frames to the `f` closure, returns the end position of the last frame.
+    fn flush_captured_frames<F>(&mut self, f: &mut F) -> Option<u64>
+    where
+        F: FnMut(u64, &[Vec<i32>], &SamplesInfo) {
+        let mut end = None;
+        for (position, samples, samples_info) in self.captured_frames.replace(Vec::new()).unwrap_or_default() {
+            f(position, &samples, &samples_info);
+            end = Some(position + samples_info.samples as u64);
+        }
+        end
+    }
+
+    fn scan_by_seeking<F>(&mut self, step: u64, stream_info: &FlacStreamInfo, f: &mut F) -> Result<(), FlacDecoderError>
+    where
+        F: FnMut(u64, &[Vec<i32>], &SamplesInfo) {
+        let total_samples = stream_info.total_samples;
+
+        // The start of the final frame is only known for the fixed block size.
+        let last_frame = if stream_info.min_blocksize == stream_info.max_blocksize && stream_info.max_blocksize > 0 {
+            (total_samples - 1) / stream_info.max_blocksize as u64 * stream_info.max_blocksize as u64
+        } else {
+            total_samples - 1
+        };
+        let mut end = 0;
+        for target in (0..total_samples).step_by(step as usize).chain([last_frame]) {
+            if target < end {
+                continue;
+            }
+            self.captured_frames = Some(Vec::new());
+            self.seek(target)?;
+            end = self.flush_captured_frames(f).unwrap_or(end);
+        }
+        Ok(())
+    }
+
+    fn scan_by_skipping<F>(&mut self, step: u64, stream_info: &FlacStreamInfo, f: &mut F) -> Result<(), FlacDecoderError>
+    where
+        F: FnMut(u64, &[Vec<i32>], &SamplesInfo) {
+        let max_blocksize = stream_info.max_blocksize.max(1) as u64;
+        let mut position = 0u64;
+        let mut target = 0u64;
+
+        // Where the last frame that was skipped is in bytes, to come back to the final frame.
+        let mut last_skipped = None;
+        let mut just_skipped = false;
+        self.captured_frames = Some(Vec::new());
+        loop {
+            if position + max_blocksize <= target {
+                let byte_position = self.decode_position()?;
+                if !self.skip_frame()? || self.is_end_of_stream() {
+                    break;
+                }
+                last_skipped = Some(byte_position);
+                just_skipped = true;
+                position += self.frame_header().blocksize as u64;
+            } else {
+                if just_skipped {
+                    // Otherwise the decoder fills the skipped frames with silence as the missing frames.
+                    let byte_position = self.decode_position()?;
+                    self.resync_at(byte_position)?;
+                    just_skipped = false;
+                }
+                if !self.process_single()? || self.is_end_of_stream() {
+                    break;
+                }
+                if let Some(end) = self.flush_captured_frames(f) {
+                    position = end;
+                    last_skipped = None;
+                    while target < position {
+                        target += step;
+                    }
+                }
+            }
+        }
+
+        // The final frame was skipped, go back to decode it.
+        if let Some(byte_position) = last_skipped {
+            self.resync_at(byte_position)?;
+            self.process_single()?;
+            self.flush_captured_frames(f);
+        }
+        Ok(())
+    }
+
+    /// * Flush the decoder and continue decoding from the byte position of a frame.
+    fn resync_at(&mut self, byte_position: u64) -> Result<(), FlacDecoderError> {
+        self.next_frame_byte = None;
+        unsafe {
+            if self.call_libflac(|decoder| FLAC__stream_decoder_flush(decoder)) == 0 {
+                return self.get_status_as_error("FLAC__stream_decoder_flush");
+            }
+        }
+        if self.call_on_seek(byte_position).is_err() {
+            return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_SEEK_ERROR, "FlacDecoderUnmovable::scan"));
+        }
+        Ok(())
+    }
+
+    /// * The byte position in the FLAC file where the next frame starts, after a frame is decoded or skipped.
+    pub(crate) fn decode_position(&mut self) -> Result<u64, FlacDecoderError> {
+        let mut byte_position = 0u64;
+        if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_get_decode_position(decoder, &mut byte_position)) == 0} {
+            return Err(FlacDecoderError::new(unsafe {FLAC__stream_decoder_get_state(self.decoder)}, "FLAC__stream_decoder_get_decode_position"));
+        }
+        Ok(byte_position)
+    }
+
+    /// * Skip one audio frame without decoding the samples, the frame header is still read, see `frame_header()`
+    /// * The MD5 checking is disabled since the audio is skipped.
+    pub(crate) fn skip_frame(&mut self) -> Result<bool, FlacDecoderError> {
+        self.audio_skipped = true;
+        self.next_frame_byte = None;
+        if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_skip_single_frame(decoder)) != 0} {
             Ok(true)
         } else {
-            match self.get_status_as_result("FLAC__stream_decoder_process_until_end_of_stream") {
+            match self.get_decode_status_as_result("FLAC__stream_decoder_skip_single_frame") {
                 Ok(_) => Ok(false),
                 Err(e) => Err(e),
             }
         }
     }
 
+    /// * Does the stream use the variable block size, `None` if no frame was decoded yet.
+    /// * It's told by the STREAMINFO block and the block sizes of the decoded frames, a stream whose frames all have the same size except the last one is a fixed block size stream.
+    /// * The frames of a variable block size stream can't be located by the frame number times the block size, seek them by the sample numbers, e.g. with the seek table.
+    pub fn is_variable_blocksize(&self) -> Option<bool> {
+        self.variable_blocksize
+    }
+
+    /// * The header of the last decoded or skipped frame.
+    pub(crate) fn frame_header(&self) -> FlacFrameHeader {
+        unsafe {
+            FlacFrameHeader {
+                blocksize: FLAC__stream_decoder_get_blocksize(self.decoder),
+                sample_rate: FLAC__stream_decoder_get_sample_rate(self.decoder),
+                channels: FLAC__stream_decoder_get_channels(self.decoder),
+                bits_per_sample: FLAC__stream_decoder_get_bits_per_sample(self.decoder),
+                channel_assignment: FLAC__stream_decoder_get_channel_assignment(self.decoder),
+            }
+        }
+    }
+
     /// * Finish decoding the FLAC file, the remaining samples will be returned to you via your `on_write()` closure.
     pub fn finish(&mut self) -> Result<(), FlacDecoderError> {
         if !self.finished {
-            if unsafe {FLAC__stream_decoder_finish(self.decoder) != 0} {
+            // The remainder of the batch, if the decoding stopped before the end of the stream.
+            if let Err(e) = self.flush_batch() {
+                eprintln!("On `write_callback()`: {:?}", e);
+            }
+            if unsafe {self.call_libflac(|decoder| FLAC__stream_decoder_finish(decoder)) != 0} || self.audio_skipped {
+                // If some audio was skipped by `scan()`, the MD5 checking failure doesn't count.
                 self.finished = true;
                 Ok(())
             } else {
@@ -2117,7 +5808,7 @@ where
     pub fn finalize(self) {}
 }
 
-impl<'a, ReadSeek> Debug for FlacDecoderUnmovable<'_, ReadSeek>
+impl<ReadSeek> Debug for FlacDecoderUnmovable<'_, ReadSeek>
 where
     ReadSeek: Read + Seek + Debug {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
@@ -2131,21 +5822,42 @@ where
             .field("on_eof", &"{{closure}}")
             .field("on_write", &"{{closure}}")
             .field("on_error", &"{{closure}}")
+            .field("on_end_of_stream", &self.on_end_of_stream.as_ref().map(|_| "{{closure}}"))
+            .field("on_error_with_context", &self.on_error_with_context.as_ref().map(|_| "{{closure}}"))
+            .field("frames_decoded", &self.frames_decoded)
+            .field("samples_decoded", &self.samples_decoded)
+            .field("last_frame_end", &self.last_frame_end)
+            .field("last_frame_position", &self.last_frame_position)
             .field("md5_checking", &self.md5_checking)
             .field("finished", &self.finished)
             .field("scale_to_i32_range", &self.scale_to_i32_range)
+            .field("i32_scaling", &self.i32_scaling)
             .field("desired_audio_form", &self.desired_audio_form)
             .field("vendor_string", &self.vendor_string)
             .field("comments", &self.comments)
             .field("pictures", &self.pictures)
+            .field("applications", &self.applications)
             .field("cue_sheets", &self.cue_sheets)
+            .field("seek_points", &self.seek_points)
+            .field("cancel_token", &self.cancel_token)
+            .field("cancelled", &self.cancelled)
+            .field("stopped_by_caller", &self.stopped_by_caller)
+            .field("limits", &self.limits)
+            .field("aborted_by", &self.aborted_by)
+            .field("channels_wanted", &self.channels_wanted)
+            .field("prebuffer_frames", &self.prebuffer_frames)
+            .field("batch_frames", &self.batch_frames)
+            .field("collect_frame_crcs", &self.collect_frame_crcs)
+            .field("profile", &self.profile)
+            .field("events", &self.events)
+            .field("io_stats", &self.io_stats)
             .finish()
     }
 }
 
-impl<'a, ReadSeek> Drop for FlacDecoderUnmovable<'_, ReadSeek>
+impl<ReadSeek> Drop for FlacDecoderUnmovable<'_, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     fn drop(&mut self) {
         self.on_drop();
     }
@@ -2155,13 +5867,13 @@ where
 /// This is the struct that should be mainly used by you.
 pub struct FlacDecoder<'a, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     decoder: Box<FlacDecoderUnmovable<'a, ReadSeek>>,
 }
 
 impl<'a, ReadSeek> FlacDecoder<'a, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     pub fn new(
         reader: ReadSeek,
         on_read: Box<dyn FnMut(&mut ReadSeek, &mut [u8]) -> (usize, FlacReadStatus) + 'a>,
@@ -2194,11 +5906,64 @@ where
         Ok(ret)
     }
 
+    /// * Create a decoder with the default `on_read()`, `on_seek()`, `on_tell()`, `on_length()` and `on_eof()` closures that just operate the `reader`.
+    /// * The length of the `reader` is retrieved once here by seeking to the end of it, and again by `reset_with_reader()`.
+    pub(crate) fn with_default_io(
+        mut reader: ReadSeek,
+        on_write: Box<dyn FnMut(&[Vec<i32>], &SamplesInfo) -> Result<(), io::Error> + 'a>,
+        on_error: Box<dyn FnMut(FlacInternalDecoderError) + 'a>,
+        md5_checking: bool,
+        scale_to_i32_range: bool,
+        desired_audio_form: FlacAudioForm,
+    ) -> Result<Self, FlacDecoderError> {
+        let length = match get_reader_length(&mut reader) {
+            Ok(length) => Arc::new(AtomicU64::new(length)),
+            Err(_) => return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_SEEK_ERROR, "FlacDecoder::with_default_io")),
+        };
+        let eof_length = length.clone();
+        let mut ret = Self::new(
+            reader,
+            Box::new(|reader: &mut ReadSeek, data: &mut [u8]| -> (usize, FlacReadStatus) {
+                loop {
+                    match reader.read(data) {
+                        Ok(0) => return (0, FlacReadStatus::Eof),
+                        Ok(size) => return (size, FlacReadStatus::GoOn),
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => return (0, FlacReadStatus::Abort),
+                    }
+                }
+            }),
+            Box::new(|reader: &mut ReadSeek, position: u64| -> Result<(), io::Error> {
+                reader.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|reader: &mut ReadSeek| -> Result<u64, io::Error> {
+                reader.stream_position()
+            }),
+            {
+                let length = length.clone();
+                Box::new(move |_reader: &mut ReadSeek| -> Result<u64, io::Error> {
+                    Ok(length.load(Ordering::Relaxed))
+                })
+            },
+            Box::new(move |reader: &mut ReadSeek| -> bool {
+                reader.stream_position().map_or(true, |position| position >= eof_length.load(Ordering::Relaxed))
+            }),
+            on_write,
+            on_error,
+            md5_checking,
+            scale_to_i32_range,
+            desired_audio_form,
+        )?;
+        ret.decoder.default_io_length = Some(length);
+        Ok(ret)
+    }
+
     /// * Call this function if you don't want the decoder anymore.
     pub fn finalize(self) {}
 }
 
-impl<'a, ReadSeek> Debug for FlacDecoder<'_, ReadSeek>
+impl<ReadSeek> Debug for FlacDecoder<'_, ReadSeek>
 where
     ReadSeek: Read + Seek + Debug {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
@@ -2210,7 +5975,7 @@ where
 
 impl<'a, ReadSeek> Deref for FlacDecoder<'a, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     type Target = FlacDecoderUnmovable<'a, ReadSeek>;
     fn deref(&self) -> &FlacDecoderUnmovable<'a, ReadSeek> {
         &self.decoder
@@ -2219,12 +5984,267 @@ where
 
 impl<'a, ReadSeek> DerefMut for FlacDecoder<'a, ReadSeek>
 where
-    ReadSeek: Read + Seek + Debug {
+    ReadSeek: Read + Seek {
     fn deref_mut(&mut self) -> &mut FlacDecoderUnmovable<'a, ReadSeek> {
         &mut self.decoder
     }
 }
 
+/// ## The object-safe `Read + Seek`, for choosing the reader at runtime, e.g. a file, a network adapter or the memory, see `DynFlacDecoder`
+/// * Every `Read + Seek` type implements it.
+pub trait ReadSeekAny: Read + Seek {}
+
+impl<T> ReadSeekAny for T
+where
+    T: Read + Seek + ?Sized {}
+
+impl Debug for dyn ReadSeekAny + '_ {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str("dyn ReadSeekAny")
+    }
+}
+
+/// ## The `FlacDecoder` over the boxed reader, one decoder type for all of the readers, see `FlacDecoder::new_dyn()`
+/// * Each IO operation costs one virtual call to the reader, the closures take the box itself.
+/// ```no_run
+/// use std::{fs::File, io::{self, Cursor}};
+/// use flac::{DynFlacDecoder, FlacDecoder, ReadSeekAny};
+/// use flac::options::FlacAudioForm;
+///
+/// // The source is chosen at runtime, the decoder type stays the same.
+/// fn open_source(path: Option<&str>, cached: Vec<u8>) -> io::Result<Box<dyn ReadSeekAny>> {
+///     Ok(match path {
+///         Some(path) => Box::new(File::open(path)?),
+///         None => Box::new(Cursor::new(cached)),
+///     })
+/// }
+///
+/// let reader = open_source(std::env::args().nth(1).as_deref(), Vec::new()).unwrap();
+/// let mut decoder: DynFlacDecoder = FlacDecoder::new_dyn(
+///     reader,
+///     Box::new(|_frames, samples_info| {
+///         println!("{} samples", samples_info.samples);
+///         Ok(())
+///     }),
+///     Box::new(|error| eprintln!("{error}")),
+///     true,
+///     false,
+///     FlacAudioForm::FrameArray,
+/// ).unwrap();
+/// decoder.decode_all().into_result().unwrap();
+/// ```
+pub type DynFlacDecoder<'a> = FlacDecoder<'a, Box<dyn ReadSeekAny + 'a>>;
+
+impl<'a> FlacDecoder<'a, Box<dyn ReadSeekAny + 'a>> {
+    /// * Create the decoder over the boxed reader, the closures read, seek, tell and get the length by the reader itself.
+    /// * The length of the `reader` is retrieved once here by seeking to the end of it.
+    pub fn new_dyn(
+        reader: Box<dyn ReadSeekAny + 'a>,
+        on_write: Box<dyn FnMut(&[Vec<i32>], &SamplesInfo) -> Result<(), io::Error> + 'a>,
+        on_error: Box<dyn FnMut(FlacInternalDecoderError) + 'a>,
+        md5_checking: bool,
+        scale_to_i32_range: bool,
+        desired_audio_form: FlacAudioForm,
+    ) -> Result<Self, FlacDecoderError> {
+        Self::with_default_io(reader, on_write, on_error, md5_checking, scale_to_i32_range, desired_audio_form)
+    }
+}
+
+impl<'a> FlacDecoder<'a, BufReader<File>> {
+    /// ## Open the FLAC file at `path` for decoding, the closures read, seek, tell and get the length by the buffered file itself
+    /// * The length of the file is retrieved once here, then the decoder is the same as the one created by `new()` with such closures.
+    /// * Fails with `FLAC_DECODER_OPEN_FAILED` if the file can't be opened, and `io_error_kind` tells why.
+    /// ```no_run
+    /// use flac::FlacDecoder;
+    /// use flac::options::FlacAudioForm;
+    ///
+    /// let mut decoder = FlacDecoder::open(
+    ///     "test.flac",
+    ///     true,
+    ///     false,
+    ///     FlacAudioForm::FrameArray,
+    ///     Box::new(|_frames, samples_info| {
+    ///         println!("{} samples", samples_info.samples);
+    ///         Ok(())
+    ///     }),
+    ///     Box::new(|error| eprintln!("{error}")),
+    /// ).unwrap();
+    /// decoder.decode_all().into_result().unwrap();
+    /// decoder.finish().unwrap();
+    /// ```
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        md5_checking: bool,
+        scale_to_i32_range: bool,
+        desired_audio_form: FlacAudioForm,
+        on_write: Box<dyn FnMut(&[Vec<i32>], &SamplesInfo) -> Result<(), io::Error> + 'a>,
+        on_error: Box<dyn FnMut(FlacInternalDecoderError) + 'a>,
+    ) -> Result<Self, FlacDecoderError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(FlacDecoderError {
+                io_error_kind: Some(e.kind()),
+                ..FlacDecoderError::new(FLAC_DECODER_OPEN_FAILED, "FlacDecoder::open")
+            }),
+        };
+        Self::with_default_io(BufReader::new(file), on_write, on_error, md5_checking, scale_to_i32_range, desired_audio_form)
+    }
+}
+
+/// ## A `FlacDecoder` that can be sent to another thread, e.g. to decode on a worker thread.
+/// * `FlacDecoder` isn't `Send` because it holds the libFLAC decoder as a raw pointer, and your closures may not be `Send`.
+///   The libFLAC decoder has no thread affinity, it just must not be used by two threads at the same time.
+///   `SendFlacDecoder` only takes the `Send` closures, and it's not `Sync`, so it's used by one thread at a time.
+/// * It's `Send` only if the `reader` is `Send`:
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<flac::SendFlacDecoder<'static, std::io::Cursor<std::rc::Rc<[u8]>>>>();
+/// ```
+/// * The plain `FlacDecoder` is never `Send`:
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<flac::FlacDecoder<'static, std::io::Cursor<Vec<u8>>>>();
+/// ```
+/// * It only derefs to `&FlacDecoder`, the decoder with the closures that aren't `Send` can't be put into it:
+/// ```compile_fail
+/// use std::{fs::File, io::BufReader, rc::Rc};
+/// use flac::{FlacDecoder, SendFlacDecoder};
+/// use flac::options::FlacAudioForm;
+///
+/// fn replace(mut send_dec: SendFlacDecoder<'static, BufReader<File>>) -> SendFlacDecoder<'static, BufReader<File>> {
+///     let rc = Rc::new(());
+///     *send_dec = FlacDecoder::open(
+///         "test.flac",
+///         true,
+///         false,
+///         FlacAudioForm::FrameArray,
+///         Box::new(move |_, _| {let _ = rc.clone(); Ok(())}),
+///         Box::new(|_| {}),
+///     ).unwrap();
+///     send_dec
+/// }
+/// ```
+pub struct SendFlacDecoder<'a, ReadSeek>(FlacDecoder<'a, ReadSeek>)
+where
+    ReadSeek: Read + Seek;
+
+// SAFETY: All of the closures are `Send`, the `on_end_of_stream()` and `on_error_with_context()` closures are only set by our `Send`-bounded setters, and the `reader` is `Send`.
+// We only deref to `&FlacDecoder`, so the decoder can't be replaced by one with the closures that aren't `Send`.
+// The libFLAC decoder and the pointers to our own boxed struct are only used by the thread that owns us.
+unsafe impl<ReadSeek> Send for SendFlacDecoder<'_, ReadSeek>
+where
+    ReadSeek: Read + Seek + Send {}
+
+impl<'a, ReadSeek> SendFlacDecoder<'a, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    /// * The same as `FlacDecoder::new()`, but the closures must be `Send`.
+    pub fn new(
+        reader: ReadSeek,
+        on_read: Box<dyn FnMut(&mut ReadSeek, &mut [u8]) -> (usize, FlacReadStatus) + Send + 'a>,
+        on_seek: Box<dyn FnMut(&mut ReadSeek, u64) -> Result<(), io::Error> + Send + 'a>,
+        on_tell: Box<dyn FnMut(&mut ReadSeek) -> Result<u64, io::Error> + Send + 'a>,
+        on_length: Box<dyn FnMut(&mut ReadSeek) -> Result<u64, io::Error> + Send + 'a>,
+        on_eof: Box<dyn FnMut(&mut ReadSeek) -> bool + Send + 'a>,
+        on_write: Box<dyn FnMut(&[Vec<i32>], &SamplesInfo) -> Result<(), io::Error> + Send + 'a>,
+        on_error: Box<dyn FnMut(FlacInternalDecoderError) + Send + 'a>,
+        md5_checking: bool,
+        scale_to_i32_range: bool,
+        desired_audio_form: FlacAudioForm,
+    ) -> Result<Self, FlacDecoderError> {
+        let decoder = FlacDecoder::new(
+            reader,
+            on_read,
+            on_seek,
+            on_tell,
+            on_length,
+            on_eof,
+            on_write,
+            on_error,
+            md5_checking,
+            scale_to_i32_range,
+            desired_audio_form,
+        )?;
+        Ok(Self(decoder))
+    }
+
+    /// * The same as `FlacDecoderUnmovable::set_on_error_with_context()`, but the closure must be `Send`.
+    pub fn set_on_error_with_context(&mut self, on_error_with_context: Option<Box<dyn FnMut(&DecodeErrorContext) + Send + 'a>>) {
+        self.0.decoder.on_error_with_context = on_error_with_context.map(|on_error_with_context| on_error_with_context as Box<dyn FnMut(&DecodeErrorContext) + 'a>);
+    }
+
+    /// * The same as `FlacDecoderUnmovable::set_on_end_of_stream()`, but the closure must be `Send`.
+    pub fn set_on_end_of_stream(&mut self, on_end_of_stream: Option<Box<dyn FnMut() + Send + 'a>>) {
+        self.0.decoder.on_end_of_stream = on_end_of_stream.map(|on_end_of_stream| on_end_of_stream as Box<dyn FnMut() + 'a>);
+    }
+
+    forward_mut! {FlacDecoderUnmovable;
+        initialize() -> Result<(), FlacDecoderError>;
+        reset_with_reader(new_reader: ReadSeek) -> Result<(), FlacDecoderError>;
+        seek(frame_index: u64) -> Result<(), FlacDecoderError>;
+        read_at(start_sample: u64, n: usize) -> Result<Vec<Vec<i32>>, FlacDecoderError>;
+        tell() -> Result<u64, io::Error>;
+        set_io_stats_enabled(enabled: bool);
+        reset_io_stats();
+        length() -> Result<u64, io::Error>;
+        eof() -> bool;
+        set_metadata_respond(block_types: Option<&[FlacMetadataType]>) -> Result<(), FlacDecoderError>;
+        set_stream_info_respond(stream_info_respond: bool) -> Result<(), FlacDecoderError>;
+        set_profile(profile: DecoderProfile) -> Result<(), FlacDecoderError>;
+        set_container(container: FlacContainer) -> Result<(), FlacDecoderError>;
+        set_stop_after_picture(picture_type: Option<FlacPictureType>);
+        set_cancel_token(cancel_token: Option<Arc<AtomicBool>>);
+        set_limits(limits: DecoderLimits);
+        set_channels_wanted(channels_wanted: Option<&[usize]>) -> Result<(), FlacDecoderError>;
+        set_prebuffer_frames(frames: usize);
+        set_batch_frames(frames: usize);
+        set_collect_frame_crcs(collect_frame_crcs: bool);
+        select_channels(channels: &[usize]) -> Result<(), FlacDecoderError>;
+        decode() -> Result<DecodeStep, FlacDecoderError>;
+        decode_metadata() -> Result<bool, FlacDecoderError>;
+        decode_all() -> DecodeOutcome;
+        next_event() -> Result<DecodeEvent, FlacDecoderError>;
+        total_samples() -> Result<u64, FlacDecoderError>;
+        finish() -> Result<(), FlacDecoderError>;
+    }
+
+    /// * The same as `FlacDecoderUnmovable::audio_frames()`.
+    pub fn audio_frames(&mut self) -> AudioFrames<'_, 'a, ReadSeek> {
+        self.0.audio_frames()
+    }
+
+    /// * The same as `FlacDecoderUnmovable::scan()`.
+    pub fn scan<F>(&mut self, step: Duration, f: F) -> Result<(), FlacDecoderError>
+    where
+        F: FnMut(u64, &[Vec<i32>], &SamplesInfo) {
+        self.0.scan(step, f)
+    }
+
+    /// * Get the `FlacDecoder` back, it can't be sent to another thread anymore.
+    pub fn into_inner(self) -> FlacDecoder<'a, ReadSeek> {
+        self.0
+    }
+}
+
+impl<ReadSeek> Debug for SendFlacDecoder<'_, ReadSeek>
+where
+    ReadSeek: Read + Seek + Debug {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple("SendFlacDecoder")
+            .field(&self.0)
+            .finish()
+    }
+}
+
+impl<'a, ReadSeek> Deref for SendFlacDecoder<'a, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    type Target = FlacDecoder<'a, ReadSeek>;
+    fn deref(&self) -> &FlacDecoder<'a, ReadSeek> {
+        &self.0
+    }
+}
+
 #[derive(Clone, Copy)]
 struct WrappedStreamInfo(FLAC__StreamMetadata_StreamInfo);
 
@@ -2239,7 +6259,7 @@ impl Debug for WrappedStreamInfo {
             .field("channels", &self.0.channels)
             .field("bits_per_sample", &self.0.bits_per_sample)
             .field("total_samples", &self.0.total_samples)
-            .field("md5sum", &format_args!("{}", self.0.md5sum.iter().map(|x|{format!("{:02x}", x)}).collect::<Vec<String>>().join("")))
+            .field("md5sum", &format_args!("{}", to_hex(&self.0.md5sum)))
             .finish()
     }
 }
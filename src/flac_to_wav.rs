@@ -0,0 +1,233 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo, FlacStreamInfo, FlacApplication};
+use crate::foreign_chunks::{ForeignLayout, RIFF_APPLICATION_ID};
+use crate::decode_i16::convert_to_u8;
+
+/// ## What `flac_to_wav()` does with the `riff` APPLICATION blocks kept by `store_foreign_metadata()`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForeignMetadata {
+    /// * Rebuild the original WAV file from the blocks, or write the plain WAV file if there are none or they're malformed.
+    #[default]
+    Restore,
+
+    /// * Rebuild the original WAV file from the blocks, fail with `FlacToWavError::MalformedForeignMetadata` if they're malformed.
+    Strict,
+
+    /// * Always write the plain WAV file.
+    Ignore,
+}
+
+/// ## Why `flac_to_wav()` failed
+#[derive(Debug)]
+pub enum FlacToWavError {
+    /// * Failed to decode the FLAC file.
+    Decoder(FlacDecoderError),
+
+    /// * Failed to write the WAV file.
+    Io(io::Error),
+
+    /// * The `riff` APPLICATION blocks can't rebuild the WAV file, e.g. a chunk is cut, or the size of the `data` chunk doesn't match the audio.
+    MalformedForeignMetadata(io::Error),
+
+    /// * The FLAC file is damaged, the first error the decoder reported, the damaged frames were written as decoded.
+    Stream(FlacInternalDecoderError),
+
+    /// * The audio is too large for the 32-bit sizes of the WAV file, the size of the audio in bytes.
+    TooLarge(u64),
+}
+
+impl Display for FlacToWavError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Decoder(error) => write!(f, "{error}"),
+            Self::Io(error) => write!(f, "IO error: {error}"),
+            Self::MalformedForeignMetadata(error) => write!(f, "Malformed foreign metadata: {error}"),
+            Self::Stream(error) => write!(f, "Damaged FLAC file: {error}"),
+            Self::TooLarge(size) => write!(f, "The audio of {size} bytes is too large for the WAV file"),
+        }
+    }
+}
+
+impl Error for FlacToWavError {}
+
+impl From<FlacDecoderError> for FlacToWavError {
+    fn from(error: FlacDecoderError) -> Self {
+        Self::Decoder(error)
+    }
+}
+
+impl From<io::Error> for FlacToWavError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// ## Decode the FLAC file from the `reader` into the WAV file in the `writer`, returns the samples per channel
+/// * If the FLAC file was encoded with the `riff` APPLICATION blocks of `store_foreign_metadata()`, the original chunks are put back around the audio in their order,
+///   so the WAV file is byte-identical to the original one, see `restore_foreign_metadata()` for the padding byte of the odd-sized audio.
+/// * Otherwise the plain WAV file is written, `WAVE_FORMAT_EXTENSIBLE` is used for more than 2 channels or the bit depths other than 8 and 16.
+/// * The samples are written as they're decoded, the sizes in the header of the plain WAV file are patched by seeking back at the end, if the total samples is unknown.
+/// * The malformed blocks, and the audio too large for the WAV file whose total samples is known, are found before anything is written.
+///   The `riff` blocks can't be used if the total samples is unknown, they're treated as malformed.
+/// * Fails with `FlacToWavError::Stream` if the decoder reported an error, e.g. a frame CRC mismatch, the WAV file is left unfinished then.
+pub fn flac_to_wav<ReadSeek, Writer>(reader: ReadSeek, writer: Writer, foreign_metadata: ForeignMetadata) -> Result<u64, FlacToWavError>
+where
+    ReadSeek: Read + Seek,
+    Writer: Write + Seek {
+    let writer = RefCell::new(writer);
+    let write_error = RefCell::new(None::<io::Error>);
+    let stream_error = Cell::new(None::<FlacInternalDecoderError>);
+    let audio_len = Cell::new(0u64);
+    let mut decoder = FlacDecoder::with_default_io(
+        reader,
+        Box::new(|frames: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+            let bits_per_sample = samples_info.bits_per_sample;
+            let mut audio = Vec::with_capacity(frames.len() * frames.first().map_or(0, |frame| frame.len()) * container_bytes(bits_per_sample) as usize);
+            for &sample in frames.iter().flatten() {
+                write_sample(&mut audio, sample, bits_per_sample);
+            }
+            if let Err(error) = writer.borrow_mut().write_all(&audio) {
+                let kind = error.kind();
+                *write_error.borrow_mut() = Some(error);
+                return Err(kind.into());
+            }
+            audio_len.set(audio_len.get() + audio.len() as u64);
+            Ok(())
+        }),
+        Box::new(|error: FlacInternalDecoderError| {
+            if stream_error.get().is_none() {
+                stream_error.set(Some(error));
+            }
+        }),
+        true,
+        false,
+        FlacAudioForm::FrameArray,
+    )?;
+    decoder.decode_metadata()?;
+    let Some(stream_info) = decoder.get_stream_info().copied() else {
+        return Err(FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_END_OF_STREAM, "flac_to_wav").into());
+    };
+    let expected_len = stream_info.known_total_samples().map(|total_samples| total_samples * stream_info.channels as u64 * container_bytes(stream_info.bits_per_sample) as u64);
+    let riff_blocks: Vec<FlacApplication> = decoder.get_applications().iter().filter(|application| application.id == RIFF_APPLICATION_ID).cloned().collect();
+
+    let mut layout = None;
+    if foreign_metadata != ForeignMetadata::Ignore && !riff_blocks.is_empty() {
+        let checked = match expected_len {
+            Some(expected_len) => ForeignLayout::new(&riff_blocks, expected_len),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "The total samples is unknown")),
+        };
+        match checked {
+            Ok(checked) => layout = Some(checked),
+            Err(error) if foreign_metadata == ForeignMetadata::Strict => return Err(FlacToWavError::MalformedForeignMetadata(error)),
+            Err(_) => (),
+        }
+    }
+    let header = if let Some(layout) = &layout {
+        layout.write_before_audio(&mut *writer.borrow_mut())?;
+        None
+    } else {
+        let data_size = expected_len.unwrap_or(0);
+        let fmt = wav_fmt(&stream_info);
+        riff_size(&fmt, data_size)?;
+        let mut writer = writer.borrow_mut();
+        let start = writer.stream_position()?;
+        write_wav_header(&mut *writer, &fmt, data_size as u32)?;
+        Some((start, fmt))
+    };
+
+    let outcome = decoder.decode_all();
+    if let Some(error) = write_error.take() {
+        return Err(error.into());
+    }
+    if let Some(error) = stream_error.get() {
+        return Err(FlacToWavError::Stream(error));
+    }
+    let samples = outcome.into_result()?;
+    decoder.finish()?;
+    drop(decoder);
+    let mut writer = writer.into_inner();
+    let audio_len = audio_len.get();
+    if let Some(layout) = &layout {
+        layout.write_after_audio(&mut writer)?;
+    } else if let Some((start, fmt)) = header {
+        if audio_len % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+        // The total samples was unknown, patch the sizes.
+        if expected_len != Some(audio_len) {
+            riff_size(&fmt, audio_len)?;
+            let end = writer.stream_position()?;
+            writer.seek(SeekFrom::Start(start))?;
+            write_wav_header(&mut writer, &fmt, audio_len as u32)?;
+            writer.seek(SeekFrom::Start(end))?;
+        }
+    }
+    Ok(samples)
+}
+
+/// * The bytes of each sample in the WAV file.
+fn container_bytes(bits_per_sample: u32) -> u32 {
+    bits_per_sample.div_ceil(8)
+}
+
+/// * Append the sample as the little-endian bytes, left-justified in its container bytes, the 8-bit samples are unsigned.
+fn write_sample(audio: &mut Vec<u8>, sample: i32, bits_per_sample: u32) {
+    let bytes = container_bytes(bits_per_sample);
+    if bytes == 1 {
+        audio.push(convert_to_u8(sample, bits_per_sample));
+    } else {
+        let sample = sample << (bytes * 8 - bits_per_sample);
+        audio.extend_from_slice(&sample.to_le_bytes()[..bytes as usize]);
+    }
+}
+
+/// * The payload of the `fmt ` chunk of the plain WAV file.
+fn wav_fmt(stream_info: &FlacStreamInfo) -> Vec<u8> {
+    let channels = stream_info.channels;
+    let bits_per_sample = stream_info.bits_per_sample;
+    let block_align = channels * container_bytes(bits_per_sample);
+    let extensible = channels > 2 || (bits_per_sample != 8 && bits_per_sample != 16);
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&(if extensible {0xFFFEu16} else {1u16}).to_le_bytes());
+    fmt.extend_from_slice(&(channels as u16).to_le_bytes());
+    fmt.extend_from_slice(&stream_info.sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(stream_info.sample_rate * block_align).to_le_bytes());
+    fmt.extend_from_slice(&(block_align as u16).to_le_bytes());
+    fmt.extend_from_slice(&(container_bytes(bits_per_sample) as u16 * 8).to_le_bytes());
+    if extensible {
+        // The valid bits, the channel mask of the FLAC channel order, and the GUID of `KSDATAFORMAT_SUBTYPE_PCM`.
+        const CHANNEL_MASKS: [u32; 8] = [0x4, 0x3, 0x7, 0x33, 0x37, 0x3F, 0x70F, 0x63F];
+        fmt.extend_from_slice(&22u16.to_le_bytes());
+        fmt.extend_from_slice(&(bits_per_sample as u16).to_le_bytes());
+        fmt.extend_from_slice(&CHANNEL_MASKS.get(channels as usize - 1).copied().unwrap_or(0).to_le_bytes());
+        fmt.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+    }
+    fmt
+}
+
+/// * The size in the `RIFF` header of the plain WAV file, `FlacToWavError::TooLarge` if it doesn't fit in 32 bits.
+fn riff_size(fmt: &[u8], data_size: u64) -> Result<u32, FlacToWavError> {
+    let riff_size = 4 + 8 + fmt.len() as u64 + 8 + data_size + (data_size & 1);
+    u32::try_from(riff_size).map_err(|_| FlacToWavError::TooLarge(data_size))
+}
+
+/// * Write the `RIFF` header, the `fmt ` chunk and the `data` chunk header of the plain WAV file, the sizes are checked by `riff_size()`.
+fn write_wav_header<Writer: Write>(writer: &mut Writer, fmt: &[u8], data_size: u32) -> io::Result<()> {
+    let riff_size = 4 + 8 + fmt.len() as u32 + 8 + data_size + (data_size & 1);
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&riff_size.to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&(fmt.len() as u32).to_le_bytes())?;
+    writer.write_all(fmt)?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())
+}
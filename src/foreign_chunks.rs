@@ -0,0 +1,194 @@
+#![allow(dead_code)]
+
+use std::io::{self, Read, Write};
+
+use crate::flac::FlacApplication;
+
+/// ## The APPLICATION ID for storing the RIFF chunks of the WAV file, the same as `flac --keep-foreign-metadata`
+pub const RIFF_APPLICATION_ID: [u8; 4] = *b"riff";
+
+/// ## The APPLICATION ID for storing the chunks of the AIFF file, the same as `flac --keep-foreign-metadata`
+pub const AIFF_APPLICATION_ID: [u8; 4] = *b"aiff";
+
+/// ## Read everything but the audio of the WAV or AIFF file as the APPLICATION blocks, as `flac --keep-foreign-metadata` does
+/// * The WAV file gets the `riff` blocks, the AIFF or AIFF-C file gets the `aiff` blocks, add them to the encoder by `add_application()` in order.
+/// * The first block is the 12-byte container header, e.g. `RIFF`, the size, `WAVE`, then each chunk before the audio is a whole block, including `fmt ` or `COMM`.
+///   The audio chunk becomes its header only, 8 bytes of `data` and the size, or 16 bytes of `SSND`, the size, the offset and the block size.
+///   Then each chunk after the audio is a whole block.
+/// * The chunks are kept byte by byte, including the padding byte of an odd-sized chunk, `restore_foreign_metadata()` puts them back around the audio.
+///   The odd-sized chunk at the end of the file without its padding byte is kept without it too.
+/// * The `reader` must be at the start of the file, it's read through once and never seeked, the audio is skipped by reading it.
+pub fn store_foreign_metadata<Reader>(mut reader: Reader) -> io::Result<Vec<FlacApplication>>
+where
+    Reader: Read {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    let Some(id) = container_of(&header) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a RIFF/WAVE or an AIFF file"));
+    };
+    let mut blocks = vec![FlacApplication{id, data: header.to_vec()}];
+    let mut audio_found = false;
+    read_chunks(&mut reader, id, |chunk_header, chunk| {
+        audio_found |= audio_header_len(id, chunk_header) > 0;
+        blocks.push(FlacApplication{id, data: chunk.to_vec()});
+    })?;
+    if !audio_found {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No audio chunk"));
+    }
+    Ok(blocks)
+}
+
+/// ## Write the WAV or AIFF file back from the APPLICATION blocks of `store_foreign_metadata()` and the `audio`
+/// * The `audio` is the raw bytes of the audio chunk as in the original file, i.e. the little-endian PCM for WAV, the big-endian PCM for AIFF.
+/// * The blocks with other IDs are ignored, so all of the APPLICATION blocks of the FLAC file can be passed in.
+/// * The blocks are written in order with the `audio` after the audio chunk header, so the file is byte-identical to the original one,
+///   except that the padding byte after the odd-sized audio is always written as zero, and it's written even if the original file lacked it at the end.
+/// * Fails with `io::ErrorKind::InvalidData` before anything is written if the blocks are malformed, e.g. a chunk is cut,
+///   or the size of the audio chunk doesn't match the length of the `audio`.
+pub fn restore_foreign_metadata<Writer>(applications: &[FlacApplication], audio: &[u8], mut writer: Writer) -> io::Result<()>
+where
+    Writer: Write {
+    let layout = ForeignLayout::new(applications, audio.len() as u64)?;
+    layout.write_before_audio(&mut writer)?;
+    writer.write_all(audio)?;
+    layout.write_after_audio(&mut writer)
+}
+
+/// * The checked blocks of `store_foreign_metadata()` for writing them around the audio, for streaming the audio in between.
+pub(crate) struct ForeignLayout<'a> {
+    blocks: Vec<&'a [u8]>,
+    audio_index: usize,
+    audio_len: u64,
+}
+
+impl<'a> ForeignLayout<'a> {
+    /// * Check the blocks against the length of the audio in bytes, fails with `io::ErrorKind::InvalidData` if they're malformed.
+    pub(crate) fn new(applications: &'a [FlacApplication], audio_len: u64) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+        let Some(id) = applications.iter().map(|application| application.id).find(|id| *id == RIFF_APPLICATION_ID || *id == AIFF_APPLICATION_ID) else {
+            return Err(invalid("No foreign metadata"));
+        };
+        let blocks: Vec<&[u8]> = applications.iter().filter(|application| application.id == id).map(|application| &application.data[..]).collect();
+        if blocks[0].len() != 12 || container_of(blocks[0]) != Some(id) {
+            return Err(invalid("The first block isn't the container header"));
+        }
+
+        let mut audio_index = None;
+        for (index, block) in blocks.iter().enumerate().skip(1) {
+            if block.len() < 8 {
+                return Err(invalid("The chunk header is cut"));
+            }
+            let size = chunk_size(id, block);
+            let audio_header_len = audio_header_len(id, block);
+            if audio_header_len > 0 && audio_index.is_none() {
+                if block.len() != audio_header_len {
+                    return Err(invalid("The audio chunk header has a wrong length"));
+                }
+                if size != audio_len + audio_header_len as u64 - 8 {
+                    return Err(invalid("The size of the audio chunk doesn't match the audio"));
+                }
+                audio_index = Some(index);
+            } else if block.len() as u64 != 8 + size + (size & 1) {
+                // The padding byte of the odd-sized chunk at the end of the file is often missing, `store_foreign_metadata()` keeps it so.
+                let unpadded_last = index == blocks.len() - 1 && block.len() as u64 == 8 + size;
+                if !unpadded_last {
+                    return Err(invalid("The chunk doesn't match its size"));
+                }
+            }
+        }
+        let Some(audio_index) = audio_index else {
+            return Err(invalid("No audio chunk header"));
+        };
+        Ok(Self {blocks, audio_index, audio_len})
+    }
+
+    /// * Write the blocks till the audio chunk header.
+    pub(crate) fn write_before_audio<Writer: Write>(&self, writer: &mut Writer) -> io::Result<()> {
+        for block in self.blocks[..=self.audio_index].iter() {
+            writer.write_all(block)?;
+        }
+        Ok(())
+    }
+
+    /// * Write the padding byte of the odd-sized audio and the blocks after the audio.
+    pub(crate) fn write_after_audio<Writer: Write>(&self, writer: &mut Writer) -> io::Result<()> {
+        if self.audio_len % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+        for block in self.blocks[self.audio_index + 1..].iter() {
+            writer.write_all(block)?;
+        }
+        Ok(())
+    }
+}
+
+/// * The APPLICATION ID for the container of the 12-byte header, `None` if it's neither WAV nor AIFF.
+fn container_of(header: &[u8]) -> Option<[u8; 4]> {
+    match (&header[0..4], &header[8..12]) {
+        (b"RIFF", b"WAVE") => Some(RIFF_APPLICATION_ID),
+        (b"FORM", b"AIFF" | b"AIFC") => Some(AIFF_APPLICATION_ID),
+        _ => None,
+    }
+}
+
+/// * The size in the chunk header, little-endian for WAV, big-endian for AIFF.
+fn chunk_size(id: [u8; 4], chunk_header: &[u8]) -> u64 {
+    let size = [chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]];
+    if id == AIFF_APPLICATION_ID {
+        u32::from_be_bytes(size) as u64
+    } else {
+        u32::from_le_bytes(size) as u64
+    }
+}
+
+/// * How many bytes of the chunk are its header before the audio, zero if it isn't the audio chunk.
+fn audio_header_len(id: [u8; 4], chunk_header: &[u8]) -> usize {
+    match (id, &chunk_header[0..4]) {
+        (RIFF_APPLICATION_ID, b"data") => 8,
+        // The SSND chunk has the offset and the block size before the audio.
+        (AIFF_APPLICATION_ID, b"SSND") => 16,
+        _ => 0,
+    }
+}
+
+/// * Read the chunks after the container header till the end of the `reader`, call `f` with the chunk header and the whole chunk, or the audio chunk header only.
+fn read_chunks<Reader, F>(reader: &mut Reader, id: [u8; 4], mut f: F) -> io::Result<()>
+where
+    Reader: Read,
+    F: FnMut(&[u8; 8], &[u8]) {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "The chunk is truncated");
+    loop {
+        let mut chunk_header = [0u8; 8];
+        match reader.read_exact(&mut chunk_header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let size = chunk_size(id, &chunk_header);
+        // The chunks are word-aligned, the padding byte follows the odd-sized chunk.
+        let padded_size = size + (size & 1);
+        let audio_header_len = audio_header_len(id, &chunk_header);
+        if audio_header_len > 0 {
+            if size < audio_header_len as u64 - 8 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "The audio chunk is too small"));
+            }
+            let mut chunk = chunk_header.to_vec();
+            chunk.resize(audio_header_len, 0);
+            reader.read_exact(&mut chunk[8..]).map_err(|_| truncated())?;
+            let skipped = io::copy(&mut (&mut *reader).take(padded_size - (audio_header_len as u64 - 8)), &mut io::sink())?;
+            // The padding byte of the audio at the end of the file is often missing.
+            if skipped + (audio_header_len as u64 - 8) < size {
+                return Err(truncated());
+            }
+            f(&chunk_header, &chunk);
+        } else {
+            let mut chunk = chunk_header.to_vec();
+            (&mut *reader).take(padded_size).read_to_end(&mut chunk)?;
+            if ((chunk.len() - chunk_header.len()) as u64) < size {
+                return Err(truncated());
+            }
+            f(&chunk_header, &chunk);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+#![allow(dead_code)]
+
+use std::io::{self, Read};
+
+use libflac_sys::*;
+
+use crate::flac::{FlacDecoderError, FLAC_DECODER_NOT_FLAC};
+
+/// ## The SHA-256 digest of the audio frames of the FLAC file, for detecting whether the audio payload changed
+/// * The metadata blocks are skipped by parsing their headers, nothing is decoded, so retagging the file doesn't change the digest, but re-encoding it does.
+/// * The ID3v2 tag before the `fLaC` signature is skipped too, as libFLAC does.
+/// * The `reader` is read through once and never seeked, e.g. a pipe works.
+pub fn frames_digest<Reader>(mut reader: Reader) -> Result<[u8; 32], FlacDecoderError>
+where
+    Reader: Read {
+    let read_error = |_: io::Error| FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "frames_digest");
+    let mut signature = [0u8; 4];
+    read_exact(&mut reader, &mut signature)?;
+    if signature[..3] == *b"ID3" {
+        let mut header = [0u8; 6];
+        read_exact(&mut reader, &mut header)?;
+        // The signature took the major version, the header has the minor version, the flags and the syncsafe size.
+        let footer = if header[1] & 0x10 != 0 {10} else {0};
+        let size = header[2..].iter().fold(0u64, |size, byte| (size << 7) | (*byte & 0x7F) as u64);
+        skip(&mut reader, size + footer)?;
+        read_exact(&mut reader, &mut signature)?;
+    }
+    if signature != *b"fLaC" {
+        return Err(FlacDecoderError::new(FLAC_DECODER_NOT_FLAC, "frames_digest"));
+    }
+    loop {
+        let mut header = [0u8; 4];
+        read_exact(&mut reader, &mut header)?;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        skip(&mut reader, length as u64)?;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    let mut sha256 = Sha256::new();
+    let mut buffer = vec![0u8; 65536];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => sha256.update(&buffer[..size]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(read_error(e)),
+        }
+    }
+    Ok(sha256.finalize())
+}
+
+/// * Read the bytes of the header, the truncated header ends the stream.
+fn read_exact<Reader: Read>(reader: &mut Reader, buf: &mut [u8]) -> Result<(), FlacDecoderError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => FlacDecoderError::new(FLAC__STREAM_DECODER_END_OF_STREAM, "frames_digest"),
+        _ => FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "frames_digest"),
+    })
+}
+
+/// * Skip the bytes by reading them, the `reader` may not seek.
+fn skip<Reader: Read>(reader: &mut Reader, size: u64) -> Result<(), FlacDecoderError> {
+    match io::copy(&mut reader.take(size), &mut io::sink()) {
+        Ok(skipped) if skipped == size => Ok(()),
+        Ok(_) => Err(FlacDecoderError::new(FLAC__STREAM_DECODER_END_OF_STREAM, "frames_digest")),
+        Err(_) => Err(FlacDecoderError::new(FLAC__STREAM_DECODER_ABORTED, "frames_digest")),
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// * The streaming SHA-256 (FIPS 180-4)
+#[derive(Debug, Clone)]
+pub(crate) struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Sha256 {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19],
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        while !data.is_empty() {
+            let size = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + size].copy_from_slice(&data[..size]);
+            self.block_len += size;
+            data = &data[size..];
+            if self.block_len == 64 {
+                self.compress();
+                self.block_len = 0;
+            }
+        }
+    }
+
+    pub(crate) fn finalize(mut self) -> [u8; 32] {
+        let bit_len = self.total_len * 8;
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+        let mut digest = [0u8; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(self.block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
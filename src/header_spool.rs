@@ -0,0 +1,134 @@
+#![allow(dead_code)]
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+/// ## How the encoder gets an accurate STREAMINFO block when your `writer` can't seek, e.g. a socket or the stdout, see `set_accurate_header()`
+/// * libFLAC patches the STREAMINFO block with the exact total samples and the MD5 sum in `finish()` by seeking back to it,
+///   without seeking, the FLAC file has zero total samples and zero MD5 sum, which some receivers dislike.
+/// * With the spool, the encoded FLAC file goes to the spool first, then `finish()` writes the patched file through your `on_write()` closure in order.
+///   Your `on_seek()` and `on_tell()` closures are not called at all with the spool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AccurateHeader {
+    /// * Write to your `writer` directly, the header is only accurate if your `writer` can seek.
+    #[default]
+    Off,
+
+    /// * Spool the FLAC file in memory, up to the limit in bytes, exceeding it fails the encoding with the `FLAC__STREAM_ENCODER_IO_ERROR` error.
+    BufferToMemory(usize),
+
+    /// * Spool the FLAC file to the temporary file of the path, the file is created and removed after `finish()`, or when the encoder is dropped even if the encoding fails.
+    /// * The file must not exist, an existing file is never overwritten or removed, it fails with the `FLAC__STREAM_ENCODER_IO_ERROR` error instead.
+    BufferToTemp(PathBuf),
+}
+
+/// * Where the encoded FLAC file is kept until `finish()`.
+#[derive(Debug)]
+pub(crate) enum HeaderSpool {
+    Memory {
+        buffer: io::Cursor<Vec<u8>>,
+        limit: usize,
+    },
+    Temp {
+        // Dropped before `path`, so the file is closed before being removed.
+        file: File,
+        path: TempPath,
+    },
+}
+
+/// * Removes the temporary file when dropped, even if the encoding fails.
+#[derive(Debug)]
+pub(crate) struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+impl HeaderSpool {
+    /// * Create the spool, `None` for `AccurateHeader::Off`.
+    pub(crate) fn new(accurate_header: &AccurateHeader) -> io::Result<Option<Self>> {
+        Ok(match accurate_header {
+            AccurateHeader::Off => None,
+            AccurateHeader::BufferToMemory(limit) => Some(Self::Memory {
+                buffer: io::Cursor::new(Vec::new()),
+                limit: *limit,
+            }),
+            AccurateHeader::BufferToTemp(path) => Some(Self::Temp {
+                file: OpenOptions::new().read(true).write(true).create_new(true).open(path)?,
+                path: TempPath(path.clone()),
+            }),
+        })
+    }
+
+    pub(crate) fn position(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Memory{buffer, ..} => Ok(buffer.position()),
+            Self::Temp{file, ..} => file.stream_position(),
+        }
+    }
+
+    /// * Pass the whole spooled FLAC file to `f` chunk by chunk in order.
+    pub(crate) fn drain_to<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()> {
+        match self {
+            Self::Memory{buffer, ..} => f(buffer.get_ref()),
+            Self::Temp{file, ..} => {
+                file.seek(SeekFrom::Start(0))?;
+                let mut chunk = vec![0u8; 65536];
+                loop {
+                    let size = file.read(&mut chunk)?;
+                    if size == 0 {
+                        return Ok(());
+                    }
+                    f(&chunk[..size])?;
+                }
+            }
+        }
+    }
+}
+
+impl Write for HeaderSpool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Memory{buffer, limit} => {
+                let end = (buffer.position() as usize).saturating_add(buf.len());
+                if end > *limit {
+                    return Err(io::Error::other(format!("The FLAC file exceeds the spool limit of {limit} bytes")));
+                }
+                buffer.write(buf)
+            }
+            Self::Temp{file, ..} => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Memory{..} => Ok(()),
+            Self::Temp{file, ..} => file.flush(),
+        }
+    }
+}
+
+impl Read for HeaderSpool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Memory{buffer, ..} => buffer.read(buf),
+            Self::Temp{file, ..} => file.read(buf),
+        }
+    }
+}
+
+impl Seek for HeaderSpool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Memory{buffer, ..} => buffer.seek(pos),
+            Self::Temp{file, ..} => file.seek(pos),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+#![allow(dead_code)]
+
+use libflac_sys::*;
+
+use crate::flac::FlacEncoderError;
+
+/// ## Interleave the planar channels into one buffer, e.g. `[[L0, L1], [R0, R1]]` to `[L0, R0, L1, R1]`
+/// * All of the channels must have the same length, otherwise it's the same framing error as `FlacEncoderUnmovable::write_monos()` returns.
+pub fn interleave(channels: &[Vec<i32>]) -> Result<Vec<i32>, FlacEncoderError> {
+    let len = channels.first().map_or(0, |channel| channel.len());
+    if channels.iter().any(|channel| channel.len() != len) {
+        return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "util::interleave"));
+    }
+    Ok((0..len).flat_map(|i| channels.iter().map(move |channel| channel[i])).collect())
+}
+
+/// ## Deinterleave one buffer into the planar channels, e.g. `[L0, R0, L1, R1]` to `[[L0, L1], [R0, R1]]`
+/// * The number of samples must be a multiple of `channels`, and `channels` must not be zero, otherwise it's the same framing error as `FlacEncoderUnmovable::write_interleaved_samples()` returns.
+pub fn deinterleave(samples: &[i32], channels: usize) -> Result<Vec<Vec<i32>>, FlacEncoderError> {
+    if channels == 0 || !samples.len().is_multiple_of(channels) {
+        return Err(FlacEncoderError::new(FLAC__STREAM_ENCODER_FRAMING_ERROR, "util::deinterleave"));
+    }
+    Ok((0..channels).map(|channel| samples.iter().skip(channel).step_by(channels).copied().collect()).collect())
+}
@@ -0,0 +1,109 @@
+#![allow(dead_code)]
+
+/// ## The statistics of the calls to your IO closures, to diagnose the expensive readers or writers, e.g. over HTTP range requests.
+/// * Enable it by `set_io_stats_enabled()` of the encoder or the decoder, get it by `io_stats()`, and reset it by `reset_io_stats()` between the phases to measure them separately.
+/// * The position for the seek distances is tracked from the reads, the writes, the seeks and the tells, it's assumed to be 0 when the statistics are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoStats {
+    /// * How many times your `on_read()` closure was called
+    pub reads: u64,
+
+    /// * The total bytes returned by your `on_read()` closure
+    pub bytes_read: u64,
+
+    /// * How many times your `on_write()` closure was called
+    pub writes: u64,
+
+    /// * The total bytes passed to your `on_write()` closure
+    pub bytes_written: u64,
+
+    /// * How many times your `on_seek()` closure was called
+    pub seeks: u64,
+
+    /// * How many seeks went forward or stayed at the same position
+    pub forward_seeks: u64,
+
+    /// * How many seeks went backward
+    pub backward_seeks: u64,
+
+    /// * The histogram of the seek distances in bytes, forward or backward.
+    /// * `seek_distances[0]` counts the seeks to the same position, `seek_distances[n]` counts the distances from `2^(n-1)` to `2^n - 1`.
+    pub seek_distances: [u64; 65],
+
+    /// * How many times your `on_tell()` closure was called
+    pub tells: u64,
+
+    /// * The tracked position
+    position: u64,
+}
+
+impl IoStats {
+    pub fn new() -> Self {
+        Self {
+            reads: 0,
+            bytes_read: 0,
+            writes: 0,
+            bytes_written: 0,
+            seeks: 0,
+            forward_seeks: 0,
+            backward_seeks: 0,
+            seek_distances: [0; 65],
+            tells: 0,
+            position: 0,
+        }
+    }
+
+    /// * Clear the counters, the tracked position is kept.
+    pub fn reset(&mut self) {
+        *self = Self {
+            position: self.position,
+            ..Self::new()
+        };
+    }
+
+    /// * The bucket of `seek_distances` for the distance
+    pub fn seek_distance_bucket(distance: u64) -> usize {
+        (u64::BITS - distance.leading_zeros()) as usize
+    }
+
+    /// * How many seeks went at least `distance` bytes, rounded down to the bucket of `distance`.
+    pub fn seeks_at_least(&self, distance: u64) -> u64 {
+        self.seek_distances[Self::seek_distance_bucket(distance)..].iter().sum()
+    }
+
+    pub(crate) fn record_read(&mut self, bytes: usize) {
+        self.reads += 1;
+        self.bytes_read += bytes as u64;
+        self.position += bytes as u64;
+    }
+
+    pub(crate) fn record_write(&mut self, bytes: usize) {
+        self.writes += 1;
+        self.bytes_written += bytes as u64;
+        self.position += bytes as u64;
+    }
+
+    pub(crate) fn record_seek(&mut self, position: u64) {
+        self.seeks += 1;
+        if position >= self.position {
+            self.forward_seeks += 1;
+        } else {
+            self.backward_seeks += 1;
+        }
+        self.seek_distances[Self::seek_distance_bucket(position.abs_diff(self.position))] += 1;
+        self.position = position;
+    }
+
+    pub(crate) fn record_tell(&mut self, position: Option<u64>) {
+        self.tells += 1;
+        if let Some(position) = position {
+            self.position = position;
+        }
+    }
+}
+
+impl Default for IoStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
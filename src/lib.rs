@@ -1,22 +1,144 @@
 #![allow(unused_imports)]
 mod flac;
+mod chapters;
+mod verify;
+mod tags;
+mod track_crc;
+mod waveform;
+mod interleave;
+mod subset;
+mod compare;
+mod io_stats;
+mod encoder_thread;
+mod reblock;
+mod header_spool;
+mod decode_i16;
+mod encode_interleaved;
+mod metadata_report;
+mod batch_convert;
+mod frames_digest;
+mod transcode;
+mod foreign_chunks;
+mod flac_to_wav;
+mod sync_decoder;
+mod best_compression;
+mod replay_gain;
+#[cfg(feature = "image")]
+mod picture_image;
 
 /// * The flac encoder. The `FlacEncoder` is a wrapper for the `FlacEncoderUnmovable` what prevents the structure moves.
 pub use crate::flac::{FlacEncoderUnmovable, FlacEncoder};
 
+/// * The flac encoder over the boxed writer, for choosing the writer at runtime.
+pub use crate::flac::{DynFlacEncoder, WriteSeekAny};
+
+/// * The flac encoder that can be sent to another thread, it only takes the `Send` closures.
+pub use crate::flac::SendFlacEncoder;
+
 /// * The flac decoder. The `FlacDecoder` is a wrapper for the `FlacDecoderUnmovable` what prevents the structure moves.
 pub use crate::flac::{FlacDecoderUnmovable, FlacDecoder};
 
+/// * The flac decoder over the boxed reader, for choosing the reader at runtime.
+pub use crate::flac::{DynFlacDecoder, ReadSeekAny};
+
+/// * The flac decoder that can be sent to another thread, it only takes the `Send` closures.
+pub use crate::flac::SendFlacDecoder;
+
+/// * The decoder handle that can be shared between threads, the metadata and the position are read without waiting for the decoding.
+pub use crate::sync_decoder::{SyncFlacDecoder, DecoderSnapshot};
+
+/// * The event-based decoding, the metadata blocks and the audio frames in the stream order from `next_event()`.
+pub use crate::flac::{DecodeEvent, DecodeStep, DecodeOutcome, AudioFrames};
+
+/// * Re-block the decoded audio into the fixed-size chunks.
+pub use crate::reblock::{Reblocker, TailPolicy};
+
 /// * The codec options for FLAC
 pub mod options {
-    pub use crate::flac::{FlacAudioForm, SamplesInfo};
-    pub use crate::flac::{FlacCompression, FlacEncoderParams};
+    pub use crate::flac::{FlacAudioForm, SamplesInfo, I32Scaling};
+    pub use crate::flac::{FlacCompression, FlacContainer, FlacEncoderParams, DEFAULT_PADDING_LENGTH};
+    pub use crate::header_spool::AccurateHeader;
+    pub use crate::flac::{DecoderProfile, DecoderLimits};
+}
+
+/// * Verify the FLAC files by decoding them, checking the frame CRCs and the MD5 signature.
+pub use crate::verify::{verify, verify_batch, VerifyReport, BatchVerifyOpts, BatchReport, ReadSeekSend};
+
+/// * Compute the CRC of each track of a CD image for the rip verification.
+pub use crate::track_crc::{track_crcs, track_crcs_with_pregap, TrackCrc, TrackInfo, PregapAttribution};
+
+/// * Compute the waveform overview of the audio for rendering.
+pub use crate::waveform::{waveform, Waveform};
+
+/// * Decode the whole FLAC file into the interleaved 16-bit or 8-bit unsigned PCM samples in one call.
+pub use crate::decode_i16::{decode_to_i16, decode_to_i16_with_limits, decode_to_u8, decode_to_u8_with_limits};
+
+/// * Encode the interleaved samples into a whole FLAC file in one call.
+pub use crate::encode_interleaved::encode_interleaved;
+
+/// * The digest of the audio frames of the FLAC file, unchanged by retagging.
+pub use crate::frames_digest::frames_digest;
+
+/// * Decode a FLAC file and encode it again with other parameters, never truncating the bit depth silently.
+pub use crate::transcode::{transcode, original_params, BitDepthMismatch, ConversionPolicy, SampleFormat, TranscodeError};
+
+/// * Find the compression level that produces the smallest FLAC file of the audio.
+pub use crate::best_compression::best_compression;
+
+/// * Analyze the ReplayGain of the tracks of an album, then tag them with the track gain and the album gain.
+pub use crate::replay_gain::{ReplayGainSession, TrackGain};
+
+/// * Decode a FLAC file into a WAV file, putting back the foreign RIFF chunks kept in the FLAC file.
+pub use crate::flac_to_wav::{flac_to_wav, ForeignMetadata, FlacToWavError};
+
+/// * Check whether the FLAC file is in the streamable subset for the hardware players.
+pub use crate::subset::{check_subset, SubsetReport, SubsetRule, SubsetViolation};
+
+/// * Compare the audio of two FLAC files, e.g. to assert a lossless transcode preserved the audio.
+pub use crate::compare::{audio_equal, compare_audio, AudioDifference};
+
+/// * The statistics of the calls to the IO closures of the encoder and the decoder.
+pub use crate::io_stats::IoStats;
+
+/// * How the total samples of the finished stream compare with the estimate.
+pub use crate::flac::FinishReport;
+
+/// * Encode on another thread, feed the samples through a bounded queue, e.g. from a real-time audio thread.
+pub mod pipeline {
+    pub use crate::encoder_thread::{spawn_encoder, spawn_encoder_with, SampleSender, EncoderHandle, Backpressure};
+}
+
+/// * Convert many inputs into FLAC files on a pool of threads.
+pub mod batch {
+    pub use crate::batch_convert::{convert, convert_with_cancel, ConvertJob, JobResult, JobError};
+}
+
+/// * The metadata for the FLAC file, e.g. the cue sheets, the pictures and the chapters.
+pub mod metadata {
+    pub use crate::flac::{PictureData, FlacPictureType, COMMENT_KEYS};
+    pub use crate::flac::{FlacStreamInfo, FlacSeekPoint, MetadataBlock, FlacMetadataType};
+    pub use crate::flac::{FlacApplication, MetadataBlockId};
+    pub use crate::metadata_report::{MetadataReport, PictureSummary, CueSheetSummary};
+    pub use crate::flac::{FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType};
+    pub use crate::chapters::{Chapter, Chapters, MAX_CHAPTERS, MAX_CUE_SHEET_CHAPTERS};
+    pub use crate::tags::{Key, parse_bpm, format_bpm, LYRICS_KEYS, parse_lyrics3};
+    pub use crate::foreign_chunks::{store_foreign_metadata, restore_foreign_metadata, RIFF_APPLICATION_ID, AIFF_APPLICATION_ID};
+    #[cfg(feature = "image")]
+    pub use crate::picture_image::PictureDecodeError;
+}
+
+/// * The utilities for the sample buffers
+pub mod util {
+    pub use crate::interleave::{interleave, deinterleave};
+    pub use crate::transcode::{convert_bit_depth, compression_from_encoder_options, block_size_from_encoder_options};
+    pub use crate::decode_i16::convert_to_u8;
 }
 
 /// * The objects for you to implement your closure, some is closures' params, some is the return value that your closure should return.
 pub mod closure_objects {
     pub use crate::flac::SamplesInfo;
-    pub use crate::flac::{FlacReadStatus, FlacInternalDecoderError};
+    pub use crate::flac::{FlacReadStatus, FlacInternalDecoderError, DecodeErrorContext};
+    pub use crate::flac::EncodedFrame;
 }
 
 /// The errors of this library
@@ -24,13 +146,19 @@ pub mod errors {
     pub use crate::flac::FlacError;
     pub use crate::flac::{FlacEncoderError, FlacDecoderError};
     pub use crate::flac::{FlacEncoderErrorCode, FlacDecoderErrorCode};
+    pub use crate::flac::{FLAC_DECODER_CANCELLED, FLAC_DECODER_NOT_CD_AUDIO, FLAC_DECODER_NO_CUE_SHEET, FLAC_DECODER_LIMIT_EXCEEDED, FLAC_DECODER_CHANNEL_OUT_OF_RANGE, FLAC_DECODER_NOT_FLAC, FLAC_DECODER_INVALID_ARGUMENT, FLAC_DECODER_TRUNCATED, FLAC_DECODER_UNSUPPORTED_FORMAT, FLAC_DECODER_OPEN_FAILED, FLAC_DECODER_DAMAGED_STREAM};
+    pub use crate::flac::{FLAC_ENCODER_FINISHED, FLAC_ENCODER_SAMPLE_OUT_OF_RANGE, FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH, FLAC_ENCODER_ALREADY_INITIALIZED, FLAC_ENCODER_NOT_FINISHED, SampleOutOfRange};
     pub use crate::flac::{FlacEncoderInitError, FlacDecoderInitError};
     pub use crate::flac::{FlacEncoderInitErrorCode, FlacDecoderInitErrorCode};
+    pub use crate::flac::{MetadataTooLarge, MAX_METADATA_BLOCK_LENGTH};
+    pub use crate::tags::ParseError;
 }
 
 #[test]
 fn test() {
     use std::{io::{self, Read, Write, Seek, SeekFrom, BufReader, BufWriter}, cmp::Ordering, fs::File};
+    use options::*;
+    use closure_objects::*;
 
     // Open the FLAC file for decoding using the `BufReader`
     type ReaderType = BufReader<File>;
@@ -46,7 +174,7 @@ fn test() {
 
     // Open the FLAC file for encoding using the `BufWriter`
     type WriterType = BufWriter<File>;
-    let mut writer: WriterType = BufWriter::new(File::create("output.flac").unwrap());
+    let writer: WriterType = BufWriter::new(File::create("output.flac").unwrap());
 
     // Prepare to get the samples
     let mut pcm_frames = Vec::<Vec<i16>>::new();
@@ -56,7 +184,7 @@ fn test() {
     // So we just guess it.
     // Let's create the encoder now
     let mut encoder = FlacEncoder::new(
-        &mut writer,
+        writer,
         // on_write
         Box::new(|writer: &mut WriterType, data: &[u8]| -> Result<(), io::Error> {
             writer.write_all(data)
@@ -76,14 +204,19 @@ fn test() {
             channels: 2,
             sample_rate: 44100,
             bits_per_sample: 16,
-            total_samples_estimate: 0
+            total_samples_estimate: 0,
+            buffer_for_header: false,
+            auto_padding: false,
+            block_size: None,
+            container: FlacContainer::Native,
+            ogg_serial_number: None,
         }
     ).unwrap();
     encoder.initialize().unwrap();
 
     // Create a decoder to decode the test file.
     let mut decoder = FlacDecoder::new(
-        &mut reader,
+        reader,
         // on_read
         Box::new(|reader: &mut ReaderType, data: &mut [u8]| -> (usize, FlacReadStatus) {
             let to_read = data.len();
@@ -124,13 +257,13 @@ fn test() {
                 panic!("The test function only tests 16-bit per sample FLAC files.")
             }
             let pcm_converted: Vec<Vec<i16>> = samples.iter().map(|frame: &Vec<i32>|{
-                frame.into_iter().map(|x32|{*x32 as i16}).collect()
+                frame.iter().map(|x32|{*x32 as i16}).collect()
             }).collect();
             pcm_frames.extend(pcm_converted);
 
             // The encoder wants the `i32` for samples to be encoded so we convert the PCM samples back to `i32` format for the encoder.
             let i32pcm: Vec::<Vec<i32>> = pcm_frames.iter().map(|frame: &Vec<i16>|{
-                frame.into_iter().map(|x16|{*x16 as i32}).collect()
+                frame.iter().map(|x16|{*x16 as i32}).collect()
             }).collect();
             encoder.write_frames(&i32pcm).unwrap();
             pcm_frames.clear();
@@ -146,8 +279,5093 @@ fn test() {
         FlacAudioForm::FrameArray
     ).unwrap();
 
-    decoder.decode_all().unwrap();
+    decoder.decode_all().into_result().unwrap();
     decoder.finalize();
     encoder.finalize();
 }
 
+
+#[cfg(test)]
+type MemoryEncoder<'a> = FlacEncoder<'a, &'a mut std::io::Cursor<Vec<u8>>>;
+
+#[cfg(test)]
+type MemoryDecoder<'a> = FlacDecoder<'a, std::io::Cursor<&'a [u8]>>;
+
+#[cfg(test)]
+type OnWrite<'a> = Box<dyn FnMut(&[Vec<i32>], &closure_objects::SamplesInfo) -> Result<(), std::io::Error> + 'a>;
+
+/// * The internal decoder errors recorded by the `on_error()` closures of the tests, a panic can't unwind through the libFLAC callbacks.
+#[cfg(test)]
+type DecoderErrors = std::cell::RefCell<Vec<closure_objects::FlacInternalDecoderError>>;
+
+/// * Record the internal decoder errors for the test to assert on after decoding.
+#[cfg(test)]
+fn record_errors(errors: &DecoderErrors) -> Box<dyn FnMut(closure_objects::FlacInternalDecoderError) + '_> {
+    Box::new(|error| errors.borrow_mut().push(error))
+}
+
+/// * Encode the interleaved samples into memory, `prepare` is called before `initialize()` to add the metadata.
+#[cfg(test)]
+fn encode_to_memory(params: &options::FlacEncoderParams, samples: &[i32], prepare: impl FnOnce(&mut MemoryEncoder)) -> Vec<u8> {
+    encode_to_memory_with(params, prepare, |encoder| {
+        encoder.write_interleaved_samples(samples).unwrap();
+    })
+}
+
+/// * Encode into memory, `prepare` is called before `initialize()`, `write` is called after it to feed the samples.
+#[cfg(test)]
+fn encode_to_memory_with(params: &options::FlacEncoderParams, prepare: impl FnOnce(&mut MemoryEncoder), write: impl FnOnce(&mut MemoryEncoder)) -> Vec<u8> {
+    use std::io::{self, Cursor, Write, Seek, SeekFrom};
+    let mut cursor = Cursor::new(Vec::<u8>::new());
+    {
+        let mut encoder = FlacEncoder::new(
+            &mut cursor,
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {
+                writer.write_all(data)
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {
+                writer.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {
+                writer.stream_position()
+            }),
+            params
+        ).unwrap();
+        prepare(&mut encoder);
+        encoder.initialize().unwrap();
+        write(&mut encoder);
+        encoder.finish().unwrap();
+    }
+    cursor.into_inner()
+}
+
+/// * Create a decoder over the FLAC file in memory.
+#[cfg(test)]
+fn decoder_from_memory<'a>(data: &'a [u8], errors: &'a DecoderErrors, on_write: OnWrite<'a>) -> MemoryDecoder<'a> {
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use closure_objects::FlacReadStatus;
+    let length = data.len() as u64;
+    FlacDecoder::new(
+        Cursor::new(data),
+        Box::new(|reader: &mut Cursor<&[u8]>, data: &mut [u8]| -> (usize, FlacReadStatus) {
+            match reader.read(data) {
+                Ok(0) => (0, FlacReadStatus::Eof),
+                Ok(size) => (size, FlacReadStatus::GoOn),
+                Err(_) => (0, FlacReadStatus::Abort),
+            }
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>, position: u64| -> Result<(), io::Error> {
+            reader.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            reader.stream_position()
+        }),
+        Box::new(move |_reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            Ok(length)
+        }),
+        Box::new(move |reader: &mut Cursor<&[u8]>| -> bool {
+            reader.position() >= length
+        }),
+        on_write,
+        record_errors(errors),
+        true,
+        false,
+        options::FlacAudioForm::FrameArray,
+    ).unwrap()
+}
+
+/// * A second of a stereo sine wave at 44100 Hz, 16 bits per sample.
+#[cfg(test)]
+fn sine_wave_stereo_16() -> Vec<i32> {
+    (0..44100).flat_map(|i| {
+        let v = ((i as f64 * 440.0 * std::f64::consts::TAU / 44100.0).sin() * 16384.0) as i32;
+        [v, -v]
+    }).collect()
+}
+
+/// * A chunk of the WAV file, or of the AIFF file if `big_endian`, with the padding byte of the odd-sized payload.
+#[cfg(test)]
+fn iff_chunk(id: &[u8; 4], payload: &[u8], big_endian: bool) -> Vec<u8> {
+    let size = payload.len() as u32;
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&if big_endian {size.to_be_bytes()} else {size.to_le_bytes()});
+    chunk.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+/// * The 16-bit stereo 44100 Hz WAV file of the `samples`, with the extra chunks before and after the `data` chunk.
+#[cfg(test)]
+fn wav_with_chunks(samples: &[i32], before_data: &[&[u8]], after_data: &[&[u8]]) -> Vec<u8> {
+    let mut fmt = Vec::new();
+    for field in [&1u16.to_le_bytes()[..], &2u16.to_le_bytes(), &44100u32.to_le_bytes(), &(44100u32 * 4).to_le_bytes(), &4u16.to_le_bytes(), &16u16.to_le_bytes()] {
+        fmt.extend_from_slice(field);
+    }
+    let data: Vec<u8> = samples.iter().flat_map(|&sample| (sample as i16).to_le_bytes()).collect();
+    let body = [b"WAVE".to_vec(), iff_chunk(b"fmt ", &fmt, false)].into_iter()
+        .chain(before_data.iter().map(|chunk| chunk.to_vec()))
+        .chain([iff_chunk(b"data", &data, false)])
+        .chain(after_data.iter().map(|chunk| chunk.to_vec()))
+        .collect::<Vec<Vec<u8>>>().concat();
+    [&b"RIFF"[..], &(body.len() as u32).to_le_bytes(), &body].concat()
+}
+
+#[test]
+fn test_chapters_round_trip() {
+    use std::time::Duration;
+    use metadata::Chapters;
+    let errors = DecoderErrors::default();
+
+    let mut chapters = Chapters::new();
+    chapters.push(Duration::from_millis(500), "Outro", Some("https://example.com/outro"));
+    chapters.push(Duration::ZERO, "Intro", None);
+    chapters.push(Duration::from_millis(250), "Middle", None);
+    chapters.push(Duration::from_millis(250), "Duplicated", None);
+    assert_eq!(chapters.len(), 3);
+
+    let samples = sine_wave_stereo_16();
+    let params = options::FlacEncoderParams {total_samples_estimate: samples.len() as u64 / 2, ..options::FlacEncoderParams::new()};
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        chapters.apply_to(encoder, params.sample_rate).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+
+    let expected = chapters.get_chapters();
+    assert_eq!(expected.iter().map(|c| c.title.as_str()).collect::<Vec<_>>(), ["Intro", "Middle", "Outro"]);
+    assert_eq!(decoder.get_comments()["CHAPTER002"], "00:00:00.250");
+    assert_eq!(Chapters::from_decoder(&decoder).get_chapters(), expected);
+
+    // The cue sheet alone keeps the start times.
+    let cue_sheet = &decoder.get_cue_sheets()[0];
+    let starts: Vec<Duration> = Chapters::from_cue_sheet(cue_sheet, params.sample_rate).get_chapters().iter().map(|c| c.start).collect();
+    assert_eq!(starts, expected.iter().map(|c| c.start).collect::<Vec<_>>());
+    // The last chapter lasts until the end of the audio.
+    assert_eq!(cue_sheet.tracks[&255].offset, samples.len() as u64 / 2);
+
+    // Without the length of the audio, nothing is written.
+    let params = options::FlacEncoderParams::new();
+    encode_to_memory(&params, &samples, |encoder| {
+        assert!(chapters.apply_to(encoder, params.sample_rate).is_err());
+        assert_eq!(encoder.describe_metadata_block(metadata::MetadataBlockId {kind: metadata::FlacMetadataType::VorbisComment, index: 0}), None);
+    });
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_chapters_mismatched_representations() {
+    use std::time::Duration;
+    use metadata::Chapters;
+    let errors = DecoderErrors::default();
+
+    let mut from_comments = Chapters::new();
+    from_comments.push(Duration::ZERO, "From comments", None);
+    from_comments.push(Duration::from_millis(500), "Second", None);
+    let mut from_cue_sheet = Chapters::new();
+    from_cue_sheet.push(Duration::from_millis(100), "From cue sheet", None);
+
+    let samples = sine_wave_stereo_16();
+    let params = options::FlacEncoderParams {total_samples_estimate: samples.len() as u64 / 2, ..options::FlacEncoderParams::new()};
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        // Write the cue sheet of one set of chapters and the comments of the other one.
+        from_cue_sheet.apply_to(encoder, params.sample_rate).unwrap();
+        from_comments.apply_to(encoder, params.sample_rate).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoder.get_cue_sheets().len(), 2);
+    assert_eq!(Chapters::from_decoder(&decoder).get_chapters(), from_comments.get_chapters());
+
+    // Without the comments, the first cue sheet is used.
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_cue_sheet(&{
+            let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+            decoder.decode_all().into_result().unwrap();
+            decoder.get_cue_sheets()[0].clone()
+        }).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    let chapters = Chapters::from_decoder(&decoder).get_chapters();
+    assert_eq!(chapters.len(), 1);
+    assert_eq!(chapters[0].start, Duration::from_millis(100));
+    assert_eq!(chapters[0].title, "");
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_chapters_limit() {
+    use std::time::Duration;
+    use metadata::{Chapters, MAX_CHAPTERS, MAX_CUE_SHEET_CHAPTERS};
+
+    let chapters_of = |count: usize| {
+        let mut chapters = Chapters::new();
+        for i in 0..count as u64 {
+            chapters.push(Duration::from_millis(i), "", None);
+        }
+        chapters
+    };
+    let params = options::FlacEncoderParams {total_samples_estimate: 44100, ..options::FlacEncoderParams::new()};
+    encode_to_memory(&params, &[], |encoder| {
+        // The cue sheet can't hold them, the comments alone are written only if asked for.
+        assert!(chapters_of(MAX_CUE_SHEET_CHAPTERS + 1).apply_to(encoder, params.sample_rate).is_err());
+        assert_eq!(encoder.describe_metadata_block(metadata::MetadataBlockId {kind: metadata::FlacMetadataType::VorbisComment, index: 0}), None);
+        chapters_of(MAX_CUE_SHEET_CHAPTERS).apply_to(encoder, params.sample_rate).unwrap();
+        chapters_of(MAX_CHAPTERS).apply_comments_to(encoder).unwrap();
+        assert!(chapters_of(MAX_CHAPTERS + 1).apply_comments_to(encoder).is_err());
+    });
+}
+
+#[test]
+fn test_estimated_metadata_size() {
+    use metadata::{FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType};
+
+    // Find where the first audio frame starts by walking the metadata block headers.
+    fn metadata_size(flac: &[u8]) -> usize {
+        let mut pos = 4;
+        loop {
+            let is_last = flac[pos] & 0x80 != 0;
+            pos += 4 + u32::from_be_bytes([0, flac[pos + 1], flac[pos + 2], flac[pos + 3]]) as usize;
+            if is_last {return pos}
+        }
+    }
+
+    let params = options::FlacEncoderParams::new();
+    let mut estimated = 0;
+    let flac = encode_to_memory(&params, &sine_wave_stereo_16(), |encoder| {
+        estimated = encoder.estimated_metadata_size();
+    });
+    assert_eq!(estimated, metadata_size(&flac));
+
+    let flac = encode_to_memory(&params, &sine_wave_stereo_16(), |encoder| {
+        encoder.insert_comments("TITLE", "Sine wave").unwrap();
+        encoder.insert_comments("ARTIST", "Nobody").unwrap();
+        encoder.insert_cue_sheet(&FlacCueSheet {
+            media_catalog_number: [0; 129],
+            lead_in: 0,
+            is_cd: false,
+            tracks: [1, 255].into_iter().map(|track_no| (track_no, FlacCueTrack {
+                offset: if track_no == 1 {0} else {44100},
+                track_no,
+                isrc: [0; 13],
+                type_: FlacTrackType::Audio,
+                pre_emphasis: false,
+                indices: if track_no == 1 {vec![FlacCueSheetIndex {offset: 0, number: 1}]} else {Vec::new()},
+            })).collect(),
+        }).unwrap();
+        encoder.add_seek_points(&[0, 4096, 4096, 20000]).unwrap();
+        estimated = encoder.estimated_metadata_size();
+    });
+    assert_eq!(estimated, metadata_size(&flac));
+}
+
+#[test]
+fn test_verify_batch() {
+    use std::io::Cursor;
+
+    let clean = encode_to_memory(&options::FlacEncoderParams::new(), &sine_wave_stereo_16(), |_| {});
+    let mut corrupted = clean.clone();
+    let middle = corrupted.len() / 2;
+    for byte in corrupted[middle..middle + 16].iter_mut() {
+        *byte ^= 0x55;
+    }
+    let truncated = clean[..clean.len() * 2 / 3].to_vec();
+    assert!(verify(Cursor::new(&clean)).is_ok());
+
+    let inputs = || -> Vec<(String, Box<dyn ReadSeekSend>)> {
+        vec![
+            ("clean 1".to_owned(), Box::new(Cursor::new(clean.clone()))),
+            ("corrupted".to_owned(), Box::new(Cursor::new(corrupted.clone()))),
+            ("clean 2".to_owned(), Box::new(Cursor::new(clean.clone()))),
+            ("truncated".to_owned(), Box::new(Cursor::new(truncated.clone()))),
+            ("clean 3".to_owned(), Box::new(Cursor::new(clean.clone()))),
+            ("garbage".to_owned(), Box::new(Cursor::new(vec![0u8; 100]))),
+        ]
+    };
+
+    for threads in [1, 4] {
+        let report = verify_batch(inputs(), BatchVerifyOpts {threads, stop_on_first_failure: false});
+        assert_eq!(report.reports.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>(), ["clean 1", "corrupted", "clean 2", "truncated", "clean 3", "garbage"]);
+        assert_eq!(report.failures(), ["corrupted", "truncated", "garbage"]);
+        assert_eq!(report.passed(), 3);
+        assert!(!report.reports[1].1.decode_errors.is_empty());
+        assert!(report.reports[3].1.samples_decoded < report.reports[3].1.total_samples);
+        assert_eq!(report.reports[3].1.decoder_error.unwrap().code, errors::FLAC_DECODER_TRUNCATED);
+        assert!(report.skipped.is_empty());
+    }
+
+    let report = verify_batch(inputs(), BatchVerifyOpts {threads: 1, stop_on_first_failure: true});
+    assert_eq!(report.reports.len(), 2);
+    assert_eq!(report.skipped, ["clean 2", "truncated", "clean 3", "garbage"]);
+    assert!(!report.is_ok());
+}
+
+#[test]
+fn test_parse_bpm() {
+    use metadata::{parse_bpm, format_bpm};
+
+    // The values observed in the wild, and what they should parse to.
+    let valid = [
+        ("128", 128.0),
+        ("128.00", 128.0),
+        ("128.5", 128.5),
+        (" 174 ", 174.0),
+        ("127,99", 127.99),
+        ("90 BPM", 90.0),
+        ("90bpm", 90.0),
+        ("0128", 128.0),
+        ("85.333", 85.333),
+    ];
+    for (raw, expected) in valid {
+        assert_eq!(parse_bpm(raw), Ok(expected), "{raw:?}");
+    }
+    for raw in ["", "fast", "0", "-120", "NaN", "inf", "128 BPM!", "1.2.3", "é12", "€a", "12€", "120 БПМ"] {
+        assert!(parse_bpm(raw).is_err(), "{raw:?}");
+    }
+
+    let formatted = [
+        (128.0, "128"),
+        (127.999, "128"),
+        (128.5, "128.5"),
+        (85.333, "85.33"),
+        (100.25, "100.25"),
+    ];
+    for (bpm, expected) in formatted {
+        assert_eq!(format_bpm(bpm), expected, "{bpm}");
+    }
+}
+
+#[test]
+fn test_parse_key() {
+    use metadata::Key;
+
+    // The values observed in the wild, and what they should parse to.
+    let valid = [
+        ("Am", Key::AMinor),
+        ("A minor", Key::AMinor),
+        ("amin", Key::AMinor),
+        ("8A", Key::AMinor),
+        ("08A", Key::AMinor),
+        ("8a", Key::AMinor),
+        ("1m", Key::AMinor),
+        ("C", Key::CMajor),
+        ("Cmaj", Key::CMajor),
+        ("C major", Key::CMajor),
+        ("8B", Key::CMajor),
+        ("1d", Key::CMajor),
+        ("F#", Key::FSharpMajor),
+        ("Gb", Key::FSharpMajor),
+        ("G♭", Key::FSharpMajor),
+        ("2B", Key::FSharpMajor),
+        ("Ebm", Key::EbMinor),
+        ("D#m", Key::EbMinor),
+        ("D♯m", Key::EbMinor),
+        ("2A", Key::EbMinor),
+        ("Abm", Key::GSharpMinor),
+        ("1A", Key::GSharpMinor),
+        ("Dbm", Key::CSharpMinor),
+        ("12A", Key::CSharpMinor),
+        ("Cb", Key::BMajor),
+        ("E#m", Key::FMinor),
+        (" Bbm ", Key::BbMinor),
+        ("12B", Key::EMajor),
+        ("6d", Key::BMajor),
+    ];
+    for (raw, expected) in valid {
+        assert_eq!(raw.parse::<Key>(), Ok(expected), "{raw:?}");
+    }
+    for raw in ["", "H", "o", "13A", "0B", "8C", "Am7", "A#b", "Amajor minor", "13m"] {
+        assert!(raw.parse::<Key>().is_err(), "{raw:?}");
+    }
+
+    // Every key survives both notations.
+    for pitch_class in 0..12 {
+        for is_minor in [false, true] {
+            let key = Key::new(pitch_class, is_minor);
+            assert_eq!(key.pitch_class(), pitch_class);
+            assert_eq!(key.is_minor(), is_minor);
+            assert_eq!(key.to_string().parse::<Key>(), Ok(key));
+            assert_eq!(key.camelot().parse::<Key>(), Ok(key));
+        }
+    }
+    assert_eq!(Key::AMinor.camelot(), "8A");
+    assert_eq!(Key::EMajor.camelot(), "12B");
+    assert_eq!(Key::FSharpMinor.to_string(), "F#m");
+}
+
+#[test]
+fn test_bpm_and_key_round_trip() {
+    use metadata::Key;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        assert!(encoder.set_bpm(0.0).is_err());
+        assert!(encoder.set_bpm(f32::NAN).is_err());
+        encoder.set_bpm(127.999).unwrap();
+        encoder.set_initial_key(Key::BbMinor).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoder.get_comments()["BPM"], "128");
+    assert_eq!(decoder.get_comments()["INITIALKEY"], "Bbm");
+    assert_eq!(decoder.bpm(), Some(Ok(128.0)));
+    assert_eq!(decoder.initial_key(), Some(Ok(Key::BbMinor)));
+
+    // The invalid values are errors from the typed getters, but still there as the raw comments.
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_comments("BPM", "fast").unwrap();
+        encoder.insert_comments("KEY", "Am7").unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoder.bpm().unwrap().unwrap_err().value, "fast");
+    assert_eq!(decoder.initial_key().unwrap().unwrap_err().value, "Am7");
+    assert_eq!(decoder.get_comments()["KEY"], "Am7");
+
+    // No such comments at all.
+    let flac = encode_to_memory(&params, &samples, |_| {});
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoder.bpm(), None);
+    assert_eq!(decoder.initial_key(), None);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_cancel_decoding() {
+    use std::{cell::Cell, rc::Rc, sync::{Arc, atomic::{AtomicBool, Ordering}}, thread};
+    use errors::FlacDecoderErrorCode;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    // Cancel from another thread once the first frame was decoded.
+    let token = Arc::new(AtomicBool::new(false));
+    let samples_decoded = Rc::new(Cell::new(0usize));
+    let mut decoder = {
+        let token = token.clone();
+        let samples_decoded = samples_decoded.clone();
+        decoder_from_memory(&flac, &errors, Box::new(move |_, samples_info| {
+            samples_decoded.set(samples_decoded.get() + samples_info.samples as usize);
+            let token = token.clone();
+            thread::spawn(move || token.store(true, Ordering::Relaxed)).join().unwrap();
+            Ok(())
+        }))
+    };
+    decoder.set_cancel_token(Some(token.clone()));
+    let outcome = decoder.decode_all();
+    assert!(matches!(outcome, DecodeOutcome::StoppedByCaller{..}));
+    assert_eq!(outcome.samples() as usize, samples_decoded.get());
+    assert!(samples_decoded.get() > 0 && samples_decoded.get() < samples.len() / 2);
+
+    // `decode()` reports the cancellation as the error.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.set_cancel_token(Some(token.clone()));
+    let err = decoder.decode().unwrap_err();
+    assert!(err.is_cancelled());
+    assert!(matches!(FlacDecoderErrorCode::from(err.code), FlacDecoderErrorCode::StreamDecoderCancelled));
+
+    // Without cancelling, it decodes everything, and aborting for other reasons isn't a cancellation.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.set_cancel_token(Some(Arc::new(AtomicBool::new(false))));
+    assert!(decoder.decode_all().is_completed());
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Err(std::io::Error::other("Stop"))));
+    assert!(matches!(decoder.decode_all(), DecodeOutcome::StoppedByCaller{..}));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_write_monos_in_chunks() {
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let monos: Vec<Vec<i32>> = (0..2).map(|channel| samples.iter().skip(channel).step_by(2).copied().collect()).collect();
+    let num_samples = monos[0].len();
+
+    let whole = encode_to_memory_with(&params, |_| {}, |encoder| {
+        encoder.write_monos(&monos).unwrap();
+    });
+    assert_eq!(whole, encode_to_memory(&params, &samples, |_| {}));
+
+    // The chunk sizes that don't divide the block size, including an empty chunk and a short final chunk.
+    for chunk_size in [1, 3, 7, 100, 4095, 4097, num_samples - 1] {
+        let chunked = encode_to_memory_with(&params, |_| {}, |encoder| {
+            let mut position = 0;
+            while position < num_samples {
+                let end = (position + chunk_size).min(num_samples);
+                encoder.write_monos(&[monos[0][position..end].to_vec(), monos[1][position..end].to_vec()]).unwrap();
+                encoder.write_monos(&[Vec::new(), Vec::new()]).unwrap();
+                position = end;
+            }
+        });
+        assert_eq!(chunked, whole, "chunk size {chunk_size}");
+    }
+
+    // The channel count and the channel lengths are still checked for every chunk.
+    encode_to_memory_with(&params, |_| {}, |encoder| {
+        assert!(encoder.write_monos(&[vec![0; 10]]).is_err());
+        assert!(encoder.write_monos(&[vec![0; 10], vec![0; 9]]).is_err());
+        encoder.write_monos(&monos).unwrap();
+    });
+}
+
+#[test]
+fn test_queued_pictures() {
+    use std::io::{self, Cursor, Write, Seek, SeekFrom};
+
+    let mut cursor = Cursor::new(Vec::<u8>::new());
+    let mut encoder = FlacEncoder::new(
+        &mut cursor,
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)
+        }),
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {
+            writer.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {
+            writer.stream_position()
+        }),
+        &options::FlacEncoderParams::new(),
+    ).unwrap();
+    assert_eq!(encoder.queued_picture_count(), 0);
+    assert_eq!(encoder.queued_picture_bytes(), 0);
+
+    encoder.add_picture(&[0u8; 1000], "Front cover", "image/png", 1, 1, 24, 0).unwrap();
+    encoder.add_picture(&[0u8; 234], "Back cover", "image/jpeg", 1, 1, 24, 0).unwrap();
+    assert_eq!(encoder.queued_picture_count(), 2);
+    assert_eq!(encoder.queued_picture_bytes(), 1234);
+}
+
+#[test]
+fn test_track_crcs() {
+    use std::{collections::BTreeMap, io::Cursor};
+    use options::{FlacCompression, FlacEncoderParams};
+    use metadata::{FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType};
+    use errors::{FLAC_DECODER_NOT_CD_AUDIO, FLAC_DECODER_NO_CUE_SHEET};
+
+    // A synthetic 3-track image, 2 seconds, track 2 has a pregap of 5 CD sectors.
+    const SECTOR: u64 = 588;
+    let total_samples = SECTOR * 150;
+    let samples: Vec<i32> = (0..total_samples).flat_map(|i| {
+        let v = ((i * 7919 + (i / 588) * 31) % 65536) as i32 - 32768;
+        [v, v / 3]
+    }).collect();
+    let track = |track_no: u8, offset: u64, indices: &[(u8, u64)]| FlacCueTrack {
+        offset,
+        track_no,
+        isrc: [0; 13],
+        type_: FlacTrackType::Audio,
+        pre_emphasis: false,
+        indices: indices.iter().map(|&(number, offset)| FlacCueSheetIndex {offset, number}).collect(),
+    };
+    let cue_sheet = FlacCueSheet {
+        media_catalog_number: [0; 129],
+        lead_in: 88200,
+        is_cd: true,
+        tracks: BTreeMap::from([
+            (1, track(1, 0, &[(1, 0)])),
+            (2, track(2, SECTOR * 50, &[(0, 0), (1, SECTOR * 5)])),
+            (3, track(3, SECTOR * 100, &[(1, 0)])),
+            (170, track(170, total_samples, &[])),
+        ]),
+    };
+
+    let encode = |compression: FlacCompression, sample_rate: u32, with_cue_sheet: bool| {
+        let params = FlacEncoderParams {
+            compression,
+            sample_rate,
+            total_samples_estimate: total_samples,
+            ..FlacEncoderParams::new()
+        };
+        encode_to_memory(&params, &samples, |encoder| {
+            if with_cue_sheet {
+                encoder.insert_cue_sheet(&cue_sheet).unwrap();
+            }
+        })
+    };
+
+    // The expected CRCs straight from the samples.
+    let expected_crc = |start: u64, end: u64| {
+        let bytes: Vec<u8> = samples[(start * 2) as usize..(end * 2) as usize].iter().flat_map(|v| (*v as i16).to_le_bytes()).collect();
+        crate::track_crc::crc32_update(0, &bytes)
+    };
+    assert_eq!(crate::track_crc::crc32_update(0, b"123456789"), 0xCBF43926);
+
+    let reference = track_crcs(Cursor::new(encode(FlacCompression::Level5, 44100, true))).unwrap();
+    assert_eq!(reference, [
+        TrackCrc {track_no: 1, crc32: expected_crc(0, SECTOR * 55), samples: SECTOR * 55},
+        TrackCrc {track_no: 2, crc32: expected_crc(SECTOR * 55, SECTOR * 100), samples: SECTOR * 45},
+        TrackCrc {track_no: 3, crc32: expected_crc(SECTOR * 100, total_samples), samples: SECTOR * 50},
+    ]);
+
+    // Stable across the compression levels.
+    for compression in [FlacCompression::Level0, FlacCompression::Level8] {
+        assert_eq!(track_crcs(Cursor::new(encode(compression, 44100, true))).unwrap(), reference);
+    }
+
+    // The other ways to attribute the pregap.
+    let flac = encode(FlacCompression::Level5, 44100, true);
+    let current = track_crcs_with_pregap(Cursor::new(&flac), PregapAttribution::CurrentTrack).unwrap();
+    assert_eq!(current.iter().map(|t| t.samples).collect::<Vec<_>>(), [SECTOR * 50, SECTOR * 50, SECTOR * 50]);
+    assert_eq!(current[1].crc32, expected_crc(SECTOR * 50, SECTOR * 100));
+    let discard = track_crcs_with_pregap(Cursor::new(&flac), PregapAttribution::Discard).unwrap();
+    assert_eq!(discard.iter().map(|t| t.samples).collect::<Vec<_>>(), [SECTOR * 50, SECTOR * 45, SECTOR * 50]);
+    assert_eq!(discard[0].crc32, expected_crc(0, SECTOR * 50));
+
+    // Not CD audio, or no cue sheet.
+    assert_eq!(track_crcs(Cursor::new(encode(FlacCompression::Level5, 48000, false))).unwrap_err().code, FLAC_DECODER_NOT_CD_AUDIO);
+    assert_eq!(track_crcs(Cursor::new(encode(FlacCompression::Level5, 44100, false))).unwrap_err().code, FLAC_DECODER_NO_CUE_SHEET);
+
+    // The failed read before the cue sheet is the error of the decoder, not the missing cue sheet.
+    struct FailingReader(Cursor<Vec<u8>>);
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.position() >= 20 {
+                return Err(std::io::Error::other("Disconnected"));
+            }
+            let len = buf.len().min(20 - self.0.position() as usize);
+            self.0.read(&mut buf[..len])
+        }
+    }
+    impl std::io::Seek for FailingReader {
+        fn seek(&mut self, position: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(position)
+        }
+    }
+    let code = track_crcs(FailingReader(Cursor::new(flac))).unwrap_err().code;
+    assert_ne!(code, FLAC_DECODER_NO_CUE_SHEET);
+    assert_ne!(code, FLAC_DECODER_NOT_CD_AUDIO);
+}
+
+#[test]
+fn test_on_end_of_stream() {
+    use std::{cell::Cell, rc::Rc, sync::{Arc, Mutex}};
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    // The events in order, the end of stream comes once after the last samples.
+    let events = Arc::new(Mutex::new(Vec::<&str>::new()));
+    let mut decoder = {
+        let events = events.clone();
+        decoder_from_memory(&flac, &errors, Box::new(move |_, _| {
+            events.lock().unwrap().push("write");
+            Ok(())
+        }))
+    };
+    let eos_events = events.clone();
+    decoder.set_on_end_of_stream(Some(Box::new(move || eos_events.lock().unwrap().push("end"))));
+    assert!(decoder.decode_all().is_completed());
+    assert!(decoder.decode_all().is_completed());
+    assert_eq!(decoder.decode().unwrap(), DecodeStep::EndOfStream);
+    let events = events.lock().unwrap();
+    assert_eq!(events.last(), Some(&"end"));
+    assert_eq!(events.iter().filter(|e| **e == "end").count(), 1);
+    assert!(events.len() > 2);
+
+    // Also with `decode()` step by step, the plain decoder takes the closure that isn't `Send`.
+    let ended = Rc::new(Cell::new(0));
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    let counter = ended.clone();
+    decoder.set_on_end_of_stream(Some(Box::new(move || counter.set(counter.get() + 1))));
+    let mut steps = 0;
+    while ended.get() == 0 {
+        assert_ne!(decoder.decode().unwrap(), DecodeStep::NothingDone);
+        steps += 1;
+        assert!(steps < 100);
+    }
+    decoder.decode().unwrap();
+    assert_eq!(ended.get(), 1);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_waveform() {
+    use std::io::Cursor;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples: Vec<i32> = sine_wave_stereo_16().iter().enumerate().map(|(i, v)| v / (1 + (i as i32 / 8000))).collect();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+    let num_samples = samples.len() / 2;
+
+    // With the unknown length, the total samples field of the STREAMINFO is zeroed.
+    let mut unknown_length = flac.clone();
+    unknown_length[21] &= 0xF0;
+    unknown_length[22..26].fill(0);
+    let mut decoder = decoder_from_memory(&unknown_length, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoder.get_stream_info().unwrap().total_samples, 0);
+
+    for buckets in [1, 7, 100, 1000] {
+        let expected = brute_force_peaks(&samples, buckets);
+        for data in [&flac, &unknown_length] {
+            let waveform = waveform(Cursor::new(data), buckets).unwrap();
+            assert_eq!(waveform.total_samples, num_samples as u64);
+            assert_eq!(waveform.bits_per_sample, 16);
+            assert!(waveform.peaks.iter().all(|channel| channel.len() == buckets));
+            assert_eq!(waveform.peaks, expected, "{buckets} buckets");
+        }
+    }
+
+    // Zero buckets is rejected before decoding.
+    assert_eq!(waveform(Cursor::new(&flac), 0).unwrap_err().code, errors::FLAC_DECODER_INVALID_ARGUMENT);
+
+    // More buckets than samples leaves the empty buckets zeroed.
+    let short = encode_to_memory(&params, &samples[..20], |_| {});
+    let waveform = waveform(Cursor::new(&short), 30).unwrap();
+    assert_eq!(waveform.peaks, brute_force_peaks(&samples[..20], 30));
+
+    let normalized = waveform.normalized();
+    assert_eq!(normalized[0][0].0, waveform.peaks[0][0].0 as f32 / 32768.0);
+    assert!(normalized.iter().flatten().all(|(min, max)| (-1.0..=1.0).contains(min) && (-1.0..=1.0).contains(max)));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+/// * The waveform peaks of the interleaved stereo samples, computed over the retained samples.
+#[cfg(test)]
+fn brute_force_peaks(samples: &[i32], buckets: usize) -> Vec<Vec<(i32, i32)>> {
+    let num_samples = samples.len() / 2;
+    (0..2).map(|channel| {
+        let mut peaks = vec![(0, 0); buckets];
+        let mut seen = vec![false; buckets];
+        for i in 0..num_samples {
+            let bucket = i * buckets / num_samples;
+            let v = samples[i * 2 + channel];
+            peaks[bucket] = if seen[bucket] {(peaks[bucket].0.min(v), peaks[bucket].1.max(v))} else {(v, v)};
+            seen[bucket] = true;
+        }
+        peaks
+    }).collect()
+}
+
+#[test]
+fn test_stream_info_sizes() {
+    use std::{cell::Cell, rc::Rc};
+    use metadata::FlacStreamInfo;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    // Only the metadata is read, no samples yet.
+    let written = Rc::new(Cell::new(false));
+    let flag = written.clone();
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(move |_, _| {
+        flag.set(true);
+        Ok(())
+    }));
+    assert_eq!(decoder.max_blocksize(), None);
+    assert!(decoder.decode_metadata().unwrap());
+    assert!(!written.get());
+    assert_eq!(decoder.min_blocksize(), Some(4096));
+    assert_eq!(decoder.max_blocksize(), Some(4096));
+
+    // The frame sizes are the real ones of the encoded frames.
+    let min_framesize = decoder.min_framesize().unwrap();
+    let max_framesize = decoder.max_framesize().unwrap();
+    assert!(min_framesize > 0 && min_framesize <= max_framesize);
+    assert!((max_framesize as usize) < flac.len());
+
+    let stream_info: FlacStreamInfo = *decoder.get_stream_info().unwrap();
+    assert_eq!(stream_info.sample_rate, 44100);
+    assert_eq!(stream_info.channels, 2);
+    assert_eq!(stream_info.bits_per_sample, 16);
+    assert_eq!(stream_info.total_samples, 44100);
+
+    decoder.decode_all().into_result().unwrap();
+    assert!(written.get());
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+/// * A reader over the FLAC file in memory that counts how many bytes were read.
+#[cfg(test)]
+#[derive(Debug)]
+struct CountingReader<'a> {
+    cursor: std::io::Cursor<&'a [u8]>,
+    bytes_read: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+#[cfg(test)]
+impl std::io::Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.cursor.read(buf)?;
+        self.bytes_read.set(self.bytes_read.get() + size);
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+impl std::io::Seek for CountingReader<'_> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+}
+
+#[test]
+fn test_scan() {
+    use std::{cell::Cell, io::Cursor, rc::Rc, time::Duration};
+    use options::{FlacAudioForm, FlacCompression, FlacEncoderParams};
+    let errors = DecoderErrors::default();
+
+    // A minute of stereo audio.
+    let params = FlacEncoderParams {
+        compression: FlacCompression::Level0,
+        ..FlacEncoderParams::new()
+    };
+    let samples: Vec<i32> = (0..44100 * 60).flat_map(|i| {
+        let v = ((i as f64 * 440.0 * std::f64::consts::TAU / 44100.0).sin() * 8192.0) as i32 + i % 97;
+        [v, -v]
+    }).collect();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+    let total_samples = samples.len() as u64 / 2;
+    let step = Duration::from_secs(5);
+    let step_samples = 44100 * 5;
+    // Level 0 uses the block size of 1152.
+    let blocksize = 1152;
+    let last_frame = (total_samples - 1) / blocksize * blocksize;
+
+    let mut unknown_length = flac.clone();
+    unknown_length[21] &= 0xF0;
+    unknown_length[22..26].fill(0);
+
+    for (data, seekable) in [(&flac, true), (&unknown_length, false)] {
+        let bytes_read = Rc::new(Cell::new(0));
+        let reader = CountingReader {
+            cursor: Cursor::new(data),
+            bytes_read: bytes_read.clone(),
+        };
+        let writes = Cell::new(0);
+        let mut decoder = FlacDecoder::with_default_io(
+            reader,
+            Box::new(|_, _| {writes.set(writes.get() + 1); Ok(())}),
+            record_errors(&errors),
+            true,
+            false,
+            FlacAudioForm::ChannelArray,
+        ).unwrap();
+
+        let mut visited = Vec::<u64>::new();
+        decoder.scan(step, |position, frame, samples_info| {
+            // The samples are the ones at the position.
+            assert_eq!(frame[0].len(), samples_info.samples as usize);
+            assert_eq!(frame[0][0], samples[position as usize * 2]);
+            visited.push(position);
+        }).unwrap();
+        decoder.finish().unwrap();
+        drop(decoder);
+        assert_eq!(writes.get(), 0, "`on_write()` shouldn't be called while scanning");
+
+        let grid: Vec<u64> = (0..total_samples).step_by(step_samples).collect();
+        assert_eq!(visited.len(), grid.len() + 1, "{visited:?}");
+        for (position, target) in visited.iter().zip(grid.iter()) {
+            assert!(*position <= *target && target - position < blocksize, "{position} for {target}");
+        }
+        assert_eq!(*visited.last().unwrap(), last_frame);
+
+        // Seeking only touches a small fraction of the file.
+        if seekable {
+            assert!(bytes_read.get() < data.len() / 4, "{} of {} bytes read", bytes_read.get(), data.len());
+        }
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_interleave() {
+    use util::{interleave, deinterleave};
+
+    let planar = vec![vec![1, 2, 3], vec![-1, -2, -3], vec![10, 20, 30]];
+    let interleaved = interleave(&planar).unwrap();
+    assert_eq!(interleaved, [1, -1, 10, 2, -2, 20, 3, -3, 30]);
+    assert_eq!(deinterleave(&interleaved, 3).unwrap(), planar);
+
+    // Round trip of the real audio.
+    let samples = sine_wave_stereo_16();
+    let planar = deinterleave(&samples, 2).unwrap();
+    assert_eq!(planar.len(), 2);
+    assert_eq!(planar[0].len(), samples.len() / 2);
+    assert_eq!(interleave(&planar).unwrap(), samples);
+
+    // The empty buffers
+    assert_eq!(interleave(&[]).unwrap(), Vec::<i32>::new());
+    assert_eq!(interleave(&[vec![], vec![]]).unwrap(), Vec::<i32>::new());
+    assert_eq!(deinterleave(&[], 2).unwrap(), vec![Vec::<i32>::new(), Vec::new()]);
+
+    // The mismatched lengths
+    assert!(interleave(&[vec![1, 2, 3], vec![1, 2]]).is_err());
+    assert!(interleave(&[vec![1], vec![1], vec![]]).is_err());
+    assert!(deinterleave(&[1, 2, 3], 2).is_err());
+    assert!(deinterleave(&[1, 2], 0).is_err());
+}
+
+#[test]
+fn test_add_seek_points() {
+    use options::FlacEncoderParams;
+    let errors = DecoderErrors::default();
+
+    let params = FlacEncoderParams {
+        total_samples_estimate: 44100,
+        ..FlacEncoderParams::new()
+    };
+    let samples = sine_wave_stereo_16();
+
+    // The frame-aligned points stay where they are, the others move to the start of their frames.
+    let requested = [0, 8192, 4096, 8192, 40960, 10000];
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        assert!(encoder.add_seek_points(&[44100]).is_err());
+        assert!(encoder.add_seek_points(&[u64::MAX]).is_err());
+        encoder.add_seek_points(&requested).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    let seek_points = decoder.get_seek_points();
+    let sample_numbers: Vec<u64> = seek_points.iter().map(|point| point.sample_number).collect();
+    for sample_number in requested {
+        let frame_start = sample_number / 4096 * 4096;
+        assert!(sample_numbers.contains(&frame_start), "{sample_number} in {sample_numbers:?}");
+    }
+    assert_eq!(sample_numbers, [0, 4096, 8192, 40960]);
+    assert!(seek_points.iter().all(|point| point.frame_samples > 0));
+    assert!(seek_points.windows(2).all(|pair| pair[0].stream_offset < pair[1].stream_offset));
+
+    // The decoder seeks with the table.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.seek(40960).unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_arbitrary_comment_keys() {
+    use options::FlacEncoderParams;
+    let errors = DecoderErrors::default();
+
+    let params = FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let pairs = [
+        ("MUSICBRAINZ_TRACKID", "8f3471b5-7e6a-48da-86a9-c1c07a0f47ae"),
+        ("MUSICBRAINZ_ALBUMID", "1e0eee38-a9f6-49bf-84d0-45d0647799af"),
+        ("DISCNUMBER", "1"),
+        ("LYRICS", "la la la"),
+        ("MY_OWN_KEY", "custom value"),
+    ];
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        assert!(encoder.insert_comments("", "empty").is_err());
+        assert!(encoder.insert_comments("A=B", "equal sign").is_err());
+        assert!(encoder.insert_comments("ÄÖÜ", "non-ASCII").is_err());
+        for (key, value) in pairs {
+            encoder.insert_comments(key, value).unwrap();
+        }
+    });
+    assert!(!metadata::COMMENT_KEYS.contains(&"MY_OWN_KEY"));
+    assert!(metadata::COMMENT_KEYS.contains(&"MUSICBRAINZ_TRACKID"));
+
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    for (key, value) in pairs {
+        assert_eq!(decoder.get_comments().get(key).map(|v| v.as_str()), Some(value));
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_check_subset() {
+    use options::{FlacEncoderParams, FlacCompression};
+
+    let params = FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    let report = check_subset(std::io::Cursor::new(&flac[..])).unwrap();
+    assert!(report.is_subset(), "{report:?}");
+    assert_eq!(report.frames, 44100u64.div_ceil(4096));
+    assert_eq!(report.unreadable_frames, 0);
+    assert_eq!(report.max_blocksize, 4096);
+    // Compression level 5 uses LPC up to order 8 and the Rice partitions up to order 5.
+    assert!((1..=8).contains(&report.max_lpc_order), "{report:?}");
+    assert!(report.max_rice_partition_order <= 5, "{report:?}");
+
+    // Compression level 0 uses the fixed predictors only, level 8 uses LPC up to order 12.
+    for (compression, max_lpc_order) in [(FlacCompression::Level0, 0), (FlacCompression::Level8, 12)] {
+        let params = FlacEncoderParams {
+            compression,
+            ..FlacEncoderParams::new()
+        };
+        let flac = encode_to_memory(&params, &samples, |_| {});
+        let report = check_subset(std::io::Cursor::new(&flac[..])).unwrap();
+        assert!(report.is_subset(), "{report:?}");
+        assert_eq!(report.unreadable_frames, 0);
+        assert!(report.max_lpc_order <= max_lpc_order, "{report:?}");
+    }
+}
+
+#[test]
+fn test_next_event() {
+    use metadata::MetadataBlock;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams {
+        total_samples_estimate: 44100,
+        ..options::FlacEncoderParams::new()
+    };
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        encoder.add_seek_points(&[0, 8192]).unwrap();
+    });
+
+    let on_write_called = std::cell::Cell::new(false);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| {
+        on_write_called.set(true);
+        Ok(())
+    }));
+
+    let mut blocks = Vec::new();
+    let mut decoded = Vec::new();
+    loop {
+        match decoder.next_event().unwrap() {
+            DecodeEvent::Metadata(block) => {
+                assert!(decoded.is_empty(), "The metadata blocks come before the audio frames");
+                blocks.push(block);
+            }
+            DecodeEvent::Audio(frames, samples_info) => {
+                assert_eq!(frames.len(), samples_info.samples as usize);
+                decoded.extend(frames.into_iter().flatten());
+            }
+            DecodeEvent::EndOfStream => break,
+        }
+    }
+    assert!(matches!(decoder.next_event().unwrap(), DecodeEvent::EndOfStream));
+    decoder.finish().unwrap();
+
+    assert!(matches!(blocks[0], MetadataBlock::StreamInfo(stream_info) if stream_info.total_samples == 44100));
+    assert!(blocks.iter().any(|block| matches!(block, MetadataBlock::SeekTable(points) if points.len() == 2)));
+    assert!(blocks.iter().any(|block| matches!(block, MetadataBlock::VorbisComment{comments, ..} if comments == &[("TITLE".to_owned(), "Sine".to_owned())])));
+    assert_eq!(decoder.get_comments().get("TITLE").map(|title| title.as_str()), Some("Sine"));
+    assert_eq!(decoded, samples);
+    assert!(!on_write_called.get());
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_send_across_threads() {
+    use std::{io::{self, Cursor, Read, Write, Seek, SeekFrom}, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}}, thread};
+    use closure_objects::FlacReadStatus;
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+
+    // Create the encoder here, encode on a thread, drop it on another thread.
+    let mut cursor = Cursor::new(Vec::<u8>::new());
+    let mut encoder = SendFlacEncoder::new(
+        &mut cursor,
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)
+        }),
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {
+            writer.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {
+            writer.stream_position()
+        }),
+        &params
+    ).unwrap();
+
+    // The closures set later must be `Send` too, the unbounded setters can't be reached through `Deref`.
+    let frames = Arc::new(AtomicUsize::new(0));
+    {
+        let frames = frames.clone();
+        encoder.set_on_frame(Some(Box::new(move |_frame| {
+            frames.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        })));
+    }
+    thread::scope(|scope| {
+        let encoder = scope.spawn(|| {
+            let mut encoder = encoder;
+            encoder.initialize().unwrap();
+            encoder.write_interleaved_samples(&samples).unwrap();
+            encoder.finish().unwrap();
+            encoder
+        }).join().unwrap();
+        scope.spawn(move || drop(encoder)).join().unwrap();
+    });
+    let flac = cursor.into_inner();
+    assert!(frames.load(Ordering::Relaxed) > 1);
+
+    // Create the decoder here, decode on a thread, drop it on another thread.
+    let decoded = Mutex::new(Vec::<i32>::new());
+    let errors = Mutex::new(Vec::new());
+    let length = flac.len() as u64;
+    let mut decoder = SendFlacDecoder::new(
+        Cursor::new(&flac[..]),
+        Box::new(|reader: &mut Cursor<&[u8]>, data: &mut [u8]| -> (usize, FlacReadStatus) {
+            match reader.read(data) {
+                Ok(0) => (0, FlacReadStatus::Eof),
+                Ok(size) => (size, FlacReadStatus::GoOn),
+                Err(_) => (0, FlacReadStatus::Abort),
+            }
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>, position: u64| -> Result<(), io::Error> {
+            reader.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            reader.stream_position()
+        }),
+        Box::new(move |_reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            Ok(length)
+        }),
+        Box::new(move |reader: &mut Cursor<&[u8]>| -> bool {
+            reader.position() >= length
+        }),
+        Box::new(|frames: &[Vec<i32>], _samples_info: &closure_objects::SamplesInfo| -> Result<(), io::Error> {
+            decoded.lock().unwrap().extend(frames.iter().flatten());
+            Ok(())
+        }),
+        Box::new(|error: closure_objects::FlacInternalDecoderError| {
+            errors.lock().unwrap().push(error);
+        }),
+        true,
+        false,
+        options::FlacAudioForm::FrameArray,
+    ).unwrap();
+    let ended = Arc::new(AtomicUsize::new(0));
+    {
+        let ended = ended.clone();
+        decoder.set_on_end_of_stream(Some(Box::new(move || {ended.fetch_add(1, Ordering::Relaxed);})));
+    }
+    decoder.set_on_error_with_context(None);
+    thread::scope(|scope| {
+        let decoder = scope.spawn(|| {
+            let mut decoder = decoder;
+            decoder.decode_all().into_result().unwrap();
+            decoder.finish().unwrap();
+            decoder
+        }).join().unwrap();
+        scope.spawn(move || drop(decoder)).join().unwrap();
+    });
+    assert_eq!(decoded.into_inner().unwrap(), samples);
+    let errors = errors.into_inner().unwrap();
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(ended.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_scale_to_i32() {
+    use flac::scale_to_i32;
+
+    // The zero sample maps to the repeated offset binary midpoint.
+    for (bits, zero) in [(8, 0x0080_8080), (12, 0x0008_0080), (16, 0x0000_8000), (20, 0x0000_0800), (24, 0x0000_0080)] {
+        let min = -(1i32 << (bits - 1));
+        let max = (1i32 << (bits - 1)) - 1;
+        assert_eq!(scale_to_i32(min, bits), i32::MIN, "{bits} bits");
+        assert_eq!(scale_to_i32(max, bits), i32::MAX, "{bits} bits");
+        assert_eq!(scale_to_i32(0, bits), zero, "{bits} bits");
+
+        // Strictly monotonic, and the upper bits are the sample itself.
+        let mut previous = None;
+        for sample in min..=max {
+            let scaled = scale_to_i32(sample, bits);
+            assert_eq!(scaled >> (32 - bits), sample, "{bits} bits");
+            if let Some(previous) = previous {
+                assert!(scaled > previous, "{bits} bits, sample {sample}");
+            }
+            previous = Some(scaled);
+        }
+
+        // The bits above `bits` are ignored.
+        assert_eq!(scale_to_i32(max + 1, bits), i32::MIN, "{bits} bits");
+    }
+    assert_eq!(scale_to_i32(i32::MIN, 32), i32::MIN);
+    assert_eq!(scale_to_i32(i32::MAX, 32), i32::MAX);
+}
+
+/// * The bit replication loop `scale_to_i32()` was originally written as, the reference to test the closed form against.
+#[cfg(test)]
+fn scale_to_i32_reference(sample: i32, bits: u32) -> i32 {
+    if bits == 32 {
+        sample
+    } else {
+        let mut lower_fill = ((sample as u32).wrapping_add(1 << (bits - 1)) & ((1u64 << bits) - 1) as u32) << (32 - bits);
+        let mut result = (sample as u32) << (32 - bits);
+        while lower_fill > 0 {
+            lower_fill >>= bits;
+            result |= lower_fill;
+        }
+        result as i32
+    }
+}
+
+#[test]
+fn test_scale_to_i32_against_reference() {
+    use flac::{scale_to_i32, scale_slice_to_i32};
+
+    for bits in 1..=32u32 {
+        let min = (-(1i64 << (bits - 1))) as i32;
+        let max = ((1i64 << (bits - 1)) - 1) as i32;
+        let samples: Vec<i32> = (i16::MIN..=i16::MAX).map(i32::from).chain([min, max, min + 1, max - 1, 0, -1]).collect();
+        for &sample in samples.iter() {
+            assert_eq!(scale_to_i32(sample, bits), scale_to_i32_reference(sample, bits), "{bits} bits, sample {sample}");
+        }
+
+        let mut scaled = samples.clone();
+        scale_slice_to_i32(&mut scaled, bits);
+        for (sample, scaled) in samples.iter().zip(scaled.iter()) {
+            assert_eq!(*scaled, scale_to_i32_reference(*sample, bits), "{bits} bits, sample {sample}");
+        }
+    }
+}
+
+#[test]
+fn test_write_stereo_frames() {
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let frames: Vec<Vec<i32>> = samples.chunks(2).map(|frame| frame.to_vec()).collect();
+    let arrays: Vec<[i32; 2]> = samples.chunks(2).map(|frame| [frame[0], frame[1]]).collect();
+
+    let by_vec = encode_to_memory_with(&params, |_| {}, |encoder| {
+        encoder.write_frames(&frames).unwrap();
+    });
+    let by_array = encode_to_memory_with(&params, |_| {}, |encoder| {
+        for chunk in arrays.chunks(1000) {
+            encoder.write_stereo_frames(chunk).unwrap();
+        }
+    });
+    assert_eq!(by_vec, by_array);
+
+    // The array size must match the channels.
+    encode_to_memory_with(&params, |_| {}, |encoder| {
+        assert!(encoder.write_frames_array(&[[0i32; 3]; 4]).is_err());
+        assert!(encoder.write_frames_array::<2>(&[]).is_ok());
+    });
+}
+
+#[test]
+fn test_write_stereo_planar_i16() {
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let left: Vec<i16> = samples.iter().step_by(2).map(|&sample| sample as i16).collect();
+    let right: Vec<i16> = samples.iter().skip(1).step_by(2).map(|&sample| sample as i16).collect();
+
+    let by_interleaved = encode_to_memory(&params, &samples, |_| {});
+    let by_planar = encode_to_memory_with(&params, |_| {}, |encoder| {
+        for (left, right) in left.chunks(1000).zip(right.chunks(1000)) {
+            encoder.write_stereo_planar_i16(left, right).unwrap();
+        }
+    });
+    assert_eq!(by_interleaved, by_planar);
+
+    // The lengths must match, the encoder must be 16-bit stereo.
+    encode_to_memory_with(&params, |_| {}, |encoder| {
+        assert!(encoder.write_stereo_planar_i16(&left[..10], &right[..9]).is_err());
+    });
+    let mut params_24 = params;
+    params_24.bits_per_sample = 24;
+    encode_to_memory_with(&params_24, |_| {}, |encoder| {
+        assert!(encoder.write_stereo_planar_i16(&left[..10], &right[..10]).is_err());
+    });
+}
+
+#[test]
+fn test_write_stereos_and_monos() {
+    use options::FlacEncoderParams;
+    let errors = DecoderErrors::default();
+
+    let decode = |flac: &[u8]| -> Vec<Vec<i32>> {
+        let frames = std::cell::RefCell::new(Vec::new());
+        let mut decoder = decoder_from_memory(flac, &errors, Box::new(|decoded, _| {
+            frames.borrow_mut().extend_from_slice(decoded);
+            Ok(())
+        }));
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        drop(decoder);
+        frames.into_inner()
+    };
+
+    // Odd lengths, and the reused buffer shrinks and grows between the calls.
+    let samples = sine_wave_stereo_16();
+    let stereos: Vec<(i32, i32)> = samples.chunks(2).map(|frame| (frame[0], frame[1])).collect();
+    let params = FlacEncoderParams::new();
+    let expected = encode_to_memory(&params, &samples, |_| {});
+    let flac = encode_to_memory_with(&params, |_| {}, |encoder| {
+        let mut rest = &stereos[..];
+        for len in [1, 3, 4097, 7, 1000].into_iter().cycle() {
+            let (chunk, remaining) = rest.split_at(len.min(rest.len()));
+            encoder.write_stereos(chunk).unwrap();
+            rest = remaining;
+            if rest.is_empty() {
+                break;
+            }
+        }
+    });
+    assert_eq!(flac, expected);
+
+    // Stereo to mono averages in `i64` so that the 32-bit samples can't overflow.
+    let params = FlacEncoderParams {
+        channels: 1,
+        bits_per_sample: 32,
+        ..FlacEncoderParams::new()
+    };
+    let stereos = [(i32::MAX, i32::MAX), (i32::MIN, i32::MIN), (i32::MIN, i32::MAX), (-3, 0), (5, 2)];
+    let flac = encode_to_memory_with(&params, |_| {}, |encoder| {
+        encoder.write_stereos(&stereos).unwrap();
+    });
+    assert_eq!(decode(&flac), [[i32::MAX], [i32::MIN], [0], [-1], [3]]);
+
+    // Mono to multi-channel duplicates the samples.
+    let monos = [1, -2, 3, -4, 5, -6, 7];
+    for channels in [2, 3] {
+        let params = FlacEncoderParams {
+            channels,
+            ..FlacEncoderParams::new()
+        };
+        let flac = encode_to_memory_with(&params, |_| {}, |encoder| {
+            encoder.write_mono_channel(&monos).unwrap();
+        });
+        let expected: Vec<Vec<i32>> = monos.iter().map(|mono| vec![*mono; channels as usize]).collect();
+        assert_eq!(decode(&flac), expected);
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_buffer_for_header() {
+    use std::io::{self, Write, Seek, SeekFrom};
+    use options::FlacEncoderParams;
+    let errors = DecoderErrors::default();
+
+    /// * A writer that can't seek, like a pipe.
+    #[derive(Debug, Default)]
+    struct Pipe(Vec<u8>);
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Pipe {
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+            Err(io::Error::from(io::ErrorKind::NotSeekable))
+        }
+    }
+
+    let params = FlacEncoderParams {
+        buffer_for_header: true,
+        ..FlacEncoderParams::new()
+    };
+    let samples = sine_wave_stereo_16();
+    let mut pipe = Pipe::default();
+    // The writer can't seek or tell.
+    let seeks = std::cell::Cell::new(0);
+    {
+        let mut encoder = FlacEncoder::new(
+            &mut pipe,
+            Box::new(|writer: &mut &mut Pipe, data: &[u8]| -> Result<(), io::Error> {
+                writer.write_all(data)
+            }),
+            Box::new(|_writer: &mut &mut Pipe, _position: u64| -> Result<(), io::Error> {
+                seeks.set(seeks.get() + 1);
+                Err(io::Error::from(io::ErrorKind::NotSeekable))
+            }),
+            Box::new(|_writer: &mut &mut Pipe| -> Result<u64, io::Error> {
+                seeks.set(seeks.get() + 1);
+                Err(io::Error::from(io::ErrorKind::NotSeekable))
+            }),
+            &params
+        ).unwrap();
+        encoder.add_seek_points(&[0, 8192]).unwrap();
+        encoder.initialize().unwrap();
+        encoder.write_interleaved_samples(&samples).unwrap();
+        encoder.finish().unwrap();
+    }
+    assert_eq!(seeks.get(), 0);
+
+    // The STREAMINFO block has the exact total samples and the MD5 sum, the seek points are filled in.
+    let flac = pipe.0;
+    assert_eq!(flac, encode_to_memory(&FlacEncoderParams::new(), &samples, |encoder| encoder.add_seek_points(&[0, 8192]).unwrap()));
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    let stream_info = decoder.get_stream_info().unwrap();
+    assert_eq!(stream_info.total_samples, 44100);
+    assert_ne!(stream_info.md5sum, [0; 16]);
+    assert!(decoder.get_seek_points().iter().all(|point| point.frame_samples > 0));
+    decoder.finish().unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_compare_audio() {
+    use std::io::Cursor;
+    use options::{FlacEncoderParams, FlacCompression};
+
+    let samples = sine_wave_stereo_16();
+    let params = FlacEncoderParams::new();
+    let level5 = encode_to_memory(&params, &samples, |encoder| encoder.insert_comments("TITLE", "Sine").unwrap());
+
+    // Different block sizes and metadata, the same audio.
+    let level0 = encode_to_memory(&FlacEncoderParams {compression: FlacCompression::Level0, ..params}, &samples, |_| {});
+    assert!(audio_equal(Cursor::new(&level5[..]), Cursor::new(&level0[..])).unwrap());
+
+    let mut modified = samples.clone();
+    modified[20001] += 1;
+    let modified = encode_to_memory(&params, &modified, |_| {});
+    assert_eq!(compare_audio(Cursor::new(&level0[..]), Cursor::new(&modified[..])).unwrap(), Some(AudioDifference::Sample {
+        position: 10000,
+        channel: 1,
+        a: samples[20001],
+        b: samples[20001] + 1,
+    }));
+    assert!(!audio_equal(Cursor::new(&level5[..]), Cursor::new(&modified[..])).unwrap());
+
+    let truncated = encode_to_memory(&params, &samples[..samples.len() - 200], |_| {});
+    assert_eq!(compare_audio(Cursor::new(&level5[..]), Cursor::new(&truncated[..])).unwrap(), Some(AudioDifference::Length(44100, 44000)));
+
+    let resampled = encode_to_memory(&FlacEncoderParams {sample_rate: 48000, ..params}, &samples, |_| {});
+    assert_eq!(compare_audio(Cursor::new(&level5[..]), Cursor::new(&resampled[..])).unwrap(), Some(AudioDifference::SampleRate(44100, 48000)));
+}
+
+#[test]
+fn test_samples_info_accessors() {
+    use std::{cell::RefCell, io::Cursor};
+    use options::FlacAudioForm;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+
+    for audio_form in [FlacAudioForm::FrameArray, FlacAudioForm::ChannelArray] {
+        let decoded = RefCell::new(Vec::<i32>::new());
+        let mut decoder = FlacDecoder::with_default_io(
+            Cursor::new(&flac[..]),
+            Box::new(|frames: &[Vec<i32>], samples_info: &closure_objects::SamplesInfo| -> Result<(), std::io::Error> {
+                assert_eq!(frames.len(), samples_info.outer_len());
+                assert!(frames.iter().all(|inner| inner.len() == samples_info.inner_len()));
+                assert_eq!(frames.iter().map(|inner| inner.len()).sum::<usize>(), samples_info.total_values());
+                assert_eq!(samples_info.get_sample(frames, samples_info.samples as usize, 0), None);
+                decoded.borrow_mut().extend(samples_info.frames_in(frames).flatten());
+                Ok(())
+            }),
+            record_errors(&errors),
+            false,
+            false,
+            audio_form,
+        ).unwrap();
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(*decoded.borrow(), samples);
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_invalid_metadata_block() {
+    use std::io::{self, Cursor, Write, Seek, SeekFrom};
+    use metadata::{FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType, FlacMetadataType, MetadataBlockId};
+    use errors::FlacEncoderInitErrorCode;
+
+    let cue_sheet = |is_cd: bool| FlacCueSheet {
+        media_catalog_number: [0; 129],
+        lead_in: if is_cd {88200} else {0},
+        is_cd,
+        tracks: [1, if is_cd {170} else {255}].into_iter().map(|track_no| (track_no, FlacCueTrack {
+            // A CD track must start at a multiple of 588 samples.
+            offset: if track_no == 1 {if is_cd {100} else {0}} else {44100},
+            track_no,
+            isrc: [0; 13],
+            type_: FlacTrackType::Audio,
+            pre_emphasis: false,
+            indices: if track_no == 1 {vec![FlacCueSheetIndex {offset: 0, number: 1}]} else {Vec::new()},
+        })).collect(),
+    };
+    let initialize = |prepare: &dyn Fn(&mut MemoryEncoder)| {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = FlacEncoder::new(
+            &mut buffer,
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {
+                writer.write_all(data)
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {
+                writer.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {
+                writer.stream_position()
+            }),
+            &options::FlacEncoderParams::new()
+        ).unwrap();
+        prepare(&mut encoder);
+        let ret = encoder.initialize();
+        let description = ret.err().and_then(|error| error.failed_block).and_then(|id| encoder.describe_metadata_block(id));
+        (ret, description)
+    };
+
+    // The third cue sheet isn't a legal CD cue sheet.
+    let (ret, description) = initialize(&|encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        encoder.insert_cue_sheet(&cue_sheet(false)).unwrap();
+        encoder.insert_cue_sheet(&cue_sheet(false)).unwrap();
+        encoder.insert_cue_sheet(&cue_sheet(true)).unwrap();
+        encoder.add_picture(&[0u8; 100], "Cover", "image/png", 0, 0, 0, 0).unwrap();
+    });
+    let error = ret.unwrap_err();
+    assert!(matches!(FlacEncoderInitErrorCode::from(error.code), FlacEncoderInitErrorCode::StreamEncoderInitStatusInvalidMetadata));
+    assert_eq!(error.failed_block, Some(MetadataBlockId {kind: FlacMetadataType::CueSheet, index: 2}));
+    assert_eq!(description.unwrap(), "2 tracks");
+
+    // The second picture doesn't fit in a metadata block, it's rejected when it's added.
+    let (ret, _) = initialize(&|encoder| {
+        encoder.add_picture(&[0u8; 100], "Cover", "image/png", 0, 0, 0, 0).unwrap();
+        let error = encoder.add_picture(&vec![0u8; 1 << 24], "Poster", "image/jpeg", 0, 0, 0, 0).unwrap_err();
+        assert_eq!(error.failed_block, Some(MetadataBlockId {kind: FlacMetadataType::Picture, index: 1}));
+    });
+    ret.unwrap();
+
+    // The valid metadata passes.
+    let (ret, _) = initialize(&|encoder| {
+        encoder.insert_cue_sheet(&cue_sheet(false)).unwrap();
+        encoder.add_picture(&[0u8; 100], "Cover", "image/png", 0, 0, 0, 0).unwrap();
+    });
+    ret.unwrap();
+}
+
+#[test]
+fn test_channels_wanted() {
+    use std::cell::RefCell;
+    use errors::FLAC_DECODER_CHANNEL_OUT_OF_RANGE;
+    use options::{FlacAudioForm, SamplesInfo};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let left: Vec<i32> = samples.iter().step_by(2).copied().collect();
+    let right: Vec<i32> = samples.iter().skip(1).step_by(2).copied().collect();
+
+    let decode_channels = |channels_wanted: Option<&[usize]>, audio_form: FlacAudioForm| {
+        let decoded = RefCell::new(Vec::<Vec<i32>>::new());
+        let mut decoder = FlacDecoder::with_default_io(
+            std::io::Cursor::new(&flac[..]),
+            Box::new(|samples: &[Vec<i32>], samples_info: &SamplesInfo| {
+                let mut decoded = decoded.borrow_mut();
+                decoded.resize(samples_info.channels as usize, Vec::new());
+                for frame in 0..samples_info.samples as usize {
+                    for (channel, decoded) in decoded.iter_mut().enumerate() {
+                        decoded.push(samples_info.get_sample(samples, frame, channel).unwrap());
+                    }
+                }
+                Ok(())
+            }),
+            record_errors(&errors),
+            true,
+            false,
+            audio_form,
+        ).unwrap();
+        decoder.set_channels_wanted(channels_wanted)?;
+        assert_eq!(decoder.get_channels_wanted(), channels_wanted);
+        let ret = decoder.decode_all().into_result();
+        drop(decoder);
+        ret.map(|_| decoded.into_inner())
+    };
+
+    for audio_form in [FlacAudioForm::FrameArray, FlacAudioForm::ChannelArray] {
+        assert_eq!(decode_channels(Some(&[0]), audio_form).unwrap(), std::slice::from_ref(&left));
+        assert_eq!(decode_channels(Some(&[1, 0]), audio_form).unwrap(), [right.clone(), left.clone()]);
+        assert_eq!(decode_channels(Some(&[1, 1]), audio_form).unwrap(), [right.clone(), right.clone()]);
+        assert_eq!(decode_channels(None, audio_form).unwrap(), [left.clone(), right.clone()]);
+
+        // The channel out of range is rejected by the STREAMINFO block before any audio.
+        assert_eq!(decode_channels(Some(&[0, 2]), audio_form).unwrap_err().code, FLAC_DECODER_CHANNEL_OUT_OF_RANGE);
+        assert_eq!(decode_channels(Some(&[]), audio_form).unwrap_err().code, FLAC_DECODER_CHANNEL_OUT_OF_RANGE);
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_applications() {
+    use metadata::FlacApplication;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let mut flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+    });
+
+    // Insert the APPLICATION blocks right after the STREAMINFO block, which isn't the last block.
+    let after_stream_info = 4 + 4 + 34;
+    let mut blocks = Vec::new();
+    for (id, data) in [(*b"riff", &b"RIFF chunk"[..]), ([0xFF, b'a', 0, b'c'], &b""[..])] {
+        let length = (4 + data.len()) as u32;
+        blocks.push(2u8);
+        blocks.extend_from_slice(&length.to_be_bytes()[1..]);
+        blocks.extend_from_slice(&id);
+        blocks.extend_from_slice(data);
+    }
+    flac.splice(after_stream_info..after_stream_info, blocks);
+
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    decoder.finish().unwrap();
+    let applications = decoder.get_applications();
+    assert_eq!(applications.len(), 2);
+    assert_eq!(applications[0], FlacApplication{id: *b"riff", data: b"RIFF chunk".to_vec()});
+    assert_eq!(applications[0].id_str(), "riff");
+    assert_eq!(applications[0].known_name(), Some("FLAC RIFF chunk storage"));
+    assert_eq!(applications[1].id_str(), "?a?c");
+    assert_eq!(applications[1].known_name(), None);
+    assert!(applications[1].data.is_empty());
+    assert_eq!(decoder.get_comments()["TITLE"], "Sine");
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_decode_error_context() {
+    use std::sync::{Arc, Mutex};
+    use closure_objects::{DecodeErrorContext, FlacInternalDecoderError};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let mut flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let corrupt_at = flac.len() / 2;
+    flac[corrupt_at] ^= 0x55;
+
+    let contexts = Arc::new(Mutex::new(Vec::<DecodeErrorContext>::new()));
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    {
+        let contexts = contexts.clone();
+        decoder.set_on_error_with_context(Some(Box::new(move |context: &DecodeErrorContext| {
+            contexts.lock().unwrap().push(*context);
+        })));
+    }
+    decoder.decode_all().into_result().unwrap();
+    let max_framesize = decoder.max_framesize().unwrap() as u64;
+    drop(decoder);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+
+    // The first error is reported within one frame of the corrupted byte, in the middle of the audio.
+    let contexts = contexts.lock().unwrap();
+    let first = contexts.first().expect("The corruption should be reported");
+    let byte_position = first.byte_position.unwrap();
+    assert!(byte_position.abs_diff(corrupt_at as u64) <= max_framesize, "{first} corrupted at {corrupt_at}");
+    assert!(first.frame_count_so_far > 0);
+    assert!(first.approx_sample.unwrap() > 0 && first.approx_sample.unwrap() < 44100);
+    assert!(first.to_string().contains(&format!("at byte {byte_position}")));
+
+    // The old `on_error()` closure still works without the context.
+    let error_count = std::cell::Cell::new(0usize);
+    let mut decoder = FlacDecoder::with_default_io(
+        std::io::Cursor::new(&flac[..]),
+        Box::new(|_, _| Ok(())),
+        Box::new(|_error: FlacInternalDecoderError| error_count.set(error_count.get() + 1)),
+        false,
+        false,
+        options::FlacAudioForm::FrameArray,
+    ).unwrap();
+    decoder.decode_all().into_result().unwrap();
+    drop(decoder);
+    assert_eq!(error_count.get(), contexts.len());
+}
+
+#[test]
+fn test_accurate_header() {
+    use std::io::{self, Write, Seek, SeekFrom};
+    use options::{AccurateHeader, FlacEncoderParams};
+    use errors::FlacEncoderError;
+    let errors = DecoderErrors::default();
+
+    /// * A writer that can't seek, like a socket.
+    #[derive(Debug, Default)]
+    struct Socket(Vec<u8>);
+
+    impl Write for Socket {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Socket {
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+            Err(io::Error::from(io::ErrorKind::NotSeekable))
+        }
+    }
+
+    let samples = sine_wave_stereo_16();
+    // The writer can't seek or tell.
+    let seeks = std::cell::Cell::new(0);
+    let encode = |accurate_header: AccurateHeader| -> (Vec<u8>, Result<(), FlacEncoderError>) {
+        let mut socket = Socket::default();
+        let result = {
+            let mut encoder = FlacEncoder::new(
+                &mut socket,
+                Box::new(|writer: &mut &mut Socket, data: &[u8]| -> Result<(), io::Error> {
+                    writer.write_all(data)
+                }),
+                Box::new(|_writer: &mut &mut Socket, _position: u64| -> Result<(), io::Error> {
+                    seeks.set(seeks.get() + 1);
+                    Err(io::Error::from(io::ErrorKind::NotSeekable))
+                }),
+                Box::new(|_writer: &mut &mut Socket| -> Result<u64, io::Error> {
+                    seeks.set(seeks.get() + 1);
+                    Err(io::Error::from(io::ErrorKind::NotSeekable))
+                }),
+                &FlacEncoderParams::new()
+            ).unwrap();
+            encoder.set_accurate_header(accurate_header.clone()).unwrap();
+            assert_eq!(encoder.get_accurate_header(), &accurate_header);
+            encoder.initialize().unwrap();
+            encoder.write_interleaved_samples(&samples).and_then(|_| encoder.finish())
+        };
+        (socket.0, result)
+    };
+
+    // Both spools give the same file as encoding to a seekable writer.
+    let expected = encode_to_memory(&FlacEncoderParams::new(), &samples, |_| {});
+    let temp_path = std::env::temp_dir().join(format!("flac-rs-test-accurate-header-{}.spool", std::process::id()));
+    for accurate_header in [AccurateHeader::BufferToMemory(expected.len()), AccurateHeader::BufferToTemp(temp_path.clone())] {
+        let (flac, result) = encode(accurate_header);
+        result.unwrap();
+        assert_eq!(flac, expected);
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_all().into_result().unwrap();
+        assert_eq!(decoder.get_stream_info().unwrap().total_samples, 44100);
+        decoder.finish().unwrap();
+        assert!(!temp_path.exists());
+    }
+
+    // Exceeding the memory limit fails the encoding and writes nothing.
+    let (flac, result) = encode(AccurateHeader::BufferToMemory(expected.len() / 2));
+    assert!(result.is_err());
+    assert!(flac.is_empty());
+    assert!(!temp_path.exists());
+
+    // The existing file isn't taken as the spool, it's left alone, and the spool can't be changed after `initialize()`.
+    std::fs::write(&temp_path, b"Keep me").unwrap();
+    encode_to_memory_with(&FlacEncoderParams::new(), |encoder| {
+        assert_eq!(encoder.set_accurate_header(AccurateHeader::BufferToTemp(temp_path.clone())).unwrap_err().code, libflac_sys::FLAC__STREAM_ENCODER_IO_ERROR);
+    }, |encoder| {
+        assert_eq!(encoder.set_accurate_header(AccurateHeader::BufferToMemory(1024)).unwrap_err().code, errors::FLAC_ENCODER_ALREADY_INITIALIZED);
+    });
+    assert_eq!(std::fs::read(&temp_path).unwrap(), b"Keep me");
+    std::fs::remove_file(&temp_path).unwrap();
+    assert_eq!(seeks.get(), 0);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_io_stats() {
+    let errors = DecoderErrors::default();
+    let params = options::FlacEncoderParams {
+        total_samples_estimate: 44100,
+        ..options::FlacEncoderParams::new()
+    };
+    let samples = sine_wave_stereo_16();
+    let mut encoder_stats = None;
+    let flac = encode_to_memory_with(&params, |encoder| {
+        encoder.add_seek_points(&[0, 22050]).unwrap();
+        encoder.set_io_stats_enabled(true);
+    }, |encoder| {
+        encoder.write_interleaved_samples(&samples).unwrap();
+        encoder.finish().unwrap();
+        encoder_stats = encoder.io_stats().copied();
+    });
+
+    // The encoder goes back to patch the STREAMINFO block and the seek table.
+    let encoder_stats = encoder_stats.unwrap();
+    assert!(encoder_stats.writes > 0);
+    assert!(encoder_stats.bytes_written >= flac.len() as u64);
+    assert!(encoder_stats.backward_seeks > 0);
+    assert_eq!(encoder_stats.seeks, encoder_stats.seek_distances.iter().sum::<u64>());
+
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    assert!(decoder.io_stats().is_none());
+    decoder.set_io_stats_enabled(true);
+
+    // Reading the metadata of a small file is sequential.
+    decoder.decode_metadata().unwrap();
+    let stats = *decoder.io_stats().unwrap();
+    assert!(stats.reads > 0);
+    assert!(stats.bytes_read > 0 && stats.bytes_read <= flac.len() as u64);
+    assert_eq!(stats.backward_seeks, 0);
+    assert_eq!(stats.writes, 0);
+
+    // Seeking to the middle takes a few seeks, not hundreds.
+    decoder.reset_io_stats();
+    decoder.seek(22050).unwrap();
+    let stats = *decoder.io_stats().unwrap();
+    assert!(stats.seeks > 0 && stats.seeks < 32, "{stats:?}");
+    assert_eq!(stats.seeks, stats.forward_seeks + stats.backward_seeks);
+
+    // Decoding the rest is sequential again, the small file may have been buffered entirely by the seek.
+    decoder.reset_io_stats();
+    decoder.decode_all().into_result().unwrap();
+    let stats = *decoder.io_stats().unwrap();
+    assert_eq!(stats.seeks, 0);
+    assert_eq!(stats.seeks_at_least(1), 0);
+    assert!(stats.bytes_read <= flac.len() as u64);
+    decoder.finish().unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_write_silence() {
+    use std::cell::RefCell;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams {
+        channels: 3,
+        bits_per_sample: 24,
+        ..options::FlacEncoderParams::new()
+    };
+    let sound: Vec<i32> = (0..1000).flat_map(|i| [i << 8, -i << 8, i]).collect();
+    let flac = encode_to_memory_with(&params, |_| {}, |encoder| {
+        encoder.write_interleaved_samples(&sound).unwrap();
+        encoder.write_silence(44100 * 10 + 1).unwrap();
+        encoder.write_silence(0).unwrap();
+    });
+
+    // Ten seconds of silence compress to almost nothing.
+    assert!(flac.len() < 16384, "{} bytes", flac.len());
+
+    let decoded = RefCell::new(Vec::<i32>::new());
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|frames, samples_info| {
+        assert_eq!(samples_info.channels, 3);
+        assert_eq!(samples_info.bits_per_sample, 24);
+        decoded.borrow_mut().extend(frames.iter().flatten());
+        Ok(())
+    }));
+    decoder.decode_all().into_result().unwrap();
+    decoder.finish().unwrap();
+
+    let decoded = decoded.borrow();
+    assert_eq!(decoded.len(), sound.len() + (44100 * 10 + 1) * 3);
+    assert_eq!(decoded[..sound.len()], sound[..]);
+    assert!(decoded[sound.len()..].iter().all(|sample| *sample == 0));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+/// * Counts the allocations of the current thread, for the tests of the allocation-free code paths.
+#[cfg(test)]
+struct CountingAllocator;
+
+#[cfg(test)]
+thread_local! {
+    static ALLOCATIONS: std::cell::Cell<u64> = const {std::cell::Cell::new(0)};
+}
+
+#[cfg(test)]
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        unsafe {std::alloc::System.alloc(layout)}
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        unsafe {std::alloc::System.dealloc(ptr, layout)}
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        unsafe {std::alloc::System.realloc(ptr, layout, new_size)}
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_decode_without_allocation() {
+    use std::cell::Cell;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams {
+        compression: options::FlacCompression::Level0,
+        ..options::FlacEncoderParams::new()
+    };
+    let sound = sine_wave_stereo_16();
+    let flac = encode_to_memory_with(&params, |_| {}, |encoder| {
+        for _ in 0..80 {
+            encoder.write_interleaved_samples(&sound).unwrap();
+        }
+    });
+
+    for audio_form in [options::FlacAudioForm::FrameArray, options::FlacAudioForm::ChannelArray] {
+        let frames = Cell::new(0u64);
+        let sum = Cell::new(0i64);
+        let mut decoder = FlacDecoder::new(
+            std::io::Cursor::new(&flac[..]),
+            Box::new(|reader: &mut std::io::Cursor<&[u8]>, data: &mut [u8]| -> (usize, closure_objects::FlacReadStatus) {
+                match std::io::Read::read(reader, data) {
+                    Ok(0) => (0, closure_objects::FlacReadStatus::Eof),
+                    Ok(size) => (size, closure_objects::FlacReadStatus::GoOn),
+                    Err(_) => (0, closure_objects::FlacReadStatus::Abort),
+                }
+            }),
+            Box::new(|reader: &mut std::io::Cursor<&[u8]>, position: u64| -> Result<(), std::io::Error> {
+                reader.set_position(position);
+                Ok(())
+            }),
+            Box::new(|reader: &mut std::io::Cursor<&[u8]>| -> Result<u64, std::io::Error> {
+                Ok(reader.position())
+            }),
+            Box::new(|_reader: &mut std::io::Cursor<&[u8]>| -> Result<u64, std::io::Error> {
+                Ok(flac.len() as u64)
+            }),
+            Box::new(|reader: &mut std::io::Cursor<&[u8]>| -> bool {
+                reader.position() >= flac.len() as u64
+            }),
+            Box::new(|samples: &[Vec<i32>], _samples_info: &closure_objects::SamplesInfo| -> Result<(), std::io::Error> {
+                frames.set(frames.get() + 1);
+                sum.set(sum.get() + samples.iter().flatten().map(|sample| *sample as i64).sum::<i64>());
+                Ok(())
+            }),
+            record_errors(&errors),
+            true,
+            true,
+            audio_form,
+        ).unwrap();
+
+        // Warm up with the metadata and the first frames.
+        decoder.decode_metadata().unwrap();
+        for _ in 0..10 {
+            decoder.decode().unwrap();
+        }
+
+        let allocations = ALLOCATIONS.with(|allocations| allocations.get());
+        while frames.get() < 3000 {
+            assert!(matches!(decoder.decode().unwrap(), DecodeStep::AudioFrame{..}));
+        }
+        assert_eq!(ALLOCATIONS.with(|allocations| allocations.get()), allocations);
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_pipeline() {
+    use std::{cell::RefCell, io::Cursor, thread};
+    use pipeline::{spawn_encoder, spawn_encoder_with, Backpressure};
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let decode = |flac: &[u8]| -> Vec<i32> {
+        let decoded = RefCell::new(Vec::new());
+        let mut decoder = decoder_from_memory(flac, &errors, Box::new(|frames, _| {
+            decoded.borrow_mut().extend(frames.iter().flatten());
+            Ok(())
+        }));
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        drop(decoder);
+        decoded.into_inner()
+    };
+
+    // Several bursts from several producer threads one after another.
+    let sound = sine_wave_stereo_16();
+    let (sender, handle) = spawn_encoder(Cursor::new(Vec::new()), &params, 2);
+    for burst in sound.chunks(10000) {
+        let sender = sender.clone();
+        let burst = burst.to_vec();
+        thread::spawn(move || {
+            for chunk in burst.chunks(1000) {
+                sender.send_interleaved(chunk).unwrap();
+            }
+        }).join().unwrap();
+    }
+    let frames: Vec<Vec<i32>> = sound.chunks(2).take(100).map(|frame| frame.to_vec()).collect();
+    sender.send_frames(&frames).unwrap();
+    assert!(sender.send_interleaved(&[1, 2, 3]).is_err());
+    assert!(sender.send_frames(&[vec![1]]).is_err());
+    let (writer, result) = handle.finish();
+    result.unwrap();
+    let mut expected = sound.clone();
+    expected.extend_from_slice(&sound[..200]);
+    assert_eq!(decode(writer.get_ref()), expected);
+
+    // The encoder thread is gone after finishing.
+    assert!(sender.send_interleaved(&[1, 2]).is_err());
+
+    // With `Backpressure::Drop` the producer never waits, the dropped chunks are counted, the rest keep their order.
+    let chunks: Vec<Vec<i32>> = (0..200).map(|i| vec![i; 882]).collect();
+    let (sender, handle) = spawn_encoder_with(Cursor::new(Vec::new()), &params, 1, Backpressure::Drop);
+    for chunk in chunks.iter() {
+        sender.send_interleaved(chunk).unwrap();
+    }
+    let dropped = handle.dropped_frames();
+    assert_eq!(dropped, sender.dropped_frames());
+    let (writer, result) = handle.finish();
+    result.unwrap();
+    let decoded = decode(writer.get_ref());
+    assert_eq!(decoded.len() as u64 / 2 + dropped, 200 * 441);
+    let received: Vec<i32> = decoded.chunks(882).map(|chunk| {
+        assert!(chunk.iter().all(|sample| *sample == chunk[0]));
+        chunk[0]
+    }).collect();
+    assert!(received.is_sorted_by(|a, b| a < b));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_stop_after_picture() {
+    use metadata::{FlacPictureType, FlacMetadataType};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        encoder.add_picture_of_type(&[1u8; 1000], FlacPictureType::BackCover, "Back", "image/png", 16, 8, 24, 0).unwrap();
+        encoder.add_picture_of_type(&[2u8; 2000], FlacPictureType::FrontCover, "Front", "image/jpeg", 32, 32, 24, 0).unwrap();
+        encoder.add_picture(&[3u8; 3000], "Leaflet", "image/png", 0, 0, 0, 0).unwrap();
+    });
+
+    // Stop right after the back cover.
+    let decoded = std::cell::Cell::new(0usize);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|frames, _| {
+        decoded.set(decoded.get() + frames.len());
+        Ok(())
+    }));
+    decoder.set_stop_after_picture(Some(FlacPictureType::BackCover));
+    assert!(decoder.decode_metadata().unwrap());
+    assert_eq!(decoder.picture_count(), 1);
+    let back = decoder.first_picture().unwrap();
+    assert_eq!(back.picture_type, FlacPictureType::BackCover);
+    assert_eq!(back.picture, vec![1u8; 1000]);
+    assert_eq!((back.description.as_str(), back.mime_type.as_str()), ("Back", "image/png"));
+    assert_eq!((back.width, back.height, back.depth), (16, 8, 24));
+    assert_eq!(decoded.get(), 0);
+
+    // Continue with the rest of the metadata and the audio.
+    decoder.decode_all().into_result().unwrap();
+    decoder.finish().unwrap();
+    let types: Vec<FlacPictureType> = decoder.get_pictures().iter().map(|picture| picture.picture_type).collect();
+    assert_eq!(types, [FlacPictureType::BackCover, FlacPictureType::FrontCover, FlacPictureType::Other]);
+    assert_eq!(decoded.get(), 44100);
+    drop(decoder);
+
+    // Stop after the front cover, and only read the pictures.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.set_metadata_respond(Some(&[FlacMetadataType::Picture])).unwrap();
+    decoder.set_stop_after_picture(Some(FlacPictureType::FrontCover));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.picture_count(), 2);
+    assert!(decoder.get_comments().is_empty());
+    assert_eq!(decoder.get_stream_info().unwrap().total_samples, 44100);
+
+    // Too late to change it after decoding.
+    decoder.decode_all().into_result().unwrap();
+    assert!(decoder.set_metadata_respond(None).is_err());
+    decoder.finish().unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_decoder_limits() {
+    use errors::FLAC_DECODER_LIMIT_EXCEEDED;
+    use options::DecoderLimits;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let decode_with = |limits: DecoderLimits| {
+        let decoded = std::cell::Cell::new(0usize);
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|frames, _| {
+            decoded.set(decoded.get() + frames.len());
+            Ok(())
+        }));
+        decoder.set_limits(limits);
+        assert_eq!(decoder.get_limits(), limits);
+        let ret = decoder.decode_all().into_result().map_err(|error| error.code);
+        drop(decoder);
+        (ret, decoded.get())
+    };
+
+    // Within the limits.
+    let (ret, decoded) = decode_with(DecoderLimits {
+        max_total_samples: Some(44100),
+        max_channels: Some(2),
+        max_sample_rate: Some(48000),
+    });
+    assert_eq!(ret.unwrap(), 44100);
+    assert_eq!(decoded, 44100);
+
+    // Each limit rejects the file by the STREAMINFO block before any audio.
+    for limits in [
+        DecoderLimits {max_total_samples: Some(44099), ..Default::default()},
+        DecoderLimits {max_channels: Some(1), ..Default::default()},
+        DecoderLimits {max_sample_rate: Some(22050), ..Default::default()},
+    ] {
+        let (ret, decoded) = decode_with(limits);
+        assert_eq!(ret.unwrap_err(), FLAC_DECODER_LIMIT_EXCEEDED, "{limits:?}");
+        assert_eq!(decoded, 0, "{limits:?}");
+    }
+
+    // Without the total samples in the STREAMINFO block, the frames are checked.
+    let mut flac = flac;
+    let total_samples_offset = 4 + 4 + 13;
+    flac[total_samples_offset] &= 0xF0;
+    flac[total_samples_offset + 1..total_samples_offset + 5].fill(0);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.set_limits(DecoderLimits {max_total_samples: Some(10000), ..Default::default()});
+    assert!(decoder.decode_metadata().unwrap());
+    assert_eq!(decoder.get_stream_info().unwrap().total_samples, 0);
+    assert_eq!(decoder.decode_all().into_result().unwrap_err().code, FLAC_DECODER_LIMIT_EXCEEDED);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_compression_from_quality() {
+    use options::FlacCompression;
+
+    assert_eq!(FlacCompression::from_quality(0), FlacCompression::Level0);
+    assert_eq!(FlacCompression::from_quality(11), FlacCompression::Level0);
+    assert_eq!(FlacCompression::from_quality(12), FlacCompression::Level1);
+    assert_eq!(FlacCompression::from_quality(60), FlacCompression::Level5);
+    assert_eq!(FlacCompression::from_quality(89), FlacCompression::Level7);
+    assert_eq!(FlacCompression::from_quality(90), FlacCompression::Level8);
+    assert_eq!(FlacCompression::from_quality(100), FlacCompression::Level8);
+    assert_eq!(FlacCompression::from_quality(255), FlacCompression::Level8);
+
+    // Never goes down as the quality goes up, and reaches every level.
+    let levels: Vec<u32> = (0..=100).map(|quality| FlacCompression::from_quality(quality) as u32).collect();
+    assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!((0..=8).all(|level| levels.contains(&level)));
+}
+
+#[test]
+fn test_low_latency_profile() {
+    use std::{cell::Cell, io::{self, Cursor, Read, Seek, SeekFrom}};
+    use closure_objects::FlacReadStatus;
+    use options::DecoderProfile;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Live").unwrap();
+        encoder.add_picture(&[1u8; 30000], "Cover", "image/png", 0, 0, 0, 0).unwrap();
+    });
+
+    // Decode until the first audio frame, count the calls to the closures.
+    let first_frame = |profile: DecoderProfile| {
+        let seeks = Cell::new(0u32);
+        let tells = Cell::new(0u32);
+        let lengths = Cell::new(0u32);
+        let length = flac.len() as u64;
+        let mut decoder = FlacDecoder::new(
+            Cursor::new(&flac[..]),
+            Box::new(|reader: &mut Cursor<&[u8]>, data: &mut [u8]| -> (usize, FlacReadStatus) {
+                match reader.read(data) {
+                    Ok(0) => (0, FlacReadStatus::Eof),
+                    Ok(size) => (size, FlacReadStatus::GoOn),
+                    Err(_) => (0, FlacReadStatus::Abort),
+                }
+            }),
+            Box::new(|reader: &mut Cursor<&[u8]>, position: u64| -> Result<(), io::Error> {
+                seeks.set(seeks.get() + 1);
+                reader.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+                tells.set(tells.get() + 1);
+                reader.stream_position()
+            }),
+            Box::new(|_reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+                lengths.set(lengths.get() + 1);
+                Ok(length)
+            }),
+            Box::new(move |reader: &mut Cursor<&[u8]>| -> bool {
+                reader.position() >= length
+            }),
+            Box::new(|_, _| Ok(())),
+            record_errors(&errors),
+            true,
+            false,
+            options::FlacAudioForm::FrameArray,
+        ).unwrap();
+        decoder.set_profile(profile).unwrap();
+        assert_eq!(decoder.get_profile(), profile);
+        decoder.set_io_stats_enabled(true);
+        while !matches!(decoder.next_event().unwrap(), DecodeEvent::Audio(..)) {}
+        let stats = *decoder.io_stats().unwrap();
+        let pictures = decoder.picture_count();
+        let comments = decoder.get_comments().len();
+        if profile == DecoderProfile::LowLatency {
+            // No `on_seek()` for the low latency profile.
+            assert!(decoder.seek(22050).is_err());
+        }
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        drop(decoder);
+        (stats, pictures, comments, seeks.get() + tells.get() + lengths.get())
+    };
+    let (default_stats, default_pictures, default_comments, _) = first_frame(DecoderProfile::Default);
+    let (stats, pictures, comments, probes) = first_frame(DecoderProfile::LowLatency);
+    assert_eq!((default_pictures, default_comments), (1, 1));
+    assert_eq!((pictures, comments), (0, 1));
+    assert_eq!(probes, 0);
+    assert_eq!((stats.seeks, stats.tells), (0, 0));
+    assert!(stats.reads <= default_stats.reads, "{stats:?} {default_stats:?}");
+    assert!(stats.bytes_read <= default_stats.bytes_read, "{stats:?} {default_stats:?}");
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_continue_new_stream() {
+    use errors::FLAC_ENCODER_FINISHED;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let mono: Vec<i32> = samples.iter().step_by(2).take(22050).map(|sample| sample >> 8).collect();
+    let mut first_length = 0;
+    let flac = encode_to_memory_with(&options::FlacEncoderParams::new(), |encoder| {
+        encoder.insert_comments("TITLE", "First").unwrap();
+    }, |encoder| {
+        assert_eq!(encoder.continue_new_stream(&options::FlacEncoderParams::new()).unwrap_err().code, errors::FLAC_ENCODER_NOT_FINISHED);
+        encoder.write_interleaved_samples(&samples).unwrap();
+        encoder.finish().unwrap();
+
+        // Appending to the finished stream is refused.
+        assert_eq!(encoder.write_interleaved_samples(&samples).unwrap_err().code, FLAC_ENCODER_FINISHED);
+        assert_eq!(encoder.write_silence(100).unwrap_err().code, FLAC_ENCODER_FINISHED);
+
+        first_length = encoder.tell().unwrap() as usize;
+        encoder.continue_new_stream(&options::FlacEncoderParams {
+            channels: 1,
+            bits_per_sample: 8,
+            sample_rate: 22050,
+            ..options::FlacEncoderParams::new()
+        }).unwrap();
+        encoder.insert_comments("TITLE", "Second").unwrap();
+        encoder.initialize().unwrap();
+        encoder.write_interleaved_samples(&mono).unwrap();
+    });
+
+    // Each stream is a complete FLAC file with its own STREAMINFO.
+    for (stream, title, expected) in [(&flac[..first_length], "First", &samples), (&flac[first_length..], "Second", &mono)] {
+        let decoded = std::cell::RefCell::new(Vec::<i32>::new());
+        let mut decoder = decoder_from_memory(stream, &errors, Box::new(|frames, _| {
+            decoded.borrow_mut().extend(frames.iter().flatten());
+            Ok(())
+        }));
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(decoder.get_comments().get("TITLE").map(|title| title.as_str()), Some(title));
+        assert_eq!(decoder.get_stream_info().unwrap().total_samples as usize * decoder.get_stream_info().unwrap().channels as usize, expected.len());
+        drop(decoder);
+        assert_eq!(&decoded.into_inner(), expected);
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_reblocker() {
+    use options::FlacAudioForm;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams {
+        compression: options::FlacCompression::Level0,
+        ..options::FlacEncoderParams::new()
+    };
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    for audio_form in [FlacAudioForm::FrameArray, FlacAudioForm::ChannelArray] {
+        for tail_policy in [TailPolicy::Deliver, TailPolicy::ZeroPad, TailPolicy::Drop] {
+            let mut decoder = FlacDecoder::with_default_io(
+                std::io::Cursor::new(&flac[..]),
+                Box::new(|_, _| Ok(())),
+                record_errors(&errors),
+                true,
+                false,
+                audio_form,
+            ).unwrap();
+            let mut reblocker = Reblocker::new(decoder.audio_frames(), 1000);
+            reblocker.set_tail_policy(tail_policy);
+
+            let mut interleaved = Vec::new();
+            let mut chunk_sizes = Vec::new();
+            for chunk in reblocker {
+                let (chunk, samples_info) = chunk.unwrap();
+                assert_eq!(chunk.len(), samples_info.outer_len());
+                assert!(chunk.iter().all(|inner| inner.len() == samples_info.inner_len()));
+                chunk_sizes.push(samples_info.samples);
+                interleaved.extend(samples_info.frames_in(&chunk).flatten());
+            }
+            decoder.finish().unwrap();
+
+            // 44100 frames are 44 full chunks and 100 frames in the tail.
+            assert!(chunk_sizes[..44].iter().all(|size| *size == 1000));
+            match tail_policy {
+                TailPolicy::Deliver => {
+                    assert_eq!(chunk_sizes.len(), 45);
+                    assert_eq!(chunk_sizes[44], 100);
+                    assert_eq!(interleaved, samples);
+                }
+                TailPolicy::ZeroPad => {
+                    assert_eq!(chunk_sizes.len(), 45);
+                    assert_eq!(chunk_sizes[44], 1000);
+                    assert_eq!(interleaved[..samples.len()], samples[..]);
+                    assert!(interleaved[samples.len()..].iter().all(|sample| *sample == 0));
+                    assert_eq!(interleaved.len(), 45000 * 2);
+                }
+                TailPolicy::Drop => {
+                    assert_eq!(chunk_sizes.len(), 44);
+                    assert_eq!(interleaved[..], samples[..44000 * 2]);
+                }
+            }
+        }
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_variable_blocksize() {
+    let errors = DecoderErrors::default();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let variable_blocksize = |flac: &[u8]| {
+        let mut decoder = decoder_from_memory(flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        assert_eq!(decoder.is_variable_blocksize(), None);
+        // The patched STREAMINFO block makes libFLAC number the last frame by the min block size, so the outcome may be `Truncated`, but all of the samples are there.
+        assert_eq!(decoder.decode_all().samples(), samples.len() as u64 / 2);
+        decoder.is_variable_blocksize()
+    };
+    assert_eq!(variable_blocksize(&flac), Some(false));
+
+    // The STREAMINFO block with different min and max block sizes tells the variable block size.
+    let mut patched = flac.clone();
+    patched[8..10].copy_from_slice(&16u16.to_be_bytes());
+    assert_eq!(variable_blocksize(&patched), Some(true));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_decode_to_i16() {
+    use errors::FLAC_DECODER_LIMIT_EXCEEDED;
+    use options::DecoderLimits;
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let (stream_info, decoded) = decode_to_i16(std::io::Cursor::new(&flac[..])).unwrap();
+    assert_eq!(stream_info.channels, 2);
+    assert_eq!(stream_info.total_samples, samples.len() as u64 / 2);
+    assert_eq!(decoded, samples.iter().map(|&sample| sample as i16).collect::<Vec<_>>());
+
+    // The 24-bit samples are right-shifted with rounding, the 8-bit ones are left-shifted.
+    let convert = |bits_per_sample: u32, samples: &[i32]| {
+        let mut params = options::FlacEncoderParams::new();
+        params.bits_per_sample = bits_per_sample;
+        let flac = encode_to_memory(&params, samples, |_| {});
+        decode_to_i16(std::io::Cursor::new(&flac[..])).unwrap().1
+    };
+    assert_eq!(convert(24, &[0x123480, -0x123480, 0x7FFFFF, -0x800000]), [0x1235, -0x1234, 0x7FFF, -0x8000]);
+    assert_eq!(convert(8, &[0x12, -0x12, 0x7F, -0x80]), [0x1200, -0x1200, 0x7F00, -0x8000]);
+
+    let limits = DecoderLimits {max_total_samples: Some(1000), ..Default::default()};
+    let error = decode_to_i16_with_limits(std::io::Cursor::new(&flac[..]), limits).unwrap_err();
+    assert_eq!(error.code, FLAC_DECODER_LIMIT_EXCEEDED);
+
+    // The damaged frame fails the decoding instead of giving the silence in its place.
+    let mut damaged = flac.clone();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0xFF;
+    let error = decode_to_i16(std::io::Cursor::new(&damaged[..])).unwrap_err();
+    assert_eq!(error.code, errors::FLAC_DECODER_DAMAGED_STREAM);
+    assert!(error.internal_error.is_some());
+}
+
+#[test]
+fn test_encode_interleaved() {
+    use std::io::Cursor;
+    use options::FlacEncoderParams;
+    let errors = DecoderErrors::default();
+
+    let params = FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let mut cursor = Cursor::new(Vec::new());
+    let size = encode_interleaved(&mut cursor, &params, &samples, &[("TITLE", "Sine"), ("ARTIST", "A=B")]).unwrap();
+    let flac = cursor.into_inner();
+    assert_eq!(size, flac.len() as u64);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoder.comments.get("TITLE").map(String::as_str), Some("Sine"));
+    assert_eq!(decoder.comments.get("ARTIST").map(String::as_str), Some("A=B"));
+    let (_, decoded) = decode_to_i16(Cursor::new(&flac[..])).unwrap();
+    assert_eq!(decoded, samples.iter().map(|&sample| sample as i16).collect::<Vec<_>>());
+
+    // The writer isn't asked for its position on every write, that flushes a `BufWriter` each time.
+    struct CountSeeks(Cursor<Vec<u8>>, usize);
+    impl std::io::Write for CountSeeks {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {self.0.write(buf)}
+        fn flush(&mut self) -> std::io::Result<()> {Ok(())}
+    }
+    impl std::io::Seek for CountSeeks {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {self.1 += 1; self.0.seek(pos)}
+    }
+    let mut counted = CountSeeks(Cursor::new(Vec::new()), 0);
+    assert_eq!(encode_interleaved(&mut counted, &params, &samples, &[]).unwrap(), counted.0.get_ref().len() as u64);
+    assert!(counted.1 < 16, "{} seeks", counted.1);
+
+    // The empty audio is still a valid FLAC file.
+    let mut cursor = Cursor::new(Vec::new());
+    assert!(encode_interleaved(&mut cursor, &params, &[], &[]).unwrap() > 0);
+
+    // The typed errors are returned before anything is written.
+    let mut cursor = Cursor::new(Vec::new());
+    assert_eq!(encode_interleaved(&mut cursor, &params, &samples[..3], &[]).unwrap_err().code, libflac_sys::FLAC__STREAM_ENCODER_FRAMING_ERROR);
+    assert_eq!(encode_interleaved(&mut cursor, &params, &samples, &[("A=B", "C")]).unwrap_err().code, libflac_sys::FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA);
+    assert!(cursor.into_inner().is_empty());
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_metadata_report() {
+    use metadata::{FlacPictureType, PictureSummary};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        encoder.insert_comments("ARTIST", "Someone").unwrap();
+        encoder.add_picture_of_type(&[2u8; 2000], FlacPictureType::FrontCover, "Front", "image/jpeg", 32, 32, 24, 0).unwrap();
+        encoder.add_seek_points(&[0, 22050]).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    let report = decoder.metadata_report();
+    assert_eq!(report.stream_info.unwrap().total_samples, 44100);
+    assert_eq!(report.comments, [("ARTIST".to_owned(), "Someone".to_owned()), ("TITLE".to_owned(), "Sine".to_owned())]);
+    assert_eq!(report.pictures, [PictureSummary {
+        picture_type: FlacPictureType::FrontCover,
+        mime_type: "image/jpeg".to_owned(),
+        description: "Front".to_owned(),
+        width: 32,
+        height: 32,
+        size: 2000,
+    }]);
+    assert_eq!(report.cue_sheet, None);
+    assert_eq!(report.seek_points, 2);
+
+    let text = report.to_string();
+    assert!(text.contains("Sample rate: 44100 Hz\n"));
+    assert!(text.contains("TITLE=Sine\n"));
+    assert!(text.contains("Picture: FrontCover, image/jpeg, 32x32, 2000 bytes\n"));
+    assert!(text.contains("Seek points: 2\n"));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_batch_convert() {
+    use std::{io::{self, Cursor}, sync::{Arc, atomic::AtomicBool}};
+    use batch::{ConvertJob, JobError};
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let inputs: Vec<Vec<i32>> = (0..8).map(|i| {
+        sine_wave_stereo_16().into_iter().map(|sample| sample / (i + 1)).collect()
+    }).collect();
+    let make_jobs = || -> Vec<ConvertJob<Vec<i32>, Cursor<Vec<u8>>>> {
+        inputs.iter().enumerate().map(|(i, input)| {
+            let input = input.clone();
+            ConvertJob {
+                label: format!("input {i}"),
+                reader: Box::new(move || Ok(input)),
+                writer: Box::new(|| Ok(Cursor::new(Vec::new()))),
+                params,
+            }
+        }).collect()
+    };
+    // The tags go in before the encoder is initialized.
+    let metadata_fn = |samples: &mut Vec<i32>, encoder: &mut FlacEncoder<&mut Cursor<Vec<u8>>>| -> Result<(), JobError> {
+        encoder.insert_comments("TITLE", &format!("Peak {}", samples.iter().max().unwrap()))?;
+        Ok(())
+    };
+    let job_fn = |samples: &mut Vec<i32>, encoder: &mut FlacEncoder<&mut Cursor<Vec<u8>>>| -> Result<(), JobError> {
+        encoder.write_interleaved_samples(samples)?;
+        Ok(())
+    };
+
+    let results = batch::convert(make_jobs(), 4, metadata_fn, job_fn);
+    assert_eq!(results.len(), 8);
+    for (i, (result, input)) in results.into_iter().zip(inputs.iter()).enumerate() {
+        assert_eq!(result.label, format!("input {i}"));
+        let flac = result.result.unwrap().into_inner();
+        let (_, decoded) = decode_to_i16(Cursor::new(&flac[..])).unwrap();
+        assert_eq!(decoded, input.iter().map(|&sample| sample as i16).collect::<Vec<_>>());
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        assert_eq!(decoder.get_comments()["TITLE"], format!("Peak {}", input.iter().max().unwrap()));
+    }
+
+    // The failed job doesn't stop the others.
+    let mut jobs = make_jobs();
+    jobs[3].reader = Box::new(|| Err(io::Error::other("No such input")));
+    let results = batch::convert(jobs, 4, metadata_fn, job_fn);
+    assert!(matches!(results[3].result, Err(JobError::Io(_))));
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 7);
+
+    // Neither does the panicking job, the results stay in the order of the jobs.
+    let mut jobs = make_jobs();
+    jobs[5].reader = Box::new(|| Ok(Vec::new()));
+    let results = batch::convert(jobs, 4, metadata_fn, job_fn);
+    assert_eq!(results.len(), 8);
+    assert!(matches!(&results[5].result, Err(JobError::Panicked(_))), "{:?}", results[5].result);
+    assert_eq!(results[6].label, "input 6");
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 7);
+
+    // The pending jobs are cancelled.
+    let results = batch::convert_with_cancel(make_jobs(), 4, Arc::new(AtomicBool::new(true)), metadata_fn, job_fn);
+    assert!(results.iter().all(|result| matches!(result.result, Err(JobError::Cancelled))));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_frames_digest() {
+    use errors::FLAC_DECODER_NOT_FLAC;
+    use options::{FlacEncoderParams, FlacCompression};
+
+    // The NIST test vectors, the one-block, the two-block and the long message, fed in uneven chunks.
+    let sha256 = |chunks: &mut dyn Iterator<Item = &[u8]>| {
+        let mut sha256 = frames_digest::Sha256::new();
+        for chunk in chunks {
+            sha256.update(chunk);
+        }
+        flac::to_hex(&sha256.finalize())
+    };
+    assert_eq!(sha256(&mut std::iter::empty()), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(sha256(&mut [&b"abc"[..]].into_iter()), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    let two_blocks = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+    assert_eq!(sha256(&mut [&two_blocks[..]].into_iter()), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+    assert_eq!(sha256(&mut two_blocks.chunks(7)), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+    let million_a = vec![b'a'; 1_000_000];
+    assert_eq!(sha256(&mut million_a.chunks(1000)), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+    assert_eq!(sha256(&mut million_a.chunks(4099)), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+
+    let samples = sine_wave_stereo_16();
+    let params = FlacEncoderParams::new();
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_comments("TITLE", "AAAA").unwrap();
+    });
+    let digest = frames_digest(&flac[..]).unwrap();
+
+    // Edit the comment in place.
+    let mut retagged = flac.clone();
+    let at = retagged.windows(10).position(|window| window == b"TITLE=AAAA").unwrap();
+    retagged[at + 6..at + 10].copy_from_slice(b"BBBB");
+    assert_eq!(frames_digest(&retagged[..]).unwrap(), digest);
+
+    // More metadata and the ID3v2 tag before the signature don't matter either.
+    let more_tags = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_comments("TITLE", "A much longer title").unwrap();
+        encoder.insert_comments("ARTIST", "Someone").unwrap();
+    });
+    assert_eq!(frames_digest(&more_tags[..]).unwrap(), digest);
+    let mut id3 = b"ID3\x04\x00\x00\x00\x00\x01\x00".to_vec();
+    id3.extend_from_slice(&[0u8; 128]);
+    id3.extend_from_slice(&flac);
+    assert_eq!(frames_digest(&id3[..]).unwrap(), digest);
+
+    let mut params = params;
+    params.compression = FlacCompression::Level0;
+    let level_0 = encode_to_memory(&params, &samples, |_| {});
+    assert_ne!(frames_digest(&level_0[..]).unwrap(), digest);
+
+    assert_eq!(frames_digest(&b"RIFF\0\0\0\0"[..]).unwrap_err().code, FLAC_DECODER_NOT_FLAC);
+    assert!(frames_digest(&flac[..20]).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_metadata() {
+    use metadata::{PictureData, FlacPictureType, FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType};
+    use options::FlacEncoderParams;
+
+    let mut isrc = [0i8; 13];
+    for (c, byte) in isrc.iter_mut().zip(b"USABC1234567") {
+        *c = *byte as i8;
+    }
+    // The longest catalog number
+    let mut media_catalog_number = [b'1' as i8; 129];
+    media_catalog_number[128] = 0;
+    let cue_sheet = FlacCueSheet {
+        media_catalog_number,
+        lead_in: 88200,
+        is_cd: true,
+        tracks: [(1, FlacCueTrack {
+            offset: 0,
+            track_no: 1,
+            isrc,
+            type_: FlacTrackType::Audio,
+            pre_emphasis: false,
+            indices: vec![FlacCueSheetIndex {offset: 0, number: 1}],
+        })].into_iter().collect(),
+    };
+    let json = serde_json::to_string(&cue_sheet).unwrap();
+    assert!(json.contains("\"isrc\":\"USABC1234567\""));
+    let parsed: FlacCueSheet = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.get_media_catalog_number(), cue_sheet.get_media_catalog_number());
+    assert_eq!(parsed.tracks[&1].get_isrc(), cue_sheet.tracks[&1].get_isrc());
+    assert_eq!(parsed.to_string(), cue_sheet.to_string());
+
+    // The ISRC must fit in the array with the NUL terminator.
+    let too_long = json.replace("USABC1234567", "USABC12345678");
+    assert!(serde_json::from_str::<FlacCueSheet>(&too_long).is_err());
+
+    let picture = PictureData {
+        picture: vec![1, 2, 3],
+        picture_type: FlacPictureType::FrontCover,
+        mime_type: "image/png".to_owned(),
+        description: "Front".to_owned(),
+        width: 1,
+        height: 1,
+        depth: 24,
+        colors: 0,
+    };
+    let parsed: PictureData = serde_json::from_str(&serde_json::to_string(&picture).unwrap()).unwrap();
+    assert_eq!(format!("{parsed:?}"), format!("{picture:?}"));
+    assert_eq!(parsed.picture, picture.picture);
+
+    let params = FlacEncoderParams::new();
+    let parsed: FlacEncoderParams = serde_json::from_str(&serde_json::to_string(&params).unwrap()).unwrap();
+    assert_eq!(parsed, params);
+}
+
+#[test]
+fn test_transcode_bit_depth() {
+    use std::io::Cursor;
+    use options::FlacEncoderParams;
+
+    assert_eq!(util::convert_bit_depth(0x1234, 16, 24), 0x123400);
+    assert_eq!(util::convert_bit_depth(0x123480, 24, 16), 0x1235);
+    assert_eq!(util::convert_bit_depth(0x7FFFFF, 24, 16), 0x7FFF);
+    assert_eq!(util::convert_bit_depth(-0x800000, 24, 8), -0x80);
+
+    let mut params_24 = FlacEncoderParams::new();
+    params_24.bits_per_sample = 24;
+    let samples_24: Vec<i32> = sine_wave_stereo_16().into_iter().map(|sample| sample * 256 + 0x7F).collect();
+    let flac_24 = encode_to_memory(&params_24, &samples_24, |_| {});
+
+    // The mismatch is rejected before anything is written.
+    let params_16 = FlacEncoderParams::new();
+    let mut output = Cursor::new(Vec::new());
+    match transcode(Cursor::new(&flac_24[..]), &mut output, &params_16, BitDepthMismatch::Reject) {
+        Err(TranscodeError::BitDepthMismatch{source: 24, target: 16}) => (),
+        other => panic!("{other:?}"),
+    }
+    assert!(output.get_ref().is_empty());
+
+    // The explicit conversion rounds the samples.
+    let mut output = Cursor::new(Vec::new());
+    assert_eq!(transcode(Cursor::new(&flac_24[..]), &mut output, &params_16, BitDepthMismatch::Convert).unwrap(), 44100);
+    let (stream_info, decoded) = decode_to_i16(Cursor::new(&output.get_ref()[..])).unwrap();
+    assert_eq!(stream_info.bits_per_sample, 16);
+    assert_eq!(decoded, sine_wave_stereo_16().into_iter().map(|sample| sample as i16).collect::<Vec<_>>());
+
+    // The same bit depth passes through losslessly.
+    let mut output = Cursor::new(Vec::new());
+    transcode(Cursor::new(&flac_24[..]), &mut output, &params_24, BitDepthMismatch::Reject).unwrap();
+    assert!(audio_equal(Cursor::new(&flac_24[..]), Cursor::new(&output.get_ref()[..])).unwrap());
+
+    // The damaged frame fails the transcoding.
+    let mut damaged = flac_24.clone();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0xFF;
+    match transcode(Cursor::new(&damaged[..]), &mut Cursor::new(Vec::new()), &params_24, BitDepthMismatch::Reject) {
+        Err(TranscodeError::Decoder(error)) => {
+            assert_eq!(error.code, errors::FLAC_DECODER_DAMAGED_STREAM);
+            assert!(error.internal_error.is_some());
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn test_track_infos_pregap() {
+    use std::collections::BTreeMap;
+    use metadata::{FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType};
+
+    // 3 tracks of 10 seconds, the tracks 2 and 3 have the 2-second pregaps.
+    const SECOND: u64 = 44100;
+    let track = |track_no: u8, offset: u64, indices: &[(u8, u64)]| FlacCueTrack {
+        offset,
+        track_no,
+        isrc: [0; 13],
+        type_: FlacTrackType::Audio,
+        pre_emphasis: false,
+        indices: indices.iter().map(|&(number, offset)| FlacCueSheetIndex {offset, number}).collect(),
+    };
+    let cue_sheet = FlacCueSheet {
+        media_catalog_number: [0; 129],
+        lead_in: 88200,
+        is_cd: true,
+        tracks: BTreeMap::from([
+            (1, track(1, 0, &[(1, 0)])),
+            (2, track(2, SECOND * 8, &[(0, 0), (1, SECOND * 2)])),
+            (3, track(3, SECOND * 18, &[(0, 0), (1, SECOND * 2)])),
+            (170, track(170, SECOND * 30, &[])),
+        ]),
+    };
+    let ranges = |pregap: PregapAttribution| -> Vec<(u8, u64, u64, u64)> {
+        cue_sheet.track_infos(pregap, 0).iter().map(|track| (track.track_no, track.start, track.end, track.pregap)).collect()
+    };
+    assert_eq!(ranges(PregapAttribution::PreviousTrack), [
+        (1, 0, SECOND * 10, 0),
+        (2, SECOND * 10, SECOND * 20, SECOND * 2),
+        (3, SECOND * 20, SECOND * 30, SECOND * 2),
+    ]);
+    assert_eq!(ranges(PregapAttribution::CurrentTrack), [
+        (1, 0, SECOND * 8, 0),
+        (2, SECOND * 8, SECOND * 18, SECOND * 2),
+        (3, SECOND * 18, SECOND * 30, SECOND * 2),
+    ]);
+    assert_eq!(ranges(PregapAttribution::Discard), [
+        (1, 0, SECOND * 8, 0),
+        (2, SECOND * 10, SECOND * 18, SECOND * 2),
+        (3, SECOND * 20, SECOND * 30, SECOND * 2),
+    ]);
+
+    // The decoded tracks follow the same boundaries.
+    let samples: Vec<i32> = (0..SECOND * 30).flat_map(|i| [(i % 1000) as i32, 0]).collect();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_cue_sheet(&cue_sheet).unwrap();
+    });
+    for pregap in [PregapAttribution::PreviousTrack, PregapAttribution::CurrentTrack, PregapAttribution::Discard] {
+        let lengths: Vec<u64> = track_crcs_with_pregap(std::io::Cursor::new(&flac[..]), pregap).unwrap().iter().map(|track| track.samples).collect();
+        assert_eq!(lengths, cue_sheet.track_infos(pregap, 0).iter().map(TrackInfo::len).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_encoded_frames() {
+    use std::sync::{Arc, Mutex};
+
+    let samples = sine_wave_stereo_16();
+    let frames = Arc::new(Mutex::new(Vec::<(Vec<u8>, u32, u32)>::new()));
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        let frames = frames.clone();
+        encoder.set_on_frame(Some(Box::new(move |frame| {
+            frames.lock().unwrap().push((frame.data.to_vec(), frame.samples, frame.frame_number));
+            Ok(())
+        })));
+    });
+    let frames = frames.lock().unwrap();
+
+    // The audio frames are the FLAC file after the metadata, and each one starts with the frame sync code.
+    let audio: Vec<&(Vec<u8>, u32, u32)> = frames.iter().filter(|(_, samples, _)| *samples > 0).collect();
+    let metadata_size = frames.iter().take_while(|(_, samples, _)| *samples == 0).map(|(data, _, _)| data.len()).sum::<usize>();
+    assert_eq!(frames[0].0, b"fLaC");
+    assert_eq!(audio.iter().map(|(data, _, _)| data.len()).sum::<usize>(), flac.len() - metadata_size);
+    assert_eq!(audio.iter().map(|(_, samples, _)| *samples as usize).sum::<usize>(), samples.len() / 2);
+    for (i, (data, _, frame_number)) in audio.iter().enumerate() {
+        assert_eq!(*frame_number as usize, i);
+        assert_eq!(data[..2], [0xFF, 0xF8]);
+    }
+    let concatenated: Vec<u8> = audio.iter().flat_map(|(data, _, _)| data.iter().copied()).collect();
+    assert_eq!(concatenated, flac[metadata_size..]);
+}
+
+#[test]
+fn test_decode_steps() {
+    use metadata::FlacMetadataType;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    let mut steps = Vec::new();
+    loop {
+        let step = decoder.decode().unwrap();
+        steps.push(step);
+        if step == DecodeStep::EndOfStream {
+            break;
+        }
+    }
+
+    // The metadata first, then the audio, then the end of the stream.
+    assert_eq!(steps[..2], [DecodeStep::Metadata(FlacMetadataType::StreamInfo), DecodeStep::Metadata(FlacMetadataType::VorbisComment)]);
+    let audio = &steps[2..steps.len() - 1];
+    assert!(audio.iter().all(|step| matches!(step, DecodeStep::AudioFrame{..})));
+    assert_eq!(audio.iter().map(|step| match step {DecodeStep::AudioFrame{samples} => *samples as usize, _ => 0}).sum::<usize>(), samples.len() / 2);
+    assert_eq!(decoder.decode().unwrap(), DecodeStep::EndOfStream);
+
+    #[allow(deprecated)]
+    let processed = decoder.decode_bool().unwrap();
+    assert!(processed);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_decode_outcomes() {
+    use std::io::Cursor;
+    use closure_objects::FlacInternalDecoderError;
+    use options::DecoderLimits;
+    use errors::FLAC_DECODER_LIMIT_EXCEEDED;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let total = samples.len() as u64 / 2;
+
+    // Decoded to the end.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    let outcome = decoder.decode_all();
+    assert!(outcome.is_completed());
+    assert_eq!(outcome.samples(), total);
+
+    // Your `on_write()` closure stops after the first frame.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Err(std::io::Error::other("Stop"))));
+    let outcome = decoder.decode_all();
+    assert!(matches!(outcome, DecodeOutcome::StoppedByCaller{samples} if samples > 0 && samples < total));
+    assert_eq!(outcome.into_result().unwrap_err().code, libflac_sys::FLAC__STREAM_DECODER_ABORTED);
+
+    // The file is cut in the middle, the partial frame is reported to `on_error()` and the decoder reaches the end.
+    let mut decoder = FlacDecoder::with_default_io(
+        Cursor::new(flac[..flac.len() / 2].to_vec()),
+        Box::new(|_, _| Ok(())),
+        Box::new(|_error: FlacInternalDecoderError| {}),
+        false,
+        false,
+        options::FlacAudioForm::FrameArray,
+    ).unwrap();
+    let outcome = decoder.decode_all();
+    assert!(matches!(outcome, DecodeOutcome::Truncated{samples} if samples > 0 && samples < total));
+    assert_eq!(outcome.into_result().unwrap_err().code, errors::FLAC_DECODER_TRUNCATED);
+
+    // The limits are exceeded.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.set_limits(DecoderLimits {max_channels: Some(1), ..Default::default()});
+    let outcome = decoder.decode_all();
+    assert_eq!(outcome.samples(), 0);
+    assert_eq!(outcome.into_result().unwrap_err().code, FLAC_DECODER_LIMIT_EXCEEDED);
+
+    // The deprecated wrapper.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Err(std::io::Error::other("Stop"))));
+    #[allow(deprecated)]
+    let ret = decoder.decode_all_bool().unwrap();
+    assert!(!ret);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_lyrics() {
+    use metadata::parse_lyrics3;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let lyrics = "Twinkle, twinkle, little star,\nHow I wonder what you are!";
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.set_lyrics(lyrics).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.get_comments()["LYRICS"], lyrics);
+    assert_eq!(decoder.lyrics().as_deref(), Some(lyrics));
+
+    // The empty `LYRICS` comment falls back to the `UNSYNCEDLYRICS` comment.
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_comments("LYRICS", " ").unwrap();
+        encoder.insert_comments("unsyncedlyrics", "La la la").unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.lyrics().as_deref(), Some("La la la"));
+
+    // The Lyrics3 tag in an APPLICATION block.
+    let lyrics3 = b"LYRICSBEGININD0000211LYR00008La la laAUT00002Me000030LYRICS200";
+    assert_eq!(parse_lyrics3(lyrics3).as_deref(), Some("La la la"));
+    assert_eq!(parse_lyrics3(b"LYRICSBEGINLa\xe9LYRICSEND").as_deref(), Some("La\u{e9}"));
+    assert_eq!(parse_lyrics3(b"LYRICSBEGINLYR99999"), None);
+    let mut flac = encode_to_memory(&params, &samples, |_| {});
+    let after_stream_info = 4 + 4 + 34;
+    let mut block = vec![2u8];
+    block.extend_from_slice(&((4 + lyrics3.len()) as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(b"LYR3");
+    block.extend_from_slice(lyrics3);
+    flac.splice(after_stream_info..after_stream_info, block);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.lyrics().as_deref(), Some("La la la"));
+
+    // No lyrics at all.
+    let flac = encode_to_memory(&params, &samples, |_| {});
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.lyrics(), None);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_dyn_encoder() {
+    use std::{fs::{self, File}, io::Cursor};
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let encode = |writer: Box<dyn WriteSeekAny + '_>| {
+        let mut encoder: DynFlacEncoder = FlacEncoder::new_dyn(writer, &params).unwrap();
+        assert!(format!("{encoder:?}").contains("dyn WriteSeekAny"));
+        encoder.initialize().unwrap();
+        encoder.write_interleaved_samples(&samples).unwrap();
+        encoder.finish().unwrap();
+    };
+    let expected = encode_to_memory(&params, &samples, |_| {});
+
+    // The same code path for the memory and the file.
+    let mut cursor = Cursor::new(Vec::new());
+    encode(Box::new(&mut cursor));
+    assert_eq!(cursor.into_inner(), expected);
+
+    let temp_path = std::env::temp_dir().join(format!("flac-rs-test-dyn-encoder-{}.flac", std::process::id()));
+    let mut file = File::create(&temp_path).unwrap();
+    encode(Box::new(&mut file));
+    drop(file);
+    let flac = fs::read(&temp_path).unwrap();
+    fs::remove_file(&temp_path).unwrap();
+    assert_eq!(flac, expected);
+}
+
+#[test]
+fn test_malformed_metadata() {
+    use std::io::Cursor;
+    use libflac_sys::*;
+    use closure_objects::FlacInternalDecoderError;
+    use metadata::{MetadataBlock, FlacCueSheet, FlacCueTrack, FlacCueSheetIndex, FlacTrackType};
+
+    // The null arrays with the non-zero counts are read as empty.
+    let null_block = |type_: u32, set: &dyn Fn(&mut FLAC__StreamMetadata)| {
+        let mut metadata: FLAC__StreamMetadata = unsafe {std::mem::zeroed()};
+        metadata.type_ = type_;
+        metadata.length = 100;
+        set(&mut metadata);
+        unsafe {MetadataBlock::from_raw(&metadata)}
+    };
+    let block = null_block(FLAC__METADATA_TYPE_VORBIS_COMMENT, &|metadata| {
+        metadata.data.vorbis_comment.vendor_string.length = 10;
+        metadata.data.vorbis_comment.num_comments = 3;
+    });
+    assert!(matches!(block, MetadataBlock::VorbisComment{vendor_string, comments} if vendor_string.is_empty() && comments.is_empty()));
+    let block = null_block(FLAC__METADATA_TYPE_CUESHEET, &|metadata| metadata.data.cue_sheet.num_tracks = 2);
+    assert!(matches!(block, MetadataBlock::CueSheet(cue_sheet) if cue_sheet.tracks.is_empty()));
+    let block = null_block(FLAC__METADATA_TYPE_PICTURE, &|metadata| metadata.data.picture.data_length = 100);
+    assert!(matches!(block, MetadataBlock::Picture(picture) if picture.picture.is_empty() && picture.mime_type.is_empty() && picture.description.is_empty()));
+    let block = null_block(FLAC__METADATA_TYPE_SEEKTABLE, &|metadata| metadata.data.seek_table.num_points = 5);
+    assert!(matches!(block, MetadataBlock::SeekTable(points) if points.is_empty()));
+    let block = null_block(FLAC__METADATA_TYPE_APPLICATION, &|_| {});
+    assert!(matches!(block, MetadataBlock::Application{data, ..} if data.is_empty()));
+
+    // The corpus: every byte of the metadata of a file with all kinds of the blocks is corrupted in turn, the decoder must not crash.
+    let samples = &sine_wave_stereo_16()[..8192];
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        encoder.add_picture(&[0x55; 16], "Cover", "image/png", 1, 1, 24, 0).unwrap();
+        encoder.add_seek_points(&[0, 1024]).unwrap();
+        encoder.insert_cue_sheet(&FlacCueSheet {
+            media_catalog_number: [0; 129],
+            lead_in: 0,
+            is_cd: false,
+            tracks: [1, 255].into_iter().map(|track_no| (track_no, FlacCueTrack {
+                offset: if track_no == 1 {0} else {4096},
+                track_no,
+                isrc: [0; 13],
+                type_: FlacTrackType::Audio,
+                pre_emphasis: false,
+                indices: if track_no == 1 {vec![FlacCueSheetIndex {offset: 0, number: 1}]} else {Vec::new()},
+            })).collect(),
+        }).unwrap();
+    });
+    let mut metadata_end = 4;
+    loop {
+        let header = &flac[metadata_end..metadata_end + 4];
+        metadata_end += 4 + u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    for position in 4..metadata_end {
+        for corrupt in [|byte: u8| !byte, |_| 0x00, |_| 0xFF] {
+            let mut malformed = flac.clone();
+            malformed[position] = corrupt(malformed[position]);
+            let mut decoder = FlacDecoder::with_default_io(
+                Cursor::new(malformed),
+                Box::new(|_, _| Ok(())),
+                Box::new(|_error: FlacInternalDecoderError| {}),
+                false,
+                false,
+                options::FlacAudioForm::FrameArray,
+            ).unwrap();
+            let _ = decoder.decode_metadata();
+            let _ = decoder.metadata_report();
+        }
+    }
+}
+
+#[test]
+fn test_dyn_decoder() {
+    use std::{cell::RefCell, fs::{self, File}, io::Cursor};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let decode = |reader: Box<dyn ReadSeekAny + '_>| {
+        let decoded = RefCell::new(Vec::new());
+        let mut decoder: DynFlacDecoder = FlacDecoder::new_dyn(
+            reader,
+            Box::new(|frames, _| {
+                decoded.borrow_mut().extend(frames.iter().flatten().copied());
+                Ok(())
+            }),
+            record_errors(&errors),
+            true,
+            false,
+            options::FlacAudioForm::FrameArray,
+        ).unwrap();
+        assert!(format!("{decoder:?}").contains("dyn ReadSeekAny"));
+        assert!(decoder.decode_all().is_completed());
+        decoder.finish().unwrap();
+        drop(decoder);
+        decoded.into_inner()
+    };
+
+    // The same code path for the memory and the file.
+    let from_memory = decode(Box::new(Cursor::new(&flac[..])));
+    let temp_path = std::env::temp_dir().join(format!("flac-rs-test-dyn-decoder-{}.flac", std::process::id()));
+    fs::write(&temp_path, &flac).unwrap();
+    let from_file = decode(Box::new(File::open(&temp_path).unwrap()));
+    fs::remove_file(&temp_path).unwrap();
+    assert_eq!(from_memory, samples);
+    assert_eq!(from_file, from_memory);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_unknown_total_samples() {
+    use std::time::Duration;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let mut flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+
+    // The known length.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    assert_eq!(decoder.duration(), None);
+    assert_eq!(decoder.progress(), None);
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.duration(), Some(Duration::from_secs(1)));
+    assert_eq!(decoder.progress(), Some(0.0));
+    assert!(decoder.decode_all().is_completed());
+    assert_eq!(decoder.progress(), Some(1.0));
+    decoder.finish().unwrap();
+    drop(decoder);
+
+    // The streaming-origin FLAC file without the total samples in the STREAMINFO block still decodes completely.
+    let total_samples_offset = 4 + 4 + 13;
+    flac[total_samples_offset] &= 0xF0;
+    flac[total_samples_offset + 1..total_samples_offset + 5].fill(0);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    let stream_info = decoder.get_stream_info().unwrap();
+    assert_eq!(stream_info.known_total_samples(), None);
+    assert_eq!(stream_info.duration(), None);
+    assert_eq!(decoder.duration(), None);
+    assert_eq!(decoder.progress(), None);
+    let outcome = decoder.decode_all();
+    assert!(outcome.is_completed());
+    assert_eq!(outcome.samples(), samples.len() as u64 / 2);
+    assert_eq!(decoder.progress(), None);
+    assert!(decoder.metadata_report().to_string().contains("Total samples: unknown"));
+    decoder.finish().unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_reset_with_writer() {
+    use std::io::{self, Cursor, Write, Seek, SeekFrom};
+    let errors = DecoderErrors::default();
+
+    let stereo = options::FlacEncoderParams::new();
+    let mono = options::FlacEncoderParams {channels: 1, ..stereo};
+    let samples = sine_wave_stereo_16();
+    let left: Vec<i32> = samples.iter().step_by(2).copied().collect();
+    let (mut first, mut second, mut third) = (Cursor::new(Vec::new()), Cursor::new(Vec::new()), Cursor::new(Vec::new()));
+    {
+        let mut encoder = FlacEncoder::new(
+            &mut first,
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {
+                writer.write_all(data)
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {
+                writer.seek(SeekFrom::Start(position))?;
+                Ok(())
+            }),
+            Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {
+                writer.stream_position()
+            }),
+            &stereo
+        ).unwrap();
+        encoder.set_keep_comments_on_reset(true);
+        encoder.insert_comments("ALBUM", "Sines").unwrap();
+        encoder.insert_comments("TITLE", "One").unwrap();
+        encoder.initialize().unwrap();
+        encoder.write_interleaved_samples(&samples).unwrap();
+
+        // The first file is finished by the reset, the album-wide comment is kept, the title is replaced.
+        encoder.reset_with_writer(&mut second, Some(&mono)).unwrap();
+        encoder.insert_comments("TITLE", "Two").unwrap();
+        encoder.initialize().unwrap();
+        encoder.write_interleaved_samples(&left).unwrap();
+        encoder.finish().unwrap();
+
+        // The comments are dropped without keeping them, the parameters stay the same.
+        encoder.set_keep_comments_on_reset(false);
+        encoder.reset_with_writer(&mut third, None).unwrap();
+        encoder.initialize().unwrap();
+        encoder.write_interleaved_samples(&left[..1000]).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    let decode = |flac: &[u8]| {
+        let decoded = std::cell::RefCell::new(Vec::new());
+        let mut decoder = decoder_from_memory(flac, &errors, Box::new(|frames, _| {
+            decoded.borrow_mut().extend(frames.iter().flatten().copied());
+            Ok(())
+        }));
+        assert!(decoder.decode_all().is_completed());
+        decoder.finish().unwrap();
+        let comments = decoder.get_comments().clone();
+        let channels = decoder.get_stream_info().unwrap().channels;
+        drop(decoder);
+        (decoded.into_inner(), comments, channels)
+    };
+    let (decoded, comments, channels) = decode(first.get_ref());
+    assert_eq!((decoded, channels), (samples, 2));
+    assert_eq!((comments["ALBUM"].as_str(), comments["TITLE"].as_str()), ("Sines", "One"));
+    let (decoded, comments, channels) = decode(second.get_ref());
+    assert_eq!((&decoded, channels), (&left, 1));
+    assert_eq!((comments["ALBUM"].as_str(), comments["TITLE"].as_str()), ("Sines", "Two"));
+    let (decoded, comments, channels) = decode(third.get_ref());
+    assert_eq!((&decoded[..], channels), (&left[..1000], 1));
+    assert!(comments.is_empty());
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_auto_padding() {
+    use options::DEFAULT_PADDING_LENGTH;
+    use metadata::MetadataBlock;
+    let errors = DecoderErrors::default();
+
+    // The type and the length of each metadata block in the file.
+    fn metadata_blocks(flac: &[u8]) -> Vec<(u8, usize)> {
+        let mut blocks = Vec::new();
+        let mut offset = 4;
+        loop {
+            let header = &flac[offset..offset + 4];
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+            blocks.push((header[0] & 0x7F, length));
+            offset += 4 + length;
+            if header[0] & 0x80 != 0 {
+                return blocks;
+            }
+        }
+    }
+
+    let samples = sine_wave_stereo_16();
+    let params = options::FlacEncoderParams {auto_padding: true, ..Default::default()};
+    assert!(!options::FlacEncoderParams::new().auto_padding);
+    let mut estimated = 0;
+    let flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        estimated = encoder.estimated_metadata_size();
+    });
+
+    // The padding is the last block, the estimated metadata size counts it.
+    let blocks = metadata_blocks(&flac);
+    assert_eq!(blocks.last(), Some(&(1, DEFAULT_PADDING_LENGTH as usize)));
+    assert_eq!(estimated, 4 + blocks.iter().map(|(_, length)| 4 + length).sum::<usize>());
+
+    // The decoder reads it as a PADDING block.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    let mut padding = None;
+    while let DecodeEvent::Metadata(block) = decoder.next_event().unwrap() {
+        if let MetadataBlock::Padding(length) = block {
+            padding = Some(length);
+        }
+    }
+    assert_eq!(padding, Some(DEFAULT_PADDING_LENGTH));
+
+    // Without it, there's no padding.
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    assert!(metadata_blocks(&flac).iter().all(|(block_type, _)| *block_type != 1));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_reset_with_reader() {
+    use std::{cell::RefCell, io::Cursor};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let first = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "One").unwrap();
+        encoder.add_picture(&[0u8; 100], "Cover", "image/png", 1, 1, 24, 0).unwrap();
+    });
+    let left: Vec<i32> = samples.iter().step_by(2).take(5000).copied().collect();
+    let second = encode_to_memory(&options::FlacEncoderParams {channels: 1, ..options::FlacEncoderParams::new()}, &left, |_| {});
+
+    let decoded = RefCell::new(Vec::new());
+    let mut decoder = FlacDecoder::with_default_io(
+        Cursor::new(&first[..]),
+        Box::new(|frames: &[Vec<i32>], _: &closure_objects::SamplesInfo| {
+            decoded.borrow_mut().extend(frames.iter().flatten().copied());
+            Ok(())
+        }),
+        record_errors(&errors),
+        true,
+        false,
+        options::FlacAudioForm::FrameArray,
+    ).unwrap();
+    assert_eq!(decoder.decode_all().into_result().unwrap(), samples.len() as u64 / 2);
+    assert_eq!(decoder.comments.get("TITLE").map(String::as_str), Some("One"));
+    assert_eq!(decoder.pictures.len(), 1);
+    assert_eq!(decoded.take(), samples);
+
+    // The shorter file after the longer one, the length of the new reader is used.
+    decoder.reset_with_reader(Cursor::new(&second[..])).unwrap();
+    assert!(decoder.comments.is_empty());
+    assert!(decoder.pictures.is_empty());
+    assert_eq!(decoder.get_stream_info(), None);
+    assert_eq!(decoder.decode_all().into_result().unwrap(), left.len() as u64);
+    assert!(decoder.comments.is_empty());
+    assert!(decoder.pictures.is_empty());
+    assert_eq!(decoder.get_stream_info().unwrap().channels, 1);
+    assert_eq!(decoder.progress(), Some(1.0));
+    decoder.finish().unwrap();
+    drop(decoder);
+    assert_eq!(decoded.into_inner(), left);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_foreign_metadata_chunks() {
+    use metadata::{store_foreign_metadata, RIFF_APPLICATION_ID};
+    let errors = DecoderErrors::default();
+
+    // The odd-sized `bext` chunk with its padding byte before the audio, and a `LIST` chunk after the audio.
+    let samples = sine_wave_stereo_16();
+    let bext = iff_chunk(b"bext", &(0..603).map(|i| i as u8).collect::<Vec<u8>>(), false);
+    let list = iff_chunk(b"LIST", b"INFOISFT\x06\x00\x00\x00Tests\x00", false);
+    let wav = wav_with_chunks(&samples, &[&bext], &[&list]);
+    let data: Vec<u8> = samples.iter().flat_map(|&sample| (sample as i16).to_le_bytes()).collect();
+
+    let blocks = store_foreign_metadata(&wav[..]).unwrap();
+    assert_eq!(blocks.len(), 5);
+    assert!(blocks.iter().all(|block| block.id == RIFF_APPLICATION_ID));
+    assert_eq!(blocks[2].data, bext);
+    assert_eq!(blocks[4].data, list);
+    assert_eq!(store_foreign_metadata(&wav[..wav.len() - 4]).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(store_foreign_metadata(&data[..100]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    // The blocks survive the FLAC file in order.
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        for block in blocks.iter() {
+            encoder.add_application(block.id, &block.data).unwrap();
+        }
+        assert_eq!(encoder.describe_metadata_block(metadata::MetadataBlockId{kind: metadata::FlacMetadataType::Application, index: 2}).unwrap(), "riff, 612 bytes");
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    decoder.finish().unwrap();
+    assert_eq!(decoder.get_applications(), &blocks);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_last_frame_position() {
+    let errors = DecoderErrors::default();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.last_frame_position(), None);
+
+    // Every frame header starts with the sync code, the frames are back to back.
+    let mut positions = Vec::new();
+    while decoder.decode().unwrap() != DecodeStep::EndOfStream {
+        if let Some(position) = decoder.last_frame_position() {
+            positions.push(position);
+        }
+    }
+    let blocksize = decoder.max_blocksize().unwrap() as u64;
+    assert_eq!(positions.len() as u64, (samples.len() as u64 / 2).div_ceil(blocksize));
+    let mut next_sample = 0;
+    for (sample, byte_offset) in positions.iter().copied() {
+        assert_eq!(sample, next_sample);
+        assert_eq!(&flac[byte_offset as usize..byte_offset as usize + 2], &[0xFF, 0xF8]);
+        next_sample += blocksize;
+    }
+
+    // The frame after a seek isn't known, the following ones are known again and agree with the first pass.
+    decoder.seek(positions[3].0 + 10).unwrap();
+    assert_eq!(decoder.last_frame_position(), None);
+    decoder.decode().unwrap();
+    assert_eq!(decoder.last_frame_position(), Some(positions[4]));
+    decoder.finish().unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_foreign_metadata() {
+    use metadata::{store_foreign_metadata, restore_foreign_metadata, FlacApplication, RIFF_APPLICATION_ID, AIFF_APPLICATION_ID};
+    let errors = DecoderErrors::default();
+
+    // The WAV file round trip through the APPLICATION blocks of the FLAC file.
+    let samples = sine_wave_stereo_16();
+    let bext = iff_chunk(b"bext", &[7u8; 345], false);
+    let list = iff_chunk(b"LIST", b"INFOICMT\x03\x00\x00\x00Hi\x00\x00", false);
+    let wav = wav_with_chunks(&samples, &[&bext], &[&list]);
+    let blocks = store_foreign_metadata(&wav[..]).unwrap();
+    assert_eq!(blocks.len(), 5);
+    assert!(blocks.iter().all(|block| block.id == RIFF_APPLICATION_ID));
+    assert_eq!(&blocks[0].data[..], &wav[..12]);
+    assert_eq!(&blocks[1].data[..4], b"fmt ");
+    assert_eq!(blocks[2].data, bext);
+    assert_eq!(&blocks[3].data[..], &[&b"data"[..], &(samples.len() as u32 * 2).to_le_bytes()].concat()[..]);
+    assert_eq!(blocks[4].data, list);
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.add_application(*b"test", b"other").unwrap();
+        for block in blocks.iter() {
+            encoder.add_application(block.id, &block.data).unwrap();
+        }
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_all().into_result().unwrap();
+    decoder.finish().unwrap();
+    let applications = decoder.get_applications().clone();
+    drop(decoder);
+    let audio: Vec<u8> = samples.iter().flat_map(|&sample| (sample as i16).to_le_bytes()).collect();
+    let mut restored = Vec::new();
+    restore_foreign_metadata(&applications, &audio, &mut restored).unwrap();
+    assert_eq!(restored, wav);
+
+    // The AIFF file with the odd-sized audio, the padding byte is restored.
+    let comm = iff_chunk(b"COMM", &[0, 1, 0, 0, 0, 3, 0, 8, 0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0], true);
+    let ssnd = [iff_chunk(b"SSND", &[0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3], true)].concat();
+    let body = [&b"AIFF"[..], &comm, &ssnd, &iff_chunk(b"NAME", b"Tone", true)].concat();
+    let aiff = [&b"FORM"[..], &(body.len() as u32).to_be_bytes(), &body].concat();
+    let blocks = store_foreign_metadata(&aiff[..]).unwrap();
+    assert!(blocks.iter().all(|block| block.id == AIFF_APPLICATION_ID));
+    assert_eq!(blocks[2].data.len(), 16);
+    let mut restored = Vec::new();
+    restore_foreign_metadata(&blocks, &[1, 2, 3], &mut restored).unwrap();
+    assert_eq!(restored, aiff);
+
+    // The malformed blocks are rejected before anything is written.
+    let mut restored = Vec::new();
+    let invalid = |result: std::io::Result<()>| result.unwrap_err().kind() == std::io::ErrorKind::InvalidData;
+    assert!(invalid(restore_foreign_metadata(&blocks, &[1, 2], &mut restored)));
+    let mut cut = blocks.clone();
+    cut[3].data.pop();
+    assert!(invalid(restore_foreign_metadata(&cut, &[1, 2, 3], &mut restored)));
+    assert!(invalid(restore_foreign_metadata(&blocks[1..], &[1, 2, 3], &mut restored)));
+    assert!(invalid(restore_foreign_metadata(&[FlacApplication{id: *b"test", data: vec![]}], &[], &mut restored)));
+    assert!(restored.is_empty());
+    assert_eq!(store_foreign_metadata(&aiff[..30]).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+
+    // The odd-sized chunk at the end of the file often lacks its padding byte, the file is restored as it was.
+    let mut unpadded = wav_with_chunks(&samples, &[], &[&iff_chunk(b"id3 ", &[9u8; 11], false)]);
+    unpadded.pop();
+    let riff_size = unpadded.len() as u32 - 8;
+    unpadded[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    let blocks = store_foreign_metadata(&unpadded[..]).unwrap();
+    assert_eq!(blocks.last().unwrap().data.len(), 8 + 11);
+    let mut restored = Vec::new();
+    restore_foreign_metadata(&blocks, &audio, &mut restored).unwrap();
+    assert_eq!(restored, unpadded);
+
+    // Only the last chunk may lack it.
+    let mut misplaced = blocks.clone();
+    misplaced.insert(2, blocks.last().unwrap().clone());
+    let mut restored = Vec::new();
+    assert!(invalid(restore_foreign_metadata(&misplaced, &audio, &mut restored)));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_flac_to_wav() {
+    use std::io::Cursor;
+    use metadata::store_foreign_metadata;
+
+    let samples = sine_wave_stereo_16();
+    let bext = iff_chunk(b"bext", &[7u8; 345], false);
+    let list = iff_chunk(b"LIST", b"INFOICMT\x03\x00\x00\x00Hi\x00\x00", false);
+    let wav = wav_with_chunks(&samples, &[&bext], &[&list]);
+    let blocks = store_foreign_metadata(&wav[..]).unwrap();
+    let encode = |blocks: &[metadata::FlacApplication]| encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        for block in blocks.iter() {
+            encoder.add_application(block.id, &block.data).unwrap();
+        }
+    });
+    let to_wav = |flac: &[u8], foreign_metadata| {
+        let mut wav = Cursor::new(Vec::new());
+        flac_to_wav(Cursor::new(flac), &mut wav, foreign_metadata).map(|samples| (samples, wav.into_inner()))
+    };
+
+    // WAV to FLAC to WAV is byte-identical.
+    let flac = encode(&blocks);
+    assert_eq!(to_wav(&flac, ForeignMetadata::Strict).unwrap(), (samples.len() as u64 / 2, wav.clone()));
+    assert_eq!(to_wav(&flac, ForeignMetadata::Restore).unwrap().1, wav);
+
+    // The odd-sized chunk at the end of the file without its padding byte is restored too.
+    let mut unpadded = wav_with_chunks(&samples, &[], &[&iff_chunk(b"LIST", b"INFOICMT\x03\x00\x00\x00Hi!", false)]);
+    unpadded.pop();
+    let riff_size = unpadded.len() as u32 - 8;
+    unpadded[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    let unpadded_flac = encode(&store_foreign_metadata(&unpadded[..]).unwrap());
+    assert_eq!(to_wav(&unpadded_flac, ForeignMetadata::Strict).unwrap().1, unpadded);
+    assert_eq!(to_wav(&unpadded_flac, ForeignMetadata::Restore).unwrap().1, unpadded);
+
+    // The plain WAV file without the foreign chunks.
+    let plain = wav_with_chunks(&samples, &[], &[]);
+    assert_eq!(to_wav(&flac, ForeignMetadata::Ignore).unwrap().1, plain);
+    assert_eq!(to_wav(&encode(&[]), ForeignMetadata::Strict).unwrap().1, plain);
+
+    // The `data` chunk size that doesn't match the audio.
+    let mut malformed = blocks.clone();
+    malformed[3].data[4] ^= 0x10;
+    let flac = encode(&malformed);
+    assert!(matches!(to_wav(&flac, ForeignMetadata::Strict), Err(FlacToWavError::MalformedForeignMetadata(_))));
+    assert_eq!(to_wav(&flac, ForeignMetadata::Restore).unwrap().1, plain);
+
+    // The 24-bit mono file uses `WAVE_FORMAT_EXTENSIBLE`.
+    let mono_24: Vec<i32> = samples.iter().step_by(2).map(|&sample| sample * 256 + 0x55).collect();
+    let flac = encode_to_memory(&options::FlacEncoderParams {channels: 1, bits_per_sample: 24, ..options::FlacEncoderParams::new()}, &mono_24, |_| {});
+    let (_, wav) = to_wav(&flac, ForeignMetadata::Restore).unwrap();
+    assert_eq!(&wav[20..22], &0xFFFEu16.to_le_bytes());
+    assert_eq!(wav.len(), 12 + 8 + 40 + 8 + mono_24.len() * 3);
+    assert_eq!(&wav[68..71], &mono_24[0].to_le_bytes()[..3]);
+
+    // The total samples is unknown, the sizes are patched after the audio, and the `riff` blocks can't be checked.
+    let flac = encode(&blocks);
+    let mut unknown_length = flac.clone();
+    unknown_length[21] &= 0xF0;
+    unknown_length[22..26].fill(0);
+    assert_eq!(to_wav(&unknown_length, ForeignMetadata::Restore).unwrap().1, plain);
+    assert!(matches!(to_wav(&unknown_length, ForeignMetadata::Strict), Err(FlacToWavError::MalformedForeignMetadata(_))));
+
+    // The audio too large for the 32-bit sizes is refused before writing anything.
+    let mut too_large = encode(&[]);
+    too_large[21] |= 0x0F;
+    let mut wav = Cursor::new(Vec::new());
+    assert!(matches!(flac_to_wav(Cursor::new(&too_large[..]), &mut wav, ForeignMetadata::Restore), Err(FlacToWavError::TooLarge(size)) if size > u32::MAX as u64));
+    assert!(wav.get_ref().is_empty());
+
+    // The damaged frame is reported.
+    let mut damaged = flac.clone();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0xFF;
+    assert!(matches!(to_wav(&damaged, ForeignMetadata::Restore), Err(FlacToWavError::Stream(_))));
+
+    // The failed write stops the decoding.
+    struct FailingWriter(Cursor<Vec<u8>>);
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0.get_ref().len() > 1000 {
+                return Err(std::io::Error::new(std::io::ErrorKind::StorageFull, "Full"));
+            }
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl std::io::Seek for FailingWriter {
+        fn seek(&mut self, position: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(position)
+        }
+    }
+    assert!(matches!(flac_to_wav(Cursor::new(&flac[..]), FailingWriter(Cursor::new(Vec::new())), ForeignMetadata::Ignore), Err(FlacToWavError::Io(error)) if error.kind() == std::io::ErrorKind::StorageFull));
+}
+
+#[test]
+fn test_transcode_conversion_policy() {
+    use std::io::Cursor;
+    use options::FlacEncoderParams;
+
+    let mut params_24 = FlacEncoderParams::new();
+    params_24.bits_per_sample = 24;
+    let samples_24: Vec<i32> = sine_wave_stereo_16().into_iter().map(|sample| sample * 256 + 0x7F).collect();
+    let flac_24 = encode_to_memory(&params_24, &samples_24, |_| {});
+    let params_16 = FlacEncoderParams::new();
+
+    // 24 to 16 bits with the dither, every sample is within 1 LSB of the rounded one, but not all of them are the rounded one.
+    let policy = ConversionPolicy {bit_depth: BitDepthMismatch::Convert, dither: true, downmix: false};
+    let mut output = Cursor::new(Vec::new());
+    assert_eq!(transcode(Cursor::new(&flac_24[..]), &mut output, &params_16, policy).unwrap(), 44100);
+    let (stream_info, decoded) = decode_to_i16(Cursor::new(&output.get_ref()[..])).unwrap();
+    assert_eq!(stream_info.bits_per_sample, 16);
+    let rounded = sine_wave_stereo_16();
+    assert!(decoded.iter().zip(rounded.iter()).all(|(&dithered, &rounded)| (dithered as i32 - rounded).abs() <= 1));
+    assert!(decoded.iter().zip(rounded.iter()).any(|(&dithered, &rounded)| dithered as i32 != rounded));
+
+    // Without the conversion policy, the bit depth mismatch is rejected.
+    let mut output = Cursor::new(Vec::new());
+    assert!(matches!(transcode(Cursor::new(&flac_24[..]), &mut output, &params_16, ConversionPolicy::default()), Err(TranscodeError::BitDepthMismatch{source: 24, target: 16})));
+
+    // The stereo to mono needs the downmix, the average is encoded.
+    let stereo: Vec<i32> = sine_wave_stereo_16().chunks(2).flat_map(|frame| [frame[0], frame[0] + 2]).collect();
+    let flac_stereo = encode_to_memory(&params_16, &stereo, |_| {});
+    let params_mono = FlacEncoderParams {channels: 1, ..params_16};
+    let mut output = Cursor::new(Vec::new());
+    match transcode(Cursor::new(&flac_stereo[..]), &mut output, &params_mono, BitDepthMismatch::Reject) {
+        Err(error @ TranscodeError::FormatMismatch{source, target}) => {
+            assert_eq!((source.channels, target.channels), (2, 1));
+            assert_eq!(error.to_string(), "The source has 2 channels, 16 bits per sample, 44100 Hz, but the encoder has 1 channels, 16 bits per sample, 44100 Hz");
+        },
+        other => panic!("{other:?}"),
+    }
+    assert!(output.get_ref().is_empty());
+    let mut output = Cursor::new(Vec::new());
+    transcode(Cursor::new(&flac_stereo[..]), &mut output, &params_mono, ConversionPolicy {downmix: true, ..ConversionPolicy::default()}).unwrap();
+    let (stream_info, decoded) = decode_to_i16(Cursor::new(&output.get_ref()[..])).unwrap();
+    assert_eq!(stream_info.channels, 1);
+    assert_eq!(decoded, stereo.chunks(2).map(|frame| (frame[0] + 1) as i16).collect::<Vec<_>>());
+
+    // The sample rate is never converted.
+    let params_48k = FlacEncoderParams {sample_rate: 48000, ..params_16};
+    let mut output = Cursor::new(Vec::new());
+    assert!(matches!(transcode(Cursor::new(&flac_stereo[..]), &mut output, &params_48k, ConversionPolicy {bit_depth: BitDepthMismatch::Convert, dither: true, downmix: true}), Err(TranscodeError::FormatMismatch{..})));
+}
+
+#[test]
+fn test_stream_info_respond() {
+    use metadata::FlacMetadataType;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+    });
+
+    // The STREAMINFO block is read even if only the comments are wanted.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    assert!(decoder.get_stream_info_respond());
+    decoder.set_metadata_respond(Some(&[FlacMetadataType::VorbisComment])).unwrap();
+    assert_eq!(decoder.decode().unwrap(), DecodeStep::Metadata(FlacMetadataType::StreamInfo));
+    decoder.decode_all().into_result().unwrap();
+    assert!(decoder.duration().is_some());
+    decoder.finish().unwrap();
+    drop(decoder);
+
+    // Turned off, with or without the other blocks selected, the helpers that need it return `None`.
+    for metadata_respond in [None, Some(&[FlacMetadataType::StreamInfo, FlacMetadataType::VorbisComment][..])] {
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.set_metadata_respond(metadata_respond).unwrap();
+        decoder.set_stream_info_respond(false).unwrap();
+        // The skipped STREAMINFO block delivers nothing.
+        assert_eq!(decoder.decode().unwrap(), DecodeStep::NothingDone);
+        assert_eq!(decoder.decode().unwrap(), DecodeStep::Metadata(FlacMetadataType::VorbisComment));
+        assert_eq!(decoder.decode_all().into_result().unwrap(), samples.len() as u64 / 2);
+        assert_eq!(decoder.get_comments()["TITLE"], "Sine");
+        assert_eq!(decoder.metadata_report().stream_info, None);
+        assert_eq!(decoder.duration(), None);
+        assert_eq!(decoder.progress(), None);
+        assert!(decoder.set_stream_info_respond(true).is_err());
+        decoder.finish().unwrap();
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_write_interleaved_chunks() {
+    use errors::FlacEncoderErrorCode;
+
+    let samples = sine_wave_stereo_16();
+    let expected = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+
+    // The odd-sized chunks split the stereo frames, even a single sample or an empty chunk.
+    let mut sizes = [1usize, 0, 777, 3, 4096, 1].into_iter().cycle();
+    let mut chunks = Vec::new();
+    let mut rest = &samples[..];
+    while !rest.is_empty() {
+        let (chunk, remaining) = rest.split_at(sizes.next().unwrap().min(rest.len()));
+        chunks.push(chunk);
+        rest = remaining;
+    }
+    assert!(chunks.iter().any(|chunk| chunk.len() % 2 == 1));
+    let flac = encode_to_memory_with(&options::FlacEncoderParams::new(), |_| {}, |encoder| {
+        encoder.write_interleaved_chunks(chunks.iter().copied()).unwrap();
+    });
+    assert_eq!(flac, expected);
+
+    // The leftover partial frame is a framing error, the complete frames were encoded.
+    let flac = encode_to_memory_with(&options::FlacEncoderParams::new(), |_| {}, |encoder| {
+        let error = encoder.write_interleaved_chunks([&samples[..100], &samples[100..201]]).unwrap_err();
+        assert!(matches!(FlacEncoderErrorCode::from(error.code), FlacEncoderErrorCode::StreamEncoderFramingError));
+        encoder.write_interleaved_samples(&samples[200..]).unwrap();
+    });
+    assert_eq!(flac, expected);
+}
+
+#[test]
+fn test_i32_scaling_msb_shift() {
+    use std::cell::RefCell;
+    use flac::{scale_to_i32, shift_to_i32};
+    let errors = DecoderErrors::default();
+
+    // Exhaustive for 8 and 12 bits, the boundary values for the wider ones, both modes shift back to the sample.
+    for bits in [8u32, 12, 16, 20, 24] {
+        let min = -(1i32 << (bits - 1));
+        let max = (1i32 << (bits - 1)) - 1;
+        let samples: Vec<i32> = if bits <= 12 {
+            (min..=max).collect()
+        } else {
+            vec![min, min + 1, -2, -1, 0, 1, 2, max - 1, max]
+        };
+        let mut previous = None;
+        for &sample in samples.iter() {
+            let shifted = shift_to_i32(sample, bits);
+            assert_eq!(shifted >> (32 - bits), sample, "{bits} bits");
+            assert_eq!(scale_to_i32(sample, bits) >> (32 - bits), sample, "{bits} bits");
+            assert_eq!(shifted & !(u32::MAX << (32 - bits)) as i32, 0, "{bits} bits");
+            if let Some(previous) = previous {
+                assert!(shifted > previous, "{bits} bits, sample {sample}");
+            }
+            previous = Some(shifted);
+        }
+        assert_eq!(shift_to_i32(min, bits), i32::MIN, "{bits} bits");
+        assert_eq!(shift_to_i32(max, bits), i32::MAX - ((1 << (32 - bits)) - 1), "{bits} bits");
+        assert_eq!(shift_to_i32(0, bits), 0, "{bits} bits");
+    }
+    assert_eq!(shift_to_i32(i32::MIN, 32), i32::MIN);
+
+    // The decoder scales by the chosen mode.
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let decode = |i32_scaling| {
+        let decoded = RefCell::new(Vec::new());
+        let mut decoder = FlacDecoder::with_default_io(
+            std::io::Cursor::new(&flac[..]),
+            Box::new(|frames: &[Vec<i32>], samples_info: &closure_objects::SamplesInfo| {
+                assert_eq!(samples_info.bits_per_sample, 32);
+                decoded.borrow_mut().extend(frames.iter().flatten().copied());
+                Ok(())
+            }),
+            record_errors(&errors),
+            true,
+            true,
+            options::FlacAudioForm::FrameArray,
+        ).unwrap();
+        decoder.i32_scaling = i32_scaling;
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        drop(decoder);
+        decoded.into_inner()
+    };
+    assert_eq!(decode(options::I32Scaling::MsbShift), samples.iter().map(|&sample| sample << 16).collect::<Vec<_>>());
+    assert_eq!(decode(options::I32Scaling::BitReplication), samples.iter().map(|&sample| scale_to_i32(sample, 16)).collect::<Vec<_>>());
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_select_channels() {
+    use std::cell::RefCell;
+    use errors::FLAC_DECODER_CHANNEL_OUT_OF_RANGE;
+    use options::{FlacAudioForm, SamplesInfo};
+    let errors = DecoderErrors::default();
+
+    // 8 channels, each of them is the sine wave at a different level.
+    let mono: Vec<i32> = sine_wave_stereo_16().into_iter().step_by(2).collect();
+    let samples: Vec<i32> = mono.iter().flat_map(|&sample| (0..8).map(move |channel| sample / (channel + 1))).collect();
+    let flac = encode_to_memory(&options::FlacEncoderParams {channels: 8, ..options::FlacEncoderParams::new()}, &samples, |_| {});
+
+    for audio_form in [FlacAudioForm::FrameArray, FlacAudioForm::ChannelArray] {
+        // How many values are delivered, and the first channel of them.
+        let decode = |channels: Option<&[usize]>| {
+            let (values, first_channel) = (RefCell::new(0usize), RefCell::new(Vec::new()));
+            let mut decoder = FlacDecoder::with_default_io(
+                std::io::Cursor::new(&flac[..]),
+                Box::new(|decoded: &[Vec<i32>], samples_info: &SamplesInfo| {
+                    *values.borrow_mut() += decoded.iter().map(|values| values.len()).sum::<usize>();
+                    first_channel.borrow_mut().extend((0..samples_info.samples as usize).map(|frame| samples_info.get_sample(decoded, frame, 0).unwrap()));
+                    Ok(())
+                }),
+                record_errors(&errors),
+                true,
+                false,
+                audio_form,
+            ).unwrap();
+            decoder.decode_metadata().unwrap();
+            if let Some(channels) = channels {
+                decoder.select_channels(channels).unwrap();
+                assert_eq!(decoder.select_channels(&[8]).unwrap_err().code, FLAC_DECODER_CHANNEL_OUT_OF_RANGE);
+            }
+            decoder.decode_all().into_result().unwrap();
+            decoder.finish().unwrap();
+            drop(decoder);
+            (values.into_inner(), first_channel.into_inner())
+        };
+        let (all_values, first) = decode(None);
+        assert_eq!(first, mono);
+        let (selected_values, selected) = decode(Some(&[5]));
+        assert_eq!(all_values, selected_values * 8);
+        assert_eq!(selected, samples.iter().skip(5).step_by(8).copied().collect::<Vec<_>>());
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_output_position() {
+    use std::cell::Cell;
+    let (position, bytes_written) = (Cell::new(0), Cell::new(0));
+    let data = encode_to_memory_with(&options::FlacEncoderParams::new(), |_| {}, |encoder| {
+        encoder.write_interleaved_samples(&sine_wave_stereo_16()).unwrap();
+        position.set(encoder.output_position().unwrap());
+        bytes_written.set(encoder.bytes_written());
+        assert_eq!(encoder.output_position().unwrap(), encoder.tell().unwrap());
+    });
+    // Nothing was rewritten before `finish()`, the file offset is the count of bytes.
+    assert!(position.get() > 0);
+    assert_eq!(position.get(), bytes_written.get());
+    assert!(position.get() <= data.len() as u64);
+
+    // With the spool, nothing reaches the `writer` until `finish()`.
+    let params = options::FlacEncoderParams {buffer_for_header: true, ..options::FlacEncoderParams::new()};
+    let data = encode_to_memory_with(&params, |_| {}, |encoder| {
+        encoder.write_interleaved_samples(&sine_wave_stereo_16()).unwrap();
+        assert_eq!(encoder.tell().unwrap(), 0);
+        assert_eq!(encoder.output_position().unwrap(), encoder.bytes_written());
+        encoder.finish().unwrap();
+        // The STREAMINFO block was rewritten, so more bytes were written than the file has.
+        assert!(encoder.bytes_written() > encoder.tell().unwrap());
+    });
+    assert_eq!(&data[0..4], b"fLaC");
+}
+
+#[test]
+fn test_decode_to_u8() {
+    use util::convert_to_u8;
+    assert_eq!(convert_to_u8(-128, 8), 0);
+    assert_eq!(convert_to_u8(0, 8), 128);
+    assert_eq!(convert_to_u8(127, 8), 255);
+    assert_eq!(convert_to_u8(-0x8000, 16), 0);
+    assert_eq!(convert_to_u8(0x7FFF, 16), 255);
+
+    let mut params = options::FlacEncoderParams::new();
+    params.bits_per_sample = 8;
+    let flac = encode_to_memory(&params, &[-128, 127, 0, -1, 1, 0], |_| {});
+    let (stream_info, decoded) = decode_to_u8(std::io::Cursor::new(&flac[..])).unwrap();
+    assert_eq!(stream_info.bits_per_sample, 8);
+    assert_eq!(decoded, [0, 255, 128, 127, 129, 128]);
+}
+
+#[test]
+fn test_read_at() {
+    use std::cell::RefCell;
+    use options::{FlacAudioForm, SamplesInfo};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let total = samples.len() / 2;
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let channels: Vec<Vec<i32>> = (0..2).map(|channel| samples.iter().skip(channel).step_by(2).copied().collect()).collect();
+    let expected = |start: usize, n: usize| -> Vec<Vec<i32>> {
+        channels.iter().map(|channel| channel[start.min(total)..(start + n).min(total)].to_vec()).collect()
+    };
+
+    let writes = RefCell::new(0);
+    let mut decoder = FlacDecoder::with_default_io(
+        std::io::Cursor::new(&flac[..]),
+        Box::new(|_: &[Vec<i32>], _: &SamplesInfo| {*writes.borrow_mut() += 1; Ok(())}),
+        record_errors(&errors),
+        true,
+        false,
+        FlacAudioForm::FrameArray,
+    ).unwrap();
+
+    // Sequential reads across the frame boundaries, without seeking in between.
+    let mut start = 1000;
+    for n in [100, 1500, 7, 2048] {
+        assert_eq!(decoder.read_at(start as u64, n).unwrap(), expected(start, n));
+        if start == 1000 {decoder.set_io_stats_enabled(true)}
+        start += n;
+    }
+    assert_eq!(decoder.io_stats().unwrap().seeks, 0);
+
+    // Backward and overlapping reads.
+    assert_eq!(decoder.read_at(10, 500).unwrap(), expected(10, 500));
+    assert_eq!(decoder.read_at(300, 500).unwrap(), expected(300, 500));
+    assert_eq!(decoder.read_at(0, 1).unwrap(), expected(0, 1));
+
+    // Short at the end of the stream, empty beyond it.
+    assert_eq!(decoder.read_at(total as u64 - 10, 100).unwrap(), expected(total - 10, 100));
+    assert_eq!(decoder.read_at(total as u64, 100).unwrap(), vec![Vec::<i32>::new(); 2]);
+    assert_eq!(decoder.read_at(total as u64 + 5, 100).unwrap(), vec![Vec::<i32>::new(); 2]);
+    assert_eq!(decoder.read_at(5000, 3).unwrap(), expected(5000, 3));
+    decoder.finish().unwrap();
+    drop(decoder);
+    assert_eq!(writes.into_inner(), 0);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_cue_sheet_audio_tracks() {
+    use metadata::{FlacCueSheet, FlacCueTrack, FlacTrackType};
+
+    let track = |track_no: u8, offset: u64| (track_no, FlacCueTrack {
+        offset,
+        track_no,
+        isrc: [0; 13],
+        type_: FlacTrackType::Audio,
+        pre_emphasis: false,
+        indices: Vec::new(),
+    });
+    let mut cue_sheet = FlacCueSheet {
+        media_catalog_number: [0; 129],
+        lead_in: 88200,
+        is_cd: true,
+        tracks: [track(2, 58800), track(170, 117600), track(1, 0)].into_iter().collect(),
+    };
+    assert_eq!(cue_sheet.audio_tracks().map(|track| track.track_no).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(cue_sheet.lead_out().map(|track| track.offset), Some(117600));
+
+    // Not a CD, the lead-out track is 255, and 170 is just a track.
+    cue_sheet.is_cd = false;
+    cue_sheet.tracks.extend([track(255, 200000)]);
+    assert_eq!(cue_sheet.audio_tracks().map(|track| track.track_no).collect::<Vec<_>>(), [1, 2, 170]);
+    assert_eq!(cue_sheet.lead_out().map(|track| track.offset), Some(200000));
+    cue_sheet.tracks.remove(&255);
+    assert!(cue_sheet.lead_out().is_none());
+}
+
+#[test]
+fn test_sync_decoder() {
+    use std::{io::{self, Cursor, Read, Seek, SeekFrom}, thread, sync::{Mutex, atomic::{AtomicBool, Ordering}}};
+    use closure_objects::{FlacReadStatus, SamplesInfo};
+    use options::FlacAudioForm;
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+    });
+    let length = flac.len() as u64;
+    let errors = Mutex::new(Vec::new());
+    let decoder = SyncFlacDecoder::new(SendFlacDecoder::new(
+        Cursor::new(&flac[..]),
+        Box::new(|reader: &mut Cursor<&[u8]>, data: &mut [u8]| -> (usize, FlacReadStatus) {
+            match reader.read(data) {
+                Ok(0) => (0, FlacReadStatus::Eof),
+                Ok(size) => (size, FlacReadStatus::GoOn),
+                Err(_) => (0, FlacReadStatus::Abort),
+            }
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>, position: u64| -> Result<(), io::Error> {
+            reader.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            reader.stream_position()
+        }),
+        Box::new(move |_reader: &mut Cursor<&[u8]>| -> Result<u64, io::Error> {
+            Ok(length)
+        }),
+        Box::new(move |reader: &mut Cursor<&[u8]>| -> bool {
+            reader.position() >= length
+        }),
+        Box::new(|_: &[Vec<i32>], _: &SamplesInfo| -> Result<(), io::Error> {Ok(())}),
+        Box::new(|error| errors.lock().unwrap().push(error)),
+        true,
+        false,
+        FlacAudioForm::FrameArray,
+    ).unwrap());
+    assert_send_sync(&decoder);
+    assert!(decoder.stream_info().is_none());
+
+    // The audio thread pulls the chunks, the UI thread polls the position and the metadata meanwhile.
+    let done = AtomicBool::new(false);
+    let decoded = thread::scope(|scope| {
+        let ui = scope.spawn(|| {
+            let mut last_position = 0;
+            while !done.load(Ordering::Acquire) {
+                let position = decoder.tell_samples();
+                assert!(position >= last_position);
+                last_position = position;
+                if let Some(stream_info) = decoder.stream_info() {
+                    assert_eq!(stream_info.channels, 2);
+                    assert_eq!(decoder.snapshot().comments.get("TITLE").map(String::as_str), Some("Sine"));
+                }
+                thread::yield_now();
+            }
+        });
+        let audio = scope.spawn(|| {
+            let mut decoded = vec![Vec::new(); 2];
+            loop {
+                let chunk = decoder.read(1000).unwrap();
+                if chunk[0].is_empty() {
+                    break;
+                }
+                for (channel, samples) in decoded.iter_mut().zip(chunk) {
+                    channel.extend(samples);
+                }
+            }
+            done.store(true, Ordering::Release);
+            decoded
+        }).join().unwrap();
+        ui.join().unwrap();
+        audio
+    });
+    assert_eq!(decoder.tell_samples(), samples.len() as u64 / 2);
+    assert_eq!(decoder.duration(), Some(std::time::Duration::from_secs(1)));
+    assert_eq!(decoded[0], samples.iter().step_by(2).copied().collect::<Vec<_>>());
+    assert_eq!(decoded[1], samples.iter().skip(1).step_by(2).copied().collect::<Vec<_>>());
+
+    // Seeking only moves the position, the next read seeks.
+    decoder.seek(100);
+    assert_eq!(decoder.read(10).unwrap()[0], samples.iter().step_by(2).skip(100).take(10).copied().collect::<Vec<_>>());
+    assert_eq!(decoder.tell_samples(), 110);
+
+    // Everything else goes through the locked decoder, without moving the position.
+    assert_eq!(decoder.with_decoder(|decoder| decoder.read_at(0, 10).unwrap())[0], samples.iter().step_by(2).take(10).copied().collect::<Vec<_>>());
+    assert_eq!(decoder.tell_samples(), 110);
+    decoder.into_inner().finish().unwrap();
+    let errors = errors.into_inner().unwrap();
+    assert!(errors.is_empty(), "{errors:?}");
+}
+
+/// * The snapshot is plain data, this runs under Miri, `cargo +nightly miri test test_sync_decoder_snapshot`.
+/// * A crafted STREAMINFO block may claim the sample rate zero, the duration is unknown then instead of a panic.
+#[test]
+fn test_sync_decoder_snapshot() {
+    use std::time::Duration;
+    use metadata::FlacStreamInfo;
+
+    assert_eq!(DecoderSnapshot::default().duration(), None);
+
+    let stream_info = FlacStreamInfo{sample_rate: 44100, channels: 2, bits_per_sample: 16, total_samples: 44100 * 3 / 2, ..FlacStreamInfo::default()};
+    let snapshot = DecoderSnapshot{stream_info: Some(stream_info), ..DecoderSnapshot::default()};
+    assert_eq!(snapshot.duration(), Some(Duration::from_millis(1500)));
+
+    let snapshot = DecoderSnapshot{stream_info: Some(FlacStreamInfo{sample_rate: 0, ..stream_info}), ..DecoderSnapshot::default()};
+    assert_eq!(snapshot.duration(), None);
+
+    let snapshot = DecoderSnapshot{stream_info: Some(FlacStreamInfo{total_samples: 0, ..stream_info}), ..DecoderSnapshot::default()};
+    assert_eq!(snapshot.duration(), None);
+}
+
+#[test]
+fn test_best_compression() {
+    use std::io::Cursor;
+    use options::FlacCompression;
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+    });
+    let (level, size) = best_compression(Cursor::new(&flac[..]), &[FlacCompression::Level0, FlacCompression::Level5]).unwrap();
+    assert_eq!(level, FlacCompression::Level5);
+
+    // The size is what encoding at the level really produces, with the comments.
+    let params = options::FlacEncoderParams {compression: FlacCompression::Level5, total_samples_estimate: samples.len() as u64 / 2, ..options::FlacEncoderParams::new()};
+    let mut output = Cursor::new(Vec::new());
+    assert_eq!(encode_interleaved(&mut output, &params, &samples, &[("TITLE", "Sine")]).unwrap(), size);
+    assert_eq!(output.into_inner().len() as u64, size);
+
+    // All of the levels, the best is never worse than level 0.
+    let (_, best_size) = best_compression(Cursor::new(&flac[..]), &[]).unwrap();
+    assert!(best_size <= size);
+    assert_eq!(best_compression(Cursor::new(&flac[..]), &[FlacCompression::Level3]).unwrap().0, FlacCompression::Level3);
+
+    // The damaged frame isn't compared as the silence.
+    let mut damaged = flac.clone();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0xFF;
+    match best_compression(Cursor::new(&damaged[..]), &[]) {
+        Err(TranscodeError::Decoder(error)) => assert_eq!(error.code, errors::FLAC_DECODER_DAMAGED_STREAM),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn test_metadata_too_large() {
+    use errors::{MetadataTooLarge, MAX_METADATA_BLOCK_LENGTH};
+    use metadata::{FlacMetadataType, MetadataBlockId};
+
+    let samples = sine_wave_stereo_16();
+    let data = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        // The picture block has 32 bytes besides the strings and the data.
+        let picture = vec![0u8; MAX_METADATA_BLOCK_LENGTH - 32 - "image/png".len()];
+        encoder.add_picture(&picture, "", "image/png", 0, 0, 0, 0).unwrap();
+        let error = encoder.add_picture(&[0; MAX_METADATA_BLOCK_LENGTH], "Cover", "image/png", 0, 0, 0, 0).unwrap_err();
+        assert_eq!(error.failed_block, Some(MetadataBlockId {kind: FlacMetadataType::Picture, index: 1}));
+        assert_eq!(error.too_large, Some(MetadataTooLarge {kind: FlacMetadataType::Picture, size: MAX_METADATA_BLOCK_LENGTH + 32 + 9 + 5, limit: MAX_METADATA_BLOCK_LENGTH}));
+        assert_eq!(encoder.queued_picture_count(), 1);
+
+        // The comments fail when the block overflows, the earlier ones are kept.
+        let value = "x".repeat(1 << 20);
+        let error = (0..20).map(|i| encoder.insert_comments(&format!("KEY{i}"), &value)).find_map(Result::err).unwrap();
+        assert_eq!(error.failed_block, Some(MetadataBlockId {kind: FlacMetadataType::VorbisComment, index: 0}));
+        assert!(error.too_large.unwrap().size > MAX_METADATA_BLOCK_LENGTH);
+        let comments = MetadataBlockId {kind: FlacMetadataType::VorbisComment, index: 0};
+        assert_eq!(encoder.describe_metadata_block(comments).unwrap(), "15 comments");
+        // Replacing a comment with a shorter one still fits.
+        encoder.insert_comments("KEY0", "short").unwrap();
+
+        let error = encoder.add_application(*b"test", &vec![0; MAX_METADATA_BLOCK_LENGTH - 3]).unwrap_err();
+        assert_eq!(error.too_large.map(|too_large| too_large.size), Some(MAX_METADATA_BLOCK_LENGTH + 1));
+    });
+    assert_eq!(&data[0..4], b"fLaC");
+}
+
+
+#[test]
+fn test_original_encoder_options() {
+    use std::io::{Cursor, Seek};
+    use options::FlacCompression;
+    use util::{compression_from_encoder_options, block_size_from_encoder_options};
+
+    assert_eq!(compression_from_encoder_options("-8 -V"), Some(FlacCompression::Level8));
+    assert_eq!(compression_from_encoder_options("-V3 --best -b 4096 -l 12"), Some(FlacCompression::Level8));
+    assert_eq!(compression_from_encoder_options("--compression-level-2 -b4096"), Some(FlacCompression::Level2));
+    assert_eq!(compression_from_encoder_options("--fast -6"), Some(FlacCompression::Level6));
+    assert_eq!(compression_from_encoder_options("-b 4608 -l12 -r 9"), None);
+    assert_eq!(compression_from_encoder_options("--compression-level-9 garbage"), None);
+    assert_eq!(block_size_from_encoder_options("-V3 --best -b 4096 -l 12"), Some(4096));
+    assert_eq!(block_size_from_encoder_options("-8b1152 -b4608"), Some(4608));
+    assert_eq!(block_size_from_encoder_options("--blocksize=2048 -8"), Some(2048));
+    assert_eq!(block_size_from_encoder_options("--blocksize 576"), Some(576));
+    assert_eq!(block_size_from_encoder_options("-l 12 -r 9 -8"), None);
+    assert_eq!(block_size_from_encoder_options("-l4 -b 8 -b 70000"), None);
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("ENCODER_OPTIONS", "-7 -V").unwrap();
+    });
+    let mut reader = Cursor::new(&flac[..]);
+    let params = original_params(&mut reader).unwrap();
+    assert_eq!(reader.stream_position().unwrap(), 0);
+    assert_eq!(params.compression, FlacCompression::Level7);
+    assert_eq!((params.channels, params.sample_rate, params.bits_per_sample), (2, 44100, 16));
+    assert_eq!(params.total_samples_estimate, samples.len() as u64 / 2);
+    assert_eq!(params.block_size, Some(4096));
+
+    // The re-encoded file has the same audio.
+    let mut output = Cursor::new(Vec::new());
+    transcode(reader, &mut output, &params, BitDepthMismatch::Reject).unwrap();
+    assert!(audio_equal(Cursor::new(&flac[..]), Cursor::new(&output.get_ref()[..])).unwrap());
+
+    // Without the comment, the default level.
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    assert_eq!(original_params(&mut Cursor::new(&flac[..])).unwrap().compression, FlacCompression::Level5);
+
+    // The block size is from the STREAMINFO block.
+    let flac = encode_to_memory(&options::FlacEncoderParams {block_size: Some(1152), ..options::FlacEncoderParams::new()}, &samples, |encoder| {
+        encoder.insert_comments("ENCODER_OPTIONS", "-8").unwrap();
+    });
+    assert_eq!(original_params(&mut Cursor::new(&flac[..])).unwrap().block_size, Some(1152));
+}
+
+#[test]
+fn test_best_cover() {
+    use metadata::FlacPictureType;
+    let errors = DecoderErrors::default();
+
+    // Decode the pictures of the types, each picture's data is its index.
+    let decode = |picture_types: &[FlacPictureType], f: &dyn Fn(&MemoryDecoder)| {
+        let flac = encode_to_memory(&options::FlacEncoderParams::new(), &sine_wave_stereo_16(), |encoder| {
+            for (index, picture_type) in picture_types.iter().enumerate() {
+                encoder.add_picture_of_type(&[index as u8], *picture_type, "", "image/png", 0, 0, 0, 0).unwrap();
+            }
+            assert_eq!(encoder.has_picture_of_type(FlacPictureType::FrontCover), picture_types.contains(&FlacPictureType::FrontCover));
+        });
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        f(&decoder);
+    };
+    let data = |picture: Option<&metadata::PictureData>| picture.map(|picture| picture.picture[0]);
+
+    decode(&[FlacPictureType::Artist, FlacPictureType::Other, FlacPictureType::FrontCover, FlacPictureType::LeafletPage, FlacPictureType::LeafletPage], &|decoder| {
+        assert_eq!(data(decoder.best_cover()), Some(2));
+        assert_eq!(data(decoder.picture_by_type(FlacPictureType::Other)), Some(1));
+        assert_eq!(decoder.pictures_of_type(FlacPictureType::LeafletPage).map(|picture| picture.picture[0]).collect::<Vec<_>>(), [3, 4]);
+        assert!(decoder.picture_by_type(FlacPictureType::BackCover).is_none());
+    });
+
+    // Without a front cover, the untyped pictures of the legacy taggers come first.
+    decode(&[FlacPictureType::Artist, FlacPictureType::Other, FlacPictureType::Other], &|decoder| {
+        assert_eq!(data(decoder.best_cover()), Some(1));
+    });
+    decode(&[FlacPictureType::Artist, FlacPictureType::BackCover], &|decoder| {
+        assert_eq!(data(decoder.best_cover()), Some(0));
+    });
+    decode(&[], &|decoder| {
+        assert!(decoder.best_cover().is_none());
+    });
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[cfg(feature = "id3")]
+#[test]
+fn test_id3_picture_types() {
+    use id3::{TagLike, frame::{Picture, PictureType}};
+    use metadata::FlacPictureType;
+    let errors = DecoderErrors::default();
+
+    let mut tag = id3::Tag::new();
+    for (picture_type, data) in [(PictureType::CoverFront, 0u8), (PictureType::CoverBack, 1), (PictureType::Icon, 2), (PictureType::OtherIcon, 3)] {
+        tag.add_frame(Picture {
+            mime_type: "image/png".to_owned(),
+            picture_type,
+            description: format!("Picture {data}"),
+            data: vec![data],
+        });
+    }
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &sine_wave_stereo_16(), |encoder| {
+        // The front cover from elsewhere stays, the one of the ID3 tag isn't added as the second one.
+        encoder.add_picture_of_type(&[9], FlacPictureType::FrontCover, "", "image/png", 0, 0, 0, 0).unwrap();
+        encoder.inherit_metadata_from_id3(&tag).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    // The types are kept, only one file icon is kept, and the icon of an unknown size isn't the 32x32 one.
+    let mut pictures: Vec<(u8, FlacPictureType)> = decoder.get_pictures().iter().map(|picture| (picture.picture[0], picture.picture_type)).collect();
+    pictures.sort_by_key(|(data, _)| *data);
+    assert_eq!(pictures, [(1, FlacPictureType::BackCover), (2, FlacPictureType::OtherFileIcon), (9, FlacPictureType::FrontCover)]);
+    assert_eq!(decoder.best_cover().unwrap().picture[0], 9);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_prebuffer_frames() {
+    use std::{cell::{Cell, RefCell}, io::{self, Cursor, Read, Seek, SeekFrom}, rc::Rc};
+    use closure_objects::SamplesInfo;
+    use flac::{FlacInternalDecoderError, FlacAudioForm, DecodeOutcome};
+    let errors = DecoderErrors::default();
+
+    /// * Shares how far the decoder has read.
+    struct TrackedReader<'a> {
+        cursor: Cursor<&'a [u8]>,
+        position: Rc<Cell<u64>>,
+    }
+    impl Read for TrackedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = self.cursor.read(buf)?;
+            self.position.set(self.position.get().max(self.cursor.position()));
+            Ok(size)
+        }
+    }
+    impl Seek for TrackedReader<'_> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.cursor.seek(pos)
+        }
+    }
+
+    // The noise doesn't compress, so the reader is well behind the end at the first frame.
+    let mut seed = 1u32;
+    let samples: Vec<i32> = (0..44100 * 2).map(|_| {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (seed >> 16) as i16 as i32
+    }).collect();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+
+    // Returns how far the reader was at the first `on_write()`, and all of the samples.
+    let decode = |prebuffer_frames: usize| -> (u64, Vec<i32>) {
+        let position = Rc::new(Cell::new(0));
+        let first_write = Cell::new(None);
+        let decoded = RefCell::new(Vec::new());
+        let mut decoder = FlacDecoder::with_default_io(
+            TrackedReader{cursor: Cursor::new(&flac), position: position.clone()},
+            Box::new(|frames: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {
+                if first_write.get().is_none() {
+                    first_write.set(Some(position.get()));
+                }
+                decoded.borrow_mut().extend(frames.iter().flatten());
+                Ok(())
+            }),
+            record_errors(&errors),
+            true,
+            false,
+            FlacAudioForm::FrameArray,
+        ).unwrap();
+        decoder.set_prebuffer_frames(prebuffer_frames);
+        assert!(matches!(decoder.decode_all(), DecodeOutcome::Completed{samples: 44100}));
+        assert_eq!(decoder.latency_samples(), Some((4096, 4096)));
+        decoder.finish().unwrap();
+        drop(decoder);
+        (first_write.get().unwrap(), decoded.into_inner())
+    };
+    let (unbuffered, unbuffered_samples) = decode(0);
+    let (buffered, buffered_samples) = decode(4);
+    assert!(buffered > unbuffered);
+    assert_eq!(unbuffered_samples, samples);
+    assert_eq!(buffered_samples, samples);
+
+    // More frames than the stream has are all passed out at the end.
+    let (_, all_buffered_samples) = decode(100);
+    assert_eq!(all_buffered_samples, samples);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_picture_image() {
+    use image::{DynamicImage, GenericImageView, ImageOutputFormat, RgbaImage, Rgba};
+    use metadata::{FlacPictureType, PictureData, PictureDecodeError};
+    let errors = DecoderErrors::default();
+
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 30, |x, y| Rgba([x as u8 * 6, y as u8 * 8, 128, 255])));
+    let png = PictureData::from_image(&image, ImageOutputFormat::Png, FlacPictureType::FrontCover, "Cover").unwrap();
+    assert_eq!((png.mime_type.as_str(), png.width, png.height, png.depth), ("image/png", 40, 30, 32));
+    assert_eq!(png.picture_type, FlacPictureType::FrontCover);
+    assert_eq!(png.description, "Cover");
+    // The PNG is lossless.
+    assert_eq!(png.decode_image().unwrap().to_rgba8(), image.to_rgba8());
+
+    // The alpha channel is dropped for the JPEG.
+    let jpeg = PictureData::from_image(&image, ImageOutputFormat::Jpeg(90), FlacPictureType::BackCover, "").unwrap();
+    assert_eq!((jpeg.mime_type.as_str(), jpeg.width, jpeg.height, jpeg.depth), ("image/jpeg", 40, 30, 24));
+    assert_eq!(jpeg.decode_image().unwrap().dimensions(), (40, 30));
+
+    // Through the FLAC file.
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &sine_wave_stereo_16(), |encoder| {
+        encoder.add_picture_of_type(&jpeg.picture, jpeg.picture_type, &jpeg.description, &jpeg.mime_type, jpeg.width, jpeg.height, jpeg.depth, jpeg.colors).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.first_picture().unwrap().decode_image().unwrap().dimensions(), (40, 30));
+
+    let link = PictureData{mime_type: "-->".to_owned(), picture: b"https://example.com/cover.png".to_vec(), ..PictureData::new()};
+    assert!(matches!(link.decode_image(), Err(PictureDecodeError::Link)));
+    let garbage = PictureData{mime_type: "image/png".to_owned(), picture: vec![0; 16], ..PictureData::new()};
+    assert!(matches!(garbage.decode_image(), Err(PictureDecodeError::Image(_))));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+/// * libFLAC is foreign code that Miri can't run, so the encode and decode paths can't be tested under Miri.
+///   The callbacks are driven through `ClientData` the way libFLAC does instead, check it by `cargo +nightly miri test test_client_data`.
+/// * The library calls back more than once per call, and after a nested call, like `process_until_end_of_stream()` calls the write callback for each frame.
+#[test]
+fn test_client_data() {
+    use std::ffi::c_void;
+    use flac::ClientData;
+
+    struct Caller {
+        client_data: ClientData,
+        depth: u32,
+        calls: Vec<u32>,
+    }
+
+    /// * The library keeps the `client_data` and calls back twice.
+    fn library(client_data: *mut c_void, callback: unsafe extern "C" fn(*mut c_void)) {
+        unsafe {
+            callback(client_data);
+            callback(client_data);
+        }
+    }
+
+    unsafe extern "C" fn callback(client_data: *mut c_void) {
+        let this = unsafe {ClientData::target::<Caller>(client_data)};
+        this.calls.push(this.depth);
+        if this.depth == 0 {
+            // Calls into the library again, e.g. to get the decode position in the write callback.
+            this.depth += 1;
+            this.call_library();
+            this.depth -= 1;
+        }
+        this.calls.push(this.depth);
+    }
+
+    impl Caller {
+        fn call_library(&mut self) {
+            let client_data = self.client_data.as_ptr();
+            let this = self as *mut Self;
+            let previous = self.client_data.bind(this);
+            library(client_data, callback);
+            self.client_data.restore(previous);
+        }
+    }
+
+    let mut caller = Caller {client_data: ClientData::new(), depth: 0, calls: Vec::new()};
+    caller.call_library();
+    caller.calls.push(9);
+
+    // The `client_data` stays the same after moving.
+    let mut caller = Box::new(caller);
+    caller.call_library();
+    let once = [0, 1, 1, 1, 1, 0, 0, 1, 1, 1, 1, 0];
+    assert_eq!(caller.calls, [&once[..], &[9], &once[..]].concat());
+}
+
+#[test]
+fn test_cue_sheet_merge() {
+    use metadata::{FlacCueSheet, FlacCueSheetIndex, FlacCueTrack, FlacTrackType};
+    let errors = DecoderErrors::default();
+
+    let track = |track_no: u8, offset: u64| (track_no, FlacCueTrack {
+        offset,
+        track_no,
+        isrc: [0; 13],
+        type_: FlacTrackType::Audio,
+        pre_emphasis: false,
+        indices: if track_no == 170 {Vec::new()} else {vec![FlacCueSheetIndex{offset: 0, number: 0}, FlacCueSheetIndex{offset: 588 * 2, number: 1}]},
+    });
+    let cue_sheet = |tracks: Vec<(u8, FlacCueTrack)>| FlacCueSheet {
+        media_catalog_number: [0; 129],
+        lead_in: 88200,
+        is_cd: true,
+        tracks: tracks.into_iter().collect(),
+    };
+    let disc_1 = cue_sheet(vec![track(1, 0), track(2, 588 * 40), track(170, 588 * 100)]);
+    let disc_2 = cue_sheet(vec![track(1, 0), track(2, 588 * 10), track(3, 588 * 30), track(170, 588 * 50)]);
+    disc_1.check_legal().unwrap();
+
+    let mut merged = disc_1.clone();
+    merged.merge(&disc_2, 588 * 100).unwrap();
+    let tracks: Vec<(u8, u8, u64)> = merged.tracks.iter().map(|(track_no, track)| (*track_no, track.track_no, track.offset)).collect();
+    assert_eq!(tracks, [(1, 1, 0), (2, 2, 588 * 40), (3, 3, 588 * 100), (4, 4, 588 * 110), (5, 5, 588 * 130), (170, 170, 588 * 150)]);
+    assert_eq!(merged.tracks[&4].indices[1].offset, 588 * 2);
+
+    // Encoded and decoded back.
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &sine_wave_stereo_16(), |encoder| {
+        encoder.insert_cue_sheet(&merged).unwrap();
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.get_cue_sheets()[0].lead_out().unwrap().offset, 588 * 150);
+
+    // The CD-DA offsets must be on the sector boundaries, and this cue sheet stays unchanged on error.
+    let mut not_merged = disc_1.clone();
+    let error = not_merged.merge(&disc_2, 588 * 100 + 1).unwrap_err();
+    assert_eq!(error.code, libflac_sys::FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA);
+    assert!(error.message.contains("588"), "{}", error.message);
+    assert_eq!(not_merged.tracks.len(), 3);
+    assert!(not_merged.merge(&disc_2, 588 * 20).is_err());
+
+    // The tracks to append can't overlap the lead-out track, and `other` needs its lead-out track after its tracks.
+    assert_eq!(not_merged.merge(&disc_2, 588 * 99).unwrap_err().message, "The tracks to append start before the lead-out track");
+    let mut early_lead_out = disc_2.clone();
+    early_lead_out.tracks.insert(170, track(170, 588 * 20).1);
+    assert!(not_merged.merge(&early_lead_out, 588 * 100).is_err());
+    early_lead_out.tracks.remove(&170);
+    assert!(not_merged.merge(&early_lead_out, 588 * 100).is_err());
+    assert_eq!(not_merged.tracks.len(), 3);
+
+    // No more than 99 tracks for CD-DA.
+    let mut many = cue_sheet((1..=60).map(|track_no| track(track_no, 588 * track_no as u64)).chain([track(170, 588 * 100)]).collect());
+    let more = many.clone();
+    assert!(many.merge(&more, 588 * 100).is_err());
+    many.is_cd = false;
+    many.tracks.remove(&170);
+    many.tracks.extend([track(255, 588 * 100)]);
+    let mut more = many.clone();
+    more.tracks.remove(&255);
+    more.tracks.extend([track(170, 588 * 100)]);
+    more.is_cd = true;
+    many.merge(&more, 588 * 100).unwrap();
+    assert_eq!(many.audio_tracks().count(), 120);
+    assert_eq!(many.audio_tracks().last().unwrap().offset, 588 * 160);
+    assert_eq!(many.lead_out().unwrap().offset, 588 * 200);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_check_samples() {
+    use std::io::Cursor;
+    use errors::{FLAC_ENCODER_SAMPLE_OUT_OF_RANGE, SampleOutOfRange};
+
+    for bits_per_sample in [4, 8, 12, 16, 20, 24, 32] {
+        let params = options::FlacEncoderParams {
+            channels: 2,
+            bits_per_sample,
+            ..options::FlacEncoderParams::new()
+        };
+        let encoder = FlacEncoder::new(
+            Cursor::new(Vec::<u8>::new()),
+            Box::new(|_, _| Ok(())),
+            Box::new(|_, _| Ok(())),
+            Box::new(|_| Ok(0)),
+            &params,
+        ).unwrap();
+        let max = if bits_per_sample == 32 {i32::MAX} else {(1i32 << (bits_per_sample - 1)) - 1};
+        let min = if bits_per_sample == 32 {i32::MIN} else {-max - 1};
+        encoder.check_samples(&[min, max, 0, -1]).unwrap();
+        if bits_per_sample == 32 {
+            continue;
+        }
+        let error = encoder.check_samples(&[min, max, max + 1, min - 1]).unwrap_err();
+        assert_eq!(error.code, FLAC_ENCODER_SAMPLE_OUT_OF_RANGE);
+        assert_eq!(error.out_of_range, Some(SampleOutOfRange{index: 2, value: max + 1, bits_per_sample}));
+        assert_eq!(encoder.check_samples(&[0, min - 1]).unwrap_err().out_of_range.unwrap().index, 1);
+    }
+
+    // The write methods check the samples only when it's enabled.
+    let mut encoder = FlacEncoder::new(
+        Cursor::new(Vec::<u8>::new()),
+        Box::new(|_, _| Ok(())),
+        Box::new(|_, _| Ok(())),
+        Box::new(|_| Ok(0)),
+        &options::FlacEncoderParams::new(),
+    ).unwrap();
+    encoder.initialize().unwrap();
+    encoder.set_sample_checking(true);
+    assert_eq!(encoder.write_interleaved_samples(&[0, 0, 0, 40000]).unwrap_err().out_of_range.unwrap().index, 3);
+    assert_eq!(encoder.write_stereos(&[(0, 0), (-32769, 0)]).unwrap_err().out_of_range.unwrap().index, 2);
+    assert_eq!(encoder.write_mono_channel(&[0, 32768]).unwrap_err().out_of_range.unwrap().index, 1);
+    assert_eq!(encoder.write_monos(&[vec![0, 0, 0], vec![0, 32768, 0]]).unwrap_err().out_of_range.unwrap().index, 3);
+    assert_eq!(encoder.write_frames(&[vec![0, 0], vec![32768, 0]]).unwrap_err().out_of_range.unwrap().index, 2);
+    encoder.write_interleaved_samples(&[32767, -32768]).unwrap();
+
+    // Otherwise libFLAC fails on it.
+    encoder.set_sample_checking(false);
+    assert_eq!(encoder.write_interleaved_samples(&[40000, 0]).unwrap_err().code, libflac_sys::FLAC__STREAM_ENCODER_CLIENT_ERROR);
+}
+
+#[test]
+fn test_get_stream_info() {
+    use metadata::FlacMetadataType;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    let writes = std::cell::Cell::new(0);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| {writes.set(writes.get() + 1); Ok(())}));
+    assert!(decoder.get_stream_info().is_none());
+    assert_eq!(decoder.decode().unwrap(), DecodeStep::Metadata(FlacMetadataType::StreamInfo));
+    let stream_info = *decoder.get_stream_info().unwrap();
+    assert_eq!(stream_info.channels, 2);
+    assert_eq!(stream_info.sample_rate, 44100);
+    assert_eq!(stream_info.bits_per_sample, 16);
+    assert_eq!(stream_info.total_samples, (samples.len() / 2) as u64);
+    assert_eq!((stream_info.min_blocksize, stream_info.max_blocksize), (4096, 4096));
+    assert_ne!(stream_info.md5sum, [0; 16]);
+    assert_eq!(writes.get(), 0, "No samples should be decoded yet");
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_block_size() {
+    use std::io::{self, Cursor, Write, Seek, SeekFrom};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+
+    // The frames are as long as the block size, the compression level doesn't change it.
+    for (compression, block_size) in [(options::FlacCompression::Level5, 1024), (options::FlacCompression::Level0, 576)] {
+        let params = options::FlacEncoderParams {compression, block_size: Some(block_size), ..options::FlacEncoderParams::new()};
+        let flac = encode_to_memory(&params, &samples, |_| {});
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        assert_eq!(decoder.latency_samples(), Some((block_size, block_size)));
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+    }
+
+    // An invalid block size fails the initialization, not panics.
+    let mut sink = Cursor::new(Vec::new());
+    let params = options::FlacEncoderParams {block_size: Some(8), ..options::FlacEncoderParams::new()};
+    let mut encoder = FlacEncoder::new(
+        &mut sink,
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {writer.write_all(data)}),
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {writer.seek(SeekFrom::Start(position)).map(|_| ())}),
+        Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {writer.stream_position()}),
+        &params,
+    ).unwrap();
+    let error = encoder.initialize().unwrap_err();
+    assert_eq!(error.code, libflac_sys::FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_BLOCK_SIZE);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_final_stream_info() {
+    let params = options::FlacEncoderParams::new();
+    let samples = sine_wave_stereo_16();
+    let mut final_stream_info = None;
+    encode_to_memory_with(&params, |encoder| {
+        assert!(encoder.final_stream_info().is_none());
+    }, |encoder| {
+        encoder.write_interleaved_samples(&samples).unwrap();
+        encoder.finish().unwrap();
+        final_stream_info = encoder.final_stream_info();
+    });
+
+    // The MD5 sum is of the little-endian 16-bit samples, interleaved.
+    let pcm: Vec<u8> = samples.iter().flat_map(|&sample| (sample as i16).to_le_bytes()).collect();
+    let final_stream_info = final_stream_info.unwrap();
+    assert_eq!(final_stream_info.md5sum, md5::compute(&pcm).0);
+    assert_eq!(final_stream_info.total_samples, (samples.len() / 2) as u64);
+    assert!(final_stream_info.min_framesize > 0 && final_stream_info.min_framesize <= final_stream_info.max_framesize);
+}
+
+#[test]
+fn test_decode_metadata_only() {
+    use metadata::FlacPictureType;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        encoder.insert_comments("TITLE", "Sine").unwrap();
+        encoder.add_picture_of_type(&[2u8; 2000], FlacPictureType::FrontCover, "Front", "image/jpeg", 32, 32, 24, 0).unwrap();
+    });
+
+    let decoded = std::cell::Cell::new(0usize);
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|frames, _| {
+        decoded.set(decoded.get() + frames.len());
+        Ok(())
+    }));
+    assert!(decoder.decode_metadata().unwrap());
+    assert_eq!(decoded.get(), 0);
+    assert_eq!(decoder.get_comments().get("TITLE").map(String::as_str), Some("Sine"));
+    assert_eq!(decoder.get_pictures().len(), 1);
+
+    // Calling it again does nothing, then the audio frames can still be decoded.
+    assert!(decoder.decode_metadata().unwrap());
+    assert_eq!(decoded.get(), 0);
+    assert_eq!(decoder.get_pictures().len(), 1);
+    decoder.decode_all().into_result().unwrap();
+    assert_eq!(decoded.get(), samples.len() / 2);
+    decoder.finish().unwrap();
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_finish_report() {
+    use std::io::{self, Cursor, Write};
+    use errors::FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH;
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let actual = (samples.len() / 2) as u64;
+
+    // The seekable writer gets the STREAMINFO block rewritten, over or under the estimate.
+    for estimated in [actual * 2, actual / 2] {
+        let params = options::FlacEncoderParams {total_samples_estimate: estimated, ..options::FlacEncoderParams::new()};
+        let mut report = None;
+        let flac = encode_to_memory_with(&params, |encoder| encoder.set_strict_total_samples(Some(0)), |encoder| {
+            encoder.write_interleaved_samples(&samples).unwrap();
+            encoder.finish().unwrap();
+            report = encoder.finish_report();
+        });
+        assert_eq!(report, Some(FinishReport {estimated, actual, header_rewritten: true}));
+        assert!(!report.unwrap().header_is_wrong());
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        assert_eq!(decoder.get_stream_info().unwrap().total_samples, actual);
+    }
+
+    // The writer that can't seek keeps the estimate in the STREAMINFO block.
+    let encode_to_pipe = |estimated: u64, tolerance: Option<u64>| -> (Vec<u8>, Result<(), errors::FlacEncoderError>, Option<FinishReport>) {
+        let params = options::FlacEncoderParams {total_samples_estimate: estimated, ..options::FlacEncoderParams::new()};
+        let mut pipe = Cursor::new(Vec::new());
+        let (result, report) = {
+            let mut encoder = FlacEncoder::new(
+                &mut pipe,
+                Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {writer.write_all(data)}),
+                Box::new(|_writer: &mut &mut Cursor<Vec<u8>>, _position: u64| -> Result<(), io::Error> {Err(io::Error::from(io::ErrorKind::NotSeekable))}),
+                Box::new(|_writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {Err(io::Error::from(io::ErrorKind::NotSeekable))}),
+                &params,
+            ).unwrap();
+            encoder.set_strict_total_samples(tolerance);
+            encoder.initialize().unwrap();
+            encoder.write_interleaved_samples(&samples).unwrap();
+            (encoder.finish(), encoder.finish_report())
+        };
+        (pipe.into_inner(), result, report)
+    };
+    for estimated in [actual * 2, actual / 2] {
+        let (flac, result, report) = encode_to_pipe(estimated, None);
+        result.unwrap();
+        let report = report.unwrap();
+        assert_eq!(report, FinishReport {estimated, actual, header_rewritten: false});
+        assert!(report.header_is_wrong());
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        assert_eq!(decoder.get_stream_info().unwrap().total_samples, estimated);
+
+        // The strict mode fails if it's off by more than the tolerance.
+        let (strict_flac, result, _) = encode_to_pipe(estimated, Some(1000));
+        assert_eq!(result.unwrap_err().code, FLAC_ENCODER_TOTAL_SAMPLES_MISMATCH);
+        assert_eq!(strict_flac, flac);
+        encode_to_pipe(estimated, Some(actual)).1.unwrap();
+    }
+
+    // The unknown total samples is never wrong.
+    let (_, result, report) = encode_to_pipe(0, Some(0));
+    result.unwrap();
+    assert!(!report.unwrap().header_is_wrong());
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_md5_hex() {
+    let errors = DecoderErrors::default();
+    let samples = sine_wave_stereo_16();
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |_| {});
+    let pcm: Vec<u8> = samples.iter().flat_map(|&sample| (sample as i16).to_le_bytes()).collect();
+
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    assert_eq!(decoder.md5_hex(), None);
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.md5_hex(), Some(format!("{:x}", md5::compute(&pcm))));
+
+    // All zero means the MD5 signature is unknown.
+    let stream_info = metadata::FlacStreamInfo {md5sum: [0; 16], ..*decoder.get_stream_info().unwrap()};
+    assert_eq!(stream_info.md5_hex(), None);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_batch_frames() {
+    use std::{cell::RefCell, io::{self, Cursor}};
+    use closure_objects::{SamplesInfo, FlacInternalDecoderError};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let params = options::FlacEncoderParams {block_size: Some(1024), ..options::FlacEncoderParams::new()};
+    let flac = encode_to_memory(&params, &samples, |_| {});
+
+    // 43 full frames and the last frame of 68 samples, in the batches of 10 frames.
+    let expected_calls = [10240, 10240, 10240, 10240, 3 * 1024 + 68];
+    for audio_form in [options::FlacAudioForm::FrameArray, options::FlacAudioForm::ChannelArray] {
+        let calls = RefCell::new(Vec::new());
+        let decoded = RefCell::new(Vec::new());
+        let mut decoder = FlacDecoder::with_default_io(
+            Cursor::new(&flac[..]),
+            Box::new(|frames: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+                assert_eq!(frames.len(), samples_info.outer_len());
+                calls.borrow_mut().push(samples_info.samples);
+                decoded.borrow_mut().extend(samples_info.frames_in(frames).flatten());
+                Ok(())
+            }),
+            record_errors(&errors),
+            true,
+            false,
+            audio_form,
+        ).unwrap();
+        decoder.set_batch_frames(10);
+        decoder.decode_all().into_result().unwrap();
+        decoder.finish().unwrap();
+        drop(decoder);
+        assert_eq!(calls.into_inner(), expected_calls);
+        assert_eq!(decoded.into_inner(), samples);
+    }
+
+    // The remainder is passed by `finish()` if the decoding stopped early.
+    let calls = RefCell::new(Vec::new());
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, samples_info| {
+        calls.borrow_mut().push(samples_info.samples);
+        Ok(())
+    }));
+    decoder.set_batch_frames(10);
+    while !matches!(decoder.decode().unwrap(), DecodeStep::AudioFrame{..}) {}
+    decoder.decode().unwrap();
+    decoder.decode().unwrap();
+    assert!(calls.borrow().is_empty());
+    // The MD5 checking fails since the stream wasn't decoded to the end.
+    let _ = decoder.finish();
+    drop(decoder);
+    assert_eq!(calls.into_inner(), [3072]);
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_frame_crcs() {
+    use std::io::Cursor;
+    use closure_objects::FlacInternalDecoderError;
+
+    let samples = sine_wave_stereo_16();
+    let params = options::FlacEncoderParams {block_size: Some(1024), ..options::FlacEncoderParams::new()};
+    let flac = encode_to_memory(&params, &samples, |_| {});
+    let frame_crcs = |flac: &[u8]| -> Vec<(u64, u16)> {
+        let mut decoder = FlacDecoder::with_default_io(
+            Cursor::new(flac),
+            Box::new(|_, _| Ok(())),
+            Box::new(|_error: FlacInternalDecoderError| {}),
+            false,
+            false,
+            options::FlacAudioForm::FrameArray,
+        ).unwrap();
+        decoder.set_collect_frame_crcs(true);
+        decoder.decode_all().into_result().unwrap();
+        decoder.frame_crcs().to_vec()
+    };
+
+    // The CRC-16 of each frame is its footer, the list is the same on every decoding.
+    let crcs = frame_crcs(&flac);
+    assert_eq!(crcs.len(), 44);
+    assert!(crcs.iter().enumerate().all(|(i, (first_sample, _))| *first_sample == i as u64 * 1024));
+    let (_, last_crc) = crcs.last().unwrap();
+    assert_eq!(last_crc.to_be_bytes(), flac[flac.len() - 2..]);
+    assert_eq!(frame_crcs(&flac), crcs);
+
+    // Only the CRC-16 of the corrupted frame changes.
+    let mut corrupted = flac.clone();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] ^= 0x55;
+    let corrupted_crcs = frame_crcs(&corrupted);
+    assert_eq!(corrupted_crcs.len(), crcs.len());
+    assert_eq!(crcs.iter().zip(&corrupted_crcs).filter(|(crc, corrupted_crc)| crc != corrupted_crc).count(), 1);
+
+    // The `reader` that can't seek gives the same list, the frames aren't read again.
+    for flac in [&flac, &corrupted] {
+        let length = flac.len() as u64;
+        let mut decoder = FlacDecoder::new(
+            Cursor::new(&flac[..]),
+            Box::new(|reader: &mut Cursor<&[u8]>, data: &mut [u8]| -> (usize, closure_objects::FlacReadStatus) {
+                match std::io::Read::read(reader, data) {
+                    Ok(0) => (0, closure_objects::FlacReadStatus::Eof),
+                    Ok(size) => (size, closure_objects::FlacReadStatus::GoOn),
+                    Err(_) => (0, closure_objects::FlacReadStatus::Abort),
+                }
+            }),
+            Box::new(|_reader: &mut Cursor<&[u8]>, _position: u64| -> Result<(), std::io::Error> {
+                Err(std::io::ErrorKind::NotSeekable.into())
+            }),
+            Box::new(|reader: &mut Cursor<&[u8]>| -> Result<u64, std::io::Error> {
+                Ok(reader.position())
+            }),
+            Box::new(move |_reader: &mut Cursor<&[u8]>| -> Result<u64, std::io::Error> {
+                Ok(length)
+            }),
+            Box::new(move |reader: &mut Cursor<&[u8]>| -> bool {
+                reader.position() >= length
+            }),
+            Box::new(|_, _| Ok(())),
+            Box::new(|_error: FlacInternalDecoderError| {}),
+            false,
+            false,
+            options::FlacAudioForm::FrameArray,
+        ).unwrap();
+        decoder.set_collect_frame_crcs(true);
+        decoder.set_io_stats_enabled(true);
+        decoder.decode_all().into_result().unwrap();
+        assert_eq!(decoder.frame_crcs(), frame_crcs(flac));
+        let stats = *decoder.io_stats().unwrap();
+        assert_eq!(stats.seeks, 0);
+        assert_eq!(stats.bytes_read, length);
+    }
+}
+
+#[test]
+fn test_ogg_container() {
+    use std::io::{self, Cursor, Write, Seek, SeekFrom};
+    use options::{FlacEncoderParams, FlacContainer};
+
+    let samples = sine_wave_stereo_16();
+    let encode = |params: &FlacEncoderParams| -> Result<(Vec<u8>, bool), errors::FlacEncoderError> {
+        let mut cursor = Cursor::new(Vec::new());
+        let header_rewritten = {
+            let mut encoder = FlacEncoder::new(
+                &mut cursor,
+                Box::new(|writer: &mut &mut Cursor<Vec<u8>>, data: &[u8]| -> Result<(), io::Error> {writer.write_all(data)}),
+                Box::new(|writer: &mut &mut Cursor<Vec<u8>>, position: u64| -> Result<(), io::Error> {writer.seek(SeekFrom::Start(position)).map(|_| ())}),
+                Box::new(|writer: &mut &mut Cursor<Vec<u8>>| -> Result<u64, io::Error> {writer.stream_position()}),
+                params,
+            )?;
+            encoder.initialize()?;
+            encoder.write_interleaved_samples(&samples)?;
+            encoder.finish()?;
+            encoder.finish_report().unwrap().header_rewritten
+        };
+        Ok((cursor.into_inner(), header_rewritten))
+    };
+
+    // The native FLAC file is still the default.
+    assert_eq!(FlacEncoderParams::new().container, FlacContainer::Native);
+    assert_eq!(&encode(&FlacEncoderParams::new()).unwrap().0[..4], b"fLaC");
+
+    let params = FlacEncoderParams {container: FlacContainer::Ogg, ogg_serial_number: Some(0x12345678), ..FlacEncoderParams::new()};
+    if cfg!(feature = "ogg") {
+        // The Ogg pages wrap the FLAC stream. Without the spool, libFLAC can't read the STREAMINFO page back, so it's left unrewritten.
+        let (ogg, header_rewritten) = encode(&params).unwrap();
+        assert_eq!(&ogg[..4], b"OggS");
+        assert!(ogg.windows(4).any(|window| window == b"fLaC"));
+        assert!(!header_rewritten);
+
+        // The spool lets libFLAC rewrite the first page, which holds the STREAMINFO block, the other pages stay the same.
+        let (buffered, header_rewritten) = encode(&FlacEncoderParams {buffer_for_header: true, ..params}).unwrap();
+        assert!(header_rewritten);
+        let first_page_len = 27 + ogg[26] as usize + ogg[27..27 + ogg[26] as usize].iter().map(|&len| len as usize).sum::<usize>();
+        assert_eq!(buffered.len(), ogg.len());
+        assert_ne!(buffered[..first_page_len], ogg[..first_page_len]);
+        assert_eq!(buffered[first_page_len..], ogg[first_page_len..]);
+
+        // Every page carries the serial number, a random one is picked for each stream without it.
+        let serial_number = |ogg: &[u8]| u32::from_le_bytes(ogg[14..18].try_into().unwrap());
+        assert_eq!(serial_number(&ogg), 0x12345678);
+        assert_eq!(serial_number(&ogg[first_page_len..]), 0x12345678);
+        let random_params = FlacEncoderParams {ogg_serial_number: None, ..params};
+        assert_ne!(serial_number(&encode(&random_params).unwrap().0), serial_number(&encode(&random_params).unwrap().0));
+    } else {
+        assert_eq!(encode(&params).unwrap_err().code, libflac_sys::FLAC__STREAM_ENCODER_INIT_STATUS_UNSUPPORTED_CONTAINER);
+    }
+}
+
+#[test]
+fn test_stream_info_getters() {
+    use std::{fs::File, io::BufReader, time::Duration};
+    let errors = DecoderErrors::default();
+
+    let mut decoder = FlacDecoder::with_default_io(
+        BufReader::new(File::open("test.flac").unwrap()),
+        Box::new(|_, _| Ok(())),
+        record_errors(&errors),
+        true,
+        false,
+        options::FlacAudioForm::FrameArray,
+    ).unwrap();
+    assert_eq!((decoder.sample_rate(), decoder.channels(), decoder.bits_per_sample(), decoder.duration()), (None, None, None, None));
+    decoder.decode_metadata().unwrap();
+    assert_eq!(decoder.sample_rate(), Some(44100));
+    assert_eq!(decoder.channels(), Some(2));
+    assert_eq!(decoder.bits_per_sample(), Some(16));
+
+    // `flac --show-total-samples test.flac` reports 15744.
+    assert_eq!(decoder.get_stream_info().unwrap().total_samples, 15744);
+    assert_eq!(decoder.duration(), Some(Duration::from_nanos(15744 * 1_000_000_000 / 44100)));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_total_samples() {
+    use std::cell::Cell;
+    let errors = DecoderErrors::default();
+
+    let params = options::FlacEncoderParams {block_size: Some(1024), ..options::FlacEncoderParams::new()};
+    let samples = sine_wave_stereo_16();
+    let mut flac = encode_to_memory(&params, &samples, |encoder| {
+        encoder.add_picture_of_type(&[2u8; 100], metadata::FlacPictureType::FrontCover, "", "image/jpeg", 1, 1, 24, 0).unwrap();
+    });
+    let total_samples = samples.len() as u64 / 2;
+
+    // The STREAMINFO block knows it.
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    assert_eq!(decoder.total_samples().unwrap(), total_samples);
+    assert!(decoder.decode_all().is_completed());
+    decoder.finish().unwrap();
+    drop(decoder);
+
+    // The streaming-origin FLAC file without the total samples in the STREAMINFO block is counted, before or after some frames were decoded.
+    let total_samples_offset = 4 + 4 + 13;
+    flac[total_samples_offset] &= 0xF0;
+    flac[total_samples_offset + 1..total_samples_offset + 5].fill(0);
+    for frames_before in [0, 3] {
+        let decoded = Cell::new(0u64);
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|frames: &[Vec<i32>], _| {
+            decoded.set(decoded.get() + frames.len() as u64);
+            Ok(())
+        }));
+        decoder.decode_metadata().unwrap();
+        for _ in 0..frames_before {
+            decoder.decode().unwrap();
+        }
+        assert_eq!(decoder.total_samples().unwrap(), total_samples);
+        assert_eq!(decoder.total_samples().unwrap(), total_samples);
+
+        // The decoding continues where it was.
+        assert!(decoder.decode_all().is_completed());
+        assert_eq!(decoded.get(), total_samples);
+        assert_eq!(decoder.get_pictures().len(), 1);
+        decoder.finish().unwrap();
+    }
+
+    // Counting before any frame was decoded keeps the MD5 checking, the wrong MD5 is still caught.
+    let md5_offset = 4 + 4 + 18;
+    flac[md5_offset] ^= 0xFF;
+    for (frames_before, md5_checked) in [(0, true), (3, false)] {
+        let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+        decoder.decode_metadata().unwrap();
+        for _ in 0..frames_before {
+            decoder.decode().unwrap();
+        }
+        assert_eq!(decoder.total_samples().unwrap(), total_samples);
+        assert!(decoder.decode_all().is_completed());
+        assert_eq!(decoder.finish().is_err(), md5_checked);
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_replay_gain_session() {
+    use std::io::Cursor;
+    let errors = DecoderErrors::default();
+
+    // The noise of the LCG of `rand()` in C, so that the reference gains are computed on the same samples.
+    let noise = |seed: u32, divisor: i32, frames: usize| -> Vec<i32> {
+        let mut state = seed;
+        (0..frames * 2).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((state >> 16) & 0x7FFF) as i32 - 16384
+        }).map(|sample| sample / divisor).collect()
+    };
+    let encode = |samples: &[i32], channels: u16, sample_rate: u32| {
+        encode_to_memory(&options::FlacEncoderParams {channels, sample_rate, ..options::FlacEncoderParams::new()}, samples, |_| {})
+    };
+    // A loud noise, a 60 Hz triangle wave where the equal-loudness filter matters most, a quiet noise at 96 kHz that's filtered as 48 kHz, and a track shorter than a window.
+    let triangle: Vec<i32> = (0..96000).map(|i| {let t = i % 800; if t < 400 {t * 80 - 16000} else {48000 - t * 80}}).collect();
+    let tracks = [
+        encode(&noise(1, 1, 44100 * 3), 2, 44100),
+        encode(&triangle, 1, 48000),
+        encode(&noise(7, 8, 96000 * 2), 2, 96000),
+        encode(&noise(5, 1, 882), 2, 44100),
+    ];
+
+    let mut session = ReplayGainSession::new();
+    assert_eq!(session.album_gain(), None);
+    let gains: Vec<Option<TrackGain>> = tracks.iter().map(|track| session.analyze(Cursor::new(track)).unwrap()).collect();
+    assert_eq!(session.tracks(), gains);
+
+    // The gains of `replaygain_analysis.c` of FLAC 1.4, which `metaflac --add-replay-gain` uses, for the same samples.
+    for (track_gain, reference) in gains.iter().zip([-5.85, 7.61, 12.50]) {
+        assert!((track_gain.unwrap().gain - reference).abs() < 0.005, "{gains:?}");
+    }
+    assert_eq!(gains[1].unwrap().peak, 16000.0 / 32768.0);
+    assert_eq!(gains[3], None);
+
+    // The album gain of the tracks of one sample rate, and its reference.
+    let mut album = ReplayGainSession::new();
+    album.analyze(Cursor::new(&tracks[0])).unwrap();
+    let quiet = album.analyze(Cursor::new(encode(&noise(3, 4, 44100 * 2), 2, 44100))).unwrap().unwrap();
+    assert!((quiet.gain - 6.10).abs() < 0.005, "{quiet:?}");
+    assert!((album.album_gain().unwrap().gain - -5.81).abs() < 0.005, "{:?}", album.album_gain());
+
+    // The mixed sample rates count all of the windows together, the peak is the highest of the tracks, even the short one.
+    let album_gain = session.album_gain().unwrap();
+    assert!(album_gain.gain > gains[0].unwrap().gain && album_gain.gain < gains[2].unwrap().gain, "{album_gain:?}");
+    assert!(album_gain.peak >= gains.iter().flatten().map(|track_gain| track_gain.peak).fold(0.0, f64::max), "{album_gain:?}");
+
+    // The sample rates without the filter are refused.
+    let unsupported = encode(&noise(1, 1, 50000), 2, 50000);
+    assert_eq!(ReplayGainSession::new().analyze(Cursor::new(&unsupported)).unwrap_err().code, errors::FLAC_DECODER_UNSUPPORTED_FORMAT);
+
+    // Tag the second track, the short track has no gain to tag.
+    let samples = vec![0i32; 2048];
+    let flac = encode_to_memory(&options::FlacEncoderParams::new(), &samples, |encoder| {
+        session.apply(encoder, 1).unwrap();
+        assert!(session.apply(encoder, 3).is_err());
+        assert!(session.apply(encoder, 4).is_err());
+    });
+    let mut decoder = decoder_from_memory(&flac, &errors, Box::new(|_, _| Ok(())));
+    decoder.decode_metadata().unwrap();
+    let comments = decoder.get_comments();
+    assert_eq!(comments["REPLAYGAIN_TRACK_GAIN"], "+7.61 dB");
+    assert_eq!(comments["REPLAYGAIN_TRACK_PEAK"], "0.48828125");
+    assert_eq!(comments["REPLAYGAIN_ALBUM_GAIN"], format!("{:+.2} dB", album_gain.gain));
+    assert_eq!(comments["REPLAYGAIN_ALBUM_PEAK"], format!("{:.8}", album_gain.peak));
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_is_streamable_subset() {
+    let samples = sine_wave_stereo_16();
+    encode_to_memory_with(&options::FlacEncoderParams::new(), |_| {}, |encoder| {
+        assert!(encoder.is_streamable_subset());
+        encoder.write_interleaved_samples(&samples).unwrap();
+    });
+
+    // The block size over 4608 at 44.1 kHz is out of the subset, the encoder refuses to start.
+    let params = options::FlacEncoderParams {block_size: Some(8192), ..options::FlacEncoderParams::new()};
+    let mut cursor = std::io::Cursor::new(Vec::<u8>::new());
+    let mut encoder = FlacEncoder::new(
+        &mut cursor,
+        Box::new(|_, _| Ok(())),
+        Box::new(|_, _| Ok(())),
+        Box::new(|_| Ok(0)),
+        &params
+    ).unwrap();
+    assert!(encoder.is_streamable_subset());
+    assert_eq!(encoder.initialize().unwrap_err().code, libflac_sys::FLAC__STREAM_ENCODER_INIT_STATUS_NOT_STREAMABLE);
+}
+
+#[test]
+fn test_ogg_decoding() {
+    use std::cell::RefCell;
+    use options::{FlacEncoderParams, FlacContainer};
+    let errors = DecoderErrors::default();
+
+    let samples = sine_wave_stereo_16();
+    let decode = |flac: &[u8], container: FlacContainer| -> Result<Vec<i32>, errors::FlacDecoderError> {
+        let decoded = RefCell::new(Vec::new());
+        let mut decoder = decoder_from_memory(flac, &errors, Box::new(|frames: &[Vec<i32>], _| {
+            decoded.borrow_mut().extend(frames.iter().flatten());
+            Ok(())
+        }));
+        assert_eq!(decoder.get_container(), FlacContainer::Native);
+        decoder.set_container(container)?;
+        assert_eq!(decoder.get_container(), container);
+        assert!(decoder.decode_all().is_completed());
+        decoder.finish()?;
+        drop(decoder);
+        Ok(decoded.into_inner())
+    };
+
+    let native = encode_to_memory(&FlacEncoderParams::new(), &samples, |_| {});
+    assert_eq!(decode(&native, FlacContainer::Native).unwrap(), samples);
+
+    if cfg!(feature = "ogg") {
+        // The same audio in the Ogg container decodes to the same PCM.
+        let ogg = encode_to_memory(&FlacEncoderParams {container: FlacContainer::Ogg, buffer_for_header: true, ..FlacEncoderParams::new()}, &samples, |_| {});
+        assert_eq!(&ogg[..4], b"OggS");
+        assert_eq!(decode(&ogg, FlacContainer::Ogg).unwrap(), decode(&native, FlacContainer::Native).unwrap());
+    } else {
+        assert_eq!(decode(&native, FlacContainer::Ogg).unwrap_err().code, libflac_sys::FLAC__STREAM_DECODER_INIT_STATUS_UNSUPPORTED_CONTAINER);
+    }
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
+
+#[test]
+fn test_open() {
+    use std::{cell::RefCell, fs::File, io::{self, BufReader}};
+    let errors = DecoderErrors::default();
+
+    let decode = |mut decoder: FlacDecoder<'_, BufReader<File>>| {
+        assert!(decoder.decode_all().is_completed());
+        decoder.finish().unwrap();
+    };
+    let opened = RefCell::new(Vec::<i32>::new());
+    decode(FlacDecoder::open("test.flac", true, false, options::FlacAudioForm::FrameArray, Box::new(|frames: &[Vec<i32>], _| {
+        opened.borrow_mut().extend(frames.iter().flatten());
+        Ok(())
+    }), record_errors(&errors)).unwrap());
+
+    // The same as the decoder with the closures over the buffered file.
+    let expected = RefCell::new(Vec::<i32>::new());
+    decode(FlacDecoder::with_default_io(BufReader::new(File::open("test.flac").unwrap()), Box::new(|frames: &[Vec<i32>], _| {
+        expected.borrow_mut().extend(frames.iter().flatten());
+        Ok(())
+    }), record_errors(&errors), true, false, options::FlacAudioForm::FrameArray).unwrap());
+    let opened = opened.into_inner();
+    assert_eq!(opened.len(), 15744 * 2);
+    assert_eq!(opened, expected.into_inner());
+
+    let error = FlacDecoder::open("no such file.flac", true, false, options::FlacAudioForm::FrameArray, Box::new(|_, _| Ok(())), Box::new(|_| {})).unwrap_err();
+    assert_eq!(error.code, errors::FLAC_DECODER_OPEN_FAILED);
+    assert_eq!(error.io_error_kind, Some(io::ErrorKind::NotFound));
+    assert_eq!(error.function, "FlacDecoder::open");
+    assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+}
@@ -0,0 +1,140 @@
+#![allow(dead_code)]
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::flac::{FlacStreamInfo, FlacPictureType, PictureData, FlacCueSheet, FlacApplication, to_hex};
+
+/// ## All of the metadata read from the FLAC file in one place, see `FlacDecoder::metadata_report()`
+/// * It's for the "show tags" commands, `Display` prints it line by line, or serialize it with the `serde` feature.
+/// * The picture data isn't kept in the report, only the sizes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MetadataReport {
+    /// * The STREAMINFO block, `None` if it wasn't read.
+    pub stream_info: Option<FlacStreamInfo>,
+
+    /// * The vendor string of the Vorbis comment block.
+    pub vendor_string: Option<String>,
+
+    /// * The comments ordered by their keys.
+    pub comments: Vec<(String, String)>,
+
+    /// * The pictures in the order of the file.
+    pub pictures: Vec<PictureSummary>,
+
+    /// * The first cue sheet, the FLAC file should have no more than one.
+    pub cue_sheet: Option<CueSheetSummary>,
+
+    /// * The number of the seek points, without the placeholder points.
+    pub seek_points: usize,
+
+    /// * The IDs of the APPLICATION blocks as strings, see `FlacApplication::id_str()`.
+    pub applications: Vec<String>,
+}
+
+/// ## A picture in the `MetadataReport`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PictureSummary {
+    pub picture_type: FlacPictureType,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+
+    /// * The size of the picture data in bytes.
+    pub size: usize,
+}
+
+/// ## The cue sheet in the `MetadataReport`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CueSheetSummary {
+    pub media_catalog_number: String,
+    pub lead_in: u64,
+    pub is_cd: bool,
+
+    /// * The number of tracks, including the lead-out track.
+    pub tracks: usize,
+}
+
+impl From<&PictureData> for PictureSummary {
+    fn from(picture: &PictureData) -> Self {
+        Self {
+            picture_type: picture.picture_type,
+            mime_type: picture.mime_type.clone(),
+            description: picture.description.clone(),
+            width: picture.width,
+            height: picture.height,
+            size: picture.picture.len(),
+        }
+    }
+}
+
+impl From<&FlacCueSheet> for CueSheetSummary {
+    fn from(cue_sheet: &FlacCueSheet) -> Self {
+        Self {
+            media_catalog_number: cue_sheet.get_media_catalog_number(),
+            lead_in: cue_sheet.lead_in,
+            is_cd: cue_sheet.is_cd,
+            tracks: cue_sheet.tracks.len(),
+        }
+    }
+}
+
+impl MetadataReport {
+    pub(crate) fn new(
+        stream_info: Option<FlacStreamInfo>,
+        vendor_string: Option<String>,
+        comments: impl IntoIterator<Item = (String, String)>,
+        pictures: &[PictureData],
+        cue_sheets: &[FlacCueSheet],
+        seek_points: usize,
+        applications: &[FlacApplication],
+    ) -> Self {
+        Self {
+            stream_info,
+            vendor_string,
+            comments: comments.into_iter().collect(),
+            pictures: pictures.iter().map(PictureSummary::from).collect(),
+            cue_sheet: cue_sheets.first().map(CueSheetSummary::from),
+            seek_points,
+            applications: applications.iter().map(FlacApplication::id_str).collect(),
+        }
+    }
+}
+
+impl Display for MetadataReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(stream_info) = &self.stream_info {
+            writeln!(f, "Sample rate: {} Hz", stream_info.sample_rate)?;
+            writeln!(f, "Channels: {}", stream_info.channels)?;
+            writeln!(f, "Bits per sample: {}", stream_info.bits_per_sample)?;
+            match stream_info.known_total_samples() {
+                Some(total_samples) => writeln!(f, "Total samples: {total_samples}")?,
+                None => writeln!(f, "Total samples: unknown")?,
+            }
+            writeln!(f, "Block size: {}-{}", stream_info.min_blocksize, stream_info.max_blocksize)?;
+            writeln!(f, "MD5: {}", to_hex(&stream_info.md5sum))?;
+        }
+        if let Some(vendor_string) = &self.vendor_string {
+            writeln!(f, "Vendor: {vendor_string}")?;
+        }
+        for (key, value) in self.comments.iter() {
+            writeln!(f, "{key}={value}")?;
+        }
+        for picture in self.pictures.iter() {
+            writeln!(f, "Picture: {:?}, {}, {}x{}, {} bytes", picture.picture_type, picture.mime_type, picture.width, picture.height, picture.size)?;
+        }
+        if let Some(cue_sheet) = &self.cue_sheet {
+            writeln!(f, "Cue sheet: {} tracks, lead-in {}, CD: {}, catalog number \"{}\"", cue_sheet.tracks, cue_sheet.lead_in, cue_sheet.is_cd, cue_sheet.media_catalog_number)?;
+        }
+        if self.seek_points > 0 {
+            writeln!(f, "Seek points: {}", self.seek_points)?;
+        }
+        for id in self.applications.iter() {
+            writeln!(f, "Application: {id}")?;
+        }
+        Ok(())
+    }
+}
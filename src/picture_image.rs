@@ -0,0 +1,91 @@
+#![allow(dead_code)]
+
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::Cursor,
+};
+
+use image::{ColorType, DynamicImage, GenericImageView, ImageError, ImageOutputFormat};
+
+use crate::flac::{PictureData, FlacPictureType};
+
+/// * The MIME type of the picture that is a URL of the image instead of the image itself.
+const LINK_MIME_TYPE: &str = "-->";
+
+/// ## Why `PictureData::decode_image()` failed
+#[derive(Debug)]
+pub enum PictureDecodeError {
+    /// * The picture is a URL of the image, its MIME type is `-->`, the URL is the `picture` bytes.
+    Link,
+
+    /// * The `image` crate can't decode the picture, e.g. the format isn't supported or the data is corrupted.
+    Image(ImageError),
+}
+
+impl Display for PictureDecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Link => write!(f, "The picture is a link, not an image"),
+            Self::Image(error) => write!(f, "Image error: {error}"),
+        }
+    }
+}
+
+impl Error for PictureDecodeError {}
+
+impl From<ImageError> for PictureDecodeError {
+    fn from(error: ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl PictureData {
+    /// * Decode the picture into an image by the `image` crate, the format is detected from the data, not from the MIME type.
+    pub fn decode_image(&self) -> Result<DynamicImage, PictureDecodeError> {
+        if self.mime_type == LINK_MIME_TYPE {
+            return Err(PictureDecodeError::Link);
+        }
+        Ok(image::load_from_memory(&self.picture)?)
+    }
+
+    /// ## Encode the `image` in the `format` into the picture, the MIME type, the width, the height and the color depth are filled from it
+    /// * The JPEG has no alpha channel nor 16-bit samples, the other images are converted to the 8-bit RGB before being encoded as JPEG.
+    /// * Add it to the encoder by `FlacEncoderUnmovable::add_picture_of_type()` with its fields.
+    /// * e.g. Re-encode the oversized cover art to the 500px JPEG before embedding it:
+    /// ```no_run
+    /// use flac::metadata::{PictureData, FlacPictureType};
+    /// use image::{imageops::FilterType, ImageOutputFormat};
+    ///
+    /// let cover = image::open("cover.png").unwrap();
+    /// let cover = if cover.width() > 500 || cover.height() > 500 {
+    ///     cover.resize(500, 500, FilterType::Lanczos3)
+    /// } else {
+    ///     cover
+    /// };
+    /// let picture = PictureData::from_image(&cover, ImageOutputFormat::Jpeg(90), FlacPictureType::FrontCover, "Cover").unwrap();
+    /// assert_eq!(picture.mime_type, "image/jpeg");
+    /// ```
+    pub fn from_image(image: &DynamicImage, format: ImageOutputFormat, picture_type: FlacPictureType, description: &str) -> Result<Self, ImageError> {
+        let image = match format {
+            ImageOutputFormat::Jpeg(_) if !matches!(image.color(), ColorType::L8 | ColorType::Rgb8) => Cow::Owned(DynamicImage::ImageRgb8(image.to_rgb8())),
+            _ => Cow::Borrowed(image),
+        };
+        let mut picture = Cursor::new(Vec::new());
+        image.write_to(&mut picture, format)?;
+        let picture = picture.into_inner();
+        let mime_type = image::guess_format(&picture).map_or("application/octet-stream", |format| format.to_mime_type());
+        let (width, height) = image.dimensions();
+        Ok(Self {
+            mime_type: mime_type.to_owned(),
+            picture,
+            picture_type,
+            description: description.to_owned(),
+            width,
+            height,
+            depth: image.color().bits_per_pixel() as u32,
+            colors: 0,
+        })
+    }
+}
@@ -0,0 +1,142 @@
+#![allow(dead_code)]
+
+use crate::flac::{FlacDecoderError, FlacAudioForm, SamplesInfo};
+
+/// ## What `Reblocker` does with the final chunk if it's shorter than the chunk size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TailPolicy {
+    /// * Deliver the short chunk as it is.
+    #[default]
+    Deliver,
+
+    /// * Fill the rest of the chunk with silence.
+    ZeroPad,
+
+    /// * Drop the short chunk.
+    Drop,
+}
+
+/// ## Re-block the decoded audio into the chunks of exactly `frames_per_chunk` audio frames, e.g. for the DSP callbacks.
+/// * The source is the audio frames of different block sizes, e.g. from `FlacDecoder::audio_frames()`.
+/// * The chunks are in the same audio form as the source, with the samples info of the chunk. See `TailPolicy` for the final chunk.
+/// * The iteration stops after an error of the source, the pending audio frames are dropped.
+#[derive(Debug)]
+pub struct Reblocker<I>
+where
+    I: Iterator<Item = Result<(Vec<Vec<i32>>, SamplesInfo), FlacDecoderError>> {
+    source: I,
+    frames_per_chunk: usize,
+    tail_policy: TailPolicy,
+
+    /// * The pending samples of each channel
+    pending: Vec<Vec<i32>>,
+
+    /// * The samples info of the last audio frame from the source
+    samples_info: Option<SamplesInfo>,
+    ended: bool,
+}
+
+impl<I> Reblocker<I>
+where
+    I: Iterator<Item = Result<(Vec<Vec<i32>>, SamplesInfo), FlacDecoderError>> {
+    /// * `frames_per_chunk` must not be zero.
+    pub fn new<S>(source: S, frames_per_chunk: usize) -> Self
+    where
+        S: IntoIterator<IntoIter = I> {
+        assert!(frames_per_chunk > 0, "The chunk size must not be zero.");
+        Self {
+            source: source.into_iter(),
+            frames_per_chunk,
+            tail_policy: TailPolicy::default(),
+            pending: Vec::new(),
+            samples_info: None,
+            ended: false,
+        }
+    }
+
+    pub fn set_tail_policy(&mut self, tail_policy: TailPolicy) {
+        self.tail_policy = tail_policy;
+    }
+
+    pub fn get_tail_policy(&self) -> TailPolicy {
+        self.tail_policy
+    }
+
+    /// * How many audio frames are waiting to fill the next chunk
+    pub fn pending_frames(&self) -> usize {
+        self.pending.first().map_or(0, Vec::len)
+    }
+
+    fn push(&mut self, samples: &[Vec<i32>], samples_info: &SamplesInfo) {
+        let channels = samples_info.channels as usize;
+        if self.pending.len() != channels {
+            self.pending.resize_with(channels, Vec::new);
+        }
+        match samples_info.audio_form {
+            FlacAudioForm::FrameArray => for frame in samples.iter() {
+                for (channel, sample) in self.pending.iter_mut().zip(frame.iter()) {
+                    channel.push(*sample);
+                }
+            },
+            FlacAudioForm::ChannelArray => for (channel, samples) in self.pending.iter_mut().zip(samples.iter()) {
+                channel.extend_from_slice(samples);
+            },
+        }
+        self.samples_info = Some(*samples_info);
+    }
+
+    fn take_chunk(&mut self, frames: usize) -> (Vec<Vec<i32>>, SamplesInfo) {
+        let samples_info = SamplesInfo {
+            samples: frames as u32,
+            ..self.samples_info.unwrap()
+        };
+        let channels: Vec<Vec<i32>> = self.pending.iter_mut().map(|channel| channel.drain(..frames).collect()).collect();
+        let chunk = match samples_info.audio_form {
+            FlacAudioForm::FrameArray => (0..frames).map(|i| channels.iter().map(|channel| channel[i]).collect()).collect(),
+            FlacAudioForm::ChannelArray => channels,
+        };
+        (chunk, samples_info)
+    }
+}
+
+impl<I> Iterator for Reblocker<I>
+where
+    I: Iterator<Item = Result<(Vec<Vec<i32>>, SamplesInfo), FlacDecoderError>> {
+    type Item = Result<(Vec<Vec<i32>>, SamplesInfo), FlacDecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let pending_frames = self.pending_frames();
+            if pending_frames >= self.frames_per_chunk {
+                return Some(Ok(self.take_chunk(self.frames_per_chunk)));
+            }
+            if self.ended {
+                if pending_frames == 0 {
+                    return None;
+                }
+                return match self.tail_policy {
+                    TailPolicy::Deliver => Some(Ok(self.take_chunk(pending_frames))),
+                    TailPolicy::ZeroPad => {
+                        for channel in self.pending.iter_mut() {
+                            channel.resize(self.frames_per_chunk, 0);
+                        }
+                        Some(Ok(self.take_chunk(self.frames_per_chunk)))
+                    }
+                    TailPolicy::Drop => {
+                        self.pending.clear();
+                        None
+                    }
+                };
+            }
+            match self.source.next() {
+                Some(Ok((samples, samples_info))) => self.push(&samples, &samples_info),
+                Some(Err(e)) => {
+                    self.ended = true;
+                    self.pending.clear();
+                    return Some(Err(e));
+                }
+                None => self.ended = true,
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    io::{self, Read, Seek, Write},
+};
+
+use libflac_sys::*;
+
+use crate::flac::{FlacEncoderUnmovable, FlacEncoderInitError};
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo, DecodeOutcome, FLAC_DECODER_UNSUPPORTED_FORMAT};
+
+/// * The loudness of the pink noise reference in dB, the gain brings the audio to it.
+const PINK_REF: f64 = 64.82;
+
+/// * How many histogram bins per dB.
+const STEPS_PER_DB: f64 = 100.0;
+
+/// * The loudest window measured in dB.
+const MAX_DB: f64 = 120.0;
+
+/// * The loudness of the track is where this percent of the windows are quieter.
+const RMS_PERCENTILE: u64 = 95;
+
+/// * The length of each window in milliseconds.
+const RMS_WINDOW_MS: u32 = 50;
+
+/// ## The ReplayGain of a track or an album
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackGain {
+    /// * The gain in dB to bring the audio to the reference loudness, negative for the loud audio.
+    pub gain: f64,
+
+    /// * The highest absolute sample value, 1.0 is the full scale.
+    pub peak: f64,
+}
+
+/// * The loudness histogram of the windows and the peak, for one track or the album.
+#[derive(Debug, Clone)]
+struct Loudness {
+    histogram: Vec<u32>,
+    peak: f64,
+}
+
+impl Loudness {
+    fn new() -> Self {
+        Self {
+            histogram: vec![0; (MAX_DB * STEPS_PER_DB) as usize],
+            peak: 0.0,
+        }
+    }
+
+    fn push_window(&mut self, mean_square: f64) {
+        let bin = ((STEPS_PER_DB * 10.0 * (mean_square + 1e-37).log10()).max(0.0) as usize).min(self.histogram.len() - 1);
+        self.histogram[bin] += 1;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (bin, count) in self.histogram.iter_mut().zip(other.histogram.iter()) {
+            *bin += count;
+        }
+        self.peak = self.peak.max(other.peak);
+    }
+
+    /// * `None` if there isn't a whole window.
+    fn gain(&self) -> Option<TrackGain> {
+        let windows: u64 = self.histogram.iter().map(|&count| count as u64).sum();
+        if windows == 0 {
+            return None;
+        }
+        let mut upper = (windows * (100 - RMS_PERCENTILE)).div_ceil(100) as i64;
+        let mut bin = self.histogram.len();
+        while bin > 0 {
+            bin -= 1;
+            upper -= self.histogram[bin] as i64;
+            if upper <= 0 {
+                break;
+            }
+        }
+        Some(TrackGain {
+            gain: PINK_REF - bin as f64 / STEPS_PER_DB,
+            peak: self.peak,
+        })
+    }
+}
+
+/// * The orders of the two stages of the equal-loudness filter.
+const YULE_ORDER: usize = 10;
+const BUTTER_ORDER: usize = 2;
+
+/// * The equal-loudness filter of ReplayGain 1.0 for a sample rate, a 10th order Yule-Walker filter followed by a 2nd order Butterworth high-pass filter.
+#[derive(Debug)]
+struct EqualLoudnessFilter {
+    sample_rate: u32,
+    yule_b: [f32; YULE_ORDER + 1],
+    yule_a: [f32; YULE_ORDER + 1],
+    butter_b: [f32; BUTTER_ORDER + 1],
+    butter_a: [f32; BUTTER_ORDER + 1],
+}
+
+/// * The coefficients of `replaygain_analysis.c` used by `metaflac --add-replay-gain`, as they are.
+#[allow(clippy::excessive_precision)]
+const FILTERS: [EqualLoudnessFilter; 13] = [
+    EqualLoudnessFilter {
+        sample_rate: 48000,
+        yule_b: [0.03857599435200, -0.02160367184185, -0.00123395316851, -0.00009291677959, -0.01655260341619, 0.02161526843274, -0.02074045215285, 0.00594298065125, 0.00306428023191, 0.00012025322027, 0.00288463683916],
+        yule_a: [1.00000000000000, -3.84664617118067, 7.81501653005538, -11.34170355132042, 13.05504219327545, -12.28759895145294, 9.48293806319790, -5.87257861775999, 2.75465861874613, -0.86984376593551, 0.13919314567432],
+        butter_b: [0.98621192462708, -1.97242384925416, 0.98621192462708],
+        butter_a: [1.00000000000000, -1.97223372919527, 0.97261396931306],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 44100,
+        yule_b: [0.05418656406430, -0.02911007808948, -0.00848709379851, -0.00851165645469, -0.00834990904936, 0.02245293253339, -0.02596338512915, 0.01624864962975, -0.00240879051584, 0.00674613682247, -0.00187763777362],
+        yule_a: [1.00000000000000, -3.47845948550071, 6.36317777566148, -8.54751527471874, 9.47693607801280, -8.81498681370155, 6.85401540936998, -4.39470996079559, 2.19611684890774, -0.75104302451432, 0.13149317958808],
+        butter_b: [0.98500175787242, -1.97000351574484, 0.98500175787242],
+        butter_a: [1.00000000000000, -1.96977855582618, 0.97022847566350],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 37800,
+        yule_b: [0.10296717174470, -0.04877975583256, -0.02878009075237, -0.03519509188311, 0.02888717172493, -0.00609872684844, 0.00209851217112, 0.00911704668543, 0.01154404718589, -0.00630293688700, 0.00107527155228],
+        yule_a: [1.00000000000000, -2.64848054923531, 3.58406058405771, -3.83794914179161, 3.90142345804575, -3.50179818637243, 2.67085284083076, -1.82581142372418, 1.09530368139801, -0.47689017820395, 0.11171431535905],
+        butter_b: [0.98252400815195, -1.96504801630391, 0.98252400815195],
+        butter_a: [1.00000000000000, -1.96474258269041, 0.96535344991740],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 36000,
+        yule_b: [0.11572297028613, -0.04120916051252, -0.04977731768022, -0.01047308680426, 0.00750863219157, 0.00055507694408, 0.00140344192886, 0.01286095246036, 0.00998223033885, -0.00725013810661, 0.00326503346879],
+        yule_a: [1.00000000000000, -2.43606802820871, 3.01907406973844, -2.90372016038192, 2.67947188094303, -2.17606479220391, 1.44912956803015, -0.87785765549050, 0.53592202672557, -0.26469344817509, 0.07495878059717],
+        butter_b: [0.98165826840326, -1.96331653680652, 0.98165826840326],
+        butter_a: [1.00000000000000, -1.96298008938934, 0.96365298422371],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 32000,
+        yule_b: [0.15457299681924, -0.09331049056315, -0.06247880153653, 0.02163541888798, -0.05588393329856, 0.04781476674921, 0.00222312597743, 0.03174092540049, -0.01390589421898, 0.00651420667831, -0.00881362733839],
+        yule_a: [1.00000000000000, -2.37898834973084, 2.84868151156327, -2.64577170229825, 2.23697657451713, -1.67148153367602, 1.00595954808547, -0.45953458054983, 0.16378164858596, -0.05032077717131, 0.02347897407020],
+        butter_b: [0.97938932735214, -1.95877865470428, 0.97938932735214],
+        butter_a: [1.00000000000000, -1.95835380975398, 0.95920349965459],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 28000,
+        yule_b: [0.23882392323383, -0.22007791534089, -0.06014581950332, 0.05004458058021, -0.03293111254977, 0.02348678189717, 0.04290549799671, -0.00938141862174, 0.00015095146303, -0.00712601540885, -0.00626520210162],
+        yule_a: [1.00000000000000, -2.06894080899139, 1.76944699577212, -0.81404732584187, 0.25418286850232, -0.30340791669762, 0.35616884070937, -0.14967310591258, -0.07024154183279, 0.11078404345174, -0.03551838002425],
+        butter_b: [0.97647981663949, -1.95295963327897, 0.97647981663949],
+        butter_a: [1.00000000000000, -1.95240635772520, 0.95351290883275],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 24000,
+        yule_b: [0.30296907319327, -0.22613988682123, -0.08587323730772, 0.03282930172664, -0.00915702933434, -0.02364141202522, -0.00584456039913, 0.06276101321749, -0.00000828086748, 0.00205861885564, -0.02950134983287],
+        yule_a: [1.00000000000000, -1.61273165137247, 1.07977492259970, -0.25656257754070, -0.16276719120440, -0.22638893773906, 0.39120800788284, -0.22138138954925, 0.04500235387352, 0.02005851806501, 0.00302439095741],
+        butter_b: [0.97531843204928, -1.95063686409857, 0.97531843204928],
+        butter_a: [1.00000000000000, -1.95002759149878, 0.95124613669835],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 22050,
+        yule_b: [0.33642304856132, -0.25572241425570, -0.11828570177555, 0.11921148675203, -0.07834489609479, -0.00469977914380, -0.00589500224440, 0.05724228140351, 0.00832043980773, -0.01635381384540, -0.01760176568150],
+        yule_a: [1.00000000000000, -1.49858979367799, 0.87350271418188, 0.12205022308084, -0.80774944671438, 0.47854794562326, -0.12453458140019, -0.04067510197014, 0.08333755284107, -0.04237348025746, 0.02977207319925],
+        butter_b: [0.97316523498161, -1.94633046996323, 0.97316523498161],
+        butter_a: [1.00000000000000, -1.94561023566527, 0.94705070426118],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 18900,
+        yule_b: [0.38412657295385, -0.44533729608120, 0.20426638066221, -0.28031676047946, 0.31484202614802, -0.26078311203207, 0.12925201224848, -0.01141164696062, 0.03036522115769, -0.03776339305406, 0.00692036603586],
+        yule_a: [1.00000000000000, -1.74403915585708, 1.96686095832499, -2.10081452941881, 1.90753918182846, -1.83814263754422, 1.36971352214969, -0.77883609116398, 0.39266422457649, -0.12529383592986, 0.05424760697665],
+        butter_b: [0.96535326815829, -1.93070653631658, 0.96535326815829],
+        butter_a: [1.00000000000000, -1.92950577983524, 0.93190729279793],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 16000,
+        yule_b: [0.44915256608450, -0.14351757464547, -0.22784394429749, -0.01419140100551, 0.04078262797139, -0.12398163381748, 0.04097565135648, 0.10478503600251, -0.01863887810927, -0.03193428438915, 0.00541907748707],
+        yule_a: [1.00000000000000, -0.62820619233671, 0.29661783706366, -0.37256372942400, 0.00213767857124, -0.42029820170918, 0.22199650564824, 0.00613424350682, 0.06747620744683, 0.05784820375801, 0.03222754072173],
+        butter_b: [0.96454515552826, -1.92909031105652, 0.96454515552826],
+        butter_a: [1.00000000000000, -1.92783286977036, 0.93034775234268],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 12000,
+        yule_b: [0.56619470757641, -0.75464456939302, 0.16242137742230, 0.16744243493672, -0.18901604199609, 0.30931782841830, -0.27562961986224, 0.00647310677246, 0.08647503780351, -0.03788984554840, -0.00588215443421],
+        yule_a: [1.00000000000000, -1.04800335126349, 0.29156311971249, -0.26806001042947, 0.00819999645858, 0.45054734505008, -0.33032403314006, 0.06739368333110, -0.04784254229033, 0.01639907836189, 0.01807364323573],
+        butter_b: [0.96009142950541, -1.92018285901082, 0.96009142950541],
+        butter_a: [1.00000000000000, -1.91858953033784, 0.92177618768381],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 11025,
+        yule_b: [0.58100494960553, -0.53174909058578, -0.14289799034253, 0.17520704835522, 0.02377945217615, 0.15558449135573, -0.25344790059353, 0.01628462406333, 0.06920467763959, -0.03721611395801, -0.00749618797172],
+        yule_a: [1.00000000000000, -0.51035327095184, -0.31863563325245, -0.20256413484477, 0.14728154134330, 0.38952639978999, -0.23313271880868, -0.05246019024463, -0.02505961724053, 0.02442357316099, 0.01818801111503],
+        butter_b: [0.95856916599601, -1.91713833199203, 0.95856916599601],
+        butter_a: [1.00000000000000, -1.91542108074780, 0.91885558323625],
+    },
+    EqualLoudnessFilter {
+        sample_rate: 8000,
+        yule_b: [0.53648789255105, -0.42163034350696, -0.00275953611929, 0.04267842219415, -0.10214864179676, 0.14590772289388, -0.02459864859345, -0.11202315195388, -0.04060034127000, 0.04788665548180, -0.02217936801134],
+        yule_a: [1.00000000000000, -0.25049871956020, -0.43193942311114, -0.03424681017675, -0.04678328784242, 0.26408300200955, 0.15113130533216, -0.17556493366449, -0.18823009262115, 0.05477720428674, 0.04704409688120],
+        butter_b: [0.94597685600279, -1.89195371200558, 0.94597685600279],
+        butter_a: [1.00000000000000, -1.88903307939452, 0.89487434461664],
+    },
+];
+
+impl EqualLoudnessFilter {
+    /// * The filter for the sample rate and the decimation factor, the same as `metaflac` accepts:
+    ///   the sample rates of `FILTERS`, and the ones above 48 kHz that halve into them, e.g. 96 kHz is filtered as 48 kHz taking every second sample.
+    fn for_sample_rate(mut sample_rate: u32) -> Option<(&'static Self, u32)> {
+        let max_rate = FILTERS.iter().map(|filter| filter.sample_rate).max().unwrap();
+        let mut downsample = 1;
+        loop {
+            if let Some(filter) = FILTERS.iter().find(|filter| filter.sample_rate == sample_rate) {
+                return Some((filter, downsample));
+            }
+            if sample_rate <= max_rate {
+                return None;
+            }
+            while sample_rate > max_rate {
+                downsample *= 2;
+                sample_rate /= 2;
+            }
+        }
+    }
+}
+
+/// * The history of the filters for one channel.
+#[derive(Debug, Clone, Default)]
+struct ChannelFilter {
+    yule_in: [f32; YULE_ORDER],
+    yule_out: [f32; YULE_ORDER],
+    butter_out: [f32; BUTTER_ORDER],
+}
+
+impl ChannelFilter {
+    /// * One IIR filter step, the latest history first. The products are `f32` and summed in `f64`, as `replaygain_analysis.c` does.
+    fn iir(input: f32, history_in: &[f32], history_out: &[f32], b: &[f32], a: &[f32]) -> f32 {
+        let mut y = (input * b[0]) as f64;
+        for k in 1..b.len() {
+            y += (history_in[k - 1] * b[k] - history_out[k - 1] * a[k]) as f64;
+        }
+        y as f32
+    }
+
+    fn push(history: &mut [f32], value: f32) {
+        history.rotate_right(1);
+        history[0] = value;
+    }
+
+    fn process(&mut self, filter: &EqualLoudnessFilter, input: f32) -> f32 {
+        let step = Self::iir(input, &self.yule_in, &self.yule_out, &filter.yule_b, &filter.yule_a);
+        // The input of the Butterworth filter is the output of the Yule-Walker filter.
+        let output = Self::iir(step, &self.yule_out, &self.butter_out, &filter.butter_b, &filter.butter_a);
+        Self::push(&mut self.yule_in, input);
+        Self::push(&mut self.yule_out, step);
+        Self::push(&mut self.butter_out, output);
+        output
+    }
+}
+
+/// * Filters the samples of a track and cuts them into the windows while they come.
+#[derive(Debug, Clone)]
+struct Analyzer {
+    loudness: Loudness,
+    filter: &'static EqualLoudnessFilter,
+    downsample: u32,
+    sample_rate: u32,
+    channels: Vec<ChannelFilter>,
+    window_len: usize,
+    sums: Vec<f64>,
+    count: usize,
+    phase: u32,
+}
+
+impl Analyzer {
+    /// * `None` if the sample rate isn't supported.
+    fn new(sample_rate: u32, channels: u32) -> Option<Self> {
+        let (filter, downsample) = EqualLoudnessFilter::for_sample_rate(sample_rate)?;
+        Some(Self {
+            loudness: Loudness::new(),
+            filter,
+            downsample,
+            sample_rate,
+            channels: vec![ChannelFilter::default(); channels as usize],
+            window_len: (filter.sample_rate * RMS_WINDOW_MS).div_ceil(1000) as usize,
+            sums: vec![0.0; channels as usize],
+            count: 0,
+            phase: 0,
+        })
+    }
+
+    /// * Returns `false` if the sample rate or the channels differ from the STREAMINFO block.
+    fn feed(&mut self, channels: &[Vec<i32>], samples_info: &SamplesInfo) -> bool {
+        if samples_info.sample_rate != self.sample_rate || channels.len() != self.channels.len() || samples_info.bits_per_sample == 0 {
+            return false;
+        }
+        // The loudness is measured on the 16-bit scale, the peak on the full scale.
+        let scale = 2f64.powi(16 - samples_info.bits_per_sample as i32);
+        let full_scale = 2f64.powi(samples_info.bits_per_sample as i32 - 1);
+        for i in 0..samples_info.samples as usize {
+            for channel in channels.iter() {
+                self.loudness.peak = self.loudness.peak.max((channel[i] as f64).abs() / full_scale);
+            }
+            let filtered = self.phase == 0;
+            self.phase = (self.phase + 1) % self.downsample;
+            if !filtered {
+                continue;
+            }
+            for ((channel, filter), sum) in channels.iter().zip(self.channels.iter_mut()).zip(self.sums.iter_mut()) {
+                let output = filter.process(self.filter, (channel[i] as f64 * scale) as f32);
+                *sum += (output * output) as f64;
+            }
+            self.count += 1;
+            if self.count >= self.window_len {
+                let sum: f64 = self.sums.iter().sum();
+                self.loudness.push_window(sum / self.count as f64 / self.channels.len() as f64);
+                self.sums.fill(0.0);
+                self.count = 0;
+            }
+        }
+        true
+    }
+}
+
+/// ## Analyze the ReplayGain of several tracks, then tag each of them with its track gain and the album gain
+/// * Call `analyze()` for every track of the album in order, then `apply()` to the encoder of each track by its index.
+/// * The audio goes through the equal-loudness filter of ReplayGain 1.0, then the loudness is the 95th percentile of the RMS of the 50 ms windows,
+///   averaged over the channels. The filter and the arithmetic are the ones of `metaflac --add-replay-gain`, so are the gains.
+/// * The sample rates are the ones `metaflac` supports: 8, 11.025, 12, 16, 18.9, 22.05, 24, 28, 32, 36, 37.8, 44.1 and 48 kHz,
+///   and the ones above 48 kHz that halve into them, e.g. 88.2, 96 or 192 kHz. The others fail `analyze()` with `FLAC_DECODER_UNSUPPORTED_FORMAT`.
+/// * `metaflac` refuses to mix the sample rates in an album, this session doesn't. Each track is filtered at its own sample rate,
+///   and the windows of all of the tracks are counted together for the album gain, as if the tracks had been resampled to one rate.
+#[derive(Debug, Clone)]
+pub struct ReplayGainSession {
+    tracks: Vec<(Option<TrackGain>, Loudness)>,
+}
+
+impl ReplayGainSession {
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+        }
+    }
+
+    /// * Decode the FLAC file from the `reader` and add it as the next track, returns its track gain.
+    /// * A track without a whole 50 ms window has no loudness to measure, its gain is `None`, only its peak counts for the album.
+    pub fn analyze<ReadSeek>(&mut self, reader: ReadSeek) -> Result<Option<TrackGain>, FlacDecoderError>
+    where
+        ReadSeek: Read + Seek {
+        let analyzer = RefCell::new(None::<Analyzer>);
+        let mut decoder = FlacDecoder::with_default_io(
+            reader,
+            Box::new(|channels: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+                if analyzer.borrow_mut().as_mut().is_some_and(|analyzer| analyzer.feed(channels, samples_info)) {
+                    Ok(())
+                } else {
+                    Err(io::Error::other("The audio format changed"))
+                }
+            }),
+            Box::new(|_error: FlacInternalDecoderError| {}),
+            true,
+            false,
+            FlacAudioForm::ChannelArray,
+        )?;
+        decoder.decode_metadata()?;
+        let Some(stream_info) = decoder.get_stream_info() else {
+            return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_END_OF_STREAM, "ReplayGainSession::analyze"));
+        };
+        let Some(new_analyzer) = Analyzer::new(stream_info.sample_rate, stream_info.channels) else {
+            return Err(FlacDecoderError::new(FLAC_DECODER_UNSUPPORTED_FORMAT, "ReplayGainSession::analyze"));
+        };
+        *analyzer.borrow_mut() = Some(new_analyzer);
+        match decoder.decode_all() {
+            DecodeOutcome::StoppedByCaller{..} => return Err(FlacDecoderError::new(FLAC_DECODER_UNSUPPORTED_FORMAT, "ReplayGainSession::analyze")),
+            outcome => outcome.into_result()?,
+        };
+        decoder.finish()?;
+        drop(decoder);
+        let loudness = analyzer.into_inner().unwrap().loudness;
+        let track_gain = loudness.gain();
+        self.tracks.push((track_gain, loudness));
+        Ok(track_gain)
+    }
+
+    /// * The gains of the analyzed tracks in order, `None` for the tracks too short to measure.
+    pub fn tracks(&self) -> Vec<Option<TrackGain>> {
+        self.tracks.iter().map(|(track_gain, _)| *track_gain).collect()
+    }
+
+    /// * The album gain of all of the analyzed tracks as if they were one long track, the peak is the highest of the tracks.
+    /// * `None` if no track was long enough to be measured.
+    pub fn album_gain(&self) -> Option<TrackGain> {
+        let mut album = Loudness::new();
+        for (_, loudness) in self.tracks.iter() {
+            album.merge(loudness);
+        }
+        album.gain()
+    }
+
+    /// * Insert the `REPLAYGAIN_TRACK_GAIN`, `REPLAYGAIN_TRACK_PEAK`, `REPLAYGAIN_ALBUM_GAIN` and `REPLAYGAIN_ALBUM_PEAK` comments
+    ///   of the track at `track_index` into the encoder before calling to `initialize()`.
+    /// * The gains are formatted as `"-6.50 dB"` and the peaks as `"0.98765432"`, the same as `metaflac` does.
+    /// * Fails with `FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA` if there's no such track, or the track is too short to measure.
+    pub fn apply<WriteSeek>(&self, encoder: &mut FlacEncoderUnmovable<'_, WriteSeek>, track_index: usize) -> Result<(), FlacEncoderInitError>
+    where
+        WriteSeek: Write + Seek {
+        // The album gain is there if any track was measured.
+        let (Some((Some(track_gain), _)), Some(album_gain)) = (self.tracks.get(track_index), self.album_gain()) else {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "ReplayGainSession::apply"));
+        };
+        encoder.insert_comments("REPLAYGAIN_TRACK_GAIN", &format_gain(track_gain.gain))?;
+        encoder.insert_comments("REPLAYGAIN_TRACK_PEAK", &format_peak(track_gain.peak))?;
+        encoder.insert_comments("REPLAYGAIN_ALBUM_GAIN", &format_gain(album_gain.gain))?;
+        encoder.insert_comments("REPLAYGAIN_ALBUM_PEAK", &format_peak(album_gain.peak))?;
+        Ok(())
+    }
+}
+
+impl Default for ReplayGainSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn format_gain(gain: f64) -> String {
+    format!("{gain:+.2} dB")
+}
+
+fn format_peak(peak: f64) -> String {
+    format!("{peak:.8}")
+}
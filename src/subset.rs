@@ -0,0 +1,386 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, Read, Seek, SeekFrom},
+    rc::Rc,
+};
+
+use libflac_sys::*;
+
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, FlacFrameHeader, SamplesInfo};
+
+/// ## A rule of the streamable subset, the restrictions for the hardware players to be able to play the FLAC file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubsetRule {
+    /// * The sample rate must be one that the frame header can encode without referring to the STREAMINFO block.
+    SampleRate,
+
+    /// * The bits per sample must be one of 8, 12, 16, 20, 24 and 32.
+    BitsPerSample,
+
+    /// * The block size must not exceed 16384, or 4608 if the sample rate is 48000 Hz or lower.
+    BlockSize,
+
+    /// * The LPC order must not exceed 12 if the sample rate is 48000 Hz or lower.
+    LpcOrder,
+
+    /// * The Rice partition order must not exceed 8.
+    RicePartitionOrder,
+
+    /// * The frame header must carry the sample rate and the bits per sample instead of referring to the STREAMINFO block.
+    FrameHeaderRefersToStreamInfo,
+}
+
+impl Display for SubsetRule {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::SampleRate => write!(f, "The sample rate is not in the streamable subset"),
+            Self::BitsPerSample => write!(f, "The bits per sample is not one of 8, 12, 16, 20, 24 and 32"),
+            Self::BlockSize => write!(f, "The block size exceeds 16384, or 4608 at 48000 Hz or lower"),
+            Self::LpcOrder => write!(f, "The LPC order exceeds 12 at 48000 Hz or lower"),
+            Self::RicePartitionOrder => write!(f, "The Rice partition order exceeds 8"),
+            Self::FrameHeaderRefersToStreamInfo => write!(f, "The frame header refers to the STREAMINFO block for the sample rate or the bits per sample"),
+        }
+    }
+}
+
+/// ## A rule that the FLAC file violates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubsetViolation {
+    pub rule: SubsetRule,
+
+    /// * The index of the first offending frame, `None` if the rule is about the STREAMINFO block.
+    pub first_frame: Option<u64>,
+
+    /// * How many frames violate the rule, zero if the rule is about the STREAMINFO block.
+    pub frames: u64,
+}
+
+/// ## The streamable subset compliance report of a FLAC file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubsetReport {
+    /// * The violated rules in the order they were first seen, empty if the file is in the streamable subset.
+    pub violations: Vec<SubsetViolation>,
+
+    /// * How many audio frames were scanned.
+    pub frames: u64,
+
+    /// * The frames that couldn't be parsed, their subframes were not checked.
+    pub unreadable_frames: u64,
+
+    /// * The largest block size used by the frames.
+    pub max_blocksize: u32,
+
+    /// * The largest LPC order used by the subframes, zero if no LPC subframe was found.
+    pub max_lpc_order: u32,
+
+    /// * The largest Rice partition order used by the subframes.
+    pub max_rice_partition_order: u32,
+}
+
+impl SubsetReport {
+    /// * Is the FLAC file in the streamable subset.
+    pub fn is_subset(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// * Find the violation of the rule.
+    pub fn violation(&self, rule: SubsetRule) -> Option<&SubsetViolation> {
+        self.violations.iter().find(|violation| violation.rule == rule)
+    }
+
+    fn violate(&mut self, rule: SubsetRule, frame: Option<u64>) {
+        match self.violations.iter_mut().find(|violation| violation.rule == rule) {
+            Some(violation) => violation.frames += 1,
+            None => self.violations.push(SubsetViolation {
+                rule,
+                first_frame: frame,
+                frames: if frame.is_some() {1} else {0},
+            }),
+        }
+    }
+}
+
+/// ## Check whether the FLAC file is in the streamable subset, e.g. to know why a file stutters on a hardware player.
+/// * The frames are skipped without decoding the samples, the subframe headers are parsed to find the LPC orders and the Rice partition orders.
+/// * Every violated rule is reported with the first offending frame.
+pub fn check_subset<ReadSeek>(reader: ReadSeek) -> Result<SubsetReport, FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    let recorder = RecordingReader::new(reader);
+    let recording = recorder.recording.clone();
+    let mut decoder = FlacDecoder::with_default_io(
+        recorder,
+        Box::new(|_channels: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {Ok(())}),
+        Box::new(|_error: FlacInternalDecoderError| {}),
+        false,
+        false,
+        FlacAudioForm::ChannelArray,
+    )?;
+
+    decoder.decode_metadata()?;
+    let Some(stream_info) = decoder.get_stream_info() else {
+        return Err(FlacDecoderError::new(FLAC__STREAM_DECODER_END_OF_STREAM, "check_subset"));
+    };
+
+    let mut report = SubsetReport::default();
+    if unsafe {FLAC__format_sample_rate_is_subset(stream_info.sample_rate) == 0} {
+        report.violate(SubsetRule::SampleRate, None);
+    }
+    if ![8, 12, 16, 20, 24, 32].contains(&stream_info.bits_per_sample) {
+        report.violate(SubsetRule::BitsPerSample, None);
+    }
+
+    let mut start = decoder.decode_position()?;
+    recording.borrow_mut().discard_before(start);
+    while decoder.skip_frame()? && !decoder.is_end_of_stream() {
+        let end = decoder.decode_position()?;
+        let header = decoder.frame_header();
+        let frame = report.frames;
+        report.frames += 1;
+        report.max_blocksize = report.max_blocksize.max(header.blocksize);
+
+        let low_rate = header.sample_rate <= 48000;
+        if unsafe {FLAC__format_blocksize_is_subset(header.blocksize, header.sample_rate) == 0} {
+            report.violate(SubsetRule::BlockSize, Some(frame));
+        }
+        match recording.borrow().get(start, end).and_then(|bytes| parse_frame(bytes, &header)) {
+            Some(stats) => {
+                report.max_lpc_order = report.max_lpc_order.max(stats.max_lpc_order);
+                report.max_rice_partition_order = report.max_rice_partition_order.max(stats.max_rice_partition_order);
+                if stats.refers_to_stream_info {
+                    report.violate(SubsetRule::FrameHeaderRefersToStreamInfo, Some(frame));
+                }
+                if low_rate && stats.max_lpc_order > FLAC__SUBSET_MAX_LPC_ORDER_48000HZ {
+                    report.violate(SubsetRule::LpcOrder, Some(frame));
+                }
+                if stats.max_rice_partition_order > FLAC__SUBSET_MAX_RICE_PARTITION_ORDER {
+                    report.violate(SubsetRule::RicePartitionOrder, Some(frame));
+                }
+            }
+            None => report.unreadable_frames += 1,
+        }
+        recording.borrow_mut().discard_before(end);
+        start = end;
+    }
+    decoder.finish()?;
+    Ok(report)
+}
+
+/// * The bytes read from the reader that the decoder may not have processed yet.
+#[derive(Debug, Default)]
+struct Recording {
+    start: u64,
+    bytes: Vec<u8>,
+}
+
+impl Recording {
+    fn get(&self, start: u64, end: u64) -> Option<&[u8]> {
+        let from = usize::try_from(start.checked_sub(self.start)?).ok()?;
+        let to = usize::try_from(end.checked_sub(self.start)?).ok()?;
+        self.bytes.get(from..to)
+    }
+
+    fn discard_before(&mut self, position: u64) {
+        let count = (position.saturating_sub(self.start) as usize).min(self.bytes.len());
+        self.bytes.drain(..count);
+        self.start += count as u64;
+    }
+}
+
+/// * Keeps a copy of the bytes that the decoder reads, to get the raw bytes of each frame.
+#[derive(Debug)]
+struct RecordingReader<R> {
+    inner: R,
+    recording: Rc<RefCell<Recording>>,
+}
+
+impl<R: Seek> RecordingReader<R> {
+    fn new(mut inner: R) -> Self {
+        let start = inner.stream_position().unwrap_or(0);
+        Self {
+            inner,
+            recording: Rc::new(RefCell::new(Recording {start, bytes: Vec::new()})),
+        }
+    }
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.recording.borrow_mut().bytes.extend_from_slice(&buf[..size]);
+        Ok(size)
+    }
+}
+
+impl<R: Seek> Seek for RecordingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        let mut recording = self.recording.borrow_mut();
+        // The recorded bytes are only kept if the reader stays at the end of them, e.g. the decoder asks for the current position.
+        if position != recording.start + recording.bytes.len() as u64 {
+            recording.start = position;
+            recording.bytes.clear();
+        }
+        Ok(position)
+    }
+}
+
+/// * What the subframes of a frame use.
+#[derive(Debug, Clone, Copy, Default)]
+struct FrameStats {
+    refers_to_stream_info: bool,
+    max_lpc_order: u32,
+    max_rice_partition_order: u32,
+}
+
+/// * Parse the raw bytes of a frame, returns `None` if the frame can't be parsed.
+fn parse_frame(frame: &[u8], header: &FlacFrameHeader) -> Option<FrameStats> {
+    if frame.len() < 7 || frame[0] != 0xFF || frame[1] & 0xFE != 0xF8 {
+        return None;
+    }
+    let blocksize_code = frame[2] >> 4;
+    let sample_rate_code = frame[2] & 0x0F;
+    let bits_per_sample_code = (frame[3] >> 1) & 0x07;
+    let mut stats = FrameStats {
+        refers_to_stream_info: sample_rate_code == 0 || bits_per_sample_code == 0,
+        ..Default::default()
+    };
+
+    // The UTF-8 coded frame number or sample number, then the optional block size and sample rate, then the CRC-8
+    let mut position = 4 + (frame[4].leading_ones() as usize).max(1);
+    position += match blocksize_code {6 => 1, 7 => 2, _ => 0};
+    position += match sample_rate_code {12 => 1, 13 | 14 => 2, _ => 0};
+    position += 1;
+
+    let mut reader = BitReader::new(frame.get(position..)?);
+    for channel in 0..header.channels {
+        let is_side = match header.channel_assignment {
+            FLAC__CHANNEL_ASSIGNMENT_LEFT_SIDE | FLAC__CHANNEL_ASSIGNMENT_MID_SIDE => channel == 1,
+            FLAC__CHANNEL_ASSIGNMENT_RIGHT_SIDE => channel == 0,
+            _ => false,
+        };
+        let mut bits_per_sample = header.bits_per_sample + if is_side {1} else {0};
+        if reader.read(1)? != 0 {
+            return None;
+        }
+        let subframe_type = reader.read(6)? as u32;
+        if reader.read(1)? != 0 {
+            bits_per_sample = bits_per_sample.checked_sub(reader.read_unary()? + 1)?;
+        }
+        match subframe_type {
+            // CONSTANT
+            0 => reader.skip(bits_per_sample as u64)?,
+            // VERBATIM
+            1 => reader.skip(bits_per_sample as u64 * header.blocksize as u64)?,
+            // FIXED
+            8..=12 => {
+                let order = subframe_type - 8;
+                reader.skip(order as u64 * bits_per_sample as u64)?;
+                let partition_order = read_residual(&mut reader, header.blocksize, order)?;
+                stats.max_rice_partition_order = stats.max_rice_partition_order.max(partition_order);
+            }
+            // LPC
+            32..=63 => {
+                let order = subframe_type - 31;
+                reader.skip(order as u64 * bits_per_sample as u64)?;
+                let precision = match reader.read(4)? {
+                    0b1111 => return None,
+                    precision => precision + 1,
+                };
+                reader.skip(5)?;
+                reader.skip(order as u64 * precision)?;
+                let partition_order = read_residual(&mut reader, header.blocksize, order)?;
+                stats.max_lpc_order = stats.max_lpc_order.max(order);
+                stats.max_rice_partition_order = stats.max_rice_partition_order.max(partition_order);
+            }
+            _ => return None,
+        }
+    }
+
+    // The subframes are padded to the byte boundary, then the CRC-16
+    if reader.position.div_ceil(8) + 2 != reader.data.len() {
+        return None;
+    }
+    Some(stats)
+}
+
+/// * Skip the residual, returns the Rice partition order.
+fn read_residual(reader: &mut BitReader, blocksize: u32, predictor_order: u32) -> Option<u32> {
+    let parameter_bits = match reader.read(2)? {
+        0 => 4,
+        1 => 5,
+        _ => return None,
+    };
+    let escape = (1u64 << parameter_bits) - 1;
+    let partition_order = reader.read(4)? as u32;
+    for partition in 0..(1u32 << partition_order) {
+        let samples = (blocksize >> partition_order).checked_sub(if partition == 0 {predictor_order} else {0})?;
+        let parameter = reader.read(parameter_bits)?;
+        if parameter == escape {
+            let bits = reader.read(5)?;
+            reader.skip(bits * samples as u64)?;
+        } else {
+            for _ in 0..samples {
+                reader.read_unary()?;
+                reader.skip(parameter)?;
+            }
+        }
+    }
+    Some(partition_order)
+}
+
+/// * Reads the bits MSB first.
+#[derive(Debug)]
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, bits: u32) -> Option<u64> {
+        let mut value = 0u64;
+        for _ in 0..bits {
+            let byte = *self.data.get(self.position / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.position % 8)) & 1) as u64;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, bits: u64) -> Option<()> {
+        let position = self.position.checked_add(usize::try_from(bits).ok()?)?;
+        if position > self.data.len() * 8 {
+            return None;
+        }
+        self.position = position;
+        Some(())
+    }
+
+    /// * Count the zero bits before a one bit.
+    fn read_unary(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        loop {
+            let offset = self.position % 8;
+            let rest = *self.data.get(self.position / 8)? << offset;
+            if rest == 0 {
+                zeros += 8 - offset as u32;
+                self.position += 8 - offset;
+            } else {
+                let leading_zeros = rest.leading_zeros();
+                zeros += leading_zeros;
+                self.position += leading_zeros as usize + 1;
+                return Some(zeros);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Formatter},
+    io::{Read, Seek},
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering}},
+    time::Duration,
+};
+
+use crate::flac::{SendFlacDecoder, FlacDecoderError, FlacStreamInfo, PictureData};
+
+/// ## The metadata of the stream as read by the `SyncFlacDecoder`, shared without locking the decoder
+#[derive(Debug, Clone, Default)]
+pub struct DecoderSnapshot {
+    /// * The STREAMINFO block, `None` if it wasn't read yet.
+    pub stream_info: Option<FlacStreamInfo>,
+
+    /// * The vendor string of the Vorbis comment block.
+    pub vendor_string: Option<String>,
+
+    /// * The Vorbis comments.
+    pub comments: BTreeMap<String, String>,
+
+    /// * The pictures, e.g. the cover art.
+    pub pictures: Vec<PictureData>,
+}
+
+impl DecoderSnapshot {
+    /// * The duration of the stream, `None` if the STREAMINFO block wasn't read yet, or the total samples or the sample rate is unknown, see `FlacStreamInfo::duration()`.
+    pub fn duration(&self) -> Option<Duration> {
+        self.stream_info?.duration()
+    }
+}
+
+/// ## A `Send + Sync` handle of the decoder for sharing between threads, e.g. the audio thread pulling the samples while the UI thread asks for the position
+/// * The decoding is behind a `Mutex`, one thread decodes at a time by `read()` and `read_at()`, see `FlacDecoder::read_at()`.
+/// * The metadata and the position are kept beside the decoder, `snapshot()`, `stream_info()`, `duration()` and `tell_samples()` never wait for the decoding in progress.
+/// * The snapshot is taken by `decode_metadata()`, or by the first read if the metadata wasn't decoded yet.
+pub struct SyncFlacDecoder<'a, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    decoder: Mutex<SendFlacDecoder<'a, ReadSeek>>,
+
+    /// * Only locked to replace or clone the `Arc`, never during the decoding.
+    snapshot: RwLock<Arc<DecoderSnapshot>>,
+
+    /// * The sample position where the next `read()` starts.
+    position: AtomicU64,
+}
+
+impl<ReadSeek> Debug for SyncFlacDecoder<'_, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("SyncFlacDecoder")
+            .field("snapshot", &self.snapshot)
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, ReadSeek> SyncFlacDecoder<'a, ReadSeek>
+where
+    ReadSeek: Read + Seek + Send {
+    /// * Wrap the decoder, the position starts from zero.
+    pub fn new(decoder: SendFlacDecoder<'a, ReadSeek>) -> Self {
+        Self {
+            decoder: Mutex::new(decoder),
+            snapshot: RwLock::new(Arc::new(DecoderSnapshot::default())),
+            position: AtomicU64::new(0),
+        }
+    }
+
+    /// * Decode the metadata blocks and take the snapshot of them.
+    pub fn decode_metadata(&self) -> Result<(), FlacDecoderError> {
+        let mut decoder = self.decoder.lock().unwrap();
+        decoder.decode_metadata()?;
+        self.take_snapshot(&decoder);
+        Ok(())
+    }
+
+    /// * Decode `n` samples per channel from the position, then move the position after them, see `FlacDecoder::read_at()`.
+    /// * The sequential reads continue decoding without seeking.
+    pub fn read(&self, n: usize) -> Result<Vec<Vec<i32>>, FlacDecoderError> {
+        let mut decoder = self.decoder.lock().unwrap();
+        // Hold the lock while reading the position, so the concurrent reads don't read the same range.
+        let start = self.position.load(Ordering::Acquire);
+        let samples = self.read_locked(&mut decoder, start, n)?;
+        // A `seek()` while reading wins, the next `read()` starts there.
+        let _ = self.position.compare_exchange(start, start + samples.first().map_or(0, |channel| channel.len()) as u64, Ordering::AcqRel, Ordering::Acquire);
+        Ok(samples)
+    }
+
+    /// * Decode `n` samples per channel starting at `start_sample`, then move the position after them, see `FlacDecoder::read_at()`.
+    pub fn read_at(&self, start_sample: u64, n: usize) -> Result<Vec<Vec<i32>>, FlacDecoderError> {
+        let mut decoder = self.decoder.lock().unwrap();
+        let samples = self.read_locked(&mut decoder, start_sample, n)?;
+        self.position.store(start_sample + samples.first().map_or(0, |channel| channel.len()) as u64, Ordering::Release);
+        Ok(samples)
+    }
+
+    fn read_locked(&self, decoder: &mut SendFlacDecoder<'a, ReadSeek>, start_sample: u64, n: usize) -> Result<Vec<Vec<i32>>, FlacDecoderError> {
+        let need_snapshot = self.snapshot.read().unwrap().stream_info.is_none();
+        let samples = decoder.read_at(start_sample, n)?;
+        if need_snapshot {
+            self.take_snapshot(decoder);
+        }
+        Ok(samples)
+    }
+
+    /// * Move the position for the next `read()`, it doesn't wait for the decoding in progress, the decoder seeks on the next `read()`.
+    pub fn seek(&self, sample: u64) {
+        self.position.store(sample, Ordering::Release);
+    }
+
+    /// * The sample position where the next `read()` starts, i.e. the end of the last read.
+    pub fn tell_samples(&self) -> u64 {
+        self.position.load(Ordering::Acquire)
+    }
+
+    /// * The snapshot of the metadata, cloning it is cheap.
+    pub fn snapshot(&self) -> Arc<DecoderSnapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    /// * The STREAMINFO block, `None` if it wasn't read yet.
+    pub fn stream_info(&self) -> Option<FlacStreamInfo> {
+        self.snapshot.read().unwrap().stream_info
+    }
+
+    /// * The duration of the stream, `None` if the STREAMINFO block wasn't read yet, or the total samples or the sample rate is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        self.stream_info()?.duration()
+    }
+
+    /// * Call `f` with the locked decoder for everything else, it waits for the decoding in progress.
+    /// * Decoding through it doesn't move the position of `read()`.
+    pub fn with_decoder<R>(&self, f: impl FnOnce(&mut SendFlacDecoder<'a, ReadSeek>) -> R) -> R {
+        f(&mut self.decoder.lock().unwrap())
+    }
+
+    /// * Get the decoder back.
+    pub fn into_inner(self) -> SendFlacDecoder<'a, ReadSeek> {
+        self.decoder.into_inner().unwrap()
+    }
+
+    fn take_snapshot(&self, decoder: &SendFlacDecoder<'a, ReadSeek>) {
+        let snapshot = DecoderSnapshot {
+            stream_info: decoder.get_stream_info().copied(),
+            vendor_string: decoder.get_vendor_string().clone(),
+            comments: decoder.get_comments().clone(),
+            pictures: decoder.get_pictures().clone(),
+        };
+        *self.snapshot.write().unwrap() = Arc::new(snapshot);
+    }
+}
@@ -0,0 +1,279 @@
+#![allow(dead_code)]
+
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    io::{Read, Write, Seek},
+    str::FromStr,
+};
+
+use libflac_sys::*;
+
+use crate::flac::{FlacEncoderUnmovable, FlacDecoderUnmovable, FlacEncoderInitError};
+
+/// * The comment keys of the lyrics in priority order, `lyrics()` takes the first one that isn't empty.
+pub const LYRICS_KEYS: [&str; 3] = ["LYRICS", "UNSYNCEDLYRICS", "UNSYNCED LYRICS"];
+
+/// ## The error for parsing a typed comment, e.g. `BPM` or `INITIALKEY`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// * What is being parsed, e.g. `"BPM"`
+    pub what: &'static str,
+
+    /// * The value that couldn't be parsed
+    pub value: String,
+}
+
+impl ParseError {
+    pub fn new(what: &'static str, value: &str) -> Self {
+        Self {
+            what,
+            value: value.to_owned(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Can't parse \"{}\" as {}", self.value, self.what)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// ## The musical key, one of the 12 major keys and the 12 minor keys.
+/// * `Display` gives the standard notation, e.g. `"Am"`, `"F#"`, `"Bbm"`, use `camelot()` for the Camelot notation, e.g. `"8A"`
+/// * `FromStr` accepts the standard notation with sharps or flats, the Camelot notation, and the Open Key notation, e.g. `"1m"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    CMajor,
+    DbMajor,
+    DMajor,
+    EbMajor,
+    EMajor,
+    FMajor,
+    FSharpMajor,
+    GMajor,
+    AbMajor,
+    AMajor,
+    BbMajor,
+    BMajor,
+    CMinor,
+    CSharpMinor,
+    DMinor,
+    EbMinor,
+    EMinor,
+    FMinor,
+    FSharpMinor,
+    GMinor,
+    GSharpMinor,
+    AMinor,
+    BbMinor,
+    BMinor,
+}
+
+const KEYS: [Key; 24] = [
+    Key::CMajor, Key::DbMajor, Key::DMajor, Key::EbMajor, Key::EMajor, Key::FMajor,
+    Key::FSharpMajor, Key::GMajor, Key::AbMajor, Key::AMajor, Key::BbMajor, Key::BMajor,
+    Key::CMinor, Key::CSharpMinor, Key::DMinor, Key::EbMinor, Key::EMinor, Key::FMinor,
+    Key::FSharpMinor, Key::GMinor, Key::GSharpMinor, Key::AMinor, Key::BbMinor, Key::BMinor,
+];
+
+const MAJOR_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+const MINOR_NAMES: [&str; 12] = ["Cm", "C#m", "Dm", "Ebm", "Em", "Fm", "F#m", "Gm", "G#m", "Am", "Bbm", "Bm"];
+
+impl Key {
+    /// * Create the key from the pitch class of the tonic (0 is C, 1 is C# and so on) and the mode.
+    pub fn new(pitch_class: u8, is_minor: bool) -> Self {
+        KEYS[(pitch_class % 12) as usize + if is_minor {12} else {0}]
+    }
+
+    /// * The pitch class of the tonic, 0 is C, 1 is C# and so on.
+    pub fn pitch_class(&self) -> u8 {
+        (*self as u8) % 12
+    }
+
+    pub fn is_minor(&self) -> bool {
+        (*self as u8) >= 12
+    }
+
+    /// * The number on the Camelot wheel, 1 to 12.
+    pub fn camelot_number(&self) -> u8 {
+        // The relative major key of a minor key shares the same number.
+        let major = if self.is_minor() {(self.pitch_class() + 3) % 12} else {self.pitch_class()};
+        match (major * 7 + 8) % 12 {
+            0 => 12,
+            n => n,
+        }
+    }
+
+    /// * The Camelot notation, e.g. `"8A"` for A minor, `"8B"` for C major.
+    pub fn camelot(&self) -> String {
+        format!("{}{}", self.camelot_number(), if self.is_minor() {'A'} else {'B'})
+    }
+
+    /// * Create the key from the Camelot number and the mode.
+    pub fn from_camelot(number: u8, is_minor: bool) -> Option<Self> {
+        if !(1..=12).contains(&number) {
+            return None;
+        }
+        KEYS.iter().copied().find(|key| key.camelot_number() == number && key.is_minor() == is_minor)
+    }
+
+    fn parse_standard(s: &str) -> Option<Self> {
+        let mut chars = s.chars().peekable();
+        let pitch_class: i32 = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let accidental = match chars.peek() {
+            Some('#' | '♯') => 1,
+            Some('b' | '♭') => -1,
+            _ => 0,
+        };
+        if accidental != 0 {
+            chars.next();
+        }
+        let mode: String = chars.collect::<String>().trim().to_ascii_lowercase();
+        let is_minor = match mode.as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        };
+        Some(Self::new((pitch_class + accidental).rem_euclid(12) as u8, is_minor))
+    }
+
+    fn parse_numbered(s: &str) -> Option<Self> {
+        let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let suffix = &s[digits.len()..];
+        let number = digits.trim().parse::<u8>().ok()?;
+        match suffix.to_ascii_lowercase().as_str() {
+            // Camelot
+            "a" => Self::from_camelot(number, true),
+            "b" => Self::from_camelot(number, false),
+            // Open Key, 1d is C major, 1m is A minor
+            "m" | "d" if (1..=12).contains(&number) => Self::from_camelot((number + 6) % 12 + 1, suffix.eq_ignore_ascii_case("m")),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let names = if self.is_minor() {&MINOR_NAMES} else {&MAJOR_NAMES};
+        write!(f, "{}", names[self.pitch_class() as usize])
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let trimmed = s.trim();
+        let parsed = if trimmed.starts_with(|c: char| c.is_ascii_digit()) {
+            Self::parse_numbered(trimmed)
+        } else {
+            Self::parse_standard(trimmed)
+        };
+        parsed.ok_or_else(|| ParseError::new("key", s))
+    }
+}
+
+/// * Parse the BPM value, tolerates the decimal comma and the `BPM` suffix.
+pub fn parse_bpm(s: &str) -> Result<f32, ParseError> {
+    let trimmed = s.trim();
+    // The comment text is untrusted, the last 3 bytes may not be on a char boundary.
+    let split = trimmed.len().saturating_sub(3);
+    let trimmed = match trimmed.get(split..) {
+        Some(suffix) if suffix.eq_ignore_ascii_case("bpm") => trimmed[..split].trim_end(),
+        _ => trimmed,
+    };
+    match trimmed.replace(',', ".").parse::<f32>() {
+        Ok(bpm) if bpm.is_finite() && bpm > 0.0 => Ok(bpm),
+        _ => Err(ParseError::new("BPM", s)),
+    }
+}
+
+/// * Get the lyrics from the Lyrics3 tag, v1 (`LYRICSBEGIN` ... `LYRICSEND`) or v2 (the `LYR` field, ends with `LYRICS200`).
+/// * The Lyrics3 tag is in ISO-8859-1, every byte is a character.
+pub fn parse_lyrics3(data: &[u8]) -> Option<String> {
+    const BEGIN: &[u8] = b"LYRICSBEGIN";
+    let start = data.windows(BEGIN.len()).position(|window| window == BEGIN)? + BEGIN.len();
+    let tag = &data[start..];
+    let latin1 = |bytes: &[u8]| bytes.iter().map(|&byte| byte as char).collect::<String>();
+    if let Some(end) = tag.windows(9).position(|window| window == b"LYRICSEND") {
+        return Some(latin1(&tag[..end]));
+    }
+
+    // v2: the fields are the 3-byte ID, the 5-digit size and the data, then the 6-digit size of the tag and `LYRICS200`.
+    let end = tag.windows(9).position(|window| window == b"LYRICS200")?;
+    let mut fields = &tag[..end.checked_sub(6)?];
+    while fields.len() >= 8 {
+        let size = std::str::from_utf8(&fields[3..8]).ok()?.parse::<usize>().ok()?;
+        let field = fields.get(8..8 + size)?;
+        if &fields[..3] == b"LYR" {
+            return Some(latin1(field));
+        }
+        fields = &fields[8 + size..];
+    }
+    None
+}
+
+/// * Format the BPM value with at most two decimals, e.g. `"128"`, `"128.5"`
+pub fn format_bpm(bpm: f32) -> String {
+    let formatted = format!("{:.2}", bpm);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+impl<WriteSeek> FlacEncoderUnmovable<'_, WriteSeek>
+where
+    WriteSeek: Write + Seek {
+    /// * Set the `BPM` comment, the value is rounded to two decimals, must be a positive number.
+    pub fn set_bpm(&mut self, bpm: f32) -> Result<(), FlacEncoderInitError> {
+        if !bpm.is_finite() || bpm <= 0.0 {
+            return Err(FlacEncoderInitError::new(FLAC__STREAM_ENCODER_INIT_STATUS_INVALID_METADATA, "FlacEncoderUnmovable::set_bpm"));
+        }
+        self.insert_comments("BPM", &format_bpm(bpm))
+    }
+
+    /// * Set the `INITIALKEY` comment in the standard notation, e.g. `"Am"`
+    pub fn set_initial_key(&mut self, key: Key) -> Result<(), FlacEncoderInitError> {
+        self.insert_comments("INITIALKEY", &key.to_string())
+    }
+
+    /// * Set the `LYRICS` comment, the multi-line lyrics are kept as they are.
+    pub fn set_lyrics(&mut self, text: &str) -> Result<(), FlacEncoderInitError> {
+        self.insert_comments("LYRICS", text)
+    }
+}
+
+impl<ReadSeek> FlacDecoderUnmovable<'_, ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    /// * Parse the `BPM` comment, returns `None` if there's no such comment.
+    /// * The raw value is still available from `get_comments()` if it can't be parsed.
+    pub fn bpm(&self) -> Option<Result<f32, ParseError>> {
+        self.get_comments().get("BPM").map(|bpm| parse_bpm(bpm))
+    }
+
+    /// * Parse the `INITIALKEY` comment, or the `KEY` comment if there's no `INITIALKEY`, returns `None` if there's no such comment.
+    /// * The raw value is still available from `get_comments()` if it can't be parsed.
+    pub fn initial_key(&self) -> Option<Result<Key, ParseError>> {
+        let comments = self.get_comments();
+        comments.get("INITIALKEY").or_else(|| comments.get("KEY")).map(|key| key.parse::<Key>())
+    }
+
+    /// * Get the lyrics from the comments by `LYRICS_KEYS` in priority order, or from a Lyrics3 tag in an APPLICATION block, returns `None` if there are no lyrics.
+    pub fn lyrics(&self) -> Option<String> {
+        let comments = self.get_comments();
+        LYRICS_KEYS.iter()
+            .filter_map(|key| comments.get(*key))
+            .find(|lyrics| !lyrics.trim().is_empty())
+            .cloned()
+            .or_else(|| self.get_applications().iter().find_map(|application| parse_lyrics3(&application.data)))
+    }
+}
@@ -0,0 +1,264 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Debug,
+    io::{self, Read, Seek},
+    rc::Rc,
+};
+
+use crate::flac::{
+    FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo,
+    FlacCueSheet, FlacCueSheetIndex, FlacTrackType,
+    FLAC_DECODER_NOT_CD_AUDIO, FLAC_DECODER_NO_CUE_SHEET,
+};
+
+/// ## The CRC of a track of a CD image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackCrc {
+    /// * The track number in the cue sheet
+    pub track_no: u8,
+
+    /// * The CRC32 over the 16-bit little-endian interleaved samples of the track
+    pub crc32: u32,
+
+    /// * How many samples per channel were decoded for the track
+    pub samples: u64,
+}
+
+/// ## Which track the pregap (the audio between `INDEX 00` and `INDEX 01` of a track) belongs to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PregapAttribution {
+    /// * The pregap is appended to the previous track, this is how EAC splits the tracks by default.
+    /// * The pregap of the first track (the hidden track one audio) is not in any track.
+    #[default]
+    PreviousTrack,
+
+    /// * The pregap is prepended to its own track.
+    CurrentTrack,
+
+    /// * The pregap is not in any track.
+    Discard,
+}
+
+/// ## A track of the cue sheet with its range in samples resolved by the `PregapAttribution`, see `FlacCueSheet::track_infos()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackInfo {
+    /// * The track number in the cue sheet
+    pub track_no: u8,
+
+    /// * Is it an audio track
+    pub is_audio: bool,
+
+    /// * The first sample of the track, `INDEX 00` if the pregap is prepended to it, otherwise `INDEX 01`
+    pub start: u64,
+
+    /// * The end of the track, exclusive, where the next track starts or the lead-out track
+    pub end: u64,
+
+    /// * The length of the pregap of this track in samples, from `INDEX 00` to `INDEX 01`, zero if there's no `INDEX 00`
+    pub pregap: u64,
+}
+
+impl TrackInfo {
+    /// * How many samples per channel are in the track
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl FlacCueSheet {
+    /// * Resolve the range of every track except the lead-out track, which is the last track.
+    /// * `total_samples` is the end of the last track if the lead-out track has no offset, zero means unknown, then the last track is open-ended.
+    pub fn track_infos(&self, pregap: PregapAttribution, total_samples: u64) -> Vec<TrackInfo> {
+        let find_index = |indices: &[FlacCueSheetIndex], number: u8| indices.iter().find(|index| index.number == number).map(|index| index.offset);
+
+        // The last track is the lead-out track.
+        let num_tracks = self.tracks.len().saturating_sub(1);
+        let lead_out = match self.tracks.values().last().map(|track| track.offset) {
+            Some(offset) if offset > 0 => offset,
+            _ if total_samples > 0 => total_samples,
+            _ => u64::MAX,
+        };
+
+        // `(track_no, is_audio, INDEX 00, INDEX 01)` in absolute samples, without the index 00 falls back to index 01.
+        let points: Vec<(u8, bool, u64, u64)> = self.tracks.values().take(num_tracks).map(|track| {
+            let index_01 = track.offset + find_index(&track.indices, 1).or_else(|| track.indices.first().map(|index| index.offset)).unwrap_or(0);
+            let index_00 = find_index(&track.indices, 0).map_or(index_01, |offset| track.offset + offset);
+            (track.track_no, matches!(track.type_, FlacTrackType::Audio), index_00.min(index_01), index_01)
+        }).collect();
+
+        points.iter().enumerate().map(|(i, &(track_no, is_audio, index_00, index_01))| {
+            let next = points.get(i + 1).map(|&(_, _, next_00, next_01)| (next_00, next_01));
+            let (start, end) = match pregap {
+                PregapAttribution::PreviousTrack => (index_01, next.map_or(lead_out, |(_, next_01)| next_01)),
+                PregapAttribution::CurrentTrack => (index_00, next.map_or(lead_out, |(next_00, _)| next_00)),
+                PregapAttribution::Discard => (index_01, next.map_or(lead_out, |(next_00, _)| next_00)),
+            };
+            TrackInfo {
+                track_no,
+                is_audio,
+                start,
+                end: end.max(start),
+                pregap: index_01 - index_00,
+            }
+        }).collect()
+    }
+}
+
+/// ## Compute the CRC of each audio track of a CD image, delimited by the cue sheet, the pregaps are appended to the previous tracks.
+/// * See `track_crcs_with_pregap()`
+pub fn track_crcs<ReadSeek>(reader: ReadSeek) -> Result<Vec<TrackCrc>, FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    track_crcs_with_pregap(reader, PregapAttribution::default())
+}
+
+/// ## Compute the CRC of each audio track of a CD image, delimited by the cue sheet.
+/// * The FLAC file is decoded once, the CRC32 is computed over the 16-bit little-endian interleaved representation of the samples of each track.
+/// * The audio must be 44100 Hz, 16 bits per sample, stereo, otherwise the error code is `FLAC_DECODER_NOT_CD_AUDIO`.
+/// * The first cue sheet is used, if the metadata is read fine but there's no cue sheet, the error code is `FLAC_DECODER_NO_CUE_SHEET`.
+///   The other decoding failures, e.g. the damaged metadata or the failed read, return the error of the decoder.
+/// * The last track of the cue sheet is the lead-out track, the non-audio tracks are skipped.
+pub fn track_crcs_with_pregap<ReadSeek>(reader: ReadSeek, pregap: PregapAttribution) -> Result<Vec<TrackCrc>, FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    let splitter = Rc::new(RefCell::new(None::<TrackSplitter>));
+    // Set when the audio comes without the cue sheet, to tell it from the other decoding failures.
+    let no_cue_sheet = Rc::new(Cell::new(false));
+    let mut decoder = {
+        let splitter = splitter.clone();
+        let no_cue_sheet = no_cue_sheet.clone();
+        FlacDecoder::with_default_io(
+            reader,
+            Box::new(move |frames: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {
+                // All of the metadata blocks come before the audio frames, the splitter is ready here.
+                match splitter.borrow_mut().as_mut() {
+                    Some(splitter) => {
+                        splitter.feed(frames);
+                        Ok(())
+                    }
+                    None => {
+                        no_cue_sheet.set(true);
+                        Err(io::Error::other("The track boundaries are unknown"))
+                    }
+                }
+            }),
+            // The decoding errors are reflected by the CRCs since the damaged frames are decoded as silence.
+            Box::new(|_error: FlacInternalDecoderError| {}),
+            false,
+            false,
+            FlacAudioForm::FrameArray,
+        )?
+    };
+
+    while !decoder.is_end_of_stream() {
+        if splitter.borrow().is_none() && let Some(stream_info) = decoder.get_stream_info() {
+            if stream_info.sample_rate != 44100 || stream_info.bits_per_sample != 16 || stream_info.channels != 2 {
+                return Err(FlacDecoderError::new(FLAC_DECODER_NOT_CD_AUDIO, "track_crcs"));
+            }
+            if let Some(cue_sheet) = decoder.get_cue_sheets().first() {
+                *splitter.borrow_mut() = Some(TrackSplitter::new(cue_sheet, pregap, stream_info.total_samples));
+            }
+        }
+        match decoder.process_single() {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(_) if no_cue_sheet.get() => return Err(FlacDecoderError::new(FLAC_DECODER_NO_CUE_SHEET, "track_crcs")),
+            Err(e) => return Err(e),
+        }
+    }
+    decoder.finish()?;
+
+    let splitter = splitter.borrow_mut().take();
+    match splitter {
+        Some(splitter) => Ok(splitter.into_track_crcs()),
+        None if decoder.get_stream_info().is_none() => Err(FlacDecoderError::new(FLAC_DECODER_NOT_CD_AUDIO, "track_crcs")),
+        None => Err(FlacDecoderError::new(FLAC_DECODER_NO_CUE_SHEET, "track_crcs")),
+    }
+}
+
+/// * A track with its range in samples `start..end`, and the running CRC.
+#[derive(Debug, Clone, Copy)]
+struct TrackRange {
+    track_no: u8,
+    start: u64,
+    end: u64,
+    crc: u32,
+    samples: u64,
+}
+
+/// * Routes the decoded samples to the tracks.
+#[derive(Debug, Clone)]
+struct TrackSplitter {
+    tracks: Vec<TrackRange>,
+    position: u64,
+}
+
+impl TrackSplitter {
+    fn new(cue_sheet: &FlacCueSheet, pregap: PregapAttribution, total_samples: u64) -> Self {
+        let tracks = cue_sheet.track_infos(pregap, total_samples).into_iter().filter(|track| track.is_audio).map(|track| TrackRange {
+            track_no: track.track_no,
+            start: track.start,
+            end: track.end,
+            crc: 0,
+            samples: 0,
+        }).collect();
+
+        Self {
+            tracks,
+            position: 0,
+        }
+    }
+
+    fn feed(&mut self, frames: &[Vec<i32>]) {
+        let begin = self.position;
+        let end = begin + frames.len() as u64;
+        for track in self.tracks.iter_mut() {
+            let start = track.start.max(begin);
+            let stop = track.end.min(end);
+            if start >= stop {
+                continue;
+            }
+            let bytes: Vec<u8> = frames[(start - begin) as usize..(stop - begin) as usize].iter().flat_map(|frame| {
+                frame.iter().flat_map(|sample| (*sample as i16).to_le_bytes())
+            }).collect();
+            track.crc = crc32_update(track.crc, &bytes);
+            track.samples += stop - start;
+        }
+        self.position = end;
+    }
+
+    fn into_track_crcs(self) -> Vec<TrackCrc> {
+        self.tracks.into_iter().map(|track| TrackCrc {
+            track_no: track.track_no,
+            crc32: track.crc,
+            samples: track.samples,
+        }).collect()
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {(crc >> 1) ^ 0xEDB88320} else {crc >> 1};
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// * Continue the CRC32 (the one used by zip and PNG) of the previous data with more data.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, byte| CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
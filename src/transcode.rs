@@ -0,0 +1,371 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, Read, Write, Seek, SeekFrom},
+};
+
+use crate::flac::{FlacEncoder, FlacEncoderError, FlacEncoderParams, FlacCompression};
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo};
+
+/// ## What `transcode()` does when the bits per sample of the source differ from the ones of the encoder parameters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BitDepthMismatch {
+    /// * Fail with `TranscodeError::BitDepthMismatch` before anything is encoded.
+    #[default]
+    Reject,
+
+    /// * Convert the samples by `util::convert_bit_depth()`, narrowing the bit depth loses the precision.
+    Convert,
+}
+
+/// ## What `transcode()` may convert when the source doesn't match the encoder parameters, anything else is rejected
+/// * A `BitDepthMismatch` turns into the policy that only sets `bit_depth`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConversionPolicy {
+    /// * Reject or convert the different bits per sample.
+    pub bit_depth: BitDepthMismatch,
+
+    /// * Add the triangular dither of 1 LSB of the target bit depth before narrowing it, instead of just rounding.
+    ///   The dither is pseudo-random from a fixed seed, so the same source transcodes into the same file.
+    pub dither: bool,
+
+    /// * Mix all of the channels into one by averaging them when the encoder parameters have 1 channel.
+    pub downmix: bool,
+}
+
+impl From<BitDepthMismatch> for ConversionPolicy {
+    fn from(bit_depth: BitDepthMismatch) -> Self {
+        Self {
+            bit_depth,
+            ..Self::default()
+        }
+    }
+}
+
+/// ## The channels, the bits per sample and the sample rate of the audio, for `TranscodeError::FormatMismatch`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SampleFormat {
+    pub channels: u32,
+    pub bits_per_sample: u32,
+    pub sample_rate: u32,
+}
+
+impl Display for SampleFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} channels, {} bits per sample, {} Hz", self.channels, self.bits_per_sample, self.sample_rate)
+    }
+}
+
+impl SampleFormat {
+    fn of_params(params: &FlacEncoderParams) -> Self {
+        Self {
+            channels: params.channels as u32,
+            bits_per_sample: params.bits_per_sample,
+            sample_rate: params.sample_rate,
+        }
+    }
+}
+
+/// ## Why `transcode()` failed
+#[derive(Debug)]
+pub enum TranscodeError {
+    /// * Failed to decode the source.
+    Decoder(FlacDecoderError),
+
+    /// * Failed to encode.
+    Encoder(FlacEncoderError),
+
+    /// * The bits per sample of the source differ from the ones of the encoder parameters, and `BitDepthMismatch::Reject` is set.
+    BitDepthMismatch {
+        source: u32,
+        target: u32,
+    },
+
+    /// * The channels or the sample rate of the source differ from the ones of the encoder parameters, and the `ConversionPolicy` doesn't allow converting them.
+    FormatMismatch {
+        source: SampleFormat,
+        target: SampleFormat,
+    },
+}
+
+impl Display for TranscodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Decoder(error) => write!(f, "{error}"),
+            Self::Encoder(error) => write!(f, "{error}"),
+            Self::BitDepthMismatch{source, target} => write!(f, "The source has {source} bits per sample, but the encoder has {target} bits per sample"),
+            Self::FormatMismatch{source, target} => write!(f, "The source has {source}, but the encoder has {target}"),
+        }
+    }
+}
+
+impl Error for TranscodeError {}
+
+impl From<FlacDecoderError> for TranscodeError {
+    fn from(error: FlacDecoderError) -> Self {
+        Self::Decoder(error)
+    }
+}
+
+impl From<FlacEncoderError> for TranscodeError {
+    fn from(error: FlacEncoderError) -> Self {
+        Self::Encoder(error)
+    }
+}
+
+/// ## Convert the sample from `from_bits` bits per sample to `to_bits` bits per sample
+/// * Widening shifts the sample left, narrowing shifts it right with rounding, and the rounded sample is clamped to the range of `to_bits`.
+pub fn convert_bit_depth(sample: i32, from_bits: u32, to_bits: u32) -> i32 {
+    if from_bits <= to_bits {
+        ((sample as i64) << (to_bits - from_bits)) as i32
+    } else {
+        let shift = from_bits - to_bits;
+        let max = (1i64 << (to_bits - 1)) - 1;
+        ((sample as i64 + (1i64 << (shift - 1))) >> shift).clamp(-max - 1, max) as i32
+    }
+}
+
+/// ## Decode the FLAC file from the `reader` and encode it into the `writer` with the `params`, returns the samples per channel transcoded
+/// * The format of the source is compared with the `params` before anything is encoded, and again for every frame, it's never silently truncated.
+/// * The sample rate must match, the bits per sample and the channels are converted only if the `policy` allows, see `ConversionPolicy`.
+///   Pass a `BitDepthMismatch` for the policy that only decides the bits per sample.
+/// * Fails with `FLAC_DECODER_DAMAGED_STREAM` if the decoder reported an error in the source, e.g. a frame CRC mismatch, the output is left unfinished then.
+pub fn transcode<ReadSeek, WriteSeek>(reader: ReadSeek, mut writer: WriteSeek, params: &FlacEncoderParams, policy: impl Into<ConversionPolicy>) -> Result<u64, TranscodeError>
+where
+    ReadSeek: Read + Seek,
+    WriteSeek: Write + Seek {
+    let policy = policy.into();
+    let target = SampleFormat::of_params(params);
+    let encoder = RefCell::new(FlacEncoder::new(
+        &mut writer,
+        Box::new(|writer: &mut &mut WriteSeek, data: &[u8]| -> Result<(), io::Error> {
+            writer.write_all(data)
+        }),
+        Box::new(|writer: &mut &mut WriteSeek, position: u64| -> Result<(), io::Error> {
+            writer.seek(SeekFrom::Start(position))?;
+            Ok(())
+        }),
+        Box::new(|writer: &mut &mut WriteSeek| -> Result<u64, io::Error> {
+            writer.stream_position()
+        }),
+        params
+    )?);
+    // The error in `on_write()` aborts the decoding, it's kept here.
+    let write_error = RefCell::new(None);
+    // libFLAC passes the silence in place of the damaged frame, the first error it reported fails the transcoding.
+    let stream_error = Cell::new(None);
+    let samples_transcoded = Cell::new(0u64);
+    let target_bits = params.bits_per_sample;
+    let mut buffer = Vec::new();
+    let mut dither = Dither::new();
+    let mut decoder = FlacDecoder::with_default_io(
+        reader,
+        Box::new(|frames: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+            let source = SampleFormat {
+                channels: samples_info.channels,
+                bits_per_sample: samples_info.bits_per_sample,
+                sample_rate: samples_info.sample_rate,
+            };
+            if let Err(error) = check_format(source, target, &policy) {
+                *write_error.borrow_mut() = Some(error);
+                return Err(io::Error::other("Format mismatch"));
+            }
+            let source_bits = source.bits_per_sample;
+            let mut convert = |sample: i32| if policy.dither && source_bits > target_bits {
+                dither.convert_bit_depth(sample, source_bits, target_bits)
+            } else {
+                convert_bit_depth(sample, source_bits, target_bits)
+            };
+            buffer.clear();
+            if source.channels != target.channels {
+                // Only the downmix to mono passes `check_format()`, the average is rounded to the nearest.
+                let channels = source.channels as i64;
+                buffer.extend(frames.iter().map(|frame| {
+                    let sum = frame.iter().map(|&sample| sample as i64).sum::<i64>();
+                    convert(((sum + sum.signum() * channels / 2) / channels) as i32)
+                }));
+            } else {
+                buffer.extend(frames.iter().flatten().map(|&sample| convert(sample)));
+            }
+            if let Err(error) = encoder.borrow_mut().write_interleaved_samples(&buffer) {
+                *write_error.borrow_mut() = Some(error.into());
+                return Err(io::Error::other("Failed to encode"));
+            }
+            samples_transcoded.set(samples_transcoded.get() + samples_info.samples as u64);
+            Ok(())
+        }),
+        Box::new(|error: FlacInternalDecoderError| {
+            if stream_error.get().is_none() {
+                stream_error.set(Some(error));
+            }
+        }),
+        true,
+        false,
+        FlacAudioForm::FrameArray,
+    )?;
+    decoder.decode_metadata()?;
+    if let Some(stream_info) = decoder.get_stream_info() {
+        check_format(SampleFormat {
+            channels: stream_info.channels,
+            bits_per_sample: stream_info.bits_per_sample,
+            sample_rate: stream_info.sample_rate,
+        }, target, &policy)?;
+    }
+    encoder.borrow_mut().initialize()?;
+    let decoded = decoder.decode_all();
+    if let Some(error) = write_error.take() {
+        return Err(error);
+    }
+    if let Some(error) = stream_error.get() {
+        return Err(FlacDecoderError::damaged_stream(error, "transcode").into());
+    }
+    decoded.into_result()?;
+    decoder.finish()?;
+    drop(decoder);
+    encoder.borrow_mut().finish()?;
+    Ok(samples_transcoded.get())
+}
+
+/// * The short options of the `flac` command line tool that take an argument, the rest of the token or the next token is the argument.
+const SHORT_OPTIONS_WITH_ARGUMENT: &str = "AbloqrSTP";
+
+/// ## Find the compression level in the options of the `flac` command line tool, e.g. the `ENCODER_OPTIONS` comment from `original_encoder_options()`
+/// * Understands `-0` to `-8`, the combined short options like `-8V`, `--compression-level-N`, `--fast` and `--best`, the last level wins as in the `flac` command line tool.
+/// * The block size `-b` is found by `block_size_from_encoder_options()`.
+///   The other options, e.g. the LPC order `-l`, can't be set in `FlacEncoderParams`, they're skipped with their arguments.
+/// * `None` if no compression level is found.
+pub fn compression_from_encoder_options(options: &str) -> Option<FlacCompression> {
+    let mut compression = None;
+    for token in options.split_whitespace() {
+        if let Some(long_option) = token.strip_prefix("--") {
+            compression = match long_option {
+                "fast" => Some(FlacCompression::Level0),
+                "best" => Some(FlacCompression::Level8),
+                _ => long_option.strip_prefix("compression-level-").and_then(|level| level.parse().ok()).and_then(FlacCompression::from_level).or(compression),
+            };
+        } else if let Some(short_options) = token.strip_prefix('-') {
+            for c in short_options.chars() {
+                if SHORT_OPTIONS_WITH_ARGUMENT.contains(c) {
+                    break;
+                }
+                if let Some(level) = c.to_digit(10) {
+                    compression = FlacCompression::from_level(level).or(compression);
+                }
+            }
+        }
+    }
+    compression
+}
+
+/// ## Find the block size in the options of the `flac` command line tool, e.g. the `ENCODER_OPTIONS` comment from `original_encoder_options()`
+/// * Understands `-b N`, `-bN`, the combined short options like `-8b4096`, `--blocksize=N` and `--blocksize N`, the last one wins as in the `flac` command line tool.
+/// * `None` if no block size is found, or it's out of the range `16..=65535` that FLAC allows.
+pub fn block_size_from_encoder_options(options: &str) -> Option<u32> {
+    let mut block_size = None;
+    let mut tokens = options.split_whitespace();
+    while let Some(token) = tokens.next() {
+        let argument = if let Some(long_option) = token.strip_prefix("--") {
+            match long_option.split_once('=') {
+                Some(("blocksize", argument)) => Some(argument),
+                None if long_option == "blocksize" => tokens.next(),
+                _ => None,
+            }
+        } else if let Some(short_options) = token.strip_prefix('-') {
+            // Only the first short option with an argument counts, the rest of the token is its argument.
+            match short_options.char_indices().find(|(_, c)| SHORT_OPTIONS_WITH_ARGUMENT.contains(*c)) {
+                Some((index, 'b')) if index + 1 < short_options.len() => Some(&short_options[index + 1..]),
+                Some((_, 'b')) => tokens.next(),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        if let Some(size) = argument.and_then(|argument| argument.parse::<u32>().ok()) {
+            block_size = (16..=65535).contains(&size).then_some(size).or(block_size);
+        }
+    }
+    block_size
+}
+
+/// ## The encoder parameters to re-encode the FLAC file from the `reader` like the original one, e.g. for the archival re-encoding by `transcode()`
+/// * The channels, the sample rate and the bits per sample are from the STREAMINFO block,
+///   the compression level is from the `ENCODER_OPTIONS` comment by `compression_from_encoder_options()`, or `Level5` if it's missing or unparseable.
+/// * The block size is from the STREAMINFO block if all of the blocks but the last one have the same size,
+///   otherwise from the `ENCODER_OPTIONS` comment by `block_size_from_encoder_options()`, or `None` for the default of the compression level.
+/// * Only the metadata blocks are read, then the `reader` is seeked back to where it was, so it can be passed to `transcode()` next.
+pub fn original_params<ReadSeek>(reader: &mut ReadSeek) -> Result<FlacEncoderParams, FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    let io_error = |_| FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_SEEK_ERROR, "original_params");
+    let start = reader.stream_position().map_err(io_error)?;
+    let mut decoder = FlacDecoder::with_default_io(
+        &mut *reader,
+        Box::new(|_frames: &[Vec<i32>], _samples_info: &SamplesInfo| -> Result<(), io::Error> {Ok(())}),
+        Box::new(|_error: FlacInternalDecoderError| {}),
+        false,
+        false,
+        FlacAudioForm::FrameArray,
+    )?;
+    decoder.decode_metadata()?;
+    let stream_info = decoder.get_stream_info().copied();
+    let compression = decoder.original_encoder_options().and_then(compression_from_encoder_options);
+    let options_block_size = decoder.original_encoder_options().and_then(block_size_from_encoder_options);
+    drop(decoder);
+    reader.seek(SeekFrom::Start(start)).map_err(io_error)?;
+    let Some(stream_info) = stream_info else {
+        return Err(FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_END_OF_STREAM, "original_params"));
+    };
+    Ok(FlacEncoderParams {
+        compression: compression.unwrap_or(FlacCompression::Level5),
+        channels: stream_info.channels as u16,
+        sample_rate: stream_info.sample_rate,
+        bits_per_sample: stream_info.bits_per_sample,
+        total_samples_estimate: stream_info.total_samples,
+        block_size: (stream_info.min_blocksize == stream_info.max_blocksize).then_some(stream_info.max_blocksize).or(options_block_size),
+        ..FlacEncoderParams::new()
+    })
+}
+
+/// * Check whether the `source` can be encoded as the `target` under the `policy`.
+fn check_format(source: SampleFormat, target: SampleFormat, policy: &ConversionPolicy) -> Result<(), TranscodeError> {
+    let downmix = policy.downmix && target.channels == 1;
+    if source.sample_rate != target.sample_rate || (source.channels != target.channels && !downmix) {
+        return Err(TranscodeError::FormatMismatch{source, target});
+    }
+    if source.bits_per_sample != target.bits_per_sample && policy.bit_depth == BitDepthMismatch::Reject {
+        return Err(TranscodeError::BitDepthMismatch{source: source.bits_per_sample, target: target.bits_per_sample});
+    }
+    Ok(())
+}
+
+/// * The triangular dither from a xorshift generator with a fixed seed.
+struct Dither {
+    state: u32,
+}
+
+impl Dither {
+    fn new() -> Self {
+        Self {
+            state: 0x2545F491,
+        }
+    }
+
+    fn next(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// * Narrow the sample like `convert_bit_depth()`, but add the difference of two uniform noises of 1 LSB of the target bit depth first.
+    fn convert_bit_depth(&mut self, sample: i32, from_bits: u32, to_bits: u32) -> i32 {
+        let shift = from_bits - to_bits;
+        let lsb = 1i64 << shift;
+        let noise = (self.next() as i64 % lsb) - (self.next() as i64 % lsb);
+        let max = (1i64 << (from_bits - 1)) - 1;
+        convert_bit_depth((sample as i64 + noise).clamp(-max - 1, max) as i32, from_bits, to_bits)
+    }
+}
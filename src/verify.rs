@@ -0,0 +1,272 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt::{self, Debug, Formatter},
+    io::{self, Read, Seek, SeekFrom},
+    rc::Rc,
+    sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}},
+    thread,
+};
+
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, DecodeErrorContext, FlacAudioForm, SamplesInfo};
+
+/// ## The result of verifying a FLAC file by decoding all of it
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// * How many samples per channel were decoded
+    pub samples_decoded: u64,
+
+    /// * How many samples per channel the STREAMINFO says, zero if unknown
+    pub total_samples: u64,
+
+    /// * Does the STREAMINFO carry an MD5 signature of the audio
+    pub has_md5: bool,
+
+    /// * Does the MD5 signature match the decoded audio, always `true` if there is no MD5 signature
+    pub md5_matched: bool,
+
+    /// * The errors reported by libFLAC during decoding, e.g. lost sync, CRC mismatch, with where they happened
+    pub decode_errors: Vec<DecodeErrorContext>,
+
+    /// * The error that stopped the decoder, if any
+    pub decoder_error: Option<FlacDecoderError>,
+}
+
+impl VerifyReport {
+    /// * The file is considered good if it decoded without any errors, all of the samples are present, and the MD5 matches.
+    pub fn is_ok(&self) -> bool {
+        self.decoder_error.is_none()
+            && self.decode_errors.is_empty()
+            && self.md5_matched
+            && (self.total_samples == 0 || self.total_samples == self.samples_decoded)
+    }
+}
+
+/// ## Verify a FLAC file by decoding all of it, checking the frame CRCs, the sample count, and the MD5 signature.
+pub fn verify<ReadSeek>(reader: ReadSeek) -> VerifyReport
+where
+    ReadSeek: Read + Seek {
+    match Verifier::new(reader) {
+        Ok(mut verifier) => verifier.verify(),
+        Err(e) => VerifyReport {
+            decoder_error: Some(e),
+            ..VerifyReport::default()
+        },
+    }
+}
+
+/// * The decoder of `verify()`, `verify_batch()` keeps one per thread and resets it with the next file.
+struct Verifier<ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    decoder: FlacDecoder<'static, ReadSeek>,
+    samples_decoded: Rc<Cell<u64>>,
+    decode_errors: Rc<RefCell<Vec<DecodeErrorContext>>>,
+}
+
+impl<ReadSeek> Verifier<ReadSeek>
+where
+    ReadSeek: Read + Seek {
+    fn new(reader: ReadSeek) -> Result<Self, FlacDecoderError> {
+        let samples_decoded = Rc::new(Cell::new(0u64));
+        let decode_errors = Rc::new(RefCell::new(Vec::<DecodeErrorContext>::new()));
+        let mut decoder = {
+            let samples_decoded = samples_decoded.clone();
+            FlacDecoder::with_default_io(
+                reader,
+                Box::new(move |_samples: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+                    samples_decoded.set(samples_decoded.get() + samples_info.samples as u64);
+                    Ok(())
+                }),
+                // Reported by the `on_error_with_context()` closure instead.
+                Box::new(|_error: FlacInternalDecoderError| {}),
+                true,
+                false,
+                FlacAudioForm::ChannelArray,
+            )?
+        };
+        {
+            let decode_errors = decode_errors.clone();
+            decoder.set_on_error_with_context(Some(Box::new(move |context: &DecodeErrorContext| {
+                decode_errors.borrow_mut().push(*context);
+            })));
+        }
+        Ok(Self {
+            decoder,
+            samples_decoded,
+            decode_errors,
+        })
+    }
+
+    /// * Start verifying the next file, see `FlacDecoder::reset_with_reader()`.
+    fn reset(&mut self, reader: ReadSeek) -> Result<(), FlacDecoderError> {
+        self.decoder.reset_with_reader(reader)
+    }
+
+    fn verify(&mut self) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        self.samples_decoded.set(0);
+        self.decode_errors.borrow_mut().clear();
+        if let Err(e) = self.decoder.decode_all().into_result() {
+            report.decoder_error = Some(e);
+        }
+        if let Some(stream_info) = self.decoder.get_stream_info() {
+            report.total_samples = stream_info.total_samples;
+            report.has_md5 = stream_info.md5sum.iter().any(|x| *x != 0);
+        }
+
+        // `FLAC__stream_decoder_finish()` returns false only if the MD5 checking failed.
+        report.md5_matched = self.decoder.finish().is_ok();
+        report.samples_decoded = self.samples_decoded.get();
+        report.decode_errors = self.decode_errors.take();
+        report
+    }
+}
+
+/// ## Options for `verify_batch()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchVerifyOpts {
+    /// * How many threads to verify the files, zero or one means verifying on the current thread.
+    pub threads: usize,
+
+    /// * Stop verifying the remaining files after the first failure, otherwise verify all of the files.
+    pub stop_on_first_failure: bool,
+}
+
+impl BatchVerifyOpts {
+    pub fn new() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            stop_on_first_failure: false,
+        }
+    }
+}
+
+impl Default for BatchVerifyOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ## The result of `verify_batch()`
+#[derive(Debug, Clone, Default)]
+pub struct BatchReport {
+    /// * The labels and the reports of the verified files, in the order of the input.
+    pub reports: Vec<(String, VerifyReport)>,
+
+    /// * The labels of the files that weren't verified because of `stop_on_first_failure`
+    pub skipped: Vec<String>,
+}
+
+impl BatchReport {
+    /// * How many files were verified and passed
+    pub fn passed(&self) -> usize {
+        self.reports.iter().filter(|(_, report)| report.is_ok()).count()
+    }
+
+    /// * How many files were verified and failed
+    pub fn failed(&self) -> usize {
+        self.reports.len() - self.passed()
+    }
+
+    /// * How many samples per channel were decoded from all of the files
+    pub fn total_samples_decoded(&self) -> u64 {
+        self.reports.iter().map(|(_, report)| report.samples_decoded).sum()
+    }
+
+    /// * All of the files were verified and passed
+    pub fn is_ok(&self) -> bool {
+        self.skipped.is_empty() && self.failed() == 0
+    }
+
+    /// * The labels of the failed files
+    pub fn failures(&self) -> Vec<&str> {
+        self.reports.iter().filter(|(_, report)| !report.is_ok()).map(|(label, _)| label.as_str()).collect()
+    }
+}
+
+/// ## A reader that could be sent to another thread, for `verify_batch()` to take boxed readers.
+pub trait ReadSeekSend: Read + Seek + Send {}
+impl<T> ReadSeekSend for T where T: Read + Seek + Send {}
+
+/// * The decoder wants a `Debug` reader.
+struct BoxedReader(Box<dyn ReadSeekSend>);
+
+impl Read for BoxedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for BoxedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl Debug for BoxedReader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("BoxedReader")
+    }
+}
+
+/// ## Verify many FLAC files, optionally on multiple threads.
+/// * Each input is a label to identify the file in the report, and a reader of the file.
+/// * The threads take the files from a shared queue, the reports are in the same order as the input.
+pub fn verify_batch<I>(inputs: I, opts: BatchVerifyOpts) -> BatchReport
+where
+    I: IntoIterator<Item = (String, Box<dyn ReadSeekSend>)> {
+    let queue = Mutex::new(inputs.into_iter().enumerate().map(|(i, (label, reader))| (i, label, reader)).collect::<VecDeque<_>>());
+    let num_inputs = queue.lock().unwrap().len();
+    let results = Mutex::new((0..num_inputs).map(|_| None).collect::<Vec<Option<(String, VerifyReport)>>>());
+    let stop = AtomicBool::new(false);
+
+    let worker = || {
+        // One decoder per thread, reset with each file instead of creating one per file.
+        let mut verifier = None::<Verifier<BoxedReader>>;
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let Some((i, label, reader)) = queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            let started = match verifier.as_mut() {
+                Some(verifier) => verifier.reset(BoxedReader(reader)),
+                None => Verifier::new(BoxedReader(reader)).map(|new_verifier| verifier = Some(new_verifier)),
+            };
+            let report = match (started, verifier.as_mut()) {
+                (Ok(()), Some(verifier)) => verifier.verify(),
+                (result, _) => {
+                    // Start over with a new decoder for the next file.
+                    verifier = None;
+                    VerifyReport {
+                        decoder_error: result.err(),
+                        ..VerifyReport::default()
+                    }
+                }
+            };
+            if !report.is_ok() && opts.stop_on_first_failure {
+                stop.store(true, Ordering::Relaxed);
+            }
+            results.lock().unwrap()[i] = Some((label, report));
+        }
+    };
+
+    if opts.threads <= 1 {
+        worker();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..opts.threads.min(num_inputs) {
+                scope.spawn(worker);
+            }
+        });
+    }
+
+    BatchReport {
+        reports: results.into_inner().unwrap().into_iter().flatten().collect(),
+        skipped: queue.into_inner().unwrap().into_iter().map(|(_, label, _)| label).collect(),
+    }
+}
@@ -0,0 +1,166 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    io::{self, Read, Seek, SeekFrom},
+    rc::Rc,
+};
+
+use crate::flac::{FlacDecoder, FlacDecoderError, FlacInternalDecoderError, FlacAudioForm, SamplesInfo, FLAC_DECODER_INVALID_ARGUMENT};
+
+/// ## The waveform overview of the audio, the minimum and the maximum sample value of each bucket
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Waveform {
+    /// * For each channel, the `(min, max)` pair of each bucket. An empty bucket is `(0, 0)`
+    pub peaks: Vec<Vec<(i32, i32)>>,
+
+    /// * The bits per sample of the audio, for normalizing the peaks.
+    pub bits_per_sample: u32,
+
+    /// * How many samples per channel were computed.
+    pub total_samples: u64,
+}
+
+impl Waveform {
+    /// * The peaks normalized to the range of -1.0 to 1.0
+    pub fn normalized(&self) -> Vec<Vec<(f32, f32)>> {
+        let scale = if self.bits_per_sample == 0 {1.0} else {(1u64 << (self.bits_per_sample - 1)) as f32};
+        self.peaks.iter().map(|channel| {
+            channel.iter().map(|(min, max)| (*min as f32 / scale, *max as f32 / scale)).collect()
+        }).collect()
+    }
+}
+
+/// * Computes the peaks while the samples come.
+#[derive(Debug, Clone)]
+struct PeakCollector {
+    buckets: usize,
+    total_samples: u64,
+    position: u64,
+    peaks: Vec<Vec<Option<(i32, i32)>>>,
+}
+
+impl PeakCollector {
+    fn new(buckets: usize, channels: usize, total_samples: u64) -> Self {
+        Self {
+            buckets,
+            total_samples,
+            position: 0,
+            peaks: vec![vec![None; buckets]; channels],
+        }
+    }
+
+    fn feed(&mut self, channels: &[Vec<i32>], num_samples: usize) {
+        for i in 0..num_samples {
+            let position = self.position + i as u64;
+            if position >= self.total_samples {
+                break;
+            }
+            let bucket = (position as u128 * self.buckets as u128 / self.total_samples as u128) as usize;
+            for (channel, peaks) in channels.iter().zip(self.peaks.iter_mut()) {
+                let sample = channel[i];
+                peaks[bucket] = Some(match peaks[bucket] {
+                    Some((min, max)) => (min.min(sample), max.max(sample)),
+                    None => (sample, sample),
+                });
+            }
+        }
+        self.position += num_samples as u64;
+    }
+}
+
+/// * What the decoding pass does with the samples.
+#[derive(Debug, Clone)]
+enum Pass {
+    /// * The STREAMINFO isn't read yet.
+    Pending,
+
+    /// * The length is unknown, just count the samples in this pass.
+    Counting(u64),
+
+    /// * Collect the peaks.
+    Collecting(PeakCollector),
+}
+
+/// ## Compute the waveform overview of the audio: the `(min, max)` peak pair of exactly `buckets` buckets for each channel.
+/// * The peaks are computed while decoding, the samples are not retained.
+/// * The total samples in the STREAMINFO block sizes the buckets. If it's unknown, the audio is decoded twice, first to count the samples.
+/// * Zero buckets is rejected with `FLAC_DECODER_INVALID_ARGUMENT` before decoding.
+pub fn waveform<ReadSeek>(mut reader: ReadSeek, buckets: usize) -> Result<Waveform, FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    if buckets == 0 {
+        return Err(FlacDecoderError::new(FLAC_DECODER_INVALID_ARGUMENT, "waveform"));
+    }
+    let start = reader.stream_position().ok();
+    let (pass, bits_per_sample) = decode_pass(&mut reader, buckets, None)?;
+    let (pass, bits_per_sample) = match pass {
+        Pass::Counting(total_samples) => {
+            let Some(start) = start else {
+                return Err(FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_SEEK_ERROR, "waveform"));
+            };
+            if reader.seek(SeekFrom::Start(start)).is_err() {
+                return Err(FlacDecoderError::new(libflac_sys::FLAC__STREAM_DECODER_SEEK_ERROR, "waveform"));
+            }
+            decode_pass(&mut reader, buckets, Some(total_samples))?
+        }
+        pass => (pass, bits_per_sample),
+    };
+    match pass {
+        Pass::Collecting(collector) => Ok(Waveform {
+            peaks: collector.peaks.into_iter().map(|channel| channel.into_iter().map(|peak| peak.unwrap_or((0, 0))).collect()).collect(),
+            bits_per_sample,
+            total_samples: collector.position.min(collector.total_samples),
+        }),
+        // No audio at all
+        _ => Ok(Waveform {
+            peaks: Vec::new(),
+            bits_per_sample,
+            total_samples: 0,
+        }),
+    }
+}
+
+/// * Decode the whole audio once, use `total_samples` instead of the one in the STREAMINFO if it's given.
+fn decode_pass<ReadSeek>(reader: ReadSeek, buckets: usize, total_samples: Option<u64>) -> Result<(Pass, u32), FlacDecoderError>
+where
+    ReadSeek: Read + Seek {
+    let pass = Rc::new(RefCell::new(Pass::Pending));
+    let mut decoder = {
+        let pass = pass.clone();
+        FlacDecoder::with_default_io(
+            reader,
+            Box::new(move |channels: &[Vec<i32>], samples_info: &SamplesInfo| -> Result<(), io::Error> {
+                match &mut *pass.borrow_mut() {
+                    Pass::Pending => return Err(io::Error::other("The STREAMINFO block is missing")),
+                    Pass::Counting(count) => *count += samples_info.samples as u64,
+                    Pass::Collecting(collector) => collector.feed(channels, samples_info.samples as usize),
+                }
+                Ok(())
+            }),
+            Box::new(|_error: FlacInternalDecoderError| {}),
+            false,
+            false,
+            FlacAudioForm::ChannelArray,
+        )?
+    };
+
+    let mut bits_per_sample = 0;
+    while !decoder.is_end_of_stream() {
+        let pending = matches!(*pass.borrow(), Pass::Pending);
+        if pending && let Some(stream_info) = decoder.get_stream_info() {
+            bits_per_sample = stream_info.bits_per_sample;
+            *pass.borrow_mut() = match total_samples.unwrap_or(stream_info.total_samples) {
+                0 if total_samples.is_none() => Pass::Counting(0),
+                total_samples => Pass::Collecting(PeakCollector::new(buckets, stream_info.channels as usize, total_samples)),
+            };
+        }
+        if !decoder.process_single()? {
+            break;
+        }
+    }
+    decoder.finish()?;
+    let pass = pass.borrow().clone();
+    Ok((pass, bits_per_sample))
+}